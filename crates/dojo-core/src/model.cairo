@@ -1,5 +1,7 @@
 trait Model<T> {
     fn name(self: @T) -> felt252;
+    // The same name, for the callers without a value of the model, eg. `get!`.
+    fn selector() -> felt252;
     fn keys(self: @T) -> Span<felt252>;
     fn values(self: @T) -> Span<felt252>;
     fn layout(self: @T) -> Span<u8>;
@@ -12,3 +14,56 @@ trait IModel<T> {
     fn layout(self: @T) -> Span<felt252>;
     fn schema(self: @T) -> Span<dojo::database::schema::Member>;
 }
+
+// `Option` members of models are stored on a fixed number of felts, as their layout expects: the
+// index of the variant, followed by the value or, if it's missing, by `size` zeros.
+
+fn serialize_option<T, impl TSerde: Serde<T>>(
+    value: @Option<T>, size: usize, ref output: Array<felt252>
+) {
+    match value {
+        Option::Some(value) => {
+            output.append(0);
+            value.serialize(ref output);
+        },
+        Option::None => {
+            output.append(1);
+            let mut i = 0;
+            loop {
+                if i == size {
+                    break;
+                }
+                output.append(0);
+                i += 1;
+            };
+        }
+    }
+}
+
+fn deserialize_option<T, impl TSerde: Serde<T>, impl TDrop: Drop<T>>(
+    size: usize, ref serialized: Span<felt252>
+) -> Option<Option<T>> {
+    let variant = match serialized.pop_front() {
+        Option::Some(variant) => *variant,
+        Option::None => {
+            return Option::None;
+        }
+    };
+
+    if variant == 0 {
+        return match TSerde::deserialize(ref serialized) {
+            Option::Some(value) => Option::Some(Option::Some(value)),
+            Option::None => Option::None,
+        };
+    }
+
+    let mut i = 0;
+    loop {
+        if i == size {
+            break;
+        }
+        serialized.pop_front();
+        i += 1;
+    };
+    Option::Some(Option::None)
+}
@@ -16,7 +16,7 @@ use cairo_lang_starknet::plugin::aux_data::StarkNetContractAuxData;
 use cairo_lang_utils::UpcastMut;
 use convert_case::{Case, Casing};
 use dojo_world::manifest::{
-    Class, Contract, BASE_CONTRACT_NAME, EXECUTOR_CONTRACT_NAME, WORLD_CONTRACT_NAME,
+    Class, Contract, Member, BASE_CONTRACT_NAME, EXECUTOR_CONTRACT_NAME, WORLD_CONTRACT_NAME,
 };
 use itertools::Itertools;
 use scarb::compiler::helpers::{build_compiler_config, collect_main_crate_ids};
@@ -44,6 +44,18 @@ pub struct DojoCompiler;
 #[serde(rename_all = "kebab-case")]
 pub struct Props {
     pub build_external_contracts: Option<Vec<ContractSelector>>,
+    /// Whether a JSON description of each model is emitted alongside the compiled contracts,
+    /// for the bindings generators.
+    #[serde(default)]
+    pub build_model_bindings: bool,
+}
+
+/// A machine-readable description of a model, written to `<target>-<model>-bindings.json` when
+/// `build-model-bindings` is enabled.
+#[derive(Debug, Serialize)]
+pub struct ModelBindings<'a> {
+    pub name: &'a str,
+    pub members: &'a [Member],
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -128,6 +140,19 @@ impl Compiler for DojoCompiler {
             target_dir.open_rw("manifest.json", "output file", ws.config())?.path(),
         )?;
 
+        if props.build_model_bindings {
+            for model in &manifest.models {
+                let target_name = &unit.target().name;
+                let model_name = model.name.to_case(Case::Snake);
+                let file_name = format!("{target_name}-{model_name}-bindings.json");
+
+                let bindings = ModelBindings { name: &model.name, members: &model.members };
+                let mut file = target_dir.open_rw(file_name, "output file", ws.config())?;
+                serde_json::to_writer_pretty(file.deref_mut(), &bindings)
+                    .with_context(|| format!("failed to serialize model bindings: {model_name}"))?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -307,12 +332,13 @@ fn get_dojo_model_artifacts(
                 .cloned()
                 .ok_or(anyhow!("Model {} not found in target.", model.name))?;
 
+            // keyed by struct name, as the models are referred to by the systems
             models.insert(
                 model.name.clone(),
                 dojo_world::manifest::Model {
                     abi,
                     class_hash,
-                    name: model.name.clone(),
+                    name: model.registered_name.clone(),
                     members: model.members.clone(),
                 },
             );
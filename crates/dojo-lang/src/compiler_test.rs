@@ -11,7 +11,7 @@ use scarb::ops::{self, CompileOpts};
 use smol_str::SmolStr;
 use starknet::macros::felt;
 
-use super::do_update_manifest;
+use super::{do_update_manifest, ModelBindings};
 
 fn build_mock_manifest() -> dojo_world::manifest::Manifest {
     dojo_world::manifest::Manifest {
@@ -149,6 +149,31 @@ fn update_manifest_correctly() {
     assert!(mock_manifest.contracts[2].address.is_none(), "new contract do not have address");
 }
 
+#[test]
+fn model_bindings_describe_members() {
+    let members = vec![
+        dojo_world::manifest::Member {
+            name: "player".into(),
+            ty: "ContractAddress".into(),
+            key: true,
+        },
+        dojo_world::manifest::Member { name: "remaining".into(), ty: "u8".into(), key: false },
+    ];
+
+    let bindings = ModelBindings { name: "Moves", members: &members };
+
+    assert_eq!(
+        serde_json::to_value(bindings).unwrap(),
+        serde_json::json!({
+            "name": "Moves",
+            "members": [
+                { "name": "player", "type": "ContractAddress", "key": true },
+                { "name": "remaining", "type": "u8", "key": false },
+            ]
+        })
+    );
+}
+
 #[test]
 fn test_compiler() {
     let config = build_test_config("../../examples/spawn-and-move/Scarb.toml").unwrap();
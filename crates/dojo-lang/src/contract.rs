@@ -89,6 +89,7 @@ impl DojoContract {
                         systems: vec![SystemAuxData {
                             name,
                             dependencies: system.dependencies.values().cloned().collect(),
+                            stable_ptr: module_ast.stable_ptr().untyped(),
                         }],
                     })),
                     diagnostics_mappings: builder.diagnostics_mappings,
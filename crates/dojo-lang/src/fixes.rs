@@ -0,0 +1,57 @@
+//! Fixes suggested for some of the diagnostics of the Dojo plugin, and of the builtin plugins on
+//! the Dojo items, for the language server to offer them as code actions.
+//!
+//! The plugin diagnostics only hold a message, so the fixes are computed on demand for the items
+//! of a file, by the same functions producing the diagnostics.
+
+use cairo_lang_syntax::attribute::structured::{AttributeArgVariant, AttributeStructurize};
+use cairo_lang_syntax::node::db::SyntaxGroup;
+use cairo_lang_syntax::node::helpers::QueryAttrs;
+use cairo_lang_syntax::node::ids::SyntaxStablePtrId;
+use cairo_lang_syntax::node::{ast, TypedSyntaxNode};
+
+use crate::model::missing_key;
+use crate::plugin::unquoted_derive_arg;
+
+/// A text edit fixing the issue reported by a plugin diagnostic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SuggestedFix {
+    /// The node the fixed diagnostic is reported on.
+    pub diagnostic_ptr: SyntaxStablePtrId,
+    /// A short description of the edit, eg. "Add `#[key]` to `id`".
+    pub title: String,
+    /// The node whose text, without trivia, is replaced.
+    pub target: SyntaxStablePtrId,
+    pub replacement: String,
+}
+
+/// Returns the fixes suggested for the diagnostics reported by the plugin on `item`, for:
+/// * the models without key members, marking their first member as a key.
+/// * the quoted names of traits in `derive` attributes, eg. `'Model'`, which the builtin `derive`
+///   plugin reports, unquoting them.
+pub fn suggested_fixes(db: &dyn SyntaxGroup, item: &ast::Item) -> Vec<SuggestedFix> {
+    let attributes = match item {
+        ast::Item::Struct(struct_ast) => struct_ast.attributes(db),
+        ast::Item::Enum(enum_ast) => enum_ast.attributes(db),
+        _ => return vec![],
+    };
+
+    let mut fixes = vec![];
+    let mut is_model = false;
+    for attr in attributes.query_attr(db, "derive") {
+        for arg in attr.structurize(db).args {
+            match &arg.variant {
+                AttributeArgVariant::Unnamed { value: ast::Expr::Path(path), .. } => {
+                    is_model |= path.as_syntax_node().get_text_without_trivia(db) == "Model";
+                }
+                _ => fixes.extend(unquoted_derive_arg(db, &arg)),
+            }
+        }
+    }
+
+    if let (true, ast::Item::Struct(struct_ast)) = (is_model, item) {
+        fixes.extend(missing_key(db, struct_ast).and_then(|(_, fix)| fix));
+    }
+
+    fixes
+}
@@ -3,7 +3,15 @@ use cairo_lang_defs::plugin::{
     InlineMacroExprPlugin, InlinePluginResult, PluginDiagnostic, PluginGeneratedFile,
 };
 use cairo_lang_semantic::inline_macros::unsupported_bracket_diagnostic;
-use cairo_lang_syntax::node::{ast, TypedSyntaxNode};
+use cairo_lang_syntax::node::db::SyntaxGroup;
+use cairo_lang_syntax::node::helpers::QueryAttrs;
+use cairo_lang_syntax::node::kind::SyntaxKind;
+use cairo_lang_syntax::node::{ast, SyntaxNode, Terminal, TypedSyntaxNode};
+use smol_str::SmolStr;
+use starknet::core::types::FieldElement;
+use starknet::core::utils::get_selector_from_name;
+
+use super::utils::parent_of_kind;
 
 #[derive(Debug)]
 pub struct EmitMacro;
@@ -28,12 +36,14 @@ impl InlineMacroExprPlugin for EmitMacro {
 
         let args = arg_list.args(db).elements(db);
 
-        if args.len() != 2 {
+        if args.len() != 2 && args.len() != 3 {
             return InlinePluginResult {
                 code: None,
                 diagnostics: vec![PluginDiagnostic {
                     stable_ptr: arg_list.args(db).stable_ptr().untyped(),
-                    message: "Invalid arguments. Expected \"emit!(world, event)\"".to_string(),
+                    message: "Invalid arguments. Expected \"emit!(world, event)\" or \
+                              \"emit!(world, event, selector: 'Name')\""
+                        .to_string(),
                 }],
             };
         }
@@ -41,12 +51,55 @@ impl InlineMacroExprPlugin for EmitMacro {
         let world = &args[0];
         let event = &args[1];
 
+        if let Some(diagnostic) = check_event_declared(db, syntax, event) {
+            return InlinePluginResult { code: None, diagnostics: vec![diagnostic] };
+        }
+
+        let selector = match args.get(2).map(|arg| selector_override(db, arg)) {
+            Some(Ok(selector)) => Some(selector),
+            Some(Err(diagnostic)) => {
+                return InlinePluginResult { code: None, diagnostics: vec![diagnostic] };
+            }
+            None => None,
+        };
+
+        // the selector of an event constructed by its type is derived from the type name, which
+        // may differ from the name of its variant
+        let derived_selector =
+            event_type_name(db, event).and_then(|name| get_selector_from_name(&name).ok());
+
         builder.add_str(
             "\n            starknet::Event::append_keys_and_data(@traits::Into::<_, Event>::into(",
         );
         builder.add_node(event.as_syntax_node());
         builder.add_str("), ref keys, ref data);");
 
+        // the first key is the selector of the event variant, derived from its name, replaced by
+        // the given selector or the one derived from the type name
+        if selector.is_some() || derived_selector.is_some() {
+            builder.add_str(
+                "
+            let mut __emit_macro_keys__ = keys.span();
+            __emit_macro_keys__.pop_front();
+            let mut keys = Default::<array::Array>::default();
+            keys.append(",
+            );
+            if let Some(selector) = selector {
+                builder.add_node(selector.as_syntax_node());
+            } else if let Some(derived) = derived_selector {
+                builder.add_str(&format!("{derived:#x}"));
+            }
+            builder.add_str(
+                ");
+            loop {
+                match __emit_macro_keys__.pop_front() {
+                    Option::Some(key) => keys.append(*key),
+                    Option::None => { break; },
+                };
+            };",
+            );
+        }
+
         builder.add_str("\n            ");
         builder.add_node(world.as_syntax_node());
         builder.add_str(".emit(keys, data.span());");
@@ -63,3 +116,117 @@ impl InlineMacroExprPlugin for EmitMacro {
         }
     }
 }
+
+/// Returns the value of the `selector: <literal>` argument, overriding the selector of the emitted
+/// event. The selector must be a literal fitting in a felt, ie. a short string or a number.
+fn selector_override(db: &dyn SyntaxGroup, arg: &ast::Arg) -> Result<ast::Expr, PluginDiagnostic> {
+    let invalid = |message: &str| PluginDiagnostic {
+        stable_ptr: arg.stable_ptr().untyped(),
+        message: message.to_string(),
+    };
+
+    let ast::ArgClause::Named(clause) = arg.arg_clause(db) else {
+        return Err(invalid("Invalid arguments. Expected the selector as \"selector: 'Name'\"."));
+    };
+    if clause.name(db).text(db) != "selector" {
+        return Err(invalid(&format!(
+            "Unknown argument `{}`. Expected \"selector: 'Name'\".",
+            clause.name(db).text(db)
+        )));
+    }
+
+    let value = clause.value(db);
+    let fits_in_felt = match &value {
+        ast::Expr::ShortString(literal) => {
+            literal.string_value(db).map_or(false, |s| s.is_ascii() && s.len() <= 31)
+        }
+        // negative and out of range numbers fail to parse
+        ast::Expr::Literal(literal) => literal
+            .numeric_value(db)
+            .map_or(false, |value| FieldElement::from_dec_str(&value.to_string()).is_ok()),
+        _ => false,
+    };
+
+    if !fits_in_felt {
+        return Err(invalid(
+            "Invalid selector. Expected a felt252 literal, eg. 'Moved' or 0x1234.",
+        ));
+    }
+
+    Ok(value)
+}
+
+/// Checks that the event emitted with a struct constructor (eg. `Moved { player, direction }`) is
+/// a variant of the `#[event]` enum declared in the module the macro is used in.
+///
+/// Events emitted from other expressions, or from modules that don't declare an `#[event]` enum,
+/// are left to the type checker.
+fn check_event_declared(
+    db: &dyn SyntaxGroup,
+    syntax: &ast::ExprInlineMacro,
+    event: &ast::Arg,
+) -> Option<PluginDiagnostic> {
+    let event_name = event_type_name(db, event)?;
+    let declared_events = declared_events(db, &syntax.as_syntax_node())?;
+
+    if declared_events.iter().any(|name| name.as_str() == event_name.as_str()) {
+        return None;
+    }
+
+    Some(PluginDiagnostic {
+        stable_ptr: event.stable_ptr().untyped(),
+        message: format!(
+            "`{event_name}` is not a declared event. Expected one of the variants of the \
+             `#[event]` enum: {}.",
+            declared_events.join(", ")
+        ),
+    })
+}
+
+/// Returns the name of the type of an event emitted with a struct constructor, eg. `Moved` for
+/// `Moved { player, direction }`.
+fn event_type_name(db: &dyn SyntaxGroup, event: &ast::Arg) -> Option<SmolStr> {
+    let ast::ArgClause::Unnamed(event_clause) = event.arg_clause(db) else {
+        return None;
+    };
+    let ast::Expr::StructCtorCall(ctor) = event_clause.value(db) else {
+        return None;
+    };
+
+    Some(ctor.path(db).elements(db).last()?.identifier(db))
+}
+
+/// Returns the type names of the variants of the `#[event]` enum declared in the module containing
+/// `node`, or `None` if the module doesn't declare one.
+fn declared_events(db: &dyn SyntaxGroup, node: &SyntaxNode) -> Option<Vec<String>> {
+    let items = match parent_of_kind(db, node, SyntaxKind::ItemModule) {
+        Some(module) => match ast::ItemModule::from_syntax_node(db, module).body(db) {
+            ast::MaybeModuleBody::Some(body) => body.items(db).elements(db),
+            ast::MaybeModuleBody::None(_) => return None,
+        },
+        None => {
+            let file = parent_of_kind(db, node, SyntaxKind::SyntaxFile)?;
+            ast::SyntaxFile::from_syntax_node(db, file).items(db).elements(db)
+        }
+    };
+
+    items.iter().find_map(|item| match item {
+        ast::Item::Enum(enum_ast) if enum_ast.has_attr(db, "event") => Some(
+            enum_ast
+                .variants(db)
+                .elements(db)
+                .iter()
+                .map(|variant| match variant.type_clause(db) {
+                    // only keep the last segment of the type path, without generic arguments
+                    ast::OptionTypeClause::TypeClause(clause) => {
+                        let ty = clause.ty(db).as_syntax_node().get_text_without_trivia(db);
+                        let ty = ty.split('<').next().unwrap_or_default();
+                        ty.rsplit("::").next().unwrap_or_default().to_string()
+                    }
+                    ast::OptionTypeClause::Empty(_) => variant.name(db).text(db).to_string(),
+                })
+                .collect(),
+        ),
+        _ => None,
+    })
+}
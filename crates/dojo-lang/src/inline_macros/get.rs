@@ -9,7 +9,9 @@ use cairo_lang_syntax::node::{ast, TypedSyntaxNode};
 use itertools::Itertools;
 
 use super::utils::{parent_of_kind, SYSTEM_READS};
-use super::{extract_models, unsupported_arg_diagnostic, CAIRO_ERR_MSG_LEN};
+use super::{
+    extract_models, non_model_diagnostic, unsupported_arg_diagnostic, world_expr, CAIRO_ERR_MSG_LEN,
+};
 
 #[derive(Debug)]
 pub struct GetMacro;
@@ -44,7 +46,9 @@ impl InlineMacroExprPlugin for GetMacro {
             };
         }
 
-        let world = &args[0];
+        let ast::ArgClause::Unnamed(world) = args[0].arg_clause(db) else {
+            return unsupported_arg_diagnostic(db, syntax);
+        };
 
         let ast::ArgClause::Unnamed(keys) = args[1].arg_clause(db) else {
             return unsupported_arg_diagnostic(db, syntax);
@@ -70,6 +74,22 @@ impl InlineMacroExprPlugin for GetMacro {
             };
         }
 
+        let non_models = models
+            .iter()
+            .filter_map(|model| {
+                non_model_diagnostic(
+                    db,
+                    &syntax.as_syntax_node(),
+                    model,
+                    Self::NAME,
+                    args[2].stable_ptr().untyped(),
+                )
+            })
+            .collect::<Vec<_>>();
+        if !non_models.is_empty() {
+            return InlinePluginResult { code: None, diagnostics: non_models };
+        }
+
         let args = match keys.value(db) {
             Expr::Literal(literal) => format!("({})", literal.as_syntax_node().get_text(db)),
             _ => keys.as_syntax_node().get_text(db),
@@ -79,6 +99,7 @@ impl InlineMacroExprPlugin for GetMacro {
             "serde::Serde::serialize(@{args}, ref __get_macro_keys__);
             let __get_macro_keys__ = array::ArrayTrait::span(@__get_macro_keys__);\n"
         ));
+        let world = world_expr(db, &mut builder, &world.value(db), "__get_macro_world__");
 
         let mut system_reads = SYSTEM_READS.lock().unwrap();
 
@@ -112,7 +133,8 @@ impl InlineMacroExprPlugin for GetMacro {
                  let mut __{model}_layout_span__ = array::ArrayTrait::span(@__{model}_layout__);
                  let mut __{model}_layout_clone_span__ = \
                  array::ArrayTrait::span(@__{model}_layout_clone__);
-                 let mut __{model}_values__ = {}.entity('{model}', __get_macro_keys__, 0_u8,
+                 let mut __{model}_values__ = {}.entity(
+                 dojo::model::Model::<{model}>::selector(), __get_macro_keys__, 0_u8,
                  dojo::packing::calculate_packed_size(ref __{model}_layout_clone_span__),
                  __{model}_layout_span__);
                  let mut __{model}_model__ = array::ArrayTrait::new();
@@ -122,7 +144,7 @@ impl InlineMacroExprPlugin for GetMacro {
                  let __{model} = option::OptionTrait::expect(serde::Serde::<{model}>::deserialize(
                     ref __{model}_model_span__
                 ), '{deser_err_msg}');\n",
-                world.as_syntax_node().get_text(db),
+                world,
             ));
         }
         builder.add_str(&format!(
@@ -1,8 +1,14 @@
+use cairo_lang_defs::patcher::PatchBuilder;
 use cairo_lang_defs::plugin::{InlinePluginResult, PluginDiagnostic};
 use cairo_lang_syntax::node::db::SyntaxGroup;
-use cairo_lang_syntax::node::{ast, Terminal, TypedSyntaxNode};
+use cairo_lang_syntax::node::ids::SyntaxStablePtrId;
+use cairo_lang_syntax::node::kind::SyntaxKind;
+use cairo_lang_syntax::node::{ast, SyntaxNode, Terminal, TypedSyntaxNode};
 use smol_str::SmolStr;
 
+use self::utils::parent_of_kind;
+use crate::plugin::derived_traits;
+
 pub mod emit;
 pub mod get;
 pub mod set;
@@ -88,3 +94,57 @@ pub fn unsupported_arg_diagnostic(
         }],
     }
 }
+
+/// Returns the code to use for the `world` argument of a macro. A variable is used as is, while any
+/// other expression, eg. `self.world()`, is bound to `var` by the code added to `builder`, so that
+/// it's evaluated once however many models the macro handles.
+pub fn world_expr(
+    db: &dyn SyntaxGroup,
+    builder: &mut PatchBuilder<'_>,
+    world: &ast::Expr,
+    var: &str,
+) -> String {
+    let text = world.as_syntax_node().get_text(db);
+    if let ast::Expr::Path(_) = world {
+        return text;
+    }
+
+    builder.add_str(&format!("\n                let {var} = {text};"));
+    var.into()
+}
+
+/// Returns a diagnostic at `stable_ptr` if `model`, used by the `macro_name!` call of `node`, is a
+/// struct of the enclosing module which doesn't derive `Model`. The other types are left to the
+/// type checker.
+pub fn non_model_diagnostic(
+    db: &dyn SyntaxGroup,
+    node: &SyntaxNode,
+    model: &str,
+    macro_name: &str,
+    stable_ptr: SyntaxStablePtrId,
+) -> Option<PluginDiagnostic> {
+    let items = match parent_of_kind(db, node, SyntaxKind::ItemModule) {
+        Some(module) => match ast::ItemModule::from_syntax_node(db, module).body(db) {
+            ast::MaybeModuleBody::Some(body) => body.items(db).elements(db),
+            ast::MaybeModuleBody::None(_) => return None,
+        },
+        None => {
+            let file = parent_of_kind(db, node, SyntaxKind::SyntaxFile)?;
+            ast::SyntaxFile::from_syntax_node(db, file).items(db).elements(db)
+        }
+    };
+
+    let struct_ast = items.into_iter().find_map(|item| match item {
+        ast::Item::Struct(struct_ast) if struct_ast.name(db).text(db) == model => Some(struct_ast),
+        _ => None,
+    })?;
+
+    // the invalid `derive` attributes are reported by the plugin on the struct itself
+    let derived = derived_traits(db, &struct_ast.attributes(db), &mut vec![]);
+    (!derived.iter().any(|derived| derived == "Model")).then(|| PluginDiagnostic {
+        stable_ptr,
+        message: format!(
+            "`{model}` isn't a model, derive `Model` on it to use it in `{macro_name}!`."
+        ),
+    })
+}
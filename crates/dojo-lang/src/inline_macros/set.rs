@@ -6,11 +6,12 @@ use cairo_lang_defs::plugin::{
 };
 use cairo_lang_semantic::inline_macros::unsupported_bracket_diagnostic;
 use cairo_lang_syntax::node::ast::{ExprPath, ExprStructCtorCall, FunctionWithBody, ItemModule};
+use cairo_lang_syntax::node::db::SyntaxGroup;
 use cairo_lang_syntax::node::kind::SyntaxKind;
 use cairo_lang_syntax::node::{ast, TypedSyntaxNode};
 
-use super::unsupported_arg_diagnostic;
 use super::utils::{parent_of_kind, SystemRWOpRecord, SYSTEM_WRITES};
+use super::{non_model_diagnostic, unsupported_arg_diagnostic, world_expr};
 
 #[derive(Debug)]
 pub struct SetMacro;
@@ -55,7 +56,13 @@ impl InlineMacroExprPlugin for SetMacro {
             };
         }
 
-        let world = &args[0];
+        let ast::ArgClause::Unnamed(world) = args[0].arg_clause(db) else {
+            return unsupported_arg_diagnostic(db, syntax);
+        };
+        if let Some(diagnostic) = invalid_world_diagnostic(db, &world.value(db)) {
+            return InlinePluginResult { code: None, diagnostics: vec![diagnostic] };
+        }
+        let world = world_expr(db, &mut builder, &world.value(db), "__set_macro_world__");
 
         let ast::ArgClause::Unnamed(models) = args[1].arg_clause(db) else {
             return unsupported_arg_diagnostic(db, syntax);
@@ -89,6 +96,16 @@ impl InlineMacroExprPlugin for SetMacro {
             }
         }
 
+        let invalid_models = bundle
+            .iter()
+            .filter_map(|(_, syntax_node)| {
+                invalid_model_diagnostic(db, &ast::Expr::from_syntax_node(db, syntax_node.clone()))
+            })
+            .collect::<Vec<_>>();
+        if !invalid_models.is_empty() {
+            return InlinePluginResult { code: None, diagnostics: invalid_models };
+        }
+
         if bundle.is_empty() {
             return InlinePluginResult {
                 code: None,
@@ -151,15 +168,17 @@ impl InlineMacroExprPlugin for SetMacro {
                 }
             }
 
+            // the trait is named so that it doesn't need to be imported, and a value which isn't a
+            // model fails on the missing implementation of `Model`
             builder.add_str(&format!(
                 "
                 let __set_macro_value__ = {};
-                {}.set_entity(dojo::model::Model::name(@__set_macro_value__),
+                dojo::world::IWorldDispatcherTrait::set_entity({},
+                 dojo::model::Model::name(@__set_macro_value__),
                  dojo::model::Model::keys(@__set_macro_value__), 0_u8,
                  dojo::model::Model::values(@__set_macro_value__),
                  dojo::model::Model::layout(@__set_macro_value__));",
-                entity,
-                world.as_syntax_node().get_text(db),
+                entity, world,
             ));
         }
         builder.add_str("}");
@@ -175,3 +194,58 @@ impl InlineMacroExprPlugin for SetMacro {
         }
     }
 }
+
+/// Returns a diagnostic if `world` obviously isn't a world dispatcher, ie. a literal, a tuple or
+/// the constructor of another struct. The other expressions are left to the type checker.
+fn invalid_world_diagnostic(db: &dyn SyntaxGroup, world: &ast::Expr) -> Option<PluginDiagnostic> {
+    let invalid = match world {
+        ast::Expr::StructCtorCall(ctor) => {
+            let name = ctor.path(db).as_syntax_node().get_text_without_trivia(db);
+            name.rsplit("::").next() != Some("IWorldDispatcher")
+        }
+        expr => is_literal_or_tuple(expr),
+    };
+
+    invalid.then(|| PluginDiagnostic {
+        stable_ptr: world.stable_ptr().untyped(),
+        message: format!(
+            "Invalid world `{}`, expected an `IWorldDispatcher`.",
+            world.as_syntax_node().get_text_without_trivia(db)
+        ),
+    })
+}
+
+/// Returns a diagnostic if `model` obviously isn't a model, ie. a literal, a tuple or the
+/// constructor of a struct of the module which doesn't derive `Model`.
+fn invalid_model_diagnostic(db: &dyn SyntaxGroup, model: &ast::Expr) -> Option<PluginDiagnostic> {
+    if let ast::Expr::StructCtorCall(ctor) = model {
+        let path = ctor.path(db);
+        let name = path.elements(db).last()?.identifier(db);
+        return non_model_diagnostic(
+            db,
+            &model.as_syntax_node(),
+            &name,
+            SetMacro::NAME,
+            path.stable_ptr().untyped(),
+        );
+    }
+
+    is_literal_or_tuple(model).then(|| PluginDiagnostic {
+        stable_ptr: model.stable_ptr().untyped(),
+        message: format!(
+            "Invalid model `{}`, expected a struct deriving `Model`.",
+            model.as_syntax_node().get_text_without_trivia(db)
+        ),
+    })
+}
+
+fn is_literal_or_tuple(expr: &ast::Expr) -> bool {
+    matches!(
+        expr,
+        ast::Expr::Literal(_)
+            | ast::Expr::ShortString(_)
+            | ast::Expr::True(_)
+            | ast::Expr::False(_)
+            | ast::Expr::Tuple(_)
+    )
+}
@@ -1,15 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use cairo_lang_defs::patcher::RewriteNode;
 use cairo_lang_defs::plugin::PluginDiagnostic;
-use cairo_lang_syntax::node::ast::{Expr, ItemEnum, ItemStruct, OptionTypeClause};
+use cairo_lang_syntax::node::ast::{self, Expr, ItemEnum, ItemStruct, OptionTypeClause};
 use cairo_lang_syntax::node::db::SyntaxGroup;
 use cairo_lang_syntax::node::helpers::QueryAttrs;
-use cairo_lang_syntax::node::{Terminal, TypedSyntaxNode};
+use cairo_lang_syntax::node::kind::SyntaxKind;
+use cairo_lang_syntax::node::{SyntaxNode, Terminal, TypedSyntaxNode};
 use cairo_lang_utils::unordered_hash_map::UnorderedHashMap;
 use dojo_world::manifest::Member;
 use itertools::Itertools;
 
+use crate::inline_macros::utils::parent_of_kind;
+
 #[derive(Clone, Default)]
 struct TypeIntrospection(usize, Vec<usize>);
 
@@ -24,11 +27,249 @@ fn primitive_type_introspection() -> HashMap<String, TypeIntrospection> {
         ("u128".into(), TypeIntrospection(1, vec![128])),
         ("u256".into(), TypeIntrospection(2, vec![128, 128])),
         ("usize".into(), TypeIntrospection(1, vec![32])),
+        // Signed integers are serialized as felts, negative values being `P - |value|`, so they
+        // can't be packed on fewer bits.
+        ("i8".into(), TypeIntrospection(1, vec![251])),
+        ("i16".into(), TypeIntrospection(1, vec![251])),
+        ("i32".into(), TypeIntrospection(1, vec![251])),
+        ("i64".into(), TypeIntrospection(1, vec![251])),
+        ("i128".into(), TypeIntrospection(1, vec![251])),
         ("ContractAddress".into(), TypeIntrospection(1, vec![251])),
         ("ClassHash".into(), TypeIntrospection(1, vec![251])),
     ])
 }
 
+/// Returns the type wrapped by `ty` if it's an `Option`.
+pub fn option_inner_type(ty: &str) -> Option<&str> {
+    ty.strip_prefix("Option<")
+        .or_else(|| ty.strip_prefix("option::Option<"))
+        .and_then(|inner| inner.strip_suffix('>'))
+        .map(str::trim)
+}
+
+/// Reports the `Option` members of a struct that isn't a model, as only models serialize them on
+/// a fixed number of felts.
+pub fn unsupported_option_members(
+    db: &dyn SyntaxGroup,
+    struct_ast: &ItemStruct,
+) -> Vec<PluginDiagnostic> {
+    struct_ast
+        .members(db)
+        .elements(db)
+        .iter()
+        .filter(|member| {
+            let ty = member.type_clause(db).ty(db).as_syntax_node().get_text(db);
+            option_inner_type(ty.trim()).is_some()
+        })
+        .map(|member| PluginDiagnostic {
+            stable_ptr: member.name(db).stable_ptr().untyped(),
+            message: "`Option` members are only supported in models.".into(),
+        })
+        .collect()
+}
+
+/// Reports the `#[skip]` members of a struct that isn't a model, as only the `Serde`
+/// implementation of models leaves them out.
+pub fn unsupported_skipped_members(
+    db: &dyn SyntaxGroup,
+    struct_ast: &ItemStruct,
+) -> Vec<PluginDiagnostic> {
+    struct_ast
+        .members(db)
+        .elements(db)
+        .iter()
+        .filter(|member| member.has_attr(db, "skip"))
+        .map(|member| PluginDiagnostic {
+            stable_ptr: member.name(db).stable_ptr().untyped(),
+            message: "`#[skip]` members are only supported in models.".into(),
+        })
+        .collect()
+}
+
+/// Reports the `#[short_string]` members whose type isn't `felt252`, the only type holding a Cairo
+/// short string.
+pub fn invalid_short_string_members(
+    db: &dyn SyntaxGroup,
+    struct_ast: &ItemStruct,
+) -> Vec<PluginDiagnostic> {
+    struct_ast
+        .members(db)
+        .elements(db)
+        .iter()
+        .filter_map(|member| {
+            let ty = member.type_clause(db).ty(db).as_syntax_node().get_text(db);
+            let ty = ty.trim();
+            (member.has_attr(db, "short_string") && ty != "felt252").then(|| PluginDiagnostic {
+                stable_ptr: member.name(db).stable_ptr().untyped(),
+                message: format!(
+                    "`#[short_string]` member `{}` must be a `felt252`, not a `{ty}`.",
+                    member.name(db).text(db)
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Reports the members of `struct_ast` whose type contains the struct itself, directly or through
+/// the structs and enums declared in the same module. A recursive type has no finite storage
+/// layout, so its schema and size would never be computed. Types declared in other modules aren't
+/// followed.
+pub fn recursive_members(db: &dyn SyntaxGroup, struct_ast: &ItemStruct) -> Vec<PluginDiagnostic> {
+    let name = struct_ast.name(db).text(db).to_string();
+    let declared = declared_type_references(db, &struct_ast.as_syntax_node());
+
+    struct_ast
+        .members(db)
+        .elements(db)
+        .iter()
+        .filter_map(|member| {
+            let ty = member.type_clause(db).ty(db).as_syntax_node().get_text(db);
+            let path = path_to(&name, &referenced_types(&ty), &declared, &mut HashSet::new())?;
+            Some(PluginDiagnostic {
+                stable_ptr: member.name(db).stable_ptr().untyped(),
+                message: format!(
+                    "Member `{}` makes `{name}` recursive: `{name} -> {}`. A recursive type has \
+                     no finite storage layout.",
+                    member.name(db).text(db),
+                    path.join(" -> ")
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Returns the names referenced by the member types of each struct, and by the variant types of
+/// each enum, declared in the module containing `node`.
+fn declared_type_references(
+    db: &dyn SyntaxGroup,
+    node: &SyntaxNode,
+) -> HashMap<String, Vec<String>> {
+    let items = match parent_of_kind(db, node, SyntaxKind::ItemModule) {
+        Some(module) => match ast::ItemModule::from_syntax_node(db, module).body(db) {
+            ast::MaybeModuleBody::Some(body) => body.items(db).elements(db),
+            ast::MaybeModuleBody::None(_) => return HashMap::new(),
+        },
+        None => match parent_of_kind(db, node, SyntaxKind::SyntaxFile) {
+            Some(file) => ast::SyntaxFile::from_syntax_node(db, file).items(db).elements(db),
+            None => return HashMap::new(),
+        },
+    };
+
+    items
+        .iter()
+        .filter_map(|item| match item {
+            ast::Item::Struct(struct_ast) => Some((
+                struct_ast.name(db).text(db).to_string(),
+                struct_ast
+                    .members(db)
+                    .elements(db)
+                    .iter()
+                    .flat_map(|member| {
+                        referenced_types(
+                            &member.type_clause(db).ty(db).as_syntax_node().get_text(db),
+                        )
+                    })
+                    .collect(),
+            )),
+            ast::Item::Enum(enum_ast) => Some((
+                enum_ast.name(db).text(db).to_string(),
+                enum_ast
+                    .variants(db)
+                    .elements(db)
+                    .iter()
+                    .flat_map(|variant| match variant.type_clause(db) {
+                        OptionTypeClause::TypeClause(clause) => {
+                            referenced_types(&clause.ty(db).as_syntax_node().get_text(db))
+                        }
+                        OptionTypeClause::Empty(_) => vec![],
+                    })
+                    .collect(),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the single-segment paths of the type expression `ty`, including the ones of its generic
+/// arguments. The paths of several segments, eg. `other::Position`, name the types of other
+/// modules rather than the ones declared next to `ty`, and are left out.
+fn referenced_types(ty: &str) -> Vec<String> {
+    let ty = ty.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    ty.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+        .filter(|path| !path.is_empty() && !path.contains("::"))
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the chain of declared types leading from one of `types` to `target`, ending with
+/// `target`. The types already in `visited` aren't explored again, so other cycles between the
+/// declared types don't prevent the search from terminating.
+fn path_to(
+    target: &str,
+    types: &[String],
+    declared: &HashMap<String, Vec<String>>,
+    visited: &mut HashSet<String>,
+) -> Option<Vec<String>> {
+    for ty in types {
+        if ty == target {
+            return Some(vec![ty.clone()]);
+        }
+        if !visited.insert(ty.clone()) {
+            continue;
+        }
+        if let Some(referenced) = declared.get(ty) {
+            if let Some(mut path) = path_to(target, referenced, declared, visited) {
+                path.insert(0, ty.clone());
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the expression of the number of felts `ty` is serialized to in storage.
+pub fn type_size(ty: &str) -> String {
+    match primitive_type_introspection().get(ty) {
+        Some(p_ty) => p_ty.0.to_string(),
+        None => format!("dojo::database::schema::SchemaIntrospection::<{ty}>::size()"),
+    }
+}
+
+/// Returns the expression of the schema of `ty`.
+fn type_ty(ty: &str) -> String {
+    if primitive_type_introspection().contains_key(ty) {
+        return format!("dojo::database::schema::Ty::Primitive('{ty}')");
+    }
+
+    match option_inner_type(ty) {
+        // `Option` is stored like an enum whose `None` variant is padded to the size of the value
+        Some(inner) => format!(
+            "dojo::database::schema::Ty::Enum(dojo::database::schema::Enum {{
+                name: 'Option<{inner}>',
+                attrs: array![].span(),
+                children: array![
+                    (
+                        'Some',
+                        dojo::database::schema::serialize_member_type(
+                            @dojo::database::schema::Ty::Tuple(array![
+                                dojo::database::schema::serialize_member_type(@{})
+                            ].span())
+                        )
+                    ),
+                    (
+                        'None',
+                        dojo::database::schema::serialize_member_type(
+                            @dojo::database::schema::Ty::Tuple(array![].span())
+                        )
+                    )
+                ].span()
+            }})",
+            type_ty(inner)
+        ),
+        None => format!("dojo::database::schema::SchemaIntrospection::<{ty}>::ty()"),
+    }
+}
+
 /// A handler for Dojo code derives Introspect for a struct
 /// Parameters:
 /// * db: The semantic database.
@@ -36,15 +277,26 @@ fn primitive_type_introspection() -> HashMap<String, TypeIntrospection> {
 /// Returns:
 /// * A RewriteNode containing the generated code.
 pub fn handle_introspect_struct(db: &dyn SyntaxGroup, struct_ast: ItemStruct) -> RewriteNode {
+    let schema_name = struct_ast.name(db).text(db).to_string();
+    handle_introspect_struct_as(db, struct_ast, &schema_name)
+}
+
+/// Derives `Introspect` for a struct whose schema is named `schema_name` instead of the name of the
+/// struct, eg. a model registered with a custom name.
+pub fn handle_introspect_struct_as(
+    db: &dyn SyntaxGroup,
+    struct_ast: ItemStruct,
+    schema_name: &str,
+) -> RewriteNode {
     let name = struct_ast.name(db).text(db).into();
 
     let mut member_types: Vec<String> = vec![];
-    let primitive_sizes = primitive_type_introspection();
 
     let members: Vec<_> = struct_ast
         .members(db)
         .elements(db)
         .iter()
+        .filter(|member| !member.has_attr(db, "skip"))
         .map(|member| {
             let key = member.has_attr(db, "key");
             let ty = member.type_clause(db).ty(db).as_syntax_node().get_text(db).trim().to_string();
@@ -53,40 +305,30 @@ pub fn handle_introspect_struct(db: &dyn SyntaxGroup, struct_ast: ItemStruct) ->
             if key {
                 attrs.push("'key'");
             }
+            // felt252 members holding short strings, so that clients can decode them
+            if member.has_attr(db, "short_string") {
+                attrs.push("'short_string'");
+            }
 
-            if primitive_sizes.get(&ty).is_some() {
-                // It's a primitive type
-                member_types.push(format!(
-                    "
+            member_types.push(format!(
+                "
                     dojo::database::schema::serialize_member(@dojo::database::schema::Member {{
                         name: '{name}',
-                        ty: dojo::database::schema::Ty::Primitive('{ty}'),
+                        ty: {},
                         attrs: array![{}].span()
                     }})\n",
-                    attrs.join(","),
-                ));
-            } else {
-                // It's a custom struct/enum
-                member_types.push(format!(
-                    "
-                    dojo::database::schema::serialize_member(@dojo::database::schema::Member {{
-                        name: '{name}',
-                        ty: dojo::database::schema::SchemaIntrospection::<{ty}>::ty(),
-                        attrs: array![{}].span()
-                    }})\n",
-                    attrs.join(","),
-                ));
-            }
+                type_ty(&ty),
+                attrs.join(","),
+            ));
 
             Member { name, ty, key }
         })
         .collect::<_>();
-    drop(primitive_sizes);
 
     let type_ty = format!(
         "
         dojo::database::schema::Ty::Struct(dojo::database::schema::Struct {{
-            name: '{name}',
+            name: '{schema_name}',
             attrs: array![].span(),
             children: array![{}].span()
         }})",
@@ -218,7 +460,17 @@ fn handle_introspect_internal(
     let primitive_sizes = primitive_type_introspection();
 
     members.iter().for_each(|m| {
-        let primitive_intro = primitive_sizes.get(&m.ty);
+        // the variant index of an `Option` is stored on 8 bits, followed by the value
+        let ty = match option_inner_type(&m.ty) {
+            Some(inner) if !m.key => {
+                size_precompute += 1;
+                layout.push(RewriteNode::Text("layout.append(8);\n".into()));
+                inner
+            }
+            _ => &m.ty,
+        };
+
+        let primitive_intro = primitive_sizes.get(ty);
         let mut attrs = vec![];
 
         if let Some(p_ty) = primitive_intro {
@@ -236,13 +488,9 @@ fn handle_introspect_internal(
             if m.key {
                 attrs.push("'key'");
             } else {
-                size.push(format!(
-                    "dojo::database::schema::SchemaIntrospection::<{}>::size()",
-                    m.ty,
-                ));
+                size.push(format!("dojo::database::schema::SchemaIntrospection::<{ty}>::size()"));
                 layout.push(RewriteNode::Text(format!(
-                    "dojo::database::schema::SchemaIntrospection::<{}>::layout(ref layout);\n",
-                    m.ty
+                    "dojo::database::schema::SchemaIntrospection::<{ty}>::layout(ref layout);\n"
                 )));
             }
         }
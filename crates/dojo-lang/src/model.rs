@@ -1,32 +1,104 @@
-use cairo_lang_defs::patcher::RewriteNode;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use cairo_lang_defs::patcher::{PatchBuilder, RewriteNode};
 use cairo_lang_defs::plugin::PluginDiagnostic;
-use cairo_lang_syntax::node::ast::ItemStruct;
+use cairo_lang_syntax::attribute::structured::{AttributeArgVariant, AttributeStructurize};
+use cairo_lang_syntax::node::ast::{self, ItemStruct};
 use cairo_lang_syntax::node::db::SyntaxGroup;
 use cairo_lang_syntax::node::helpers::QueryAttrs;
 use cairo_lang_syntax::node::{Terminal, TypedSyntaxNode};
 use cairo_lang_utils::unordered_hash_map::UnorderedHashMap;
 use convert_case::{Case, Casing};
 use dojo_world::manifest::Member;
+use itertools::Itertools;
+
+use crate::fixes::SuggestedFix;
+use crate::introspect::{
+    handle_introspect_struct_as, invalid_short_string_members, option_inner_type,
+    recursive_members, type_size,
+};
+use crate::plugin::{DojoAuxData, Model, ModelIndex};
 
-use crate::introspect::handle_introspect_struct;
-use crate::plugin::{DojoAuxData, Model};
+/// Generates code along with the code of the models, eg. companion functions of a framework built
+/// on Dojo. The processors are registered on the plugin with
+/// [`crate::plugin::BuiltinDojoPlugin::with_model_processors`], and run on the valid models only.
+/// The code of a processor defining an item already defined by the model or another processor is
+/// left out, with a diagnostic.
+pub trait ModelProcessor: std::fmt::Debug + Send + Sync {
+    /// Returns the code to add for the model `struct_ast`, whose members are `members`, along with
+    /// the diagnostics to report on it.
+    fn process(
+        &self,
+        db: &dyn SyntaxGroup,
+        struct_ast: &ItemStruct,
+        members: &[Member],
+    ) -> (Vec<RewriteNode>, Vec<PluginDiagnostic>);
+}
 
 /// A handler for Dojo code that modifies a model struct.
 /// Parameters:
 /// * db: The semantic database.
 /// * struct_ast: The AST of the model struct.
+/// * processors: The model processors to run on the model, in order.
 /// Returns:
-/// * A RewriteNode containing the generated code.
+/// * A RewriteNode containing the generated code, or None if the model is generic or has invalid
+///   keys.
 pub fn handle_model_struct(
     db: &dyn SyntaxGroup,
     aux_data: &mut DojoAuxData,
     struct_ast: ItemStruct,
-) -> (RewriteNode, Vec<PluginDiagnostic>) {
+    processors: &[(String, Arc<dyn ModelProcessor>)],
+) -> (Option<RewriteNode>, Vec<PluginDiagnostic>) {
     let mut diagnostics = vec![];
 
+    // Model storage layouts are derived from the concrete member types, which aren't known for
+    // a generic struct, so reject it before generating any code.
+    if let ast::OptionWrappedGenericParamList::WrappedGenericParamList(params) =
+        struct_ast.generic_params(db)
+    {
+        diagnostics.push(PluginDiagnostic {
+            message: format!(
+                "Model `{}` cannot have generic parameters. Define a model struct for each \
+                 concrete type instead.",
+                struct_ast.name(db).text(db)
+            ),
+            stable_ptr: params.stable_ptr().untyped(),
+        });
+        return (None, diagnostics);
+    }
+
+    let recursive_diagnostics = recursive_members(db, &struct_ast);
+    if !recursive_diagnostics.is_empty() {
+        diagnostics.extend(recursive_diagnostics);
+        return (None, diagnostics);
+    }
+
+    let short_string_diagnostics = invalid_short_string_members(db, &struct_ast);
+    if !short_string_diagnostics.is_empty() {
+        diagnostics.extend(short_string_diagnostics);
+        return (None, diagnostics);
+    }
+
+    let registered_name = match registered_name(db, &struct_ast) {
+        Ok(registered_name) => registered_name,
+        Err(diagnostic) => {
+            diagnostics.push(diagnostic);
+            return (None, diagnostics);
+        }
+    };
+
     let elements = struct_ast.members(db).elements(db);
+    let indexes = match declared_indexes(db, &elements) {
+        Ok(indexes) => indexes,
+        Err(diagnostic) => {
+            diagnostics.push(diagnostic);
+            return (None, diagnostics);
+        }
+    };
     let members: &Vec<_> = &elements
         .iter()
+        .filter(|member| !member.has_attr(db, "skip"))
         .map(|member| Member {
             name: member.name(db).text(db).to_string(),
             ty: member.type_clause(db).ty(db).as_syntax_node().get_text(db).trim().to_string(),
@@ -36,11 +108,138 @@ pub fn handle_model_struct(
 
     let keys: Vec<_> = members.iter().filter(|m| m.key).collect::<_>();
 
-    if keys.is_empty() {
+    if let Some((diagnostic, _)) = missing_key(db, &struct_ast) {
+        diagnostics.push(diagnostic);
+    }
+
+    let mut has_invalid_keys = false;
+    for member in elements.iter().filter(|m| m.has_attr(db, "key")) {
+        if member.has_attr(db, "skip") {
+            has_invalid_keys = true;
+            diagnostics.push(PluginDiagnostic {
+                message: format!(
+                    "Key member `{}` can't be skipped, the keys are hashed into the entity id.",
+                    member.name(db).text(db),
+                ),
+                stable_ptr: member.name(db).stable_ptr().untyped(),
+            });
+            continue;
+        }
+
+        let ty = member.type_clause(db).ty(db);
+        if !is_valid_key_type(db, &ty) {
+            has_invalid_keys = true;
+            diagnostics.push(PluginDiagnostic {
+                message: format!(
+                    "Key member `{}` has type `{}` which cannot be used as a key. Key members \
+                     must be felt252, integers, bool, ContractAddress, ClassHash or enums.",
+                    member.name(db).text(db),
+                    ty.as_syntax_node().get_text(db).trim()
+                ),
+                stable_ptr: member.name(db).stable_ptr().untyped(),
+            });
+        }
+    }
+
+    // The entity id is the hash of the keys in declaration order, so their order is part of the
+    // identity of the entities. Declaring them first keeps it explicit.
+    let mut first_value = None;
+    for member in elements.iter() {
+        match (member.has_attr(db, "key"), first_value) {
+            (false, None) => first_value = Some(member),
+            (true, Some(value)) => {
+                has_invalid_keys = true;
+                diagnostics.push(PluginDiagnostic {
+                    message: format!(
+                        "Key member `{}` is declared after the value member `{}`. Key members \
+                         must be declared first, in the order they are hashed into the entity id: \
+                         reordering them changes the id of every entity of the model.",
+                        member.name(db).text(db),
+                        value.name(db).text(db),
+                    ),
+                    stable_ptr: member.name(db).stable_ptr().untyped(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // Keys are hashed to form the entity id, so don't generate a model that can't be addressed.
+    if has_invalid_keys {
+        return (None, diagnostics);
+    }
+
+    // `Option` members are stored on a fixed number of felts, which the `Serde` implementation of
+    // `Option` doesn't do. A `Serde` implementation of the model using the storage encoding is
+    // generated instead of the derived one.
+    let option_members: Vec<_> = elements
+        .iter()
+        .filter(|m| !m.has_attr(db, "skip") && option_inner_type(&type_text(db, m)).is_some())
+        .collect();
+    if !option_members.is_empty() {
+        let mut has_invalid_options = false;
+        for member in &option_members {
+            let ty = type_text(db, member);
+            if option_inner_type(&ty).and_then(option_inner_type).is_some() {
+                has_invalid_options = true;
+                diagnostics.push(PluginDiagnostic {
+                    message: format!(
+                        "Member `{}` has type `{ty}`. Nested `Option` members are not supported.",
+                        member.name(db).text(db),
+                    ),
+                    stable_ptr: member.name(db).stable_ptr().untyped(),
+                });
+            }
+        }
+
+        if let Some(serde) = derived_trait(db, &struct_ast, "Serde") {
+            has_invalid_options = true;
+            diagnostics.push(PluginDiagnostic {
+                message: format!(
+                    "Model `{}` has `Option` members and can't derive `Serde`, it is implemented \
+                     by `#[derive(Model)]` instead.",
+                    struct_ast.name(db).text(db),
+                ),
+                stable_ptr: serde.stable_ptr().untyped(),
+            });
+        }
+
+        if has_invalid_options {
+            return (None, diagnostics);
+        }
+    }
+
+    // `#[skip]` members are left out of the storage and set to their default value when the model
+    // is read, which also requires the `Serde` implementation of the model.
+    let skipped: Vec<_> = elements
+        .iter()
+        .filter(|m| m.has_attr(db, "skip"))
+        .map(|m| Member { name: m.name(db).text(db).to_string(), ty: type_text(db, m), key: false })
+        .collect();
+    if let Some(member) =
+        elements.iter().find(|m| m.has_attr(db, "skip") && m.has_attr(db, "index"))
+    {
         diagnostics.push(PluginDiagnostic {
-            message: "Model must define atleast one #[key] attribute".into(),
-            stable_ptr: struct_ast.name(db).stable_ptr().untyped(),
+            message: format!(
+                "Member `{}` is skipped and can't be indexed.",
+                member.name(db).text(db)
+            ),
+            stable_ptr: member.name(db).stable_ptr().untyped(),
         });
+        return (None, diagnostics);
+    }
+    if !skipped.is_empty() && option_members.is_empty() {
+        if let Some(serde) = derived_trait(db, &struct_ast, "Serde") {
+            diagnostics.push(PluginDiagnostic {
+                message: format!(
+                    "Model `{}` has `#[skip]` members and can't derive `Serde`, it is implemented \
+                     by `#[derive(Model)]` instead.",
+                    struct_ast.name(db).text(db),
+                ),
+                stable_ptr: serde.stable_ptr().untyped(),
+            });
+            return (None, diagnostics);
+        }
     }
 
     let serialize_member = |m: &Member, include_key: bool| {
@@ -55,6 +254,14 @@ pub fn handle_model_struct(
             )));
         }
 
+        if let Some(inner) = option_inner_type(&m.ty) {
+            return Some(RewriteNode::Text(format!(
+                "dojo::model::serialize_option(self.{}, {}, ref serialized);",
+                m.name,
+                type_size(inner)
+            )));
+        }
+
         Some(RewriteNode::Text(format!(
             "serde::Serde::serialize(self.{}, ref serialized);",
             m.name
@@ -68,15 +275,26 @@ pub fn handle_model_struct(
         members.iter().filter_map(|m| serialize_member(m, false)).collect::<_>();
 
     let name = struct_ast.name(db).text(db);
-    aux_data.models.push(Model { name: name.to_string(), members: members.to_vec() });
+    aux_data.models.push(Model {
+        name: name.to_string(),
+        registered_name: registered_name.clone(),
+        members: members.to_vec(),
+        keys: keys.iter().map(|m| m.name.clone()).collect(),
+        indexes,
+        stable_ptr: struct_ast.stable_ptr().untyped(),
+    });
 
-    (
-        RewriteNode::interpolate_patched(
-            "
+    let model = RewriteNode::interpolate_patched(
+        "
             impl $type_name$Model of dojo::model::Model<$type_name$> {
                 #[inline(always)]
                 fn name(self: @$type_name$) -> felt252 {
-                    '$type_name$'
+                    '$registered_name$'
+                }
+
+                #[inline(always)]
+                fn selector() -> felt252 {
+                    '$registered_name$'
                 }
 
                 #[inline(always)]
@@ -123,7 +341,7 @@ pub fn handle_model_struct(
 
                 #[external(v0)]
                 fn name(self: @ContractState) -> felt252 {
-                    '$type_name$'
+                    '$registered_name$'
                 }
 
                 #[external(v0)]
@@ -150,19 +368,389 @@ pub fn handle_model_struct(
                 fn schema(self: @ContractState) -> dojo::database::schema::Ty {
                     dojo::database::schema::SchemaIntrospection::<$type_name$>::ty()
                 }
+
+                const SELECTOR: felt252 = '$registered_name$';
+            }
+        ",
+        &UnorderedHashMap::from([
+            ("contract_name".to_string(), RewriteNode::Text(name.to_case(Case::Snake))),
+            (
+                "type_name".to_string(),
+                RewriteNode::new_trimmed(struct_ast.name(db).as_syntax_node()),
+            ),
+            ("registered_name".to_string(), RewriteNode::Text(registered_name.clone())),
+            (
+                "schema_introspection".to_string(),
+                handle_introspect_struct_as(db, struct_ast.clone(), &registered_name),
+            ),
+            ("serialized_keys".to_string(), RewriteNode::new_modified(serialized_keys)),
+            ("serialized_values".to_string(), RewriteNode::new_modified(serialized_values)),
+        ]),
+    );
+
+    let mut nodes = vec![model];
+    if !option_members.is_empty() || !skipped.is_empty() {
+        nodes.push(model_serde(&name, members, &skipped));
+    }
+
+    // the items of the processors are added next to the model, so their names can't clash with
+    // the ones of the model or of the other processors
+    let mut defined_by: HashMap<String, Option<&str>> =
+        item_names(&generated_code(db, &nodes)).into_iter().map(|item| (item, None)).collect();
+    defined_by.insert(struct_ast.name(db).text(db).to_string(), None);
+    for (processor_name, processor) in processors {
+        let (processor_nodes, processor_diagnostics) = processor.process(db, &struct_ast, members);
+        diagnostics.extend(processor_diagnostics);
+
+        let processor_items = item_names(&generated_code(db, &processor_nodes));
+        let mut has_duplicates = false;
+        for item in &processor_items {
+            let Some(owner) = defined_by.get(item) else {
+                continue;
+            };
+
+            has_duplicates = true;
+            let owner = match owner {
+                Some(other) => format!("model processor `{other}`"),
+                None => format!("model `{}`", struct_ast.name(db).text(db)),
+            };
+            diagnostics.push(PluginDiagnostic {
+                message: format!(
+                    "Model processor `{processor_name}` generates the item `{item}`, which is \
+                     already defined by the {owner}."
+                ),
+                stable_ptr: struct_ast.name(db).stable_ptr().untyped(),
+            });
+        }
+        if has_duplicates {
+            continue;
+        }
+
+        defined_by.extend(processor_items.into_iter().map(|item| (item, Some(&**processor_name))));
+        nodes.extend(processor_nodes);
+    }
+
+    (Some(RewriteNode::new_modified(nodes)), diagnostics)
+}
+
+/// Returns the code of `nodes`.
+fn generated_code(db: &dyn SyntaxGroup, nodes: &[RewriteNode]) -> String {
+    let mut builder = PatchBuilder::new(db);
+    for node in nodes {
+        builder.add_modified(node.clone());
+    }
+    builder.code
+}
+
+/// Returns the names of the items defined at the top level of the generated `code`, eg. `Foo` for
+/// `impl Foo<T> of Bar<T> { .. }`. The `use` items define no name.
+fn item_names(code: &str) -> Vec<String> {
+    const ITEM_KEYWORDS: [&str; 8] =
+        ["const", "enum", "fn", "impl", "mod", "struct", "trait", "type"];
+
+    let mut names = vec![];
+    let mut depth = 0_usize;
+    let mut after_keyword = false;
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_alphabetic() || c == '_' {
+            let len =
+                rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            let word = &rest[..len];
+            if depth == 0 && after_keyword {
+                names.push(word.to_string());
+                after_keyword = false;
+            } else if depth == 0 {
+                after_keyword = ITEM_KEYWORDS.contains(&word);
             }
+            rest = &rest[len..];
+            continue;
+        }
+
+        let len = match c {
+            // the generic parameters come before the name of an `impl`
+            '<' if after_keyword => {
+                let mut open = 0;
+                let end = rest.find(|c: char| {
+                    match c {
+                        '<' => open += 1,
+                        '>' => open -= 1,
+                        _ => {}
+                    }
+                    open == 0
+                });
+                end.map_or(rest.len(), |end| end + 1)
+            }
+            '\'' | '"' => rest[1..].find(c).map_or(rest.len(), |end| end + 2),
+            '/' if rest.starts_with("//") => rest.find('\n').unwrap_or(rest.len()),
+            '{' => {
+                depth += 1;
+                1
+            }
+            '}' => {
+                depth = depth.saturating_sub(1);
+                1
+            }
+            _ => c.len_utf8(),
+        };
+        rest = &rest[len..];
+    }
+
+    names
+}
+
+/// Implements `Serde` for a model with `Option` or `#[skip]` members, serializing them as they are
+/// stored. The `skipped` members aren't serialized, and are deserialized to their default value.
+fn model_serde(name: &str, members: &[Member], skipped: &[Member]) -> RewriteNode {
+    let mut serialize = vec![];
+    let mut deserialize = vec![];
+
+    for Member { name, ty, .. } in members {
+        match option_inner_type(ty) {
+            Some(inner) => {
+                let size = type_size(inner);
+                serialize.push(format!(
+                    "dojo::model::serialize_option(self.{name}, {size}, ref output);\n"
+                ));
+                deserialize.push(format!(
+                    "let {name} = match dojo::model::deserialize_option::<{inner}>({size}, ref \
+                     serialized) {{
+                        Option::Some({name}) => {name},
+                        Option::None => {{ return Option::None; }},
+                    }};\n"
+                ));
+            }
+            None => {
+                serialize.push(format!("serde::Serde::serialize(self.{name}, ref output);\n"));
+                deserialize.push(format!(
+                    "let {name} = match serde::Serde::<{ty}>::deserialize(ref serialized) {{
+                        Option::Some({name}) => {name},
+                        Option::None => {{ return Option::None; }},
+                    }};\n"
+                ));
+            }
+        }
+    }
+
+    for Member { name, ty, .. } in skipped {
+        deserialize.push(format!("let {name}: {ty} = Default::default();\n"));
+    }
+
+    RewriteNode::interpolate_patched(
+        "
+        impl $name$Serde of serde::Serde<$name$> {
+            fn serialize(self: @$name$, ref output: Array<felt252>) {
+                $serialize$
+            }
+
+            fn deserialize(ref serialized: Span<felt252>) -> Option<$name$> {
+                $deserialize$
+                Option::Some($name$ { $members$ })
+            }
+        }
         ",
-            &UnorderedHashMap::from([
-                ("contract_name".to_string(), RewriteNode::Text(name.to_case(Case::Snake))),
-                (
-                    "type_name".to_string(),
-                    RewriteNode::new_trimmed(struct_ast.name(db).as_syntax_node()),
+        &UnorderedHashMap::from([
+            ("name".to_string(), RewriteNode::Text(name.to_string())),
+            ("serialize".to_string(), RewriteNode::Text(serialize.join(""))),
+            ("deserialize".to_string(), RewriteNode::Text(deserialize.join(""))),
+            (
+                "members".to_string(),
+                RewriteNode::Text(
+                    members.iter().chain(skipped).map(|m| m.name.as_str()).join(", "),
                 ),
-                ("schema_introspection".to_string(), handle_introspect_struct(db, struct_ast)),
-                ("serialized_keys".to_string(), RewriteNode::new_modified(serialized_keys)),
-                ("serialized_values".to_string(), RewriteNode::new_modified(serialized_values)),
-            ]),
-        ),
-        diagnostics,
+            ),
+        ]),
     )
 }
+
+/// Reports a model without key members, suggesting to mark its first member as a key.
+pub(crate) fn missing_key(
+    db: &dyn SyntaxGroup,
+    struct_ast: &ItemStruct,
+) -> Option<(PluginDiagnostic, Option<SuggestedFix>)> {
+    let members = struct_ast.members(db).elements(db);
+    if members.iter().any(|member| member.has_attr(db, "key")) {
+        return None;
+    }
+
+    let diagnostic = PluginDiagnostic {
+        message: "Model must define atleast one #[key] attribute".into(),
+        stable_ptr: struct_ast.name(db).stable_ptr().untyped(),
+    };
+    let fix = members.first().map(|member| {
+        let node = member.as_syntax_node();
+        let (text, member_text) = (node.get_text(db), node.get_text_without_trivia(db));
+        // the member stays at its indentation, ie. the end of its leading trivia, on the line after
+        // the attribute, or after it on the same line if it isn't indented
+        let leading_trivia = &text[..text.find(&member_text).unwrap_or_default()];
+        let indentation = leading_trivia.rsplit('\n').next().unwrap_or_default();
+        let separator =
+            if indentation.is_empty() { " ".to_string() } else { format!("\n{indentation}") };
+
+        SuggestedFix {
+            diagnostic_ptr: diagnostic.stable_ptr,
+            title: format!("Add `#[key]` to `{}`", member.name(db).text(db)),
+            target: member.stable_ptr().untyped(),
+            replacement: format!("#[key]{separator}{member_text}"),
+        }
+    });
+
+    Some((diagnostic, fix))
+}
+
+/// The maximum length of a model name, which is stored in a short string.
+const MAX_NAME_LEN: usize = 31;
+
+/// Returns the name `struct_ast` is registered with as a model: the one set with the `name`
+/// argument of its `#[model]` attribute, eg. `#[model(name: 'Position_v2')]` to version a model,
+/// or the name of the struct otherwise.
+fn registered_name(
+    db: &dyn SyntaxGroup,
+    struct_ast: &ItemStruct,
+) -> Result<String, PluginDiagnostic> {
+    let mut registered_name = struct_ast.name(db).text(db).to_string();
+
+    for attr in struct_ast.attributes(db).query_attr(db, "model") {
+        let attr = attr.structurize(db);
+        for arg in attr.args {
+            let AttributeArgVariant::Named { name, value: ast::Expr::ShortString(value), .. } =
+                arg.variant
+            else {
+                return Err(PluginDiagnostic {
+                    message: "Unsupported argument of the `model` attribute, expected `name: \
+                              'ModelName'`."
+                        .into(),
+                    stable_ptr: arg.arg_stable_ptr.untyped(),
+                });
+            };
+            if name != "name" {
+                return Err(PluginDiagnostic {
+                    message: format!("Unknown argument `{name}` of the `model` attribute."),
+                    stable_ptr: arg.arg_stable_ptr.untyped(),
+                });
+            }
+
+            let value_text = value.string_value(db).unwrap_or_default();
+            if value_text.is_empty() || value_text.len() > MAX_NAME_LEN || !value_text.is_ascii() {
+                return Err(PluginDiagnostic {
+                    message: format!(
+                        "Model name `{value_text}` is invalid, it must be a non-empty ASCII \
+                         string of at most {MAX_NAME_LEN} characters to fit in a short string."
+                    ),
+                    stable_ptr: value.stable_ptr().untyped(),
+                });
+            }
+
+            registered_name = value_text;
+        }
+    }
+
+    Ok(registered_name)
+}
+
+/// Returns the secondary indexes declared with the `#[index]` attribute on `members`, eg.
+/// `#[index(name: 'coords')]` on several members to index them together, in the order of their
+/// first member.
+fn declared_indexes(
+    db: &dyn SyntaxGroup,
+    members: &[ast::Member],
+) -> Result<Vec<ModelIndex>, PluginDiagnostic> {
+    let mut indexes: Vec<ModelIndex> = vec![];
+
+    for member in members {
+        let member_name = member.name(db).text(db).to_string();
+
+        for attr in member.attributes(db).query_attr(db, "index") {
+            let attr = attr.structurize(db);
+            let mut index_name = member_name.clone();
+
+            for arg in attr.args {
+                let AttributeArgVariant::Named {
+                    name, value: ast::Expr::ShortString(value), ..
+                } = arg.variant
+                else {
+                    return Err(PluginDiagnostic {
+                        message: "Unsupported argument of the `index` attribute, expected `name: \
+                                  'IndexName'`."
+                            .into(),
+                        stable_ptr: arg.arg_stable_ptr.untyped(),
+                    });
+                };
+                if name != "name" {
+                    return Err(PluginDiagnostic {
+                        message: format!("Unknown argument `{name}` of the `index` attribute."),
+                        stable_ptr: arg.arg_stable_ptr.untyped(),
+                    });
+                }
+
+                index_name = value.string_value(db).unwrap_or_default();
+                if index_name.is_empty() {
+                    return Err(PluginDiagnostic {
+                        message: "The name of an index can't be empty.".into(),
+                        stable_ptr: value.stable_ptr().untyped(),
+                    });
+                }
+            }
+
+            match indexes.iter_mut().find(|index| index.name == index_name) {
+                Some(index) if index.members.contains(&member_name) => {
+                    return Err(PluginDiagnostic {
+                        message: format!(
+                            "Member `{member_name}` is declared twice in the index `{index_name}`."
+                        ),
+                        stable_ptr: attr.stable_ptr.untyped(),
+                    });
+                }
+                Some(index) => index.members.push(member_name.clone()),
+                None => indexes
+                    .push(ModelIndex { name: index_name, members: vec![member_name.clone()] }),
+            }
+        }
+    }
+
+    Ok(indexes)
+}
+
+/// The text of the type of a struct member.
+fn type_text(db: &dyn SyntaxGroup, member: &ast::Member) -> String {
+    member.type_clause(db).ty(db).as_syntax_node().get_text(db).trim().to_string()
+}
+
+/// Returns the argument of the `derive` attributes of `struct_ast` deriving `trait_name`, if any.
+fn derived_trait(
+    db: &dyn SyntaxGroup,
+    struct_ast: &ItemStruct,
+    trait_name: &str,
+) -> Option<ast::Expr> {
+    struct_ast.attributes(db).query_attr(db, "derive").into_iter().find_map(|attr| {
+        attr.structurize(db).args.into_iter().find_map(|arg| match arg.variant {
+            AttributeArgVariant::Unnamed { value: value @ ast::Expr::Path(_), .. }
+                if value.as_syntax_node().get_text(db).trim() == trait_name =>
+            {
+                Some(value)
+            }
+            _ => None,
+        })
+    })
+}
+
+/// Types that serialize to a variable number of felts and hence can't be used as keys.
+const COLLECTION_TYPES: [&str; 5] = ["Array", "Span", "Felt252Dict", "Nullable", "Box"];
+
+/// Whether `ty` can be used as the type of a model key.
+///
+/// Only plain, non-generic types are accepted (felt252, integers, bool, ContractAddress,
+/// ClassHash, enums...); tuples, snapshots and collections are rejected.
+fn is_valid_key_type(db: &dyn SyntaxGroup, ty: &ast::Expr) -> bool {
+    let ast::Expr::Path(path) = ty else {
+        return false;
+    };
+
+    let segments = path.elements(db);
+    let Some(ast::PathSegment::Simple(last)) = segments.last() else {
+        return false;
+    };
+
+    segments.iter().all(|segment| matches!(segment, ast::PathSegment::Simple(_)))
+        && !COLLECTION_TYPES.contains(&last.ident(db).text(db).as_str())
+}
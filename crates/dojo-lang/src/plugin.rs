@@ -1,9 +1,11 @@
 use std::fs;
 use std::fs::File;
 use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use cairo_lang_defs::patcher::PatchBuilder;
 use cairo_lang_defs::plugin::{
     DynGeneratedFileAuxData, GeneratedFileAuxData, InlineMacroExprPlugin, MacroPlugin,
@@ -14,7 +16,8 @@ use cairo_lang_syntax::attribute::structured::{
 };
 use cairo_lang_syntax::node::db::SyntaxGroup;
 use cairo_lang_syntax::node::helpers::QueryAttrs;
-use cairo_lang_syntax::node::{ast, Terminal};
+use cairo_lang_syntax::node::ids::SyntaxStablePtrId;
+use cairo_lang_syntax::node::{ast, Terminal, TypedSyntaxNode};
 use camino::{Utf8Path, Utf8PathBuf};
 use directories::ProjectDirs;
 use dojo_types::system::Dependency;
@@ -27,25 +30,53 @@ use semver::Version;
 use smol_str::SmolStr;
 
 use crate::contract::DojoContract;
+use crate::fixes::SuggestedFix;
 use crate::inline_macros::emit::EmitMacro;
 use crate::inline_macros::get::GetMacro;
 use crate::inline_macros::set::SetMacro;
-use crate::introspect::{handle_introspect_enum, handle_introspect_struct};
-use crate::model::handle_model_struct;
+use crate::introspect::{
+    handle_introspect_enum, handle_introspect_struct, invalid_short_string_members,
+    recursive_members, unsupported_option_members, unsupported_skipped_members,
+};
+use crate::model::{handle_model_struct, ModelProcessor};
 use crate::print::derive_print;
 
 const DOJO_CONTRACT_ATTR: &str = "dojo::contract";
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Model {
+    /// The name of the model struct.
     pub name: String,
+    /// The name the model is registered with in the world, which defaults to the name of the
+    /// struct and can be set with `#[model(name: 'Name')]`. `get!` and `set!` read and write the
+    /// models under this name.
+    pub registered_name: String,
     pub members: Vec<Member>,
+    /// The names of the key members, in the order they are hashed into the entity id. Reordering
+    /// them changes the id of the entities, so tooling can compare them across versions.
+    pub keys: Vec<String>,
+    /// The secondary indexes declared on the members, for the indexer to create on their columns.
+    pub indexes: Vec<ModelIndex>,
+    /// The definition of the model struct in the original source.
+    pub stable_ptr: SyntaxStablePtrId,
+}
+
+/// A secondary index declared with `#[index]` on members of a model, to speed up the queries
+/// filtering on them. The members sharing the name set with `#[index(name: 'coords')]` form a
+/// composite index, on their columns in declaration order. The index of a member is named after it
+/// otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelIndex {
+    pub name: String,
+    pub members: Vec<String>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct SystemAuxData {
     pub name: SmolStr,
     pub dependencies: Vec<Dependency>,
+    /// The definition of the contract module in the original source.
+    pub stable_ptr: SyntaxStablePtrId,
 }
 
 /// Dojo related auxiliary data of the Dojo plugin.
@@ -71,31 +102,157 @@ mod test;
 
 pub const PACKAGE_NAME: &str = "dojo_plugin";
 
-#[derive(Debug, Default)]
-pub struct BuiltinDojoPlugin;
+/// The Dojo plugin, along with the model processors and the custom inline macros registered on it.
+/// The instances created by scarb from it, see [`CairoPlugin::instantiate`], share them.
+#[derive(Clone, Debug, Default)]
+pub struct BuiltinDojoPlugin {
+    model_processors: Vec<(String, Arc<dyn ModelProcessor>)>,
+    inline_macros: Vec<(String, Arc<dyn InlineMacroExprPlugin>)>,
+}
 
 lazy_static! {
-    static ref MANIFEST_VERSION: Version = Version::parse(env!("CARGO_PKG_VERSION")).expect("Manifest version not defined");
-    static ref MANIFEST_PATH: Utf8PathBuf = {
-        let pd = ProjectDirs::from("com", "dojoengine", "sozo")
-            .expect("no valid home directory path could be retrieved from the operating system");
+    static ref MANIFEST_VERSION: Version =
+        Version::parse(env!("CARGO_PKG_VERSION")).expect("Manifest version not defined");
+    static ref MANIFEST_PATH: Result<Utf8PathBuf> = {
+        let pd = ProjectDirs::from("com", "dojoengine", "sozo").ok_or_else(|| {
+            anyhow!("no valid home directory path could be retrieved from the operating system")
+        })?;
 
-        let content = include_str!("../Scarb.toml");
         let path = pd.cache_dir().join(MANIFEST_VERSION.to_string()).join("Scarb.toml");
+        write_manifest(&path, include_str!("../Scarb.toml"))?;
 
-        // Create the directory if it doesn't exist
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).expect("Failed to create directory");
+        Utf8PathBuf::from_path_buf(path)
+            .map_err(|path| anyhow!("invalid UTF-8 path: {}", path.display()))
+    };
+}
+
+/// Returns the path of the manifest of the plugin package, written to the cache directory on
+/// first use.
+fn manifest_path() -> &'static Utf8Path {
+    match &*MANIFEST_PATH {
+        Ok(path) => path,
+        Err(e) => panic!("failed to write the dojo plugin manifest: {e:#}"),
+    }
+}
+
+/// Writes the manifest of the plugin package to `path`.
+///
+/// The content is written to a temporary file which is then renamed into place, so that
+/// concurrent writers, eg. parallel build processes sharing the cache directory, never observe a
+/// partially written manifest.
+fn write_manifest(path: &Path, content: &str) -> Result<()> {
+    // makes the temporary files of the writers of this process unique
+    static WRITE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let parent = path.parent().ok_or_else(|| anyhow!("invalid manifest path"))?;
+    fs::create_dir_all(parent)
+        .with_context(|| format!("failed to create directory {}", parent.display()))?;
+
+    let tmp_path = parent.join(format!(
+        ".Scarb.toml.{}.{}.tmp",
+        std::process::id(),
+        WRITE_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let write = || -> Result<()> {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    };
+
+    write().with_context(|| format!("failed to write {}", path.display())).map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        e
+    })
+}
+
+/// Returns the names of the traits derived by the `derive` attributes of an item. The empty
+/// `derive` lists and the arguments which aren't the path of a trait are reported by the builtin
+/// `derive` plugin, and the paths of several segments are left to the other plugins.
+pub(crate) fn derived_traits(
+    db: &dyn SyntaxGroup,
+    attributes: &ast::AttributeList,
+) -> Vec<SmolStr> {
+    let mut derived = vec![];
+    for attr in attributes.query_attr(db, "derive") {
+        for arg in attr.structurize(db).args {
+            let AttributeArgVariant::Unnamed { value: ast::Expr::Path(path), .. } = arg.variant
+            else {
+                continue;
+            };
+
+            if let [ast::PathSegment::Simple(segment)] = &path.elements(db)[..] {
+                derived.push(segment.ident(db).text(db));
+            }
         }
+    }
 
-        let mut file = File::create(&path).expect("unable to create file");
-        write!(file, "{}", content).expect("unable to write to file");
+    derived
+}
 
-        Utf8Path::from_path(&path).expect("invalid UTF-8 path").to_path_buf()
+/// Suggests to unquote an argument of a `derive` attribute which is the quoted name of a trait,
+/// eg. `'Model'`, reported by the builtin `derive` plugin as it isn't a path.
+pub(crate) fn unquoted_derive_arg(
+    db: &dyn SyntaxGroup,
+    arg: &AttributeArg,
+) -> Option<SuggestedFix> {
+    let AttributeArgVariant::Unnamed { value: ast::Expr::ShortString(literal), .. } = &arg.variant
+    else {
+        return None;
     };
+
+    literal.string_value(db).filter(|name| is_identifier(name)).map(|name| SuggestedFix {
+        diagnostic_ptr: arg.arg_stable_ptr.untyped(),
+        title: format!("Replace `'{name}'` with `{name}`"),
+        target: literal.stable_ptr().untyped(),
+        replacement: name,
+    })
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
 impl BuiltinDojoPlugin {
+    /// Creates a plugin running the `model_processors` on every model, in order, after generating
+    /// its code. Fails if several processors have the same name.
+    pub fn with_model_processors(
+        model_processors: Vec<(String, Arc<dyn ModelProcessor>)>,
+    ) -> Result<Self> {
+        let mut plugin = Self::default();
+
+        for (name, processor) in model_processors {
+            if plugin.model_processors.iter().any(|(registered, _)| *registered == name) {
+                bail!("model processor `{name}` is already registered");
+            }
+
+            plugin.model_processors.push((name, processor));
+        }
+
+        Ok(plugin)
+    }
+
+    /// Provides the `inline_macros` in addition to the built-in ones. Fails if several macros have
+    /// the same name.
+    pub fn with_inline_macros(
+        mut self,
+        inline_macros: Vec<(String, Arc<dyn InlineMacroExprPlugin>)>,
+    ) -> Result<Self> {
+        for (name, plugin) in inline_macros {
+            let builtin = [GetMacro::NAME, SetMacro::NAME, EmitMacro::NAME].contains(&&*name);
+            if builtin || self.inline_macros.iter().any(|(registered, _)| *registered == name) {
+                bail!("inline macro `{name}!` is already registered");
+            }
+
+            self.inline_macros.push((name, plugin));
+        }
+
+        Ok(self)
+    }
+
     fn handle_mod(&self, db: &dyn SyntaxGroup, module_ast: ast::ItemModule) -> PluginResult {
         if module_ast.has_attr(db, DOJO_CONTRACT_ATTR) {
             return DojoContract::from_module(db, module_ast);
@@ -104,11 +261,34 @@ impl BuiltinDojoPlugin {
         PluginResult::default()
     }
 
+    /// Reports the `dojo::contract` attribute applied to an item which is not a module.
+    fn check_contract_attr(
+        &self,
+        db: &dyn SyntaxGroup,
+        item_ast: &ast::Item,
+    ) -> Option<PluginResult> {
+        if matches!(item_ast, ast::Item::Module(_)) {
+            return None;
+        }
+
+        let attr = item_ast.query_attr(db, DOJO_CONTRACT_ATTR).into_iter().next()?;
+        Some(PluginResult {
+            diagnostics: vec![PluginDiagnostic {
+                stable_ptr: attr.stable_ptr().untyped(),
+                message: format!(
+                    "The `{DOJO_CONTRACT_ATTR}` attribute can only be applied to modules. Move \
+                     this item into a module annotated with `#[{DOJO_CONTRACT_ATTR}]`."
+                ),
+            }],
+            ..PluginResult::default()
+        })
+    }
+
     pub fn manifest_dependency() -> ManifestDependency {
         let version_req = DependencyVersionReq::exact(&MANIFEST_VERSION);
         ManifestDependency::builder()
             .name(PackageName::new(PACKAGE_NAME))
-            .source_id(SourceId::for_path(MANIFEST_PATH.as_path()).unwrap())
+            .source_id(SourceId::for_path(manifest_path()).unwrap())
             .version_req(version_req)
             .build()
     }
@@ -119,32 +299,71 @@ impl CairoPlugin for BuiltinDojoPlugin {
         PackageId::new(
             PackageName::new(PACKAGE_NAME),
             MANIFEST_VERSION.to_owned(),
-            SourceId::for_path(MANIFEST_PATH.as_path()).unwrap(),
+            SourceId::for_path(manifest_path()).unwrap(),
         )
     }
 
     fn instantiate(&self) -> Result<Box<dyn CairoPluginInstance>> {
-        Ok(Box::new(BuiltinDojoPluginInstance))
+        Ok(Box::new(BuiltinDojoPluginInstance::from(self.clone())))
+    }
+}
+
+/// An instance of the Dojo plugin, providing the `get!`, `set!` and `emit!` inline macros along
+/// with the custom ones registered on the plugin.
+#[derive(Default)]
+pub struct BuiltinDojoPluginInstance {
+    plugin: Arc<BuiltinDojoPlugin>,
+}
+
+impl BuiltinDojoPluginInstance {
+    /// Creates an instance providing the `inline_macros` in addition to the built-in ones, see
+    /// [`BuiltinDojoPlugin::with_inline_macros`].
+    pub fn with_inline_macros(
+        inline_macros: Vec<(String, Arc<dyn InlineMacroExprPlugin>)>,
+    ) -> Result<Self> {
+        BuiltinDojoPlugin::default().with_inline_macros(inline_macros).map(Self::from)
+    }
+
+    /// Runs the `model_processors` on every model, see
+    /// [`BuiltinDojoPlugin::with_model_processors`].
+    pub fn with_model_processors(
+        self,
+        model_processors: Vec<(String, Arc<dyn ModelProcessor>)>,
+    ) -> Result<Self> {
+        let plugin = BuiltinDojoPlugin::with_model_processors(model_processors)?;
+        let inline_macros = self.plugin.inline_macros.clone();
+        Ok(Self::from(BuiltinDojoPlugin { inline_macros, ..plugin }))
+    }
+}
+
+impl From<BuiltinDojoPlugin> for BuiltinDojoPluginInstance {
+    fn from(plugin: BuiltinDojoPlugin) -> Self {
+        Self { plugin: Arc::new(plugin) }
     }
 }
 
-struct BuiltinDojoPluginInstance;
 impl CairoPluginInstance for BuiltinDojoPluginInstance {
     fn macro_plugins(&self) -> Vec<Arc<dyn MacroPlugin>> {
-        vec![Arc::new(BuiltinDojoPlugin)]
+        vec![self.plugin.clone()]
     }
 
     fn inline_macro_plugins(&self) -> Vec<(String, Arc<dyn InlineMacroExprPlugin>)> {
-        vec![
+        let builtin: [(String, Arc<dyn InlineMacroExprPlugin>); 3] = [
             (GetMacro::NAME.into(), Arc::new(GetMacro)),
             (SetMacro::NAME.into(), Arc::new(SetMacro)),
             (EmitMacro::NAME.into(), Arc::new(EmitMacro)),
-        ]
+        ];
+
+        builtin.into_iter().chain(self.plugin.inline_macros.iter().cloned()).collect()
     }
 }
 
 impl MacroPlugin for BuiltinDojoPlugin {
     fn generate_code(&self, db: &dyn SyntaxGroup, item_ast: ast::Item) -> PluginResult {
+        if let Some(result) = self.check_contract_attr(db, &item_ast) {
+            return result;
+        }
+
         match item_ast {
             ast::Item::Module(module_ast) => self.handle_mod(db, module_ast),
             ast::Item::Enum(enum_ast) => {
@@ -152,53 +371,16 @@ impl MacroPlugin for BuiltinDojoPlugin {
                 let mut rewrite_nodes = vec![];
                 let mut diagnostics = vec![];
 
-                // Iterate over all the derive attributes of the struct
-                for attr in enum_ast.attributes(db).query_attr(db, "derive") {
-                    let attr = attr.structurize(db);
-
-                    // Check if the derive attribute has arguments
-                    if attr.args.is_empty() {
-                        diagnostics.push(PluginDiagnostic {
-                            stable_ptr: attr.args_stable_ptr.untyped(),
-                            message: "Expected args.".into(),
-                        });
-                        continue;
-                    }
-
-                    // Iterate over all the arguments of the derive attribute
-                    for arg in attr.args {
-                        // Check if the argument is a path then set it to arg
-                        let AttributeArg {
-                            variant:
-                                AttributeArgVariant::Unnamed { value: ast::Expr::Path(path), .. },
-                            ..
-                        } = arg
-                        else {
-                            diagnostics.push(PluginDiagnostic {
-                                stable_ptr: arg.arg_stable_ptr.untyped(),
-                                message: "Expected path.".into(),
-                            });
-                            continue;
-                        };
-
-                        // Check if the path has a single segment
-                        let [ast::PathSegment::Simple(segment)] = &path.elements(db)[..] else {
-                            continue;
-                        };
-
-                        // Get the text of the segment and check if it is "Model"
-                        let derived = segment.ident(db).text(db);
-
-                        match derived.as_str() {
-                            "Introspect" => {
-                                rewrite_nodes.push(handle_introspect_enum(
-                                    db,
-                                    &mut diagnostics,
-                                    enum_ast.clone(),
-                                ));
-                            }
-                            _ => continue,
+                for derived in derived_traits(db, &enum_ast.attributes(db)) {
+                    match derived.as_str() {
+                        "Introspect" => {
+                            rewrite_nodes.push(handle_introspect_enum(
+                                db,
+                                &mut diagnostics,
+                                enum_ast.clone(),
+                            ));
                         }
+                        _ => continue,
                     }
                 }
 
@@ -228,59 +410,49 @@ impl MacroPlugin for BuiltinDojoPlugin {
                 let mut rewrite_nodes = vec![];
                 let mut diagnostics = vec![];
 
-                // Iterate over all the derive attributes of the struct
-                for attr in struct_ast.attributes(db).query_attr(db, "derive") {
-                    let attr = attr.structurize(db);
-
-                    // Check if the derive attribute has arguments
-                    if attr.args.is_empty() {
-                        diagnostics.push(PluginDiagnostic {
-                            stable_ptr: attr.args_stable_ptr.untyped(),
-                            message: "Expected args.".into(),
-                        });
-                        continue;
-                    }
-
-                    // Iterate over all the arguments of the derive attribute
-                    for arg in attr.args {
-                        // Check if the argument is a path then set it to arg
-                        let AttributeArg {
-                            variant:
-                                AttributeArgVariant::Unnamed { value: ast::Expr::Path(path), .. },
-                            ..
-                        } = arg
-                        else {
-                            diagnostics.push(PluginDiagnostic {
-                                stable_ptr: arg.arg_stable_ptr.untyped(),
-                                message: "Expected path.".into(),
-                            });
-                            continue;
-                        };
-
-                        // Check if the path has a single segment
-                        let [ast::PathSegment::Simple(segment)] = &path.elements(db)[..] else {
-                            continue;
-                        };
-
-                        // Get the text of the segment and check if it is "Model"
-                        let derived = segment.ident(db).text(db);
-
-                        match derived.as_str() {
-                            "Model" => {
-                                let (model_rewrite_nodes, model_diagnostics) =
-                                    handle_model_struct(db, &mut aux_data, struct_ast.clone());
-                                rewrite_nodes.push(model_rewrite_nodes);
-                                diagnostics.extend(model_diagnostics);
+                for derived in derived_traits(db, &struct_ast.attributes(db)) {
+                    match derived.as_str() {
+                        "Model" => {
+                            let (model_rewrite_nodes, model_diagnostics) = handle_model_struct(
+                                db,
+                                &mut aux_data,
+                                struct_ast.clone(),
+                                &self.model_processors,
+                            );
+                            rewrite_nodes.extend(model_rewrite_nodes);
+                            diagnostics.extend(model_diagnostics);
+                        }
+                        "Print" => {
+                            rewrite_nodes.push(derive_print(db, struct_ast.clone()));
+                        }
+                        "Introspect" => {
+                            // the derived `Serde` of the struct wouldn't store its
+                            // `Option` members on the number of felts their layout expects
+                            let option_diagnostics = unsupported_option_members(db, &struct_ast);
+                            if !option_diagnostics.is_empty() {
+                                diagnostics.extend(option_diagnostics);
+                                continue;
+                            }
+                            let skipped_diagnostics = unsupported_skipped_members(db, &struct_ast);
+                            if !skipped_diagnostics.is_empty() {
+                                diagnostics.extend(skipped_diagnostics);
+                                continue;
                             }
-                            "Print" => {
-                                rewrite_nodes.push(derive_print(db, struct_ast.clone()));
+                            let recursive_diagnostics = recursive_members(db, &struct_ast);
+                            if !recursive_diagnostics.is_empty() {
+                                diagnostics.extend(recursive_diagnostics);
+                                continue;
                             }
-                            "Introspect" => {
-                                rewrite_nodes
-                                    .push(handle_introspect_struct(db, struct_ast.clone()));
+                            let short_string_diagnostics =
+                                invalid_short_string_members(db, &struct_ast);
+                            if !short_string_diagnostics.is_empty() {
+                                diagnostics.extend(short_string_diagnostics);
+                                continue;
                             }
-                            _ => continue,
+
+                            rewrite_nodes.push(handle_introspect_struct(db, struct_ast.clone()));
                         }
+                        _ => continue,
                     }
                 }
 
@@ -310,21 +482,63 @@ impl MacroPlugin for BuiltinDojoPlugin {
     }
 
     fn declared_attributes(&self) -> Vec<String> {
-        vec!["dojo::contract".to_string(), "key".to_string()]
+        vec![
+            "dojo::contract".to_string(),
+            "index".to_string(),
+            "key".to_string(),
+            "model".to_string(),
+            "short_string".to_string(),
+            "skip".to_string(),
+        ]
+    }
+}
+
+/// Runs the Dojo plugin only to collect its diagnostics and auxiliary data, eg. to lint the code
+/// from an editor. The generated files are emptied, so no code is compiled on top of the original
+/// items, and the plugin manifest is never written to disk.
+#[derive(Debug, Default)]
+pub struct DryRunDojoPlugin;
+
+impl MacroPlugin for DryRunDojoPlugin {
+    fn generate_code(&self, db: &dyn SyntaxGroup, item_ast: ast::Item) -> PluginResult {
+        let PluginResult { code, diagnostics, remove_original_item } =
+            BuiltinDojoPlugin::default().generate_code(db, item_ast);
+
+        PluginResult {
+            code: code.map(|file| PluginGeneratedFile {
+                content: String::new(),
+                diagnostics_mappings: vec![],
+                ..file
+            }),
+            diagnostics,
+            remove_original_item,
+        }
+    }
+
+    fn declared_attributes(&self) -> Vec<String> {
+        BuiltinDojoPlugin::default().declared_attributes()
     }
 }
 
 pub struct CairoPluginRepository(scarb::compiler::plugin::CairoPluginRepository);
 
-impl Default for CairoPluginRepository {
-    fn default() -> Self {
+impl CairoPluginRepository {
+    /// Creates a repository compiling the packages with the `dojo_plugin`, along with the model
+    /// processors and the inline macros registered on it.
+    pub fn new(dojo_plugin: BuiltinDojoPlugin) -> Self {
         let mut repo = scarb::compiler::plugin::CairoPluginRepository::empty();
-        repo.add(Box::new(BuiltinDojoPlugin)).unwrap();
+        repo.add(Box::new(dojo_plugin)).unwrap();
         repo.add(Box::new(BuiltinStarkNetPlugin)).unwrap();
         Self(repo)
     }
 }
 
+impl Default for CairoPluginRepository {
+    fn default() -> Self {
+        Self::new(BuiltinDojoPlugin::default())
+    }
+}
+
 impl From<CairoPluginRepository> for scarb::compiler::plugin::CairoPluginRepository {
     fn from(val: CairoPluginRepository) -> Self {
         val.0
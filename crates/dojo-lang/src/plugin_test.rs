@@ -1,26 +1,38 @@
 use std::sync::Arc;
+use std::{fs, thread};
 
 use cairo_lang_defs::db::{DefsDatabase, DefsGroup};
-use cairo_lang_defs::ids::{LanguageElementId, ModuleId, ModuleItemId};
-use cairo_lang_defs::plugin::MacroPlugin;
+use cairo_lang_defs::ids::{LanguageElementId, ModuleId, ModuleItemId, NamedLanguageElementId};
+use cairo_lang_defs::patcher::RewriteNode;
+use cairo_lang_defs::plugin::{MacroPlugin, PluginDiagnostic, PluginGeneratedFile};
 use cairo_lang_diagnostics::{format_diagnostics, DiagnosticLocation};
 use cairo_lang_filesystem::cfg::CfgSet;
 use cairo_lang_filesystem::db::{
     init_files_group, AsFilesGroupMut, FilesDatabase, FilesGroup, FilesGroupEx,
 };
-use cairo_lang_filesystem::ids::{CrateLongId, Directory, FileLongId};
-use cairo_lang_parser::db::ParserDatabase;
+use cairo_lang_filesystem::ids::{CrateId, CrateLongId, Directory, FileId, FileLongId};
+use cairo_lang_parser::db::{ParserDatabase, ParserGroup};
 use cairo_lang_plugins::get_default_plugins;
 use cairo_lang_syntax::node::db::{SyntaxDatabase, SyntaxGroup};
 use cairo_lang_syntax::node::kind::SyntaxKind;
-use cairo_lang_syntax::node::{ast, TypedSyntaxNode};
+use cairo_lang_syntax::node::{ast, Terminal, TypedSyntaxNode};
 use cairo_lang_test_utils::parse_test_file::TestRunnerResult;
 use cairo_lang_test_utils::verify_diagnostics_expectation;
 use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
 use cairo_lang_utils::unordered_hash_set::UnorderedHashSet;
 use cairo_lang_utils::Upcast;
+use dojo_world::manifest::Member;
+use scarb::compiler::plugin::{CairoPlugin, CairoPluginInstance};
+use starknet::core::types::FieldElement;
+use starknet::core::utils::cairo_short_string_to_felt;
 
-use super::BuiltinDojoPlugin;
+use super::{
+    write_manifest, BuiltinDojoPlugin, BuiltinDojoPluginInstance, DojoAuxData, DryRunDojoPlugin,
+    Model, ModelIndex,
+};
+use crate::fixes;
+use crate::inline_macros::get::GetMacro;
+use crate::model::ModelProcessor;
 
 cairo_lang_test_utils::test_file_test!(
     expand_plugin,
@@ -38,7 +50,395 @@ pub fn test_expand_plugin(
     inputs: &OrderedHashMap<String, String>,
     args: &OrderedHashMap<String, String>,
 ) -> TestRunnerResult {
-    test_expand_plugin_inner(inputs, args, &[Arc::new(BuiltinDojoPlugin)])
+    test_expand_plugin_inner(inputs, args, &[Arc::new(BuiltinDojoPlugin::default())])
+}
+
+#[test]
+fn concurrent_manifest_writes() {
+    let dir = std::env::temp_dir().join(format!("dojo-plugin-manifest-{}", std::process::id()));
+    let path = dir.join("Scarb.toml");
+    let content = include_str!("../Scarb.toml");
+
+    let writers = (0..16)
+        .map(|_| {
+            let path = path.clone();
+            thread::spawn(move || {
+                write_manifest(&path, content).unwrap();
+                // the manifest is always observed fully written
+                assert_eq!(fs::read_to_string(&path).unwrap(), content);
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    // no temporary file is left behind
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn custom_inline_macros() {
+    let names = |instance: &dyn CairoPluginInstance| {
+        instance.inline_macro_plugins().into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+    };
+
+    assert_eq!(names(&BuiltinDojoPluginInstance::default()), ["get", "set", "emit"]);
+
+    let instance =
+        BuiltinDojoPluginInstance::with_inline_macros(vec![("fetch".into(), Arc::new(GetMacro))]);
+    assert_eq!(names(&instance.unwrap()), ["get", "set", "emit", "fetch"]);
+
+    // the instances created by scarb provide the macros registered on the plugin
+    let plugin =
+        BuiltinDojoPlugin::default().with_inline_macros(vec![("fetch".into(), Arc::new(GetMacro))]);
+    let instance = plugin.unwrap().instantiate().unwrap();
+    assert_eq!(names(&*instance), ["get", "set", "emit", "fetch"]);
+
+    let err =
+        BuiltinDojoPluginInstance::with_inline_macros(vec![("set".into(), Arc::new(GetMacro))])
+            .err()
+            .unwrap();
+    assert_eq!(err.to_string(), "inline macro `set!` is already registered");
+}
+
+/// Compiles `source` with the Dojo plugin, and returns the struct and registered names of the
+/// models of the plugin auxiliary data, along with the selector of the `contract` of a model.
+/// Returns a database holding a crate whose root file is `source`, expanded by the Dojo plugin.
+fn database_with_source(source: &str) -> (DatabaseForTesting, CrateId, FileId) {
+    database_with_plugin(source, BuiltinDojoPlugin::default())
+}
+
+/// Returns a database holding a crate whose root file is `source`, expanded by `plugin`.
+fn database_with_plugin(
+    source: &str,
+    plugin: BuiltinDojoPlugin,
+) -> (DatabaseForTesting, CrateId, FileId) {
+    let mut db = DatabaseForTesting::default();
+    let mut plugins = db.macro_plugins();
+    plugins.push(Arc::new(plugin));
+    db.set_macro_plugins(plugins);
+
+    let crate_id = db.intern_crate(CrateLongId::Real("test".into()));
+    db.set_crate_root(crate_id, Some(Directory::Real("test_src".into())));
+    let file_id = db.intern_file(FileLongId::OnDisk("test_src/lib.cairo".into()));
+    db.as_files_group_mut().override_file_content(file_id, Some(Arc::new(source.into())));
+
+    (db, crate_id, file_id)
+}
+
+/// Returns the models found by the plugin in `source`.
+fn models(source: &str) -> Vec<Model> {
+    let (db, crate_id, _) = &database_with_source(source);
+
+    db.module_generated_file_infos(ModuleId::CrateRoot(*crate_id))
+        .unwrap()
+        .iter()
+        .filter_map(|info| info.as_ref()?.aux_data.as_ref())
+        .filter_map(|aux_data| aux_data.0.as_any().downcast_ref::<DojoAuxData>())
+        .flat_map(|aux_data| aux_data.models.clone())
+        .collect()
+}
+
+fn models_and_selector(source: &str, contract: &str) -> (Vec<(String, String)>, FieldElement) {
+    let (db, crate_id, _) = &database_with_source(source);
+    let syntax_db: &dyn SyntaxGroup = db.upcast();
+    let root = ModuleId::CrateRoot(*crate_id);
+
+    let models =
+        models(source).into_iter().map(|model| (model.name, model.registered_name)).collect();
+
+    let module = db
+        .module_items(root)
+        .unwrap()
+        .iter()
+        .find_map(|item| match item {
+            ModuleItemId::Submodule(module)
+                if module.stable_ptr(db).lookup(syntax_db).name(syntax_db).text(syntax_db)
+                    == contract =>
+            {
+                Some(*module)
+            }
+            _ => None,
+        })
+        .expect("the model contract is generated");
+
+    let selector = db
+        .module_items(ModuleId::Submodule(module))
+        .unwrap()
+        .iter()
+        .find_map(|item| match item {
+            ModuleItemId::Constant(constant) => Some(constant.stable_ptr(db).lookup(syntax_db)),
+            _ => None,
+        })
+        .filter(|constant| constant.name(syntax_db).text(syntax_db) == "SELECTOR")
+        .expect("the model contract defines its selector");
+
+    let ast::Expr::ShortString(value) = selector.value(syntax_db) else {
+        panic!("the selector is a short string literal");
+    };
+    let (_, bytes) = value.numeric_value(syntax_db).unwrap().to_bytes_be();
+
+    (models, FieldElement::from_byte_slice_be(&bytes).unwrap())
+}
+
+#[test]
+fn model_selector_matches_the_indexer() {
+    let (_, selector) =
+        models_and_selector(
+            "#[derive(Model, Copy, Drop, Serde)]\nstruct Position {\n    #[key]\n    id: \
+             felt252,\n    x: u32,\n}\n",
+            "position",
+        );
+
+    // the indexer identifies the models by the short string of their name
+    assert_eq!(selector, cairo_short_string_to_felt("Position").unwrap());
+}
+
+#[test]
+fn model_with_a_custom_name() {
+    let (models, selector) =
+        models_and_selector(
+            "#[derive(Model, Copy, Drop, Serde)]\n#[model(name: 'Position_v2')]\nstruct Position \
+             {\n    #[key]\n    id: felt252,\n    x: u32,\n}\n",
+            "position",
+        );
+
+    assert_eq!(models, vec![("Position".to_string(), "Position_v2".to_string())]);
+    assert_eq!(selector, cairo_short_string_to_felt("Position_v2").unwrap());
+}
+
+#[test]
+fn model_indexes() {
+    let models = models(
+        "#[derive(Model, Copy, Drop, Serde)]\nstruct Position {\n    #[key]\n    id: \
+         felt252,\n    #[index(name: 'coords')]\n    x: u32,\n    #[index(name: 'coords')]\n    \
+         y: u32,\n    #[index]\n    layer: u8,\n}\n",
+    );
+
+    assert_eq!(
+        models[0].indexes,
+        vec![
+            ModelIndex { name: "coords".into(), members: vec!["x".into(), "y".into()] },
+            ModelIndex { name: "layer".into(), members: vec!["layer".into()] },
+        ]
+    );
+}
+
+#[test]
+fn model_with_skipped_members() {
+    let source =
+        "#[derive(Model, Copy, Drop)]\nstruct Position {\n    #[key]\n    id: felt252,\n    x: \
+         u32,\n    #[skip]\n    distance: u32,\n}\n";
+    let (db, crate_id, _) = &database_with_source(source);
+    let mut diagnostics = vec![];
+    let expanded = expand_module_text(db, ModuleId::CrateRoot(*crate_id), &mut diagnostics);
+    assert_eq!(diagnostics, Vec::<String>::new());
+
+    // the skipped member is left out of the members, and hence of the layout and schema
+    let members = models(source).remove(0).members;
+    let names: Vec<_> = members.iter().map(|member| member.name.as_str()).collect();
+    assert_eq!(names, ["id", "x"]);
+    assert!(!expanded.contains("name: 'distance'"));
+
+    // and is set to its default value when the model is read
+    assert!(expanded.contains("impl PositionSerde of serde::Serde<Position>"));
+    assert!(expanded.contains("let distance: u32 = Default::default();"));
+    assert!(!expanded.contains("self.distance"));
+}
+
+/// Generates a function returning the number of members of the model.
+#[derive(Debug)]
+struct MemberCount;
+
+impl ModelProcessor for MemberCount {
+    fn process(
+        &self,
+        db: &dyn SyntaxGroup,
+        struct_ast: &ast::ItemStruct,
+        members: &[Member],
+    ) -> (Vec<RewriteNode>, Vec<PluginDiagnostic>) {
+        let name = struct_ast.name(db).text(db).to_lowercase();
+        let node = RewriteNode::Text(format!(
+            "fn {name}_member_count() -> usize {{\n    {}\n}}\n",
+            members.len()
+        ));
+
+        (vec![node], vec![])
+    }
+}
+
+/// Generates an interface named like the one of the model.
+#[derive(Debug)]
+struct ModelInterface;
+
+impl ModelProcessor for ModelInterface {
+    fn process(
+        &self,
+        db: &dyn SyntaxGroup,
+        struct_ast: &ast::ItemStruct,
+        _members: &[Member],
+    ) -> (Vec<RewriteNode>, Vec<PluginDiagnostic>) {
+        let name = struct_ast.name(db).text(db);
+        let node = RewriteNode::Text(format!("trait I{name}<T, +Drop<T>> {{}}\n"));
+
+        (vec![node], vec![])
+    }
+}
+
+#[test]
+fn custom_model_processors() {
+    let source = "#[derive(Model, Copy, Drop, Serde)]\nstruct Position {\n    #[key]\n    id: \
+                  felt252,\n    x: u32,\n}\n";
+    let functions = |plugin: BuiltinDojoPlugin| {
+        let (db, crate_id, _) = &database_with_plugin(source, plugin);
+        db.module_items(ModuleId::CrateRoot(*crate_id))
+            .unwrap()
+            .iter()
+            .filter_map(|item| match item {
+                ModuleItemId::FreeFunction(function) => Some(function.name(db).to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert!(functions(BuiltinDojoPlugin::default()).is_empty());
+
+    let plugin =
+        BuiltinDojoPlugin::with_model_processors(vec![("count".into(), Arc::new(MemberCount))]);
+    assert_eq!(functions(plugin.unwrap()), ["position_member_count"]);
+
+    let err = BuiltinDojoPlugin::with_model_processors(vec![
+        ("count".into(), Arc::new(MemberCount)),
+        ("count".into(), Arc::new(MemberCount)),
+    ])
+    .err()
+    .unwrap();
+    assert_eq!(err.to_string(), "model processor `count` is already registered");
+
+    // the code of the processors defining an item already defined is left out
+    let plugin = BuiltinDojoPlugin::with_model_processors(vec![
+        ("count".into(), Arc::new(MemberCount)),
+        ("count_again".into(), Arc::new(MemberCount)),
+        ("interface".into(), Arc::new(ModelInterface)),
+    ])
+    .unwrap();
+    let (db, crate_id, _) = &database_with_plugin(source, plugin);
+    let diagnostics = db.module_plugin_diagnostics(ModuleId::CrateRoot(*crate_id)).unwrap();
+    let messages = diagnostics.iter().map(|(_, diag)| diag.message.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            "Model processor `count_again` generates the item `position_member_count`, which is \
+             already defined by the model processor `count`.",
+            "Model processor `interface` generates the item `IPosition`, which is already defined \
+             by the model `Position`.",
+        ]
+    );
+}
+
+#[test]
+fn recursive_members_of_other_modules() {
+    let source = "#[derive(Model, Copy, Drop, Serde)]\nstruct Position {\n    #[key]\n    id: \
+                  felt252,\n    previous: other::Position,\n    next: \
+                  core::option::Option<Position>,\n}\n\nmod other {\n    #[derive(Introspect, \
+                  Copy, Drop, Serde)]\n    struct Position {\n        x: u32,\n    }\n}\n";
+
+    let (db, crate_id, _) = &database_with_source(source);
+    let diagnostics = db.module_plugin_diagnostics(ModuleId::CrateRoot(*crate_id)).unwrap();
+    let messages = diagnostics.iter().map(|(_, diag)| diag.message.as_str()).collect::<Vec<_>>();
+
+    // only the generic argument names the model itself
+    assert_eq!(
+        messages,
+        ["Member `next` makes `Position` recursive: `Position -> Position`. A recursive type has \
+          no finite storage layout."]
+    );
+}
+
+#[test]
+fn suggested_fixes() {
+    let source = "#[derive(Model, Copy, Drop, Serde)]\nstruct Position {\n    id: felt252,\n    \
+                  x: u32,\n}\n\n#[derive('Introspect', Copy, Drop, Serde)]\nstruct Vec2 {\n    x: \
+                  u32,\n}\n\n#[derive(Model, Copy, Drop, Serde)]\nstruct Moves {\n\tremaining: \
+                  u8,\n}\n";
+
+    let (db, crate_id, file_id) = &database_with_source(source);
+    let syntax_db: &dyn SyntaxGroup = db.upcast();
+    let fixes = db
+        .file_syntax(*file_id)
+        .unwrap()
+        .items(syntax_db)
+        .elements(syntax_db)
+        .iter()
+        .flat_map(|item| fixes::suggested_fixes(syntax_db, item))
+        .collect::<Vec<_>>();
+
+    let edits = fixes
+        .iter()
+        .map(|fix| {
+            let target = fix.target.lookup(syntax_db).get_text_without_trivia(syntax_db);
+            (fix.title.as_str(), target, fix.replacement.as_str())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        edits,
+        vec![
+            ("Add `#[key]` to `id`", "id: felt252".to_string(), "#[key]\n    id: felt252"),
+            ("Replace `'Introspect'` with `Introspect`", "'Introspect'".to_string(), "Introspect"),
+            // the indentation of the member is kept
+            ("Add `#[key]` to `remaining`", "remaining: u8".to_string(), "#[key]\n\tremaining: u8"),
+        ]
+    );
+
+    // the fixes are attached to the diagnostics reported by the Dojo and builtin plugins
+    let diagnostics = db.module_plugin_diagnostics(ModuleId::CrateRoot(*crate_id)).unwrap();
+    for fix in &fixes {
+        assert!(diagnostics.iter().any(|(_, diag)| diag.stable_ptr == fix.diagnostic_ptr));
+    }
+}
+
+#[test]
+fn dry_run_plugin() {
+    // a model missing a key, and a valid one
+    let source =
+        "#[derive(Model, Copy, Drop, Serde)]\nstruct Position {\n    id: felt252,\n    x: \
+         u32,\n}\n\n#[derive(Model, Copy, Drop, Serde)]\nstruct Moves {\n    #[key]\n    id: \
+         felt252,\n    remaining: u8,\n}\n";
+
+    let (db, _, file_id) = &database_with_source(source);
+    let syntax_db: &dyn SyntaxGroup = db.upcast();
+    let models = |file: &PluginGeneratedFile| {
+        let aux_data = file.aux_data.as_ref().unwrap().0.as_any().downcast_ref::<DojoAuxData>();
+        aux_data.unwrap().models.iter().map(|model| model.name.clone()).collect::<Vec<_>>()
+    };
+
+    let mut diagnostics = vec![];
+    for item in db.file_syntax(*file_id).unwrap().items(syntax_db).elements(syntax_db) {
+        let expected = BuiltinDojoPlugin::default().generate_code(syntax_db, item.clone());
+        let result = DryRunDojoPlugin.generate_code(syntax_db, item);
+
+        // the same diagnostics and auxiliary data, without any generated code
+        assert_eq!(result.diagnostics, expected.diagnostics);
+        assert_eq!(result.remove_original_item, expected.remove_original_item);
+        assert_eq!(result.code.is_some(), expected.code.is_some());
+        if let (Some(file), Some(expected)) = (result.code, expected.code) {
+            assert_eq!(file.name, expected.name);
+            assert_eq!(models(&file), models(&expected));
+            assert!(file.content.is_empty());
+            assert!(file.diagnostics_mappings.is_empty());
+        }
+
+        diagnostics.extend(result.diagnostics);
+    }
+
+    assert!(!diagnostics.is_empty());
+    assert_eq!(
+        DryRunDojoPlugin.declared_attributes(),
+        BuiltinDojoPlugin::default().declared_attributes()
+    );
 }
 
 #[salsa::database(DefsDatabase, ParserDatabase, SyntaxDatabase, FilesDatabase)]
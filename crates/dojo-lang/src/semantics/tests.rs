@@ -6,6 +6,7 @@ use cairo_lang_semantic::Expr;
 use cairo_lang_test_utils::parse_test_file::TestRunnerResult;
 use cairo_lang_test_utils::test_file_test;
 use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
+use starknet::core::utils::get_selector_from_name;
 
 use crate::semantics::test_utils::DojoSemanticDatabase;
 
@@ -13,6 +14,8 @@ test_file_test!(
     dojo_semantics,
     "src/semantics/test_data",
     {
+        emit: "emit",
+
         get: "get",
 
         set: "set",
@@ -49,3 +52,198 @@ pub fn semantics_test_setup<'a>(
 
     (expr, diagnostics, expr_formatter)
 }
+
+#[test]
+fn world_expressions() {
+    let setup_code = "
+use dojo::world::{IWorldDispatcher, IWorldDispatcherTrait};
+
+#[derive(Copy, Drop, Serde, Model)]
+struct Health {
+	#[key]
+	id: u32,
+	health: u16,
+}
+
+#[derive(Copy, Drop, Serde, Model)]
+struct Position {
+	#[key]
+	id: u32,
+	x: u32,
+	y: u32,
+}
+
+#[derive(Copy, Drop)]
+struct Context {
+	world: IWorldDispatcher,
+}
+
+#[generate_trait]
+impl ContextImpl of ContextTrait {
+	fn get_world(self: @Context) -> IWorldDispatcher {
+		*self.world
+	}
+}
+";
+    let function_code = "
+let ctx = Context { world: IWorldDispatcher{contract_address: 0x0.try_into().unwrap()} };
+";
+
+    for world in ["ctx.world", "ctx.get_world()"] {
+        for expression in [
+            format!("set!({world}, (Health{{id: 1, health: 79}}, Position{{id: 1, x: 2, y: 3}}))"),
+            format!("get!({world}, 1, (Health, Position))"),
+        ] {
+            let inputs = OrderedHashMap::from([
+                ("setup_code".to_string(), setup_code.to_string()),
+                ("function_code".to_string(), function_code.to_string()),
+                ("expression".to_string(), expression.clone()),
+            ]);
+            let mut db = DojoSemanticDatabase::default();
+            let (expr, diagnostics, expr_formatter) = semantics_test_setup(&inputs, &mut db);
+            assert_eq!(diagnostics, "", "{expression}");
+
+            // the world is evaluated once, not once per model
+            let expanded = format!("{:#?}", expr.debug(&expr_formatter));
+            assert_eq!(expanded.matches("get_world").count(), world.matches("get_world").count());
+        }
+    }
+}
+
+#[test]
+fn emit_with_selector_override() {
+    let setup_code = "
+use dojo::world::{IWorldDispatcher, IWorldDispatcherTrait};
+
+#[event]
+#[derive(Drop, starknet::Event)]
+enum Event {
+	Moved: Moved,
+}
+
+#[derive(Drop, starknet::Event)]
+struct Moved {
+	#[key]
+	player: felt252,
+	x: u32,
+}
+";
+    let function_code = "
+let world = IWorldDispatcher{contract_address: 0x0.try_into().unwrap()};
+";
+
+    let emit = |selector: &str| {
+        let inputs = OrderedHashMap::from([
+            ("setup_code".to_string(), setup_code.to_string()),
+            ("function_code".to_string(), function_code.to_string()),
+            (
+                "expression".to_string(),
+                format!("emit!(world, Moved {{ player: 1, x: 2 }}, {selector})"),
+            ),
+        ]);
+        let mut db = DojoSemanticDatabase::default();
+        let (expr, diagnostics, expr_formatter) = semantics_test_setup(&inputs, &mut db);
+        (format!("{:#?}", expr.debug(&expr_formatter)), diagnostics)
+    };
+
+    for selector in ["selector: 'PlayerMoved'", "selector: 0x1234", "selector: 42"] {
+        let (expanded, diagnostics) = emit(selector);
+        assert_eq!(diagnostics, "", "{selector}");
+        // the derived selector is replaced, the other keys are kept
+        assert!(expanded.contains("pop_front"), "{selector}");
+    }
+
+    let too_long = format!("selector: '{}'", "a".repeat(32));
+    let out_of_range = format!("selector: 0x{}", "f".repeat(64));
+    for selector in [too_long.as_str(), &out_of_range, "selector: -1", "selector: x", "'Moved'"] {
+        let (_, diagnostics) = emit(selector);
+        assert!(diagnostics.contains("Plugin diagnostic"), "{selector}: {diagnostics}");
+    }
+}
+
+#[test]
+fn emit_with_selector_of_the_event_type() {
+    // the variant name differs from the name of the event type
+    let setup_code = "
+use dojo::world::{IWorldDispatcher, IWorldDispatcherTrait};
+
+#[event]
+#[derive(Drop, starknet::Event)]
+enum Event {
+	PlayerMoved: Moved,
+}
+
+#[derive(Drop, starknet::Event)]
+struct Moved {
+	#[key]
+	player: felt252,
+	x: u32,
+}
+";
+    let inputs = OrderedHashMap::from([
+        ("setup_code".to_string(), setup_code.to_string()),
+        (
+            "function_code".to_string(),
+            "let world = IWorldDispatcher{contract_address: 0x0.try_into().unwrap()};".to_string(),
+        ),
+        ("expression".to_string(), "emit!(world, Moved { player: 1, x: 2 })".to_string()),
+    ]);
+    let mut db = DojoSemanticDatabase::default();
+    let (expr, diagnostics, expr_formatter) = semantics_test_setup(&inputs, &mut db);
+    assert_eq!(diagnostics, "");
+
+    // the selector of the variant is replaced by the one of the type
+    let expanded = format!("{:#?}", expr.debug(&expr_formatter));
+    let selector = get_selector_from_name("Moved").unwrap();
+    assert!(expanded.contains("pop_front"));
+    assert!(expanded.contains(&selector.to_string()));
+}
+
+#[test]
+fn set_misuses() {
+    // the trait of the dispatcher isn't imported
+    let setup_code = "
+use dojo::world::IWorldDispatcher;
+
+#[derive(Copy, Drop, Serde, Model)]
+struct Health {
+	#[key]
+	id: u32,
+	health: u16,
+}
+
+#[derive(Copy, Drop, Serde)]
+struct Position {
+	#[key]
+	id: u32,
+	x: u32,
+}
+";
+    let function_code = "
+let world = IWorldDispatcher{contract_address: 0x0.try_into().unwrap()};
+";
+
+    let set = |expression: &str| {
+        let inputs = OrderedHashMap::from([
+            ("setup_code".to_string(), setup_code.to_string()),
+            ("function_code".to_string(), function_code.to_string()),
+            ("expression".to_string(), expression.to_string()),
+        ]);
+        let mut db = DojoSemanticDatabase::default();
+        semantics_test_setup(&inputs, &mut db).1
+    };
+
+    assert_eq!(set("set!(world, (Health{id: 1, health: 79}))"), "");
+
+    for (expression, error) in [
+        ("set!(world, (Position{id: 1, x: 2}))", "dojo::model::Model::<test::Position>"),
+        ("set!(0x1, (Health{id: 1, health: 79}))", "Invalid world `0x1`"),
+        ("set!(Position{id: 1, x: 2}, (Health{id: 1, health: 79}))", "Invalid world `Position"),
+        ("set!(world, (42))", "Invalid model `42`"),
+        ("set!(world, (Health{id: 1, health: 79}, 'health'))", "Invalid model `'health'`"),
+        ("set!(world, (Health{id: 1, health: 79}, (1, 2)))", "Invalid model `(1, 2)`"),
+    ] {
+        let diagnostics = set(expression);
+        assert!(diagnostics.contains(error), "{expression}: {diagnostics}");
+    }
+}
@@ -1,24 +1,214 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use cairo_lang_compiler::db::RootDatabase;
 use cairo_lang_filesystem::cfg::{Cfg, CfgSet};
+use cairo_lang_filesystem::db::FilesGroup;
+use cairo_lang_filesystem::ids::FileId;
+use cairo_lang_filesystem::span::TextOffset;
+use cairo_lang_language_server::vfs::{ProvideVirtualFileRequest, ProvideVirtualFileResponse};
 use cairo_lang_language_server::Backend;
+use cairo_lang_parser::db::ParserGroup;
 use cairo_lang_starknet::inline_macros::selector::SelectorMacro;
 use cairo_lang_starknet::plugin::StarkNetPlugin;
+use cairo_lang_syntax::node::db::SyntaxGroup;
+use cairo_lang_syntax::node::ids::SyntaxStablePtrId;
+use cairo_lang_syntax::node::{ast, TypedSyntaxNode};
 use cairo_lang_test_plugin::TestPlugin;
 use cairo_lang_utils::logging::init_logging;
+use cairo_lang_utils::Upcast;
 use clap::Parser;
+use dojo_lang::fixes::suggested_fixes;
 use dojo_lang::inline_macros::emit::EmitMacro;
 use dojo_lang::inline_macros::get::GetMacro;
 use dojo_lang::inline_macros::set::SetMacro;
 use dojo_lang::plugin::BuiltinDojoPlugin;
-use tower_lsp::{LspService, Server};
+use tower_lsp::jsonrpc::Result as LspResult;
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOptions, CodeActionOrCommand, CodeActionParams,
+    CodeActionProviderCapability, CodeActionResponse, CompletionParams, CompletionResponse,
+    DidChangeConfigurationParams, DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+    DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidSaveTextDocumentParams, DocumentFormattingParams, ExecuteCommandParams,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverParams, InitializeParams,
+    InitializeResult, InitializedParams, Position, Range, SemanticTokensParams,
+    SemanticTokensResult, TextEdit, Url, WorkspaceEdit,
+};
+use tower_lsp::{LanguageServer, LspService, Server};
 
 /// Dojo Language Server
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {}
 
+/// The Cairo language server, which also offers the fixes suggested for the diagnostics of the
+/// Dojo plugin as quick fixes.
+struct DojoBackend {
+    inner: Backend,
+}
+
+impl DojoBackend {
+    async fn vfs_provide(
+        &self,
+        params: ProvideVirtualFileRequest,
+    ) -> LspResult<ProvideVirtualFileResponse> {
+        self.inner.vfs_provide(params).await
+    }
+
+    /// Returns the quick fixes of the plugin diagnostics of the file at `uri` which are reported
+    /// in `range`.
+    async fn quick_fixes(&self, uri: &Url, range: Range) -> Vec<CodeActionOrCommand> {
+        // the virtual files are generated, they can't be edited
+        let Ok(path) = uri.to_file_path() else {
+            return vec![];
+        };
+
+        let db = self.inner.db().await;
+        let file_id = FileId::new(&*db, path);
+        let Ok(syntax) = db.file_syntax(file_id) else {
+            return vec![];
+        };
+
+        let syntax_db: &dyn SyntaxGroup = db.upcast();
+        let mut items = syntax.items(syntax_db).elements(syntax_db);
+        let mut actions = vec![];
+        while let Some(item) = items.pop() {
+            if let ast::Item::Module(module) = &item {
+                if let ast::MaybeModuleBody::Some(body) = module.body(syntax_db) {
+                    items.extend(body.items(syntax_db).elements(syntax_db));
+                }
+            }
+
+            for fix in suggested_fixes(syntax_db, &item) {
+                let (Some(reported), Some(target)) = (
+                    lsp_range(&db, file_id, fix.diagnostic_ptr),
+                    lsp_range(&db, file_id, fix.target),
+                ) else {
+                    continue;
+                };
+                if reported.end < range.start || range.end < reported.start {
+                    continue;
+                }
+
+                let edit = TextEdit { range: target, new_text: fix.replacement };
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: fix.title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }));
+            }
+        }
+
+        actions
+    }
+}
+
+/// Returns the range of the node of `ptr` in `file_id`, without its trivia.
+fn lsp_range(db: &RootDatabase, file_id: FileId, ptr: SyntaxStablePtrId) -> Option<Range> {
+    let files_db: &dyn FilesGroup = db.upcast();
+    let span = ptr.lookup(db.upcast()).span_without_trivia(db.upcast());
+    let position = |offset: TextOffset| {
+        let position = offset.position_in_file(files_db, file_id)?;
+        Some(Position { line: position.line as u32, character: position.col as u32 })
+    };
+
+    Some(Range { start: position(span.start)?, end: position(span.end)? })
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for DojoBackend {
+    async fn initialize(&self, params: InitializeParams) -> LspResult<InitializeResult> {
+        let mut result = self.inner.initialize(params).await?;
+        result.capabilities.code_action_provider =
+            Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                ..Default::default()
+            }));
+
+        Ok(result)
+    }
+
+    async fn initialized(&self, params: InitializedParams) {
+        self.inner.initialized(params).await
+    }
+
+    async fn shutdown(&self) -> LspResult<()> {
+        self.inner.shutdown().await
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        self.inner.did_change_workspace_folders(params).await
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        self.inner.did_change_configuration(params).await
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        self.inner.did_change_watched_files(params).await
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> LspResult<Option<serde_json::Value>> {
+        self.inner.execute_command(params).await
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.inner.did_open(params).await
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        self.inner.did_change(params).await
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        self.inner.did_save(params).await
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.inner.did_close(params).await
+    }
+
+    async fn completion(&self, params: CompletionParams) -> LspResult<Option<CompletionResponse>> {
+        self.inner.completion(params).await
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> LspResult<Option<SemanticTokensResult>> {
+        self.inner.semantic_tokens_full(params).await
+    }
+
+    async fn formatting(
+        &self,
+        params: DocumentFormattingParams,
+    ) -> LspResult<Option<Vec<TextEdit>>> {
+        self.inner.formatting(params).await
+    }
+
+    async fn hover(&self, params: HoverParams) -> LspResult<Option<Hover>> {
+        self.inner.hover(params).await
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> LspResult<Option<GotoDefinitionResponse>> {
+        self.inner.goto_definition(params).await
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> LspResult<Option<CodeActionResponse>> {
+        Ok(Some(self.quick_fixes(&params.text_document.uri, params.range).await))
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let _args = Args::parse();
@@ -34,7 +224,7 @@ async fn main() {
 
     let db = RootDatabase::builder()
         .with_cfg(CfgSet::from_iter([Cfg::name("test")]))
-        .with_macro_plugin(Arc::new(BuiltinDojoPlugin))
+        .with_macro_plugin(Arc::new(BuiltinDojoPlugin::default()))
         .with_macro_plugin(Arc::new(StarkNetPlugin::default()))
         .with_macro_plugin(Arc::new(TestPlugin::default()))
         .with_inline_macro_plugin(EmitMacro::NAME, Arc::new(EmitMacro))
@@ -46,8 +236,9 @@ async fn main() {
             panic!("Problem creating language database: {error:?}");
         });
 
-    let (service, socket) = LspService::build(|client| Backend::new(client, db))
-        .custom_method("vfs/provide", Backend::vfs_provide)
-        .finish();
+    let (service, socket) =
+        LspService::build(|client| DojoBackend { inner: Backend::new(client, db) })
+            .custom_method("vfs/provide", DojoBackend::vfs_provide)
+            .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 }
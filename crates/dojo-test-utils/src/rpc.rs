@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Display;
+use std::sync::Mutex;
 
 use async_trait::async_trait;
 use serde::de::DeserializeOwned;
@@ -9,19 +10,22 @@ use starknet::providers::jsonrpc::{JsonRpcMethod, JsonRpcResponse, JsonRpcTransp
 use thiserror::Error;
 
 pub struct MockJsonRpcTransport {
-    responses: HashMap<(String, String), String>,
+    responses: Mutex<HashMap<(String, String), VecDeque<String>>>,
 }
 
 impl MockJsonRpcTransport {
     pub fn new() -> Self {
-        MockJsonRpcTransport { responses: HashMap::new() }
+        MockJsonRpcTransport { responses: Mutex::new(HashMap::new()) }
     }
 
+    /// Sets the response to a request. Setting several responses to the same request makes them
+    /// returned in order to the successive requests, the last one being returned to all the
+    /// requests that follow.
     pub fn set_response(&mut self, method: JsonRpcMethod, params: Value, response: Value) {
         let method = serde_json::to_string(&method).unwrap();
         let params = serde_json::to_string(&params).unwrap();
         let response = serde_json::to_string(&response).unwrap();
-        self.responses.insert((method, params), response);
+        self.responses.get_mut().unwrap().entry((method, params)).or_default().push_back(response);
     }
 }
 
@@ -58,8 +62,13 @@ impl JsonRpcTransport for MockJsonRpcTransport {
         let method = serde_json::to_string(&method).unwrap();
         let params = serde_json::to_string(&params).unwrap();
 
-        match self.responses.get(&(method.clone(), params.clone())) {
-            Some(res) => serde_json::from_str(res).map_err(|e| MockError { msg: e.to_string() }),
+        let mut responses = self.responses.lock().unwrap();
+        let res = responses.get_mut(&(method.clone(), params.clone())).map(|responses| {
+            if responses.len() > 1 { responses.pop_front().unwrap() } else { responses[0].clone() }
+        });
+
+        match res {
+            Some(res) => serde_json::from_str(&res).map_err(|e| MockError { msg: e.to_string() }),
             None => {
                 panic!("Response not set in mock for method {method:?} and params {params:?}")
             }
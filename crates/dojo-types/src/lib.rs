@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use schema::ModelMetadata;
 use serde::Serialize;
 use starknet::core::types::FieldElement;
+use storage::EntityIdScheme;
 
 pub mod event;
 pub mod packing;
@@ -10,15 +11,24 @@ pub mod primitive;
 pub mod schema;
 pub mod storage;
 pub mod system;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 /// Represents the metadata of a World
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct WorldMetadata {
     pub world_address: FieldElement,
     pub world_class_hash: FieldElement,
-    pub executor_address: FieldElement,
-    pub executor_class_hash: FieldElement,
+    /// The address of the executor, `None` until the executor of the world is set.
+    pub executor_address: Option<FieldElement>,
+    /// The class hash of the executor, `None` until the executor of the world is set.
+    pub executor_class_hash: Option<FieldElement>,
     pub models: HashMap<String, ModelMetadata>,
+    /// Whether the indexed world class hash differs from the one deployed on chain.
+    pub stale: bool,
+    /// The hash the ids of the entities are derived from their keys with, and thus the storage
+    /// addresses of their models.
+    pub entity_id_scheme: EntityIdScheme,
 }
 
 impl WorldMetadata {
@@ -30,6 +30,8 @@ pub enum PackingError {
     Parse(#[from] ParseError),
     #[error("Error when unpacking entity")]
     UnpackingEntityError,
+    #[error("Error when packing entity")]
+    PackingEntityError,
 }
 
 /// Unpacks a vector of packed values according to a given layout.
@@ -83,6 +85,53 @@ pub fn unpack(
     Ok(unpacked)
 }
 
+/// Packs a vector of values according to a given layout, the inverse of [`unpack`]. A value is
+/// packed in the next felt if it doesn't fit in the remaining bits of the current one.
+///
+/// # Arguments
+///
+/// * `unpacked` - A slice of FieldElement values, one per entry of the layout.
+/// * `layout` - A slice of FieldElement values that describe the layout of the packed values.
+///
+/// # Returns
+///
+/// * `Result<Vec<FieldElement>, PackingError>` - A Result containing a vector of packed
+///   FieldElement values if successful, or an error if unsuccessful.
+pub fn pack(
+    unpacked: &[FieldElement],
+    layout: &[FieldElement],
+) -> Result<Vec<FieldElement>, PackingError> {
+    if unpacked.len() != layout.len() {
+        return Err(PackingError::PackingEntityError);
+    }
+
+    let mut packed = vec![];
+    let mut packing = U256::from(0_u8);
+    let mut offset = 0;
+
+    for (value, size) in unpacked.iter().zip(layout) {
+        let size: u8 = (*size).try_into().map_err(ParseError::ValueOutOfRange)?;
+        let size: usize = size.into();
+        let remaining_bits = 251 - offset;
+
+        // If there are less remaining bits than the size, move to the next felt for packing.
+        if remaining_bits < size {
+            packed.push(
+                FieldElement::from_hex_be(&packing.to_string()).map_err(ParseError::FromStr)?,
+            );
+            packing = U256::from(0_u8);
+            offset = 0;
+        }
+
+        let value: U256 = value.as_ref().into();
+        packing = packing | (value << offset);
+        offset += size;
+    }
+
+    packed.push(FieldElement::from_hex_be(&packing.to_string()).map_err(ParseError::FromStr)?);
+    Ok(packed)
+}
+
 /// Parse a raw schema of a model into a Cairo type, [Ty]
 pub fn parse_ty(data: &[FieldElement]) -> Result<Ty, ParseError> {
     let member_type: u8 = data[0].try_into()?;
@@ -135,7 +184,14 @@ fn parse_member(data: &[FieldElement]) -> Result<schema::Member, ParseError> {
     let attributes = &data[slice_start..slice_end];
 
     let key = attributes.contains(&cairo_short_string_to_felt("key")?);
-    let ty = parse_ty(&data[slice_end..])?;
+    let mut ty = parse_ty(&data[slice_end..])?;
+
+    // `felt252` members holding short strings are marked by the `#[short_string]` attribute
+    if attributes.contains(&cairo_short_string_to_felt("short_string")?) {
+        if let Ty::Primitive(Primitive::Felt252(value)) = ty {
+            ty = Ty::Primitive(Primitive::ShortString(value));
+        }
+    }
 
     Ok(schema::Member { name, ty, key })
 }
@@ -191,3 +247,83 @@ fn parse_tuple(data: &[FieldElement]) -> Result<Ty, ParseError> {
 
     Ok(Ty::Tuple(children))
 }
+
+#[cfg(test)]
+mod tests {
+    use starknet::core::types::FieldElement;
+    use starknet::core::utils::cairo_short_string_to_felt;
+
+    use super::{pack, parse_ty, unpack};
+    use crate::primitive::Primitive;
+    use crate::schema::{Member, Struct, Ty};
+
+    #[test]
+    fn small_integers_share_a_felt() {
+        // three `u8` followed by an `u16` and an `u8`, from the lowest bits
+        let layout = [8u8, 8, 8, 16, 8].map(FieldElement::from);
+        let packed = vec![FieldElement::from(0x060504030201u64)];
+        let unpacked = [1u16, 2, 3, 0x0504, 6].map(FieldElement::from).to_vec();
+
+        assert_eq!(unpack(packed.clone(), layout.to_vec()).unwrap(), unpacked);
+        assert_eq!(pack(&unpacked, &layout).unwrap(), packed);
+    }
+
+    #[test]
+    fn pack_is_the_inverse_of_unpack() {
+        let layout = [8u8, 128, 128, 64, 251].map(FieldElement::from);
+        let unpacked = vec![
+            FieldElement::from(0xffu8),
+            FieldElement::from(u128::MAX),
+            FieldElement::from(7u8),
+            FieldElement::from(u64::MAX),
+            FieldElement::from_hex_be("0x123456789abcdef").unwrap(),
+        ];
+
+        let packed = pack(&unpacked, &layout).unwrap();
+        // the second `u128` and the felt don't fit in the previous felts
+        assert_eq!(packed.len(), 3);
+        assert_eq!(unpack(packed, layout.to_vec()).unwrap(), unpacked);
+
+        assert!(pack(&unpacked[1..], &layout).is_err());
+    }
+
+    #[test]
+    fn short_string_members() {
+        let felt = |s: &str| cairo_short_string_to_felt(s).unwrap();
+        let member = |name: &str, short_string: bool, ty: &str| {
+            let mut data = vec![felt(name)];
+            if short_string {
+                data.extend([FieldElement::ONE, felt("short_string")]);
+            } else {
+                data.push(FieldElement::ZERO);
+            }
+            data.extend([FieldElement::ZERO, felt(ty)]);
+            data
+        };
+
+        let mut data = vec![FieldElement::ONE, felt("Player"), FieldElement::ZERO, 3u8.into()];
+        for member in [
+            member("id", false, "felt252"),
+            member("name", true, "felt252"),
+            member("score", true, "u32"),
+        ] {
+            data.push(member.len().into());
+            data.extend(member);
+        }
+
+        // only the `felt252` members hold short strings
+        let member =
+            |name: &str, ty| Member { name: name.into(), ty: Ty::Primitive(ty), key: false };
+        assert_eq!(
+            parse_ty(&data).unwrap(),
+            Ty::Struct(Struct {
+                name: "Player".into(),
+                children: vec![
+                    member("id", Primitive::Felt252(None)),
+                    member("name", Primitive::ShortString(None)),
+                    member("score", Primitive::U32(None)),
+                ],
+            })
+        );
+    }
+}
@@ -17,12 +17,22 @@ pub enum Primitive {
     U128(Option<u128>),
     U256(Option<U256>),
     USize(Option<u32>),
+    I8(Option<i8>),
+    I16(Option<i16>),
+    I32(Option<i32>),
+    I64(Option<i64>),
+    I128(Option<i128>),
     Bool(Option<bool>),
     Felt252(Option<FieldElement>),
     #[strum(serialize = "ClassHash")]
     ClassHash(Option<FieldElement>),
     #[strum(serialize = "ContractAddress")]
     ContractAddress(Option<FieldElement>),
+    /// A `felt252` member holding a Cairo short string, marked with the `#[short_string]`
+    /// attribute. The value is kept as a raw felt, so felts that are not valid short strings are
+    /// preserved as is.
+    #[strum(serialize = "ShortString")]
+    ShortString(Option<FieldElement>),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -35,6 +45,12 @@ pub enum PrimitiveError {
     UnsupportedType,
     #[error(transparent)]
     ValueOutOfRange(#[from] ValueOutOfRangeError),
+    #[error("Value out of range for a signed integer")]
+    SignedValueOutOfRange,
+    #[error("Enum option {0} out of range")]
+    InvalidEnumOption(u8),
+    #[error("{0} FieldElements left once the value is decoded")]
+    TooManyFieldElements(usize),
 }
 
 #[derive(AsRefStr, Debug, Display, EnumString, PartialEq)]
@@ -120,6 +136,17 @@ impl Primitive {
         }
     }
 
+    /// If the `Primitive` is a ShortString, returns the associated [`FieldElement`]. Returns
+    /// `None` otherwise. Use [`parse_cairo_short_string`] to decode it.
+    ///
+    /// [`parse_cairo_short_string`]: starknet::core::utils::parse_cairo_short_string
+    pub fn as_short_string(&self) -> Option<FieldElement> {
+        match self {
+            Primitive::ShortString(value) => *value,
+            _ => None,
+        }
+    }
+
     /// If the `Primitive` is a usize, returns the associated [`u32`]. Returns `None` otherwise.
     pub fn as_usize(&self) -> Option<u32> {
         match self {
@@ -128,6 +155,46 @@ impl Primitive {
         }
     }
 
+    /// If the `Primitive` is a i8, returns the associated [`i8`]. Returns `None` otherwise.
+    pub fn as_i8(&self) -> Option<i8> {
+        match self {
+            Primitive::I8(value) => *value,
+            _ => None,
+        }
+    }
+
+    /// If the `Primitive` is a i16, returns the associated [`i16`]. Returns `None` otherwise.
+    pub fn as_i16(&self) -> Option<i16> {
+        match self {
+            Primitive::I16(value) => *value,
+            _ => None,
+        }
+    }
+
+    /// If the `Primitive` is a i32, returns the associated [`i32`]. Returns `None` otherwise.
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Primitive::I32(value) => *value,
+            _ => None,
+        }
+    }
+
+    /// If the `Primitive` is a i64, returns the associated [`i64`]. Returns `None` otherwise.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Primitive::I64(value) => *value,
+            _ => None,
+        }
+    }
+
+    /// If the `Primitive` is a i128, returns the associated [`i128`]. Returns `None` otherwise.
+    pub fn as_i128(&self) -> Option<i128> {
+        match self {
+            Primitive::I128(value) => *value,
+            _ => None,
+        }
+    }
+
     /// If the `Primitive` is a bool, returns the associated [`bool`]. Returns `None` otherwise.
     pub fn as_bool(&self) -> Option<bool> {
         match self {
@@ -143,12 +210,18 @@ impl Primitive {
             | Primitive::U32(_)
             | Primitive::U64(_)
             | Primitive::USize(_)
+            | Primitive::I8(_)
+            | Primitive::I16(_)
+            | Primitive::I32(_)
+            | Primitive::I64(_)
             | Primitive::Bool(_) => SqlType::Integer,
             Primitive::U128(_)
             | Primitive::U256(_)
+            | Primitive::I128(_)
             | Primitive::ContractAddress(_)
             | Primitive::ClassHash(_)
-            | Primitive::Felt252(_) => SqlType::Text,
+            | Primitive::Felt252(_)
+            | Primitive::ShortString(_) => SqlType::Text,
         }
     }
 
@@ -167,10 +240,17 @@ impl Primitive {
             | Primitive::USize(_)
             | Primitive::Bool(_) => Ok(format!("'{}'", value[0])),
 
+            Primitive::I8(_) | Primitive::I16(_) | Primitive::I32(_) | Primitive::I64(_) => {
+                Ok(format!("'{}'", felt_to_signed::<i64>(value[0])?))
+            }
+
+            // stored as the felt encoding of the value, like the other 128 bits integers
             Primitive::U128(_)
+            | Primitive::I128(_)
             | Primitive::ContractAddress(_)
             | Primitive::ClassHash(_)
-            | Primitive::Felt252(_) => Ok(format!("'0x{:064x}'", value[0])),
+            | Primitive::Felt252(_)
+            | Primitive::ShortString(_) => Ok(format!("'0x{:064x}'", value[0])),
 
             Primitive::U256(_) => {
                 if value.len() < 2 {
@@ -213,6 +293,26 @@ impl Primitive {
                 *value = Some(felts.remove(0).try_into().map_err(PrimitiveError::ValueOutOfRange)?);
                 Ok(())
             }
+            Primitive::I8(ref mut value) => {
+                *value = Some(felt_to_signed(felts.remove(0))?);
+                Ok(())
+            }
+            Primitive::I16(ref mut value) => {
+                *value = Some(felt_to_signed(felts.remove(0))?);
+                Ok(())
+            }
+            Primitive::I32(ref mut value) => {
+                *value = Some(felt_to_signed(felts.remove(0))?);
+                Ok(())
+            }
+            Primitive::I64(ref mut value) => {
+                *value = Some(felt_to_signed(felts.remove(0))?);
+                Ok(())
+            }
+            Primitive::I128(ref mut value) => {
+                *value = Some(felt_to_signed(felts.remove(0))?);
+                Ok(())
+            }
             Primitive::Bool(ref mut value) => {
                 let raw = felts.remove(0);
                 *value = Some(raw == FieldElement::ONE);
@@ -248,6 +348,10 @@ impl Primitive {
                 *value = Some(felts.remove(0));
                 Ok(())
             }
+            Primitive::ShortString(ref mut value) => {
+                *value = Some(felts.remove(0));
+                Ok(())
+            }
         }
     }
 
@@ -268,6 +372,21 @@ impl Primitive {
             Primitive::USize(value) => value
                 .map(|v| Ok(vec![FieldElement::from(v)]))
                 .unwrap_or(Err(PrimitiveError::MissingFieldElement)),
+            Primitive::I8(value) => value
+                .map(|v| Ok(vec![signed_to_felt(v.into())]))
+                .unwrap_or(Err(PrimitiveError::MissingFieldElement)),
+            Primitive::I16(value) => value
+                .map(|v| Ok(vec![signed_to_felt(v.into())]))
+                .unwrap_or(Err(PrimitiveError::MissingFieldElement)),
+            Primitive::I32(value) => value
+                .map(|v| Ok(vec![signed_to_felt(v.into())]))
+                .unwrap_or(Err(PrimitiveError::MissingFieldElement)),
+            Primitive::I64(value) => value
+                .map(|v| Ok(vec![signed_to_felt(v.into())]))
+                .unwrap_or(Err(PrimitiveError::MissingFieldElement)),
+            Primitive::I128(value) => value
+                .map(|v| Ok(vec![signed_to_felt(v)]))
+                .unwrap_or(Err(PrimitiveError::MissingFieldElement)),
             Primitive::Bool(value) => value
                 .map(|v| Ok(vec![if v { FieldElement::ONE } else { FieldElement::ZERO }]))
                 .unwrap_or(Err(PrimitiveError::MissingFieldElement)),
@@ -297,16 +416,40 @@ impl Primitive {
             Primitive::Felt252(value) => {
                 value.map(|v| Ok(vec![v])).unwrap_or(Err(PrimitiveError::MissingFieldElement))
             }
+            Primitive::ShortString(value) => {
+                value.map(|v| Ok(vec![v])).unwrap_or(Err(PrimitiveError::MissingFieldElement))
+            }
         }
     }
 }
 
+/// Encodes a signed integer the way Cairo does, negative values being represented as
+/// `P - |value|`.
+fn signed_to_felt(value: i128) -> FieldElement {
+    let abs = FieldElement::from(value.unsigned_abs());
+    if value < 0 { FieldElement::ZERO - abs } else { abs }
+}
+
+/// Decodes a signed integer encoded with [`signed_to_felt`], felts in the upper end of the field
+/// being negative values.
+fn felt_to_signed<T: TryFrom<i128>>(felt: FieldElement) -> Result<T, PrimitiveError> {
+    let value = match u128::try_from(felt) {
+        Ok(value) => i128::try_from(value).ok(),
+        Err(_) => u128::try_from(FieldElement::ZERO - felt)
+            .ok()
+            .and_then(|abs| 0i128.checked_sub_unsigned(abs)),
+    };
+
+    value.and_then(|value| T::try_from(value).ok()).ok_or(PrimitiveError::SignedValueOutOfRange)
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
     use crypto_bigint::U256;
     use starknet::core::types::FieldElement;
+    use starknet::core::utils::cairo_short_string_to_felt;
 
     use super::Primitive;
 
@@ -351,6 +494,16 @@ mod tests {
         assert_eq!(primitive.as_u256(), Some(U256::from(1u128)));
         let primitive = Primitive::USize(Some(1u32));
         assert_eq!(primitive.as_usize(), Some(1u32));
+        let primitive = Primitive::I8(Some(-1i8));
+        assert_eq!(primitive.as_i8(), Some(-1i8));
+        let primitive = Primitive::I16(Some(-1i16));
+        assert_eq!(primitive.as_i16(), Some(-1i16));
+        let primitive = Primitive::I32(Some(-1i32));
+        assert_eq!(primitive.as_i32(), Some(-1i32));
+        let primitive = Primitive::I64(Some(-1i64));
+        assert_eq!(primitive.as_i64(), Some(-1i64));
+        let primitive = Primitive::I128(Some(-1i128));
+        assert_eq!(primitive.as_i128(), Some(-1i128));
         let primitive = Primitive::Bool(Some(true));
         assert_eq!(primitive.as_bool(), Some(true));
         let primitive = Primitive::Felt252(Some(FieldElement::from(1u128)));
@@ -360,4 +513,82 @@ mod tests {
         let primitive = Primitive::ContractAddress(Some(FieldElement::from(1u128)));
         assert_eq!(primitive.as_contract_address(), Some(FieldElement::from(1u128)));
     }
+
+    #[test]
+    fn signed_integers_round_trip() {
+        let cases = [
+            Primitive::I8(Some(i8::MIN)),
+            Primitive::I8(Some(i8::MAX)),
+            Primitive::I16(Some(-300)),
+            Primitive::I32(Some(-1)),
+            Primitive::I32(Some(0)),
+            Primitive::I64(Some(i64::MIN)),
+            Primitive::I128(Some(i128::MIN)),
+            Primitive::I128(Some(i128::MAX)),
+        ];
+
+        for primitive in cases {
+            let mut deserialized = primitive;
+            deserialized.deserialize(&mut primitive.serialize().unwrap()).unwrap();
+            assert_eq!(deserialized, primitive);
+        }
+    }
+
+    #[test]
+    fn signed_integers_felt_encoding() {
+        // negative values are encoded as `P - |value|`
+        let minus_one = FieldElement::ZERO - FieldElement::ONE;
+        assert_eq!(Primitive::I32(Some(-1)).serialize().unwrap(), vec![minus_one]);
+        assert_eq!(Primitive::I32(Some(-1)).to_sql_value().unwrap(), "'-1'");
+        assert_eq!(
+            Primitive::I128(Some(-1)).to_sql_value().unwrap(),
+            format!("'0x{:064x}'", minus_one)
+        );
+
+        let mut primitive = Primitive::I8(None);
+        primitive.deserialize(&mut vec![FieldElement::ZERO - FieldElement::from(128u8)]).unwrap();
+        assert_eq!(primitive, Primitive::I8(Some(i8::MIN)));
+    }
+
+    #[test]
+    fn signed_integers_out_of_range() {
+        // one past the bounds of i8, on both sides of the field boundary
+        let mut primitive = Primitive::I8(None);
+        assert!(primitive.deserialize(&mut vec![FieldElement::from(128u8)]).is_err());
+        assert!(
+            primitive
+                .deserialize(&mut vec![FieldElement::ZERO - FieldElement::from(129u8)])
+                .is_err()
+        );
+
+        // 2^127 doesn't fit in a i128, neither does -(2^127 + 1)
+        let two_pow_127 = FieldElement::from(1u128 << 127);
+        let mut primitive = Primitive::I128(None);
+        assert!(primitive.deserialize(&mut vec![two_pow_127]).is_err());
+        assert!(
+            primitive
+                .deserialize(&mut vec![FieldElement::ZERO - two_pow_127 - FieldElement::ONE])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn short_strings_round_trip_as_felts() {
+        let valid = cairo_short_string_to_felt("dojo").unwrap();
+
+        // not a short string, as it doesn't fit in 31 bytes
+        for value in [valid, FieldElement::MAX] {
+            let primitive = Primitive::ShortString(Some(value));
+            let serialized = primitive.serialize().unwrap();
+
+            let mut deserialized = Primitive::ShortString(None);
+            deserialized.deserialize(&mut serialized.clone()).unwrap();
+
+            assert_eq!(serialized, vec![value]);
+            assert_eq!(deserialized.as_short_string(), Some(value));
+            assert_eq!(primitive.to_sql_value().unwrap(), format!("'0x{value:064x}'"));
+        }
+
+        assert_eq!(Primitive::from_str("ShortString").unwrap(), Primitive::ShortString(None));
+    }
 }
@@ -1,6 +1,7 @@
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use starknet::core::types::FieldElement;
+use starknet::core::utils::{parse_cairo_short_string, starknet_keccak};
 use strum_macros::AsRefStr;
 
 use crate::primitive::{Primitive, PrimitiveError};
@@ -24,6 +25,10 @@ impl Member {
 pub struct EntityQuery {
     pub model: String,
     pub clause: Clause,
+    /// The names of the members of the model to return, along with the key members. All the
+    /// members are returned if empty.
+    #[serde(default)]
+    pub members: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Eq, Clone)]
@@ -76,6 +81,34 @@ pub enum Value {
     Bytes(Vec<u8>),
 }
 
+/// The direction in which entities are sorted.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Eq, Clone, Copy, Default)]
+pub enum OrderDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Sorts entities by the value of a model member.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Eq, Clone)]
+pub struct OrderBy {
+    /// The name of the model member to sort by.
+    pub member: String,
+    pub direction: OrderDirection,
+}
+
+/// Represents an entity along with the values of its models.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Entity {
+    pub id: FieldElement,
+    pub keys: Vec<FieldElement>,
+    pub models: Vec<Ty>,
+    /// The block of the last change to any model of the entity, not only to the returned ones.
+    pub last_updated_block: Option<u64>,
+    /// The transaction of the last change to any model of the entity.
+    pub last_updated_transaction: Option<FieldElement>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelMetadata {
     pub schema: Ty,
@@ -84,6 +117,184 @@ pub struct ModelMetadata {
     pub unpacked_size: u32,
     pub class_hash: FieldElement,
     pub layout: Vec<FieldElement>,
+    /// A hash of the model definition, see [`compute_schema_hash`].
+    pub schema_hash: FieldElement,
+    /// The number of entities having a value for the model, if it was requested.
+    pub entity_count: Option<u64>,
+    /// The systems allowed to write the model, if recorded by the indexer.
+    pub writers: Vec<FieldElement>,
+    /// The account which sent the transaction registering the model, unknown if the model was
+    /// indexed by a version of the indexer not recording it.
+    pub registered_by: Option<FieldElement>,
+}
+
+impl ModelMetadata {
+    /// The position of the value of each member in the packed storage of the model, see
+    /// [`member_offsets`].
+    pub fn member_offsets(&self) -> Vec<MemberOffset> {
+        member_offsets(&self.schema, &self.layout)
+    }
+}
+
+/// The position of the value of a member of a model in the packed storage of the model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberOffset {
+    pub name: String,
+    /// The index of the packed felt the value starts in.
+    pub offset: u32,
+    /// The number of packed felts the value spans, 0 for the values without layout entries, eg.
+    /// the empty tuples.
+    pub width: u32,
+    /// The bit of the first packed felt the value starts at, from the least significant bit.
+    pub bit_offset: u32,
+    /// The number of bits of the value, ie. the sum of its layout entries.
+    pub bits: u32,
+}
+
+/// Computes the position of the value of each member of the model `schema` in its packed storage,
+/// following its `layout`: the values are packed from the least significant bit of a felt, and
+/// continue in the next felt once a value doesn't fit in the 251 bits of the current one. The key
+/// members aren't stored, and are skipped.
+///
+/// The offsets are empty if `schema` isn't a struct.
+pub fn member_offsets(schema: &Ty, layout: &[FieldElement]) -> Vec<MemberOffset> {
+    /// The number of layout entries of the value of a member, key members being excluded.
+    fn layout_len(ty: &Ty) -> usize {
+        match ty {
+            Ty::Primitive(Primitive::U256(_)) => 2,
+            Ty::Primitive(_) | Ty::Enum(_) => 1,
+            Ty::Struct(s) => s.children.iter().filter(|m| !m.key).map(|m| layout_len(&m.ty)).sum(),
+            Ty::Tuple(tys) => tys.iter().map(layout_len).sum(),
+        }
+    }
+
+    // the felt and bit each layout entry starts at, the entries of a valid layout being at most
+    // 251 bits
+    let mut positions = Vec::with_capacity(layout.len());
+    let (mut felt, mut bit) = (0, 0);
+    for size in layout.iter().map(|size| u8::try_from(*size).unwrap_or(u8::MAX) as u32) {
+        if bit + size > 251 {
+            felt += 1;
+            bit = 0;
+        }
+
+        positions.push((felt, bit, size));
+        bit += size;
+    }
+
+    let Ty::Struct(model) = schema else {
+        return vec![];
+    };
+
+    let mut entry = 0;
+    model
+        .children
+        .iter()
+        .filter(|m| !m.key)
+        .map(|member| {
+            let len = layout_len(&member.ty);
+            let entries = positions.get(entry..(entry + len).min(positions.len()));
+            let entries = entries.unwrap_or_default();
+            // the values without layout entries are where the next value starts
+            let (offset, bit_offset) = match positions.get(entry) {
+                Some((felt, bit, _)) => (*felt, *bit),
+                None => (felt, bit),
+            };
+            entry += len;
+
+            let width = entries.last().map_or(0, |(last, ..)| last - offset + 1);
+            let bits = entries.iter().map(|(.., size)| size).sum();
+
+            MemberOffset { name: member.name.clone(), offset, width, bit_offset, bits }
+        })
+        .collect()
+}
+
+/// Computes a hash identifying the definition of a model from its schema and layout.
+///
+/// Only the shape of the schema (type names, member names, key attributes, enum options) and the
+/// layout are hashed, so the hash is stable for identical definitions regardless of any values
+/// set in the schema. Clients can compare it against a cached value to detect model upgrades.
+pub fn compute_schema_hash(schema: &Ty, layout: &[FieldElement]) -> FieldElement {
+    fn write_ty(ty: &Ty, out: &mut String) {
+        match ty {
+            Ty::Primitive(p) => out.push_str(p.as_ref()),
+            Ty::Struct(s) => {
+                out.push_str(&format!("struct {} {{", s.name));
+                for member in &s.children {
+                    if member.key {
+                        out.push_str("#[key] ");
+                    }
+                    out.push_str(&format!("{}: ", member.name));
+                    write_ty(&member.ty, out);
+                    out.push(',');
+                }
+                out.push('}');
+            }
+            Ty::Enum(e) => {
+                out.push_str(&format!("enum {} {{", e.name));
+                for option in &e.options {
+                    out.push_str(&format!("{}: ", option.name));
+                    write_ty(&option.ty, out);
+                    out.push(',');
+                }
+                out.push('}');
+            }
+            Ty::Tuple(tys) => {
+                out.push('(');
+                for ty in tys {
+                    write_ty(ty, out);
+                    out.push(',');
+                }
+                out.push(')');
+            }
+        }
+    }
+
+    let mut definition = String::new();
+    write_ty(schema, &mut definition);
+    let layout = layout.iter().map(|l| format!("{l:#x}")).join(",");
+    definition.push_str(&format!(";layout: [{layout}]"));
+
+    starknet_keccak(definition.as_bytes())
+}
+
+/// Serializes `ty` to canonical JSON: object keys are sorted and no whitespace is added, so that
+/// the bytes only depend on the schema and its values, and can be hashed or compared by clients.
+pub fn canonical_json(ty: &Ty) -> String {
+    fn write_value(value: &serde_json::Value, out: &mut String) {
+        match value {
+            serde_json::Value::Object(object) => {
+                out.push('{');
+                for (idx, (key, value)) in object.iter().sorted_by_key(|(key, _)| *key).enumerate()
+                {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    out.push_str(&serde_json::Value::String(key.clone()).to_string());
+                    out.push(':');
+                    write_value(value, out);
+                }
+                out.push('}');
+            }
+            serde_json::Value::Array(values) => {
+                out.push('[');
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    write_value(value, out);
+                }
+                out.push(']');
+            }
+            value => out.push_str(&value.to_string()),
+        }
+    }
+
+    let value = serde_json::to_value(ty).expect("a Ty is always serializable");
+    let mut json = String::new();
+    write_value(&value, &mut json);
+    json
 }
 
 /// Represents all possible types in Cairo
@@ -193,8 +404,16 @@ impl Ty {
                 }
             }
             Ty::Enum(e) => {
-                e.option =
-                    Some(felts.remove(0).try_into().map_err(PrimitiveError::ValueOutOfRange)?);
+                if felts.is_empty() {
+                    return Err(PrimitiveError::MissingFieldElement);
+                }
+
+                let option: u8 = felts.remove(0).try_into()?;
+                if option as usize >= e.options.len() {
+                    return Err(PrimitiveError::InvalidEnumOption(option));
+                }
+
+                e.option = Some(option);
                 for EnumOption { ty, .. } in &mut e.options {
                     ty.deserialize(felts)?;
                 }
@@ -209,6 +428,21 @@ impl Ty {
     }
 }
 
+/// Decodes `felts`, serialized with [`Ty::serialize`], into the values of a copy of `schema`.
+///
+/// Fails without panicking on any input, notably when fuzzing the decoding of the models, if the
+/// felts don't hold a value of the schema or aren't all decoded.
+pub fn decode_ty(schema: &Ty, felts: &[FieldElement]) -> Result<Ty, PrimitiveError> {
+    let mut ty = schema.clone();
+    let mut felts = felts.to_vec();
+    ty.deserialize(&mut felts)?;
+
+    if !felts.is_empty() {
+        return Err(PrimitiveError::TooManyFieldElements(felts.len()));
+    }
+    Ok(ty)
+}
+
 pub struct TyIter<'a> {
     stack: Vec<&'a Ty>,
 }
@@ -325,6 +559,18 @@ impl Enum {
         Ok(self.options[option].name.clone())
     }
 
+    /// Sets the option of the enum to the one with the given name.
+    pub fn set_option(&mut self, name: &str) -> Result<(), EnumError> {
+        let option = self
+            .options
+            .iter()
+            .position(|option| option.name == name)
+            .ok_or(EnumError::OptionInvalid)?;
+
+        self.option = Some(option.try_into().map_err(|_| EnumError::OptionInvalid)?);
+        Ok(())
+    }
+
     pub fn to_sql_value(&self) -> Result<String, EnumError> {
         Ok(format!("'{}'", self.option()?))
     }
@@ -374,6 +620,31 @@ fn format_member(m: &Member) -> String {
                     str.push_str(&format!(" = {}", value));
                 }
             }
+            Primitive::I8(value) => {
+                if let Some(value) = value {
+                    str.push_str(&format!(" = {}", value));
+                }
+            }
+            Primitive::I16(value) => {
+                if let Some(value) = value {
+                    str.push_str(&format!(" = {}", value));
+                }
+            }
+            Primitive::I32(value) => {
+                if let Some(value) = value {
+                    str.push_str(&format!(" = {}", value));
+                }
+            }
+            Primitive::I64(value) => {
+                if let Some(value) = value {
+                    str.push_str(&format!(" = {}", value));
+                }
+            }
+            Primitive::I128(value) => {
+                if let Some(value) = value {
+                    str.push_str(&format!(" = {}", value));
+                }
+            }
             Primitive::Bool(value) => {
                 if let Some(value) = value {
                     str.push_str(&format!(" = {}", value));
@@ -394,6 +665,14 @@ fn format_member(m: &Member) -> String {
                     str.push_str(&format!(" = {:#x}", value));
                 }
             }
+            Primitive::ShortString(value) => {
+                if let Some(value) = value {
+                    match parse_cairo_short_string(value) {
+                        Ok(value) => str.push_str(&format!(" = '{value}'")),
+                        Err(_) => str.push_str(&format!(" = {:#x}", value)),
+                    }
+                }
+            }
         }
     } else if let Ty::Enum(e) = &m.ty {
         match e.option() {
@@ -404,3 +683,123 @@ fn format_member(m: &Member) -> String {
 
     str
 }
+
+#[cfg(test)]
+mod tests {
+    use starknet::core::types::FieldElement;
+
+    use super::{
+        canonical_json, compute_schema_hash, member_offsets, Member, MemberOffset, Struct, Ty,
+    };
+    use crate::primitive::Primitive;
+
+    fn position(y_ty: Primitive) -> Ty {
+        Ty::Struct(Struct {
+            name: "Position".into(),
+            children: vec![
+                Member {
+                    name: "player".into(),
+                    ty: Ty::Primitive(Primitive::ContractAddress(None)),
+                    key: true,
+                },
+                Member { name: "x".into(), ty: Ty::Primitive(Primitive::U32(None)), key: false },
+                Member { name: "y".into(), ty: Ty::Primitive(y_ty), key: false },
+            ],
+        })
+    }
+
+    #[test]
+    fn schema_hash_is_stable_for_identical_models() {
+        let layout = vec![FieldElement::from(32u8), FieldElement::from(32u8)];
+
+        let hash = compute_schema_hash(&position(Primitive::U32(None)), &layout);
+
+        assert_eq!(hash, compute_schema_hash(&position(Primitive::U32(None)), &layout));
+        // values set in the schema don't affect the hash
+        assert_eq!(hash, compute_schema_hash(&position(Primitive::U32(Some(7))), &layout));
+    }
+
+    #[test]
+    fn schema_hash_changes_with_model_definition() {
+        let layout = vec![FieldElement::from(32u8), FieldElement::from(32u8)];
+        let hash = compute_schema_hash(&position(Primitive::U32(None)), &layout);
+
+        let changed_member = compute_schema_hash(&position(Primitive::U64(None)), &layout);
+        assert_ne!(hash, changed_member);
+
+        let changed_layout = compute_schema_hash(
+            &position(Primitive::U32(None)),
+            &[FieldElement::from(32u8), FieldElement::from(64u8)],
+        );
+        assert_ne!(hash, changed_layout);
+    }
+
+    #[test]
+    fn canonical_json_is_stable() {
+        let ty = Ty::Struct(Struct {
+            name: "Position".into(),
+            children: vec![
+                Member {
+                    name: "player".into(),
+                    ty: Ty::Primitive(Primitive::ContractAddress(None)),
+                    key: true,
+                },
+                Member { name: "x".into(), ty: Ty::Primitive(Primitive::U32(Some(7))), key: false },
+            ],
+        });
+
+        let json = canonical_json(&ty);
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"content":{"children":["#,
+                r#"{"key":true,"member_type":{"content":{"scalar_type":"contractaddress","#,
+                r#""value":null},"type":"primitive"},"name":"player"},"#,
+                r#"{"key":false,"member_type":{"content":{"scalar_type":"u32","value":7},"#,
+                r#""type":"primitive"},"name":"x"}"#,
+                r#"],"name":"Position"},"type":"struct"}"#
+            )
+        );
+        assert_eq!(serde_json::from_str::<Ty>(&json).unwrap(), ty);
+    }
+
+    #[test]
+    fn member_offsets_follow_the_packing() {
+        let member = |name: &str, ty| Member { name: name.into(), key: false, ty };
+        let schema = Ty::Struct(Struct {
+            name: "Player".into(),
+            children: vec![
+                Member {
+                    name: "player".into(),
+                    key: true,
+                    ty: Ty::Primitive(Primitive::ContractAddress(None)),
+                },
+                member("level", Ty::Primitive(Primitive::U8(None))),
+                member("score", Ty::Primitive(Primitive::U256(None))),
+                member("nothing", Ty::Tuple(vec![])),
+                member("name", Ty::Primitive(Primitive::Felt252(None))),
+            ],
+        });
+        let layout = [8u8, 128, 128, 251].into_iter().map(FieldElement::from).collect::<Vec<_>>();
+
+        let offset = |name: &str, offset, width, bit_offset, bits| MemberOffset {
+            name: name.into(),
+            offset,
+            width,
+            bit_offset,
+            bits,
+        };
+        assert_eq!(
+            member_offsets(&schema, &layout),
+            vec![
+                offset("level", 0, 1, 0, 8),
+                offset("score", 0, 2, 8, 256),
+                // where the next value starts
+                offset("nothing", 2, 0, 0, 0),
+                offset("name", 2, 1, 0, 251),
+            ]
+        );
+
+        assert!(member_offsets(&Ty::Tuple(vec![]), &layout).is_empty());
+    }
+}
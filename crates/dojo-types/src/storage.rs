@@ -1,7 +1,122 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+use starknet::core::crypto::compute_hash_on_elements;
 use starknet::core::types::FieldElement;
+use starknet_crypto::poseidon_hash_many;
 
 #[derive(Clone, Debug)]
 pub struct Query {
     pub address_domain: u32,
     pub keys: Vec<FieldElement>,
 }
+
+/// The hash the world contract derives the entity ids from the keys with, the storage addresses of
+/// the models of an entity being derived from its id. The worlds deployed before the switch to
+/// poseidon hash the keys with pedersen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityIdScheme {
+    /// The poseidon hash of the keys, as `poseidon_hash_span` of the Cairo core library.
+    #[default]
+    Poseidon,
+    /// The pedersen hash chain of the keys followed by their number, as `compute_hash_on_elements`
+    /// of the Starknet libraries.
+    Pedersen,
+}
+
+impl EntityIdScheme {
+    /// Computes the id of the entity with the given serialized `keys`.
+    pub fn entity_id(self, keys: &[FieldElement]) -> FieldElement {
+        match self {
+            Self::Poseidon => poseidon_hash_many(keys),
+            Self::Pedersen => compute_hash_on_elements(keys),
+        }
+    }
+}
+
+impl FromStr for EntityIdScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "poseidon" => Ok(Self::Poseidon),
+            "pedersen" => Ok(Self::Pedersen),
+            _ => Err(format!("unknown entity id scheme `{s}`, expected `poseidon` or `pedersen`")),
+        }
+    }
+}
+
+impl fmt::Display for EntityIdScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poseidon => write!(f, "poseidon"),
+            Self::Pedersen => write!(f, "pedersen"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet::core::types::FieldElement;
+
+    use super::EntityIdScheme;
+
+    #[test]
+    fn entity_ids() {
+        // computed with the `poseidon_hash_span` of the Cairo core library
+        let ids = [
+            (vec![], "0x2272be0f580fd156823304800919530eaa97430e972d7213ee13f4fbf7a5dbc"),
+            (vec![0_u32], "0x545d6f7d28a8a398e543948be5a026af60c4dea482867a6eeb2525b35d1e1e1"),
+            (vec![1], "0x579e8877c7755365d5ec1ec7d3a94a457eff5d1f40482bbe9729c064cdead2"),
+            (vec![1, 2], "0x371cb6995ea5e7effcd2e174de264b5b407027a75a231a70c2c8d196107f0e7"),
+            (
+                vec![0x1337, 0x420],
+                "0x3ea2e4c7a77f90ef6b18ab68e4bc06a2028733b74058a6ffa8edcb490e235c4",
+            ),
+        ];
+
+        for (keys, id) in ids {
+            let keys = keys.into_iter().map(FieldElement::from).collect::<Vec<_>>();
+            assert_eq!(
+                EntityIdScheme::Poseidon.entity_id(&keys),
+                FieldElement::from_hex_be(id).unwrap()
+            );
+        }
+
+        // the scheme of the current world contract
+        assert_eq!(EntityIdScheme::default(), EntityIdScheme::Poseidon);
+    }
+
+    #[test]
+    fn pedersen_entity_ids() {
+        // the pedersen hash chain of the keys and their number, starting from 0
+        let ids = [
+            (vec![], "0x49ee3eba8c1600700ee1b87eb599f16716b0b1022947733551fde4050ca6804"),
+            (vec![0_u32], "0x137c95c76862129847d0f5e3618c7a4c3822ee344f4aa80bcb897cb97d3e16"),
+            (vec![1], "0x78d74f61aeaa8286418fd34b3a12a610445eba11d00ecc82ecac2542d55f7a4"),
+            (vec![1, 2], "0x501a3a8e6cd4f5241c639c74052aaa34557aafa84dd4ba983d6443c590ab7df"),
+            (
+                vec![0x1337, 0x420],
+                "0x577a6e98cc6e4959617c03716035e72d582dd05a0dca018e9c2c85f50628351",
+            ),
+        ];
+
+        for (keys, id) in ids {
+            let keys = keys.into_iter().map(FieldElement::from).collect::<Vec<_>>();
+            assert_eq!(
+                EntityIdScheme::Pedersen.entity_id(&keys),
+                FieldElement::from_hex_be(id).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn parses_entity_id_schemes() {
+        for scheme in [EntityIdScheme::Poseidon, EntityIdScheme::Pedersen] {
+            assert_eq!(scheme.to_string().parse::<EntityIdScheme>(), Ok(scheme));
+        }
+        assert!("keccak".parse::<EntityIdScheme>().is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! [`proptest`] strategies generating arbitrary model schemas and values, to check that the values
+//! survive their serialization and to fuzz [`decode_ty`](crate::schema::decode_ty).
+//!
+//! ```ignore
+//! use dojo_types::schema::decode_ty;
+//! use dojo_types::testing::arb_schema_and_felts;
+//! use proptest::proptest;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn decoding_never_panics((schema, felts) in arb_schema_and_felts(3)) {
+//!         let _ = decode_ty(&schema, &felts);
+//!     }
+//! }
+//! ```
+
+use crypto_bigint::U256;
+use proptest::prelude::*;
+use starknet::core::types::FieldElement;
+
+use crate::primitive::Primitive;
+use crate::schema::{Enum, EnumOption, Member, Struct, Ty};
+
+/// An arbitrary felt.
+pub fn arb_felt() -> impl Strategy<Value = FieldElement> {
+    any::<[u8; 32]>().prop_map(|mut bytes| {
+        // below 2^251, thus below the prime
+        bytes[0] &= 0x07;
+        FieldElement::from_bytes_be(&bytes).expect("felt below the prime")
+    })
+}
+
+/// An arbitrary primitive holding a value.
+pub fn arb_primitive() -> impl Strategy<Value = Primitive> {
+    prop_oneof![
+        any::<u8>().prop_map(|v| Primitive::U8(Some(v))),
+        any::<u16>().prop_map(|v| Primitive::U16(Some(v))),
+        any::<u32>().prop_map(|v| Primitive::U32(Some(v))),
+        any::<u64>().prop_map(|v| Primitive::U64(Some(v))),
+        any::<u128>().prop_map(|v| Primitive::U128(Some(v))),
+        any::<[u8; 32]>().prop_map(|v| Primitive::U256(Some(U256::from_be_bytes(v)))),
+        any::<u32>().prop_map(|v| Primitive::USize(Some(v))),
+        any::<i8>().prop_map(|v| Primitive::I8(Some(v))),
+        any::<i16>().prop_map(|v| Primitive::I16(Some(v))),
+        any::<i32>().prop_map(|v| Primitive::I32(Some(v))),
+        any::<i64>().prop_map(|v| Primitive::I64(Some(v))),
+        any::<i128>().prop_map(|v| Primitive::I128(Some(v))),
+        any::<bool>().prop_map(|v| Primitive::Bool(Some(v))),
+        arb_felt().prop_map(|v| Primitive::Felt252(Some(v))),
+        arb_felt().prop_map(|v| Primitive::ClassHash(Some(v))),
+        arb_felt().prop_map(|v| Primitive::ContractAddress(Some(v))),
+        arb_felt().prop_map(|v| Primitive::ShortString(Some(v))),
+    ]
+}
+
+/// An arbitrary type holding values, whose structs, enums and tuples are nested up to `depth`
+/// levels. All the options of the enums hold a value, as serialized by [`Ty::serialize`].
+pub fn arb_ty(depth: u32) -> impl Strategy<Value = Ty> {
+    arb_primitive().prop_map(Ty::Primitive).prop_recursive(depth, 64, 4, |inner| {
+        prop_oneof![
+            prop::collection::vec((inner.clone(), any::<bool>()), 0..4).prop_map(|members| {
+                let children = members
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, (ty, key))| Member { name: format!("m{idx}"), key, ty })
+                    .collect();
+                Ty::Struct(Struct { name: "Struct".into(), children })
+            }),
+            prop::collection::vec(inner.clone(), 1..4)
+                .prop_flat_map(|tys| {
+                    let len = tys.len() as u8;
+                    (Just(tys), 0..len)
+                })
+                .prop_map(|(tys, option)| {
+                    let options = tys
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, ty)| EnumOption { name: format!("O{idx}"), ty })
+                        .collect();
+                    Ty::Enum(Enum { name: "Enum".into(), option: Some(option), options })
+                }),
+            prop::collection::vec(inner, 0..4).prop_map(Ty::Tuple),
+        ]
+    })
+}
+
+/// An arbitrary schema, without values, and the serialized values of one of its instances.
+pub fn arb_schema_and_felts(depth: u32) -> impl Strategy<Value = (Ty, Vec<FieldElement>)> {
+    arb_ty(depth).prop_map(|ty| {
+        let felts = ty.serialize().expect("values are set");
+        (without_values(&ty), felts)
+    })
+}
+
+/// Returns the schema of `ty`, ie. `ty` without its values.
+pub fn without_values(ty: &Ty) -> Ty {
+    match ty {
+        Ty::Primitive(primitive) => Ty::Primitive(match primitive {
+            Primitive::U8(_) => Primitive::U8(None),
+            Primitive::U16(_) => Primitive::U16(None),
+            Primitive::U32(_) => Primitive::U32(None),
+            Primitive::U64(_) => Primitive::U64(None),
+            Primitive::U128(_) => Primitive::U128(None),
+            Primitive::U256(_) => Primitive::U256(None),
+            Primitive::USize(_) => Primitive::USize(None),
+            Primitive::I8(_) => Primitive::I8(None),
+            Primitive::I16(_) => Primitive::I16(None),
+            Primitive::I32(_) => Primitive::I32(None),
+            Primitive::I64(_) => Primitive::I64(None),
+            Primitive::I128(_) => Primitive::I128(None),
+            Primitive::Bool(_) => Primitive::Bool(None),
+            Primitive::Felt252(_) => Primitive::Felt252(None),
+            Primitive::ClassHash(_) => Primitive::ClassHash(None),
+            Primitive::ContractAddress(_) => Primitive::ContractAddress(None),
+            Primitive::ShortString(_) => Primitive::ShortString(None),
+        }),
+        Ty::Struct(s) => Ty::Struct(Struct {
+            name: s.name.clone(),
+            children: s
+                .children
+                .iter()
+                .map(|m| Member { name: m.name.clone(), key: m.key, ty: without_values(&m.ty) })
+                .collect(),
+        }),
+        Ty::Enum(e) => Ty::Enum(Enum {
+            name: e.name.clone(),
+            option: None,
+            options: e
+                .options
+                .iter()
+                .map(|o| EnumOption { name: o.name.clone(), ty: without_values(&o.ty) })
+                .collect(),
+        }),
+        Ty::Tuple(tys) => Ty::Tuple(tys.iter().map(without_values).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::{arb_felt, arb_schema_and_felts, arb_ty, without_values};
+    use crate::schema::decode_ty;
+
+    proptest! {
+        #[test]
+        fn values_round_trip(ty in arb_ty(3)) {
+            let felts = ty.serialize().unwrap();
+            prop_assert_eq!(decode_ty(&without_values(&ty), &felts).unwrap(), ty);
+        }
+
+        #[test]
+        fn truncated_or_extended_values_are_rejected(
+            (schema, mut felts) in arb_schema_and_felts(3),
+            extra in arb_felt(),
+        ) {
+            let len = felts.len();
+            felts.push(extra);
+            prop_assert!(decode_ty(&schema, &felts).is_err());
+
+            if len > 0 {
+                felts.truncate(len - 1);
+                prop_assert!(decode_ty(&schema, &felts).is_err());
+            }
+        }
+
+        #[test]
+        fn decoding_arbitrary_felts_never_panics(
+            ty in arb_ty(3),
+            felts in vec(arb_felt(), 0..16),
+        ) {
+            let _ = decode_ty(&without_values(&ty), &felts);
+        }
+    }
+}
@@ -106,8 +106,8 @@ where
         let packed_size: u8 =
             self.packed_size().await?.try_into().map_err(ParseError::ValueOutOfRange)?;
 
-        let key = poseidon_hash_many(keys);
-        let key = poseidon_hash_many(&[short_string!("dojo_storage"), self.name, key]);
+        let entity_id = self.world_reader.entity_id_scheme().entity_id(keys);
+        let key = poseidon_hash_many(&[short_string!("dojo_storage"), self.name, entity_id]);
 
         let mut packed = Vec::with_capacity(packed_size as usize);
         for slot in 0..packed_size {
@@ -1,5 +1,6 @@
 use std::result::Result;
 
+use dojo_types::storage::EntityIdScheme;
 use http::uri::{InvalidUri, Uri};
 use starknet::accounts::{AccountError, Call, ConnectedAccount};
 use starknet::core::types::{
@@ -181,6 +182,7 @@ pub struct WorldContractReader<P> {
     provider: P,
     block_id: BlockId,
     address: FieldElement,
+    entity_id_scheme: EntityIdScheme,
 }
 
 impl<P> WorldContractReader<P>
@@ -188,13 +190,23 @@ where
     P: Provider,
 {
     pub fn new(address: FieldElement, provider: P) -> Self {
-        Self { address, provider, block_id: BlockId::Tag(BlockTag::Latest) }
+        Self {
+            address,
+            provider,
+            block_id: BlockId::Tag(BlockTag::Latest),
+            entity_id_scheme: EntityIdScheme::default(),
+        }
     }
 
     pub fn with_block(self, block: BlockId) -> Self {
         Self { block_id: block, ..self }
     }
 
+    /// Sets the hash the world derives the entity ids from the keys with, poseidon by default.
+    pub fn with_entity_id_scheme(self, entity_id_scheme: EntityIdScheme) -> Self {
+        Self { entity_id_scheme, ..self }
+    }
+
     pub fn address(&self) -> FieldElement {
         self.address
     }
@@ -206,6 +218,10 @@ where
     pub fn block_id(&self) -> BlockId {
         self.block_id
     }
+
+    pub fn entity_id_scheme(&self) -> EntityIdScheme {
+        self.entity_id_scheme
+    }
 }
 
 impl<P> WorldContractReader<P>
@@ -91,7 +91,7 @@ pub(crate) fn build_root_database(unit: &CompilationUnit) -> Result<RootDatabase
     b.with_cfg(CfgSet::from_iter([Cfg::name("test")]));
 
     b.with_macro_plugin(Arc::new(TestPlugin::default()));
-    b.with_macro_plugin(Arc::new(BuiltinDojoPlugin));
+    b.with_macro_plugin(Arc::new(BuiltinDojoPlugin::default()));
     b.with_macro_plugin(Arc::new(StarkNetPlugin::default()));
 
     b.with_inline_macro_plugin(EmitMacro::NAME, Arc::new(EmitMacro));
@@ -1,3 +1,5 @@
+use dojo_types::packing::PackingError;
+use dojo_types::primitive::PrimitiveError;
 use dojo_world::contracts::model::ModelError;
 use starknet::core::utils::{CairoShortStringToFeltError, ParseCairoShortStringError};
 
@@ -20,6 +22,10 @@ pub enum Error {
     Model(#[from] ModelError),
     #[error("Unsupported query")]
     UnsupportedQuery,
+    #[error(transparent)]
+    Packing(#[from] PackingError),
+    #[error(transparent)]
+    Decoding(#[from] PrimitiveError),
 }
 
 #[derive(Debug, thiserror::Error)]
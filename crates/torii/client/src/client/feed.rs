@@ -0,0 +1,512 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::time::Duration;
+
+use dojo_types::packing::unpack;
+use dojo_types::schema::{Clause, Entity, EntityQuery};
+use dojo_types::WorldMetadata;
+use futures_timer::Delay;
+use futures_util::{Stream, StreamExt};
+use starknet::core::utils::cairo_short_string_to_felt;
+use starknet_crypto::FieldElement;
+use torii_grpc::client::{
+    classify_status, EntityUpdate, Error as GrpcError, StatusClass, WorldClient,
+};
+
+use super::error::{Error, ParseError};
+use crate::utils::compute_all_storage_addresses;
+
+/// The number of times in a row a dropped subscription is re-established without receiving any
+/// update before the feed fails.
+const MAX_RESUBSCRIBE_ATTEMPTS: usize = 5;
+
+/// The delay before re-establishing a dropped subscription, doubled for every attempt made in a
+/// row.
+const RESUBSCRIBE_BASE_DELAY: Duration = Duration::from_millis(250);
+
+/// An entity of the feed, and the values of its packed storage slots.
+struct FeedEntity {
+    model: String,
+    keys: Vec<FieldElement>,
+    values: Vec<FieldElement>,
+}
+
+/// Decodes the updates of a delta subscription to the entities of several models, following the
+/// schemas of the models when subscribing.
+pub(super) struct EntityFeed {
+    world_address: FieldElement,
+    metadata: WorldMetadata,
+    queries: Vec<EntityQuery>,
+    entities: Vec<FeedEntity>,
+    /// The storage address of each packed slot of the entities, mapped to the index of the entity
+    /// and of the slot.
+    slots: HashMap<FieldElement, (usize, usize)>,
+}
+
+impl EntityFeed {
+    /// Creates a feed of the entities of `queries`, which must be keys clauses with the keys of a
+    /// single entity. The duplicated queries are subscribed once.
+    pub(super) fn new(metadata: WorldMetadata, queries: Vec<EntityQuery>) -> Result<Self, Error> {
+        let mut seen = HashSet::new();
+        let queries: Vec<_> =
+            queries.into_iter().filter(|query| seen.insert(query.clone())).collect();
+
+        let mut entities = Vec::with_capacity(queries.len());
+        let mut slots = HashMap::new();
+        for query in &queries {
+            let keys = match &query.clause {
+                Clause::Keys(clause) if !clause.keys.is_empty() => clause.keys.clone(),
+                _ => return Err(Error::UnsupportedQuery),
+            };
+            let model = metadata
+                .model(&query.model)
+                .ok_or_else(|| Error::UnknownModel(query.model.clone()))?;
+
+            let addresses = compute_all_storage_addresses(
+                cairo_short_string_to_felt(&query.model)
+                    .map_err(ParseError::CairoShortStringToFelt)?,
+                &keys,
+                model.packed_size,
+                metadata.entity_id_scheme,
+            );
+            slots.extend(
+                addresses
+                    .into_iter()
+                    .enumerate()
+                    .map(|(slot, addr)| (addr, (entities.len(), slot))),
+            );
+            entities.push(FeedEntity {
+                model: query.model.clone(),
+                keys,
+                values: vec![FieldElement::ZERO; model.packed_size as usize],
+            });
+        }
+
+        Ok(Self { world_address: metadata.world_address, metadata, queries, entities, slots })
+    }
+
+    /// Applies the storage entries of `update` to the entities, and returns the decoded values of
+    /// the changed ones, tagged with their model.
+    fn apply(&mut self, update: EntityUpdate) -> Result<Vec<(String, Entity)>, Error> {
+        let mut changed = BTreeSet::new();
+        let diffs = update.state_update.state_diff.storage_diffs.into_iter();
+        for diff in diffs.filter(|diff| diff.address == self.world_address) {
+            for entry in diff.storage_entries {
+                if let Some(&(entity, slot)) = self.slots.get(&entry.key) {
+                    self.entities[entity].values[slot] = entry.value;
+                    changed.insert(entity);
+                }
+            }
+        }
+
+        changed.into_iter().map(|entity| self.decode(entity, update.block_number)).collect()
+    }
+
+    fn decode(&self, entity: usize, block_number: u64) -> Result<(String, Entity), Error> {
+        let FeedEntity { model, keys, values } = &self.entities[entity];
+        let metadata = self.metadata.model(model).expect("qed; model checked when subscribing");
+
+        let mut schema = metadata.schema.clone();
+        let unpacked = unpack(values.clone(), metadata.layout.clone())?;
+        schema.deserialize(&mut [keys.clone(), unpacked].concat())?;
+
+        Ok((
+            model.clone(),
+            Entity {
+                id: self.metadata.entity_id_scheme.entity_id(keys),
+                keys: keys.clone(),
+                models: vec![schema],
+                last_updated_block: Some(block_number),
+                last_updated_transaction: None,
+            },
+        ))
+    }
+}
+
+struct FeedState<F, S> {
+    /// Makes a delta subscription to the entities of the queries.
+    subscribe: F,
+    feed: EntityFeed,
+    stream: Option<S>,
+    /// The number of subscriptions made since the last update received.
+    attempts: usize,
+    /// The delay before the first resubscription, see [`RESUBSCRIBE_BASE_DELAY`].
+    base_delay: Duration,
+    /// Whether the subscription was dropped, and is made again after a delay.
+    resubscribing: bool,
+    decoded: VecDeque<(String, Entity)>,
+    done: bool,
+}
+
+impl<F, Fut, S> FeedState<F, S>
+where
+    F: FnMut(Vec<EntityQuery>) -> Fut,
+    Fut: Future<Output = Result<S, GrpcError>>,
+    S: Stream<Item = Result<EntityUpdate, tonic::Status>> + Unpin,
+{
+    async fn next(mut self) -> Option<(Result<(String, Entity), Error>, Self)> {
+        loop {
+            if let Some(entity) = self.decoded.pop_front() {
+                return Some((Ok(entity), self));
+            }
+            if self.done {
+                return None;
+            }
+
+            // a new delta subscription starts with the full values of the entities, so no change
+            // is missed while resubscribing
+            if self.stream.is_none() {
+                if self.resubscribing {
+                    Delay::new(self.base_delay * 2_u32.pow(self.attempts as u32)).await;
+                }
+
+                self.attempts += 1;
+                self.resubscribing = true;
+                let queries = self.feed.queries.clone();
+                match (self.subscribe)(queries).await {
+                    Ok(stream) => self.stream = Some(stream),
+                    Err(GrpcError::Grpc(status)) if self.can_retry(&status) => continue,
+                    Err(err) => return self.fail(err.into()),
+                }
+            }
+
+            let stream = self.stream.as_mut().expect("qed; subscribed above");
+            match stream.next().await {
+                Some(Ok(update)) => {
+                    self.attempts = 0;
+                    match self.feed.apply(update) {
+                        Ok(decoded) => self.decoded.extend(decoded),
+                        Err(err) => return self.fail(err),
+                    }
+                }
+                Some(Err(status)) if self.can_retry(&status) => self.stream = None,
+                Some(Err(status)) => return self.fail(GrpcError::Grpc(status).into()),
+                None => return None,
+            }
+        }
+    }
+
+    /// Whether the subscription can be made again after failing with `status`.
+    fn can_retry(&self, status: &tonic::Status) -> bool {
+        classify_status(status) == StatusClass::Retryable
+            && self.attempts < MAX_RESUBSCRIBE_ATTEMPTS
+    }
+
+    /// Ends the feed with `err`.
+    fn fail(mut self, err: Error) -> Option<(Result<(String, Entity), Error>, Self)> {
+        self.done = true;
+        self.stream = None;
+        Some((Err(err), self))
+    }
+}
+
+/// Returns the stream of the decoded entities of `feed`, subscribed with `client`.
+pub(super) fn entity_feed(
+    client: WorldClient,
+    feed: EntityFeed,
+) -> impl Stream<Item = Result<(String, Entity), Error>> {
+    let subscribe = move |queries| {
+        let mut client = client.clone();
+        async move { client.subscribe_entities_delta(queries, false).await }
+    };
+    subscribed_feed(subscribe, feed, RESUBSCRIBE_BASE_DELAY)
+}
+
+/// Returns the stream of the decoded entities of `feed`, subscribed with `subscribe`. A dropped
+/// subscription is made again after `base_delay`, doubled for every attempt made in a row.
+fn subscribed_feed<F, Fut, S>(
+    subscribe: F,
+    feed: EntityFeed,
+    base_delay: Duration,
+) -> impl Stream<Item = Result<(String, Entity), Error>>
+where
+    F: FnMut(Vec<EntityQuery>) -> Fut,
+    Fut: Future<Output = Result<S, GrpcError>>,
+    S: Stream<Item = Result<EntityUpdate, tonic::Status>> + Unpin,
+{
+    let state = FeedState {
+        subscribe,
+        feed,
+        stream: None,
+        attempts: 0,
+        base_delay,
+        resubscribing: false,
+        decoded: VecDeque::new(),
+        done: false,
+    };
+    futures_util::stream::unfold(state, FeedState::next)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use dojo_types::primitive::Primitive;
+    use dojo_types::schema::{Clause, EntityQuery, KeysClause, Member, ModelMetadata, Struct, Ty};
+    use dojo_types::storage::EntityIdScheme;
+    use dojo_types::WorldMetadata;
+    use futures_util::StreamExt;
+    use parking_lot::Mutex;
+    use starknet::core::types::{ContractStorageDiffItem, StateDiff, StateUpdate, StorageEntry};
+    use starknet::core::utils::cairo_short_string_to_felt;
+    use starknet::macros::felt;
+    use starknet_crypto::FieldElement;
+    use tonic::Status;
+    use torii_grpc::client::{EntityUpdate, Error as GrpcError};
+
+    use super::{subscribed_feed, EntityFeed, MAX_RESUBSCRIBE_ATTEMPTS};
+    use crate::client::error::Error;
+    use crate::utils::compute_storage_base_address;
+
+    fn model(name: &str, value_member: &str) -> ModelMetadata {
+        let member = |name: &str, key| Member {
+            name: name.into(),
+            ty: Ty::Primitive(Primitive::U32(None)),
+            key,
+        };
+
+        ModelMetadata {
+            name: name.into(),
+            class_hash: felt!("1"),
+            packed_size: 1,
+            unpacked_size: 1,
+            layout: vec![felt!("32")],
+            schema: Ty::Struct(Struct {
+                name: name.into(),
+                children: vec![member("player", true), member(value_member, false)],
+            }),
+            schema_hash: felt!("1"),
+            entity_count: None,
+            writers: vec![],
+            registered_by: None,
+        }
+    }
+
+    fn query(model: &str, keys: Vec<FieldElement>) -> EntityQuery {
+        EntityQuery {
+            model: model.into(),
+            clause: Clause::Keys(KeysClause { keys }),
+            members: vec![],
+        }
+    }
+
+    fn update(world: FieldElement, entries: Vec<(FieldElement, FieldElement)>) -> EntityUpdate {
+        EntityUpdate {
+            block_number: 7,
+            state_update: StateUpdate {
+                block_hash: FieldElement::ZERO,
+                new_root: FieldElement::ZERO,
+                old_root: FieldElement::ZERO,
+                state_diff: StateDiff {
+                    storage_diffs: vec![ContractStorageDiffItem {
+                        address: world,
+                        storage_entries: entries
+                            .into_iter()
+                            .map(|(key, value)| StorageEntry { key, value })
+                            .collect(),
+                    }],
+                    deprecated_declared_classes: vec![],
+                    declared_classes: vec![],
+                    deployed_contracts: vec![],
+                    replaced_classes: vec![],
+                    nonces: vec![],
+                },
+            },
+            query_indices: vec![0],
+            models: vec![],
+        }
+    }
+
+    fn base_address(model: &str, keys: &[FieldElement]) -> FieldElement {
+        let model = cairo_short_string_to_felt(model).unwrap();
+        compute_storage_base_address(model, keys, EntityIdScheme::default())
+    }
+
+    type Subscription = Result<Vec<Result<EntityUpdate, Status>>, Status>;
+
+    /// Returns the entities streamed by a feed of the `Moves` of `player`, whose subscriptions
+    /// are answered by `subscriptions` in order, and the number of subscriptions made.
+    async fn feed_entities(
+        world: FieldElement,
+        player: FieldElement,
+        subscriptions: Vec<Subscription>,
+    ) -> (Vec<Result<Option<u32>, String>>, usize) {
+        let metadata = WorldMetadata {
+            world_address: world,
+            models: HashMap::from([("Moves".into(), model("Moves", "remaining"))]),
+            ..Default::default()
+        };
+        let feed = EntityFeed::new(metadata, vec![query("Moves", vec![player])]).unwrap();
+
+        let subscriptions = Arc::new(Mutex::new(VecDeque::from(subscriptions)));
+        let made = Arc::new(Mutex::new(0));
+        let subscribe = {
+            let (subscriptions, made) = (subscriptions.clone(), made.clone());
+            move |_| {
+                *made.lock() += 1;
+                let subscription = subscriptions.lock().pop_front().expect("no more subscriptions");
+                let subscription =
+                    subscription.map(futures_util::stream::iter).map_err(GrpcError::Grpc);
+                futures_util::future::ready(subscription)
+            }
+        };
+
+        let entities = subscribed_feed(subscribe, feed, Duration::from_millis(1))
+            .map(|entity| {
+                let (_, entity) = entity.map_err(|err| err.to_string())?;
+                let Ty::Struct(value) = &entity.models[0] else { panic!("expected a struct") };
+                let Ty::Primitive(Primitive::U32(value)) = value.children[1].ty else {
+                    panic!("expected an u32")
+                };
+                Ok(value)
+            })
+            .collect()
+            .await;
+
+        let made = *made.lock();
+        (entities, made)
+    }
+
+    #[tokio::test]
+    async fn resubscribes_after_the_subscription_is_dropped() {
+        let (world, player) = (felt!("0x1234"), felt!("0x5"));
+        let moves =
+            |remaining| Ok(update(world, vec![(base_address("Moves", &[player]), remaining)]));
+
+        let (entities, subscriptions) = feed_entities(
+            world,
+            player,
+            vec![
+                Ok(vec![moves(felt!("0xa")), Err(Status::unavailable("restarting"))]),
+                Err(Status::unavailable("restarting")),
+                // the new subscription starts with the current values of the entities
+                Ok(vec![moves(felt!("0x8")), moves(felt!("0x7"))]),
+            ],
+        )
+        .await;
+
+        assert_eq!(entities, vec![Ok(Some(10)), Ok(Some(8)), Ok(Some(7))]);
+        assert_eq!(subscriptions, 3);
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_subscription_can_not_be_made_again() {
+        let (world, player) = (felt!("0x1234"), felt!("0x5"));
+
+        // a fatal status ends the feed
+        let (entities, subscriptions) = feed_entities(
+            world,
+            player,
+            vec![Ok(vec![Err(Status::invalid_argument("unknown model"))])],
+        )
+        .await;
+        assert_eq!(entities.len(), 1);
+        assert!(entities[0].is_err());
+        assert_eq!(subscriptions, 1);
+
+        // so do the retryable ones, once no update was received for too many attempts
+        let dropped =
+            (0..MAX_RESUBSCRIBE_ATTEMPTS).map(|_| Err(Status::unavailable("restarting"))).collect();
+        let (entities, subscriptions) = feed_entities(world, player, dropped).await;
+        assert_eq!(entities.len(), 1);
+        assert!(entities[0].is_err());
+        assert_eq!(subscriptions, MAX_RESUBSCRIBE_ATTEMPTS);
+    }
+
+    // A feed merging the entities of two models, as streamed by `Client::subscribe_many`.
+    #[test]
+    fn decodes_the_entities_of_several_models() {
+        let world = felt!("0x1234");
+        let metadata = WorldMetadata {
+            world_address: world,
+            models: HashMap::from([
+                ("Moves".into(), model("Moves", "remaining")),
+                ("Position".into(), model("Position", "x")),
+            ]),
+            ..Default::default()
+        };
+
+        let player = felt!("0x5");
+        let mut feed = EntityFeed::new(
+            metadata,
+            vec![query("Moves", vec![player]), query("Position", vec![player])],
+        )
+        .unwrap();
+
+        // the first update of the delta subscription holds the values of both entities, an
+        // entry of another entity is ignored
+        let decoded = feed
+            .apply(update(
+                world,
+                vec![
+                    (base_address("Position", &[player]), felt!("0x2")),
+                    (base_address("Moves", &[player]), felt!("0xa")),
+                    (base_address("Moves", &[felt!("0x6")]), felt!("0xb")),
+                ],
+            ))
+            .unwrap();
+
+        let values: Vec<_> = decoded
+            .iter()
+            .map(|(model, entity)| {
+                let Ty::Struct(value) = &entity.models[0] else { panic!("expected a struct") };
+                let Ty::Primitive(Primitive::U32(value)) = value.children[1].ty else {
+                    panic!("expected an u32")
+                };
+                (model.as_str(), entity.keys.clone(), value, entity.last_updated_block)
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("Moves", vec![player], Some(10), Some(7)),
+                ("Position", vec![player], Some(2), Some(7)),
+            ]
+        );
+
+        // only the changed entity is decoded again
+        let decoded =
+            feed.apply(update(world, vec![(base_address("Moves", &[player]), felt!("0x9"))]));
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].0, "Moves");
+    }
+
+    #[test]
+    fn follows_the_entity_id_scheme_of_the_world() {
+        let (world, player) = (felt!("0x1234"), felt!("0x5"));
+        let metadata = WorldMetadata {
+            world_address: world,
+            models: HashMap::from([("Moves".into(), model("Moves", "remaining"))]),
+            entity_id_scheme: EntityIdScheme::Pedersen,
+            ..Default::default()
+        };
+        let mut feed = EntityFeed::new(metadata, vec![query("Moves", vec![player])]).unwrap();
+
+        let model = cairo_short_string_to_felt("Moves").unwrap();
+        let address = compute_storage_base_address(model, &[player], EntityIdScheme::Pedersen);
+        let decoded = feed.apply(update(world, vec![(address, felt!("0xa"))])).unwrap();
+
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].1.id, EntityIdScheme::Pedersen.entity_id(&[player]));
+    }
+
+    #[test]
+    fn rejects_the_queries_without_keys() {
+        let metadata = WorldMetadata {
+            models: HashMap::from([("Moves".into(), model("Moves", "remaining"))]),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            EntityFeed::new(metadata.clone(), vec![query("Moves", vec![])]),
+            Err(Error::UnsupportedQuery)
+        ));
+        assert!(matches!(
+            EntityFeed::new(metadata, vec![query("Position", vec![felt!("0x5")])]),
+            Err(Error::UnknownModel(model)) if model == "Position"
+        ));
+    }
+}
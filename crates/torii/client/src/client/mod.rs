@@ -1,4 +1,5 @@
 pub mod error;
+mod feed;
 pub mod storage;
 pub mod subscription;
 
@@ -7,9 +8,10 @@ use std::collections::HashSet;
 use std::sync::Arc;
 
 use dojo_types::packing::unpack;
-use dojo_types::schema::{Clause, EntityQuery, Ty};
+use dojo_types::schema::{Clause, Entity, EntityQuery, Ty};
 use dojo_types::WorldMetadata;
 use dojo_world::contracts::WorldContractReader;
+use futures_util::Stream;
 use parking_lot::{RwLock, RwLockReadGuard};
 use starknet::core::utils::cairo_short_string_to_felt;
 use starknet::providers::jsonrpc::HttpTransport;
@@ -19,6 +21,7 @@ use tokio::sync::RwLock as AsyncRwLock;
 use torii_grpc::client::EntityUpdateStreaming;
 
 use self::error::{Error, ParseError};
+use self::feed::{entity_feed, EntityFeed};
 use self::storage::ModelStorage;
 use self::subscription::{SubscribedEntities, SubscriptionClientHandle};
 use crate::client::subscription::SubscriptionService;
@@ -50,7 +53,10 @@ impl Client {
     ) -> Result<Self, Error> {
         let mut grpc_client = torii_grpc::client::WorldClient::new(torii_url, world).await?;
 
-        let metadata = grpc_client.metadata().await?;
+        // the storage addresses of the entities derive from their ids
+        let entity_id_scheme = grpc_client.server_info().await?.entity_id_scheme;
+        let mut metadata = grpc_client.metadata().await?;
+        metadata.entity_id_scheme = entity_id_scheme;
 
         let shared_metadata: Arc<_> = RwLock::new(metadata).into();
         let client_storage: Arc<_> = ModelStorage::new(shared_metadata.clone()).into();
@@ -59,14 +65,15 @@ impl Client {
         // initialize the entities to be synced with the latest values
         let rpc_url = url::Url::parse(&rpc_url).map_err(ParseError::Url)?;
         let provider = JsonRpcClient::new(HttpTransport::new(rpc_url));
-        let world_reader = WorldContractReader::new(world, provider);
+        let world_reader =
+            WorldContractReader::new(world, provider).with_entity_id_scheme(entity_id_scheme);
 
         if let Some(queries) = queries {
             subbed_entities.add_entities(queries)?;
 
             // TODO: change this to querying the gRPC url instead
             let subbed_entities = subbed_entities.entities.read().clone();
-            for EntityQuery { model, clause } in subbed_entities {
+            for EntityQuery { model, clause, .. } in subbed_entities {
                 let model_reader = world_reader.model(&model).await?;
                 let keys = if let Clause::Keys(clause) = clause {
                     clause.keys
@@ -210,6 +217,27 @@ impl Client {
         Ok(())
     }
 
+    /// Subscribes to the entities of `queries` on a single stream, yielding the value of each
+    /// entity when subscribing and then every time it changes, tagged with the name of its model.
+    /// The queries can target different models, and must be keys clauses with the keys of a
+    /// single entity: the storage slots of the entities are computed from their keys, so the
+    /// queries matching entities whose keys aren't known, eg. the keys clauses without keys, fail
+    /// with [`Error::UnsupportedQuery`].
+    ///
+    /// The schemas of the models are fetched once, when subscribing. The subscription is made
+    /// again when the server drops it for a transient reason, eg. when restarting, waiting longer
+    /// after each attempt, and the stream only fails once it can't be made again.
+    pub async fn subscribe_many(
+        &self,
+        queries: Vec<EntityQuery>,
+    ) -> Result<impl Stream<Item = Result<(String, Entity), Error>>, Error> {
+        let mut grpc_client = self.inner.read().await.clone();
+        let mut metadata = grpc_client.metadata().await?;
+        metadata.entity_id_scheme = self.metadata.read().entity_id_scheme;
+        let feed = EntityFeed::new(metadata, queries)?;
+        Ok(entity_feed(grpc_client, feed))
+    }
+
     pub fn storage(&self) -> Arc<ModelStorage> {
         Arc::clone(&self.storage)
     }
@@ -219,7 +247,7 @@ impl Client {
         entities: Vec<EntityQuery>,
     ) -> Result<EntityUpdateStreaming, Error> {
         let mut grpc_client = self.inner.write().await;
-        let stream = grpc_client.subscribe_entities(entities).await?;
+        let stream = grpc_client.subscribe_entities(entities, false).await?;
         Ok(stream)
     }
 
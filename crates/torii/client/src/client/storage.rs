@@ -148,14 +148,18 @@ impl ModelStorage {
         let model_name =
             parse_cairo_short_string(&model).map_err(ParseError::ParseCairoShortString)?;
 
-        let model_packed_size = self
-            .metadata
-            .read()
+        let metadata = self.metadata.read();
+        let model_packed_size = metadata
             .model(&model_name)
             .map(|c| c.packed_size)
             .ok_or(Error::UnknownModel(model_name))?;
 
-        Ok(compute_all_storage_addresses(model, raw_keys, model_packed_size))
+        Ok(compute_all_storage_addresses(
+            model,
+            raw_keys,
+            model_packed_size,
+            metadata.entity_id_scheme,
+        ))
     }
 
     fn index_entity(&self, model: FieldElement, raw_keys: Vec<FieldElement>) {
@@ -169,6 +173,7 @@ mod tests {
     use std::sync::Arc;
 
     use dojo_types::schema::{KeysClause, Ty};
+    use dojo_types::storage::EntityIdScheme;
     use dojo_types::WorldMetadata;
     use parking_lot::RwLock;
     use starknet::core::utils::cairo_short_string_to_felt;
@@ -187,10 +192,15 @@ mod tests {
                 unpacked_size: 4,
                 layout: vec![],
                 schema: Ty::Primitive(dojo_types::primitive::Primitive::Bool(None)),
+                schema_hash: felt!("1"),
+                entity_count: None,
+                writers: vec![],
+                registered_by: None,
             },
         )]);
 
-        WorldMetadata { models, ..Default::default() }
+        // the entity ids of a legacy world
+        WorldMetadata { models, entity_id_scheme: EntityIdScheme::Pedersen, ..Default::default() }
     }
 
     fn create_dummy_storage() -> super::ModelStorage {
@@ -205,6 +215,7 @@ mod tests {
         let entity = dojo_types::schema::EntityQuery {
             model: "Position".into(),
             clause: dojo_types::schema::Clause::Keys(KeysClause { keys: keys.clone() }),
+            members: vec![],
         };
 
         let values = vec![felt!("1"), felt!("2"), felt!("3"), felt!("4"), felt!("5")];
@@ -225,6 +236,7 @@ mod tests {
         let entity = dojo_types::schema::EntityQuery {
             model: "Position".into(),
             clause: dojo_types::schema::Clause::Keys(KeysClause { keys: keys.clone() }),
+            members: vec![],
         };
 
         let values = vec![felt!("1"), felt!("2")];
@@ -245,6 +257,7 @@ mod tests {
         let entity = dojo_types::schema::EntityQuery {
             model: "Position".into(),
             clause: dojo_types::schema::Clause::Keys(KeysClause { keys: keys.clone() }),
+            members: vec![],
         };
 
         assert!(storage.storage.read().is_empty(), "storage must be empty initially");
@@ -255,6 +268,7 @@ mod tests {
             cairo_short_string_to_felt(&model.name).unwrap(),
             &keys,
             model.packed_size,
+            EntityIdScheme::Pedersen,
         );
 
         let expected_values = vec![felt!("1"), felt!("2"), felt!("3"), felt!("4")];
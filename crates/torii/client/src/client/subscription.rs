@@ -9,10 +9,10 @@ use dojo_types::WorldMetadata;
 use futures::channel::mpsc::{self, Receiver, Sender};
 use futures_util::StreamExt;
 use parking_lot::{Mutex, RwLock};
-use starknet::core::types::{StateDiff, StateUpdate};
+use starknet::core::types::StateDiff;
 use starknet::core::utils::cairo_short_string_to_felt;
 use starknet_crypto::FieldElement;
-use torii_grpc::client::EntityUpdateStreaming;
+use torii_grpc::client::{EntityUpdate, EntityUpdateStreaming};
 
 use super::error::{Error, ParseError};
 use super::ModelStorage;
@@ -67,9 +67,8 @@ impl SubscribedEntities {
             return Err(Error::UnsupportedQuery);
         };
 
-        let model_packed_size = self
-            .metadata
-            .read()
+        let metadata = self.metadata.read();
+        let model_packed_size = metadata
             .models
             .get(&entity.model)
             .map(|c| c.packed_size)
@@ -80,6 +79,7 @@ impl SubscribedEntities {
                 .map_err(ParseError::CairoShortStringToFelt)?,
             &keys,
             model_packed_size,
+            metadata.entity_id_scheme,
         );
 
         let storage_lock = &mut self.subscribed_storage_addresses.write();
@@ -101,9 +101,8 @@ impl SubscribedEntities {
             return Err(Error::UnsupportedQuery);
         };
 
-        let model_packed_size = self
-            .metadata
-            .read()
+        let metadata = self.metadata.read();
+        let model_packed_size = metadata
             .models
             .get(&entity.model)
             .map(|c| c.packed_size)
@@ -114,6 +113,7 @@ impl SubscribedEntities {
                 .map_err(ParseError::CairoShortStringToFelt)?,
             &keys,
             model_packed_size,
+            metadata.entity_id_scheme,
         );
 
         let storage_lock = &mut self.subscribed_storage_addresses.write();
@@ -188,10 +188,10 @@ impl SubscriptionService {
     }
 
     // handle the response from the subscription stream
-    fn handle_response(&mut self, response: Result<StateUpdate, tonic::Status>) {
+    fn handle_response(&mut self, response: Result<EntityUpdate, tonic::Status>) {
         match response {
             Ok(update) => {
-                self.process_entity_diff(update.state_diff);
+                self.process_entity_diff(update.state_update.state_diff);
             }
 
             Err(err) => {
@@ -257,6 +257,7 @@ mod tests {
     use std::sync::Arc;
 
     use dojo_types::schema::{KeysClause, Ty};
+    use dojo_types::storage::EntityIdScheme;
     use dojo_types::WorldMetadata;
     use parking_lot::RwLock;
     use starknet::core::utils::cairo_short_string_to_felt;
@@ -274,6 +275,10 @@ mod tests {
                 unpacked_size: 2,
                 layout: vec![],
                 schema: Ty::Primitive(dojo_types::primitive::Primitive::Bool(None)),
+                schema_hash: felt!("1"),
+                entity_count: None,
+                writers: vec![],
+                registered_by: None,
             },
         )]);
 
@@ -290,6 +295,7 @@ mod tests {
             cairo_short_string_to_felt(&model_name).unwrap(),
             &keys,
             packed_size,
+            EntityIdScheme::default(),
         )
         .into_iter();
 
@@ -298,6 +304,7 @@ mod tests {
         let entity = dojo_types::schema::EntityQuery {
             model: model_name,
             clause: dojo_types::schema::Clause::Keys(KeysClause { keys }),
+            members: vec![],
         };
 
         let subscribed_entities = super::SubscribedEntities::new(Arc::new(RwLock::new(metadata)));
@@ -1,12 +1,16 @@
+use dojo_types::storage::EntityIdScheme;
 use starknet::macros::short_string;
 use starknet_crypto::{poseidon_hash_many, FieldElement};
 
-/// Compute the base storage address for a given component of an entity.
+/// Compute the base storage address for a given component of an entity, whose id is derived from
+/// its keys with `entity_id_scheme`.
 pub fn compute_storage_base_address(
     model: FieldElement,
     entity_keys: &[FieldElement],
+    entity_id_scheme: EntityIdScheme,
 ) -> FieldElement {
-    poseidon_hash_many(&[short_string!("dojo_storage"), model, poseidon_hash_many(entity_keys)])
+    let entity_id = entity_id_scheme.entity_id(entity_keys);
+    poseidon_hash_many(&[short_string!("dojo_storage"), model, entity_id])
 }
 
 /// Compute all the storage addresses that are used for a given component of an entity when it is
@@ -15,7 +19,8 @@ pub(crate) fn compute_all_storage_addresses(
     model: FieldElement,
     entity_keys: &[FieldElement],
     packed_size: u32,
+    entity_id_scheme: EntityIdScheme,
 ) -> Vec<FieldElement> {
-    let base = compute_storage_base_address(model, entity_keys);
+    let base = compute_storage_base_address(model, entity_keys, entity_id_scheme);
     (0..packed_size).map(|i| base + i.into()).collect::<Vec<_>>()
 }
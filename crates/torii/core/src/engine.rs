@@ -253,3 +253,11 @@ impl<'db, P: Provider + Sync> Engine<'db, P> {
         Ok(())
     }
 }
+
+/// Returns the number of the block that emitted the event identified by `event_id`, as built by the
+/// engine: `{block number}:{transaction index}:{event index}`, hex-encoded. Returns `None` for the
+/// ids not built by the engine.
+pub fn event_block_number(event_id: &str) -> Option<u64> {
+    let (block_number, _) = event_id.split_once(':')?;
+    u64::from_str_radix(block_number.strip_prefix("0x")?, 16).ok()
+}
@@ -1,5 +1,8 @@
-use starknet::core::types::{FromByteSliceError, FromStrError};
-use starknet::core::utils::CairoShortStringToFeltError;
+use dojo_types::packing::PackingError;
+use dojo_types::primitive::PrimitiveError;
+use dojo_types::schema::EnumError;
+use starknet::core::types::{FieldElement, FromByteSliceError, FromStrError};
+use starknet::core::utils::{CairoShortStringToFeltError, ParseCairoShortStringError};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -9,6 +12,15 @@ pub enum Error {
     Sql(#[from] sqlx::Error),
     #[error("unsupported query clause")]
     UnsupportedQuery,
+    #[error(transparent)]
+    Query(#[from] QueryError),
+    #[error(
+        "incompatible database schema version {found}, expected {expected}: the database was \
+         created by another version of Torii, index the world into a new database"
+    )]
+    IncompatibleSchema { found: i64, expected: i64 },
+    #[error("the schema of model `{model}` can't be decoded from its stored members")]
+    UndecodableModel { model: String },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -19,4 +31,60 @@ pub enum ParseError {
     CairoShortStringToFelt(#[from] CairoShortStringToFeltError),
     #[error(transparent)]
     FromByteSliceError(#[from] FromByteSliceError),
+    #[error(transparent)]
+    Primitive(#[from] PrimitiveError),
+    #[error(transparent)]
+    Enum(#[from] EnumError),
+    #[error("invalid key {index} `{value}`: {source}")]
+    InvalidKey { index: usize, value: String, source: FromByteSliceError },
+    #[error("invalid model name `{value}`: {source}")]
+    InvalidModelName { value: String, source: CairoShortStringToFeltError },
+    #[error("invalid model selector `{value:#x}`: {source}")]
+    InvalidModelSelector { value: FieldElement, source: ParseCairoShortStringError },
+    #[error(transparent)]
+    Packing(#[from] PackingError),
+    #[error("invalid model layout: {0}")]
+    InvalidLayout(#[from] hex::FromHexError),
+    #[error(
+        "an entity of model `{model}` is {expected} felts long, its keys followed by its packed \
+         values, got {found}"
+    )]
+    InvalidEntityLength { model: String, expected: usize, found: usize },
+    #[error(
+        "key `{key}` of model `{model}` holds an enum with values, whose serialized length \
+         depends on its variant"
+    )]
+    VariableLengthKey { model: String, key: String },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueryError {
+    #[error("model member `{0}` does not exist")]
+    MemberNotFound(String),
+    #[error("model member `{0}` of type `{1}` is not sortable")]
+    UnsortableMember(String, String),
+    #[error("blocks {from} to {to} can't be replayed, only the blocks {first} to {last} can")]
+    BlockRangeUnavailable { from: u64, to: u64, first: u64, last: u64 },
+    #[error("blocks {from} to {to} can't be replayed, no block was recorded yet")]
+    NoReplayableBlocks { from: u64, to: u64 },
+    #[error("block {0} can't be replayed, its changes weren't recorded")]
+    UnrecordedBlock(u64),
+    #[error(
+        "the query of model `{0}` has no keys and matches all its entities, which can send a lot \
+         of updates: set `allow_full_scan` to subscribe to them anyway"
+    )]
+    FullScanNotAllowed(String),
+    #[error("model member `{member}` of type `{ty}` can't be compared with `{operator}`")]
+    UnsupportedComparison { member: String, ty: String, operator: &'static str },
+    #[error("model member `{member}` of type `{ty}` can't be compared to {value}")]
+    InvalidComparisonValue { member: String, ty: String, value: String },
+    #[error(
+        "model member `{0}` of type `{1}` can't be aggregated, only the unsigned integers of up \
+         to 128 bits can"
+    )]
+    NotAggregatable(String, String),
+    #[error("the entities kept changing while their values were read, subscribe again")]
+    OutdatedValues,
+    #[error("too many hydrations are running, retry once one of them completes")]
+    TooManyHydrations,
 }
@@ -0,0 +1,365 @@
+use std::fmt;
+use std::str::FromStr;
+
+use dojo_types::primitive::Primitive;
+use dojo_types::schema::Ty;
+use serde_json::{json, Map, Value};
+use starknet::core::types::FieldElement;
+use starknet::core::utils::{parse_cairo_short_string, starknet_keccak};
+
+/// How the contract addresses and class hashes are rendered in JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddressFormat {
+    /// Hex strings without leading zeros, like the other felts.
+    Felt,
+    /// Hex strings padded to 64 hex digits, the canonical form of the Starknet addresses.
+    #[default]
+    Padded,
+    /// Padded hex strings whose letters are upper-cased following the bits of the keccak hash of
+    /// the address, an EIP-55 style checksum computed as by `getChecksumAddress` of starknet.js.
+    Checksummed,
+}
+
+impl FromStr for AddressFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "felt" => Ok(Self::Felt),
+            "padded" => Ok(Self::Padded),
+            "checksummed" => Ok(Self::Checksummed),
+            _ => Err(format!(
+                "unknown address format `{s}`, expected `felt`, `padded` or `checksummed`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AddressFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Felt => write!(f, "felt"),
+            Self::Padded => write!(f, "padded"),
+            Self::Checksummed => write!(f, "checksummed"),
+        }
+    }
+}
+
+/// The options of the conversion of the models to JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonOptions {
+    pub addresses: AddressFormat,
+}
+
+/// Converts a [`Ty`] holding values to JSON:
+/// * integers up to 64 bits and booleans are JSON numbers and booleans. The 128 bits integers don't
+///   fit in the numbers of most JSON parsers and are decimal strings instead.
+/// * `u256` and `felt252` are hex strings. Contract addresses and class hashes are hex strings
+///   formatted following `options`.
+/// * short strings are decoded, or kept as hex strings if they aren't valid short strings.
+/// * structs are objects keyed by member name, and tuples are arrays.
+/// * enums are the name of the selected option if it holds no value, or an object mapping the name
+///   of the option to its value otherwise. `Option<T>` members are the value they hold, or `null`
+///   for `None`.
+///
+/// Missing values are `null`.
+pub fn ty_to_json_value(ty: &Ty, options: &JsonOptions) -> Value {
+    let to_json = |ty: &Ty| ty_to_json_value(ty, options);
+
+    match ty {
+        Ty::Primitive(primitive) => primitive_to_json_value(primitive, options),
+        Ty::Struct(s) => Value::Object(
+            s.children
+                .iter()
+                .map(|member| (member.name.clone(), to_json(&member.ty)))
+                .collect::<Map<_, _>>(),
+        ),
+        Ty::Enum(e) => {
+            let Some(option) = e.option.and_then(|option| e.options.get(option as usize)) else {
+                return Value::Null;
+            };
+
+            match &option.ty {
+                Ty::Tuple(tys) if e.name.starts_with("Option<") => {
+                    tys.first().map(to_json).unwrap_or(Value::Null)
+                }
+                Ty::Tuple(tys) if tys.is_empty() => Value::String(option.name.clone()),
+                ty => Value::Object(Map::from_iter([(option.name.clone(), to_json(ty))])),
+            }
+        }
+        Ty::Tuple(tys) => Value::Array(tys.iter().map(to_json).collect()),
+    }
+}
+
+fn primitive_to_json_value(primitive: &Primitive, options: &JsonOptions) -> Value {
+    match *primitive {
+        Primitive::U8(value) => json!(value),
+        Primitive::U16(value) => json!(value),
+        Primitive::U32(value) => json!(value),
+        Primitive::U64(value) => json!(value),
+        Primitive::USize(value) => json!(value),
+        Primitive::I8(value) => json!(value),
+        Primitive::I16(value) => json!(value),
+        Primitive::I32(value) => json!(value),
+        Primitive::I64(value) => json!(value),
+        Primitive::Bool(value) => json!(value),
+        Primitive::U128(value) => json!(value.map(|v| v.to_string())),
+        Primitive::I128(value) => json!(value.map(|v| v.to_string())),
+        Primitive::U256(value) => json!(value.map(|v| format!("0x{v:x}"))),
+        Primitive::Felt252(value) => json!(value.map(|v| format!("{v:#x}"))),
+        Primitive::ClassHash(value) | Primitive::ContractAddress(value) => {
+            json!(value.map(|v| match options.addresses {
+                AddressFormat::Felt => format!("{v:#x}"),
+                AddressFormat::Padded => format!("{v:#066x}"),
+                AddressFormat::Checksummed => checksummed_address(v),
+            }))
+        }
+        Primitive::ShortString(value) => {
+            json!(value.map(|v| parse_cairo_short_string(&v).unwrap_or_else(|_| format!("{v:#x}"))))
+        }
+    }
+}
+
+/// Formats `address` as 64 hex digits, upper-casing each digit whose matching nibble of the keccak
+/// hash of the address is 8 or more.
+fn checksummed_address(address: FieldElement) -> String {
+    // the address is hashed without its leading zero bytes, but with at least one byte
+    let bytes = address.to_bytes_be();
+    let first = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len() - 1);
+    let hash = starknet_keccak(&bytes[first..]).to_bytes_be();
+
+    let digits = format!("{address:064x}")
+        .chars()
+        .enumerate()
+        .map(|(idx, digit)| {
+            let byte = hash[idx / 2];
+            let nibble = if idx % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+            if nibble >= 8 { digit.to_ascii_uppercase() } else { digit }
+        })
+        .collect::<String>();
+
+    format!("0x{digits}")
+}
+
+#[cfg(test)]
+mod tests {
+    use dojo_types::primitive::{Primitive, PrimitiveError};
+    use dojo_types::schema::{Enum, EnumOption, Member, Struct, Ty};
+    use serde_json::{json, Value};
+    use starknet::core::types::FieldElement;
+    use starknet::core::utils::cairo_short_string_to_felt;
+
+    use super::{ty_to_json_value, AddressFormat, JsonOptions};
+
+    /// Decodes the serialized `values` following the schema `ty` and converts them to JSON.
+    fn to_json_value_with(
+        ty: &Ty,
+        values: &[FieldElement],
+        options: &JsonOptions,
+    ) -> Result<Value, PrimitiveError> {
+        let mut ty = ty.clone();
+        ty.deserialize(&mut values.to_vec())?;
+        Ok(ty_to_json_value(&ty, options))
+    }
+
+    fn to_json_value(ty: &Ty, values: &[FieldElement]) -> Result<Value, PrimitiveError> {
+        to_json_value_with(ty, values, &JsonOptions::default())
+    }
+
+    fn member(name: &str, ty: Ty) -> Member {
+        Member { name: name.into(), ty, key: false }
+    }
+
+    #[test]
+    fn primitives_to_json() {
+        let ty = Ty::Tuple(vec![
+            Ty::Primitive(Primitive::U8(None)),
+            Ty::Primitive(Primitive::I32(None)),
+            Ty::Primitive(Primitive::U64(None)),
+            Ty::Primitive(Primitive::U128(None)),
+            Ty::Primitive(Primitive::U256(None)),
+            Ty::Primitive(Primitive::Bool(None)),
+            Ty::Primitive(Primitive::Felt252(None)),
+            Ty::Primitive(Primitive::ContractAddress(None)),
+            Ty::Primitive(Primitive::ShortString(None)),
+            Ty::Primitive(Primitive::ShortString(None)),
+        ]);
+
+        // 32 bytes long, so not a valid short string
+        let not_short_string =
+            FieldElement::from_hex_be(&format!("0x1{}", "0".repeat(62))).unwrap();
+        let values = vec![
+            FieldElement::from(255u8),
+            -FieldElement::from(42u8),
+            FieldElement::from(u64::MAX),
+            FieldElement::from(u128::MAX),
+            FieldElement::from(1u8),
+            FieldElement::from(2u8),
+            FieldElement::ONE,
+            FieldElement::from(0xabcu32),
+            FieldElement::from(0x123u32),
+            cairo_short_string_to_felt("dojo").unwrap(),
+            not_short_string,
+        ];
+
+        assert_eq!(
+            to_json_value(&ty, &values).unwrap(),
+            json!([
+                255,
+                -42,
+                u64::MAX,
+                u128::MAX.to_string(),
+                "0x0000000000000000000000000000000200000000000000000000000000000001",
+                true,
+                "0xabc",
+                "0x0000000000000000000000000000000000000000000000000000000000000123",
+                "dojo",
+                format!("{not_short_string:#x}"),
+            ])
+        );
+    }
+
+    #[test]
+    fn nested_structs_to_json() {
+        let ty = Ty::Struct(Struct {
+            name: "Player".into(),
+            children: vec![
+                member("name", Ty::Primitive(Primitive::ShortString(None))),
+                member(
+                    "position",
+                    Ty::Struct(Struct {
+                        name: "Vec2".into(),
+                        children: vec![
+                            member("x", Ty::Primitive(Primitive::U32(None))),
+                            member("y", Ty::Primitive(Primitive::U32(None))),
+                        ],
+                    }),
+                ),
+            ],
+        });
+
+        let values = vec![
+            cairo_short_string_to_felt("alice").unwrap(),
+            FieldElement::from(3u8),
+            FieldElement::from(4u8),
+        ];
+
+        assert_eq!(
+            to_json_value(&ty, &values).unwrap(),
+            json!({ "name": "alice", "position": { "x": 3, "y": 4 } })
+        );
+    }
+
+    #[test]
+    fn enums_to_json() {
+        let direction = Ty::Enum(Enum {
+            name: "Direction".into(),
+            option: None,
+            options: vec![
+                EnumOption { name: "Left".into(), ty: Ty::Tuple(vec![]) },
+                EnumOption { name: "Right".into(), ty: Ty::Tuple(vec![]) },
+            ],
+        });
+        assert_eq!(to_json_value(&direction, &[FieldElement::ONE]).unwrap(), json!("Right"));
+
+        let amount = Ty::Enum(Enum {
+            name: "Amount".into(),
+            option: None,
+            options: vec![EnumOption {
+                name: "Some".into(),
+                ty: Ty::Tuple(vec![Ty::Primitive(Primitive::U16(None))]),
+            }],
+        });
+        assert_eq!(
+            to_json_value(&amount, &[FieldElement::ZERO, FieldElement::from(7u8)]).unwrap(),
+            json!({ "Some": [7] })
+        );
+    }
+
+    #[test]
+    fn options_to_json() {
+        let ty = Ty::Enum(Enum {
+            name: "Option<u32>".into(),
+            option: None,
+            options: vec![
+                EnumOption {
+                    name: "Some".into(),
+                    ty: Ty::Tuple(vec![Ty::Primitive(Primitive::U32(None))]),
+                },
+                EnumOption { name: "None".into(), ty: Ty::Tuple(vec![]) },
+            ],
+        });
+
+        assert_eq!(
+            to_json_value(&ty, &[FieldElement::ZERO, FieldElement::from(7u8)]).unwrap(),
+            json!(7)
+        );
+        assert_eq!(
+            to_json_value(&ty, &[FieldElement::ONE, FieldElement::ZERO]).unwrap(),
+            json!(null)
+        );
+    }
+
+    #[test]
+    fn tuples_to_json() {
+        let ty = Ty::Tuple(vec![
+            Ty::Primitive(Primitive::U8(None)),
+            Ty::Tuple(vec![Ty::Primitive(Primitive::Bool(None)), Ty::Tuple(vec![])]),
+        ]);
+
+        assert_eq!(
+            to_json_value(&ty, &[FieldElement::from(1u8), FieldElement::ZERO]).unwrap(),
+            json!([1, [false, []]])
+        );
+    }
+
+    #[test]
+    fn address_formats() {
+        let ty = Ty::Struct(Struct {
+            name: "Account".into(),
+            children: vec![
+                member("owner", Ty::Primitive(Primitive::ContractAddress(None))),
+                member("balance", Ty::Primitive(Primitive::Felt252(None))),
+            ],
+        });
+        let owner = FieldElement::from_hex_be(
+            "0x2fd23d9182193775423497fc0c472e156c57c69e4089a1967fb288a2d84e914",
+        )
+        .unwrap();
+        let values = vec![owner, FieldElement::from(0x2au8)];
+
+        let json =
+            |addresses| to_json_value_with(&ty, &values, &JsonOptions { addresses }).unwrap();
+
+        assert_eq!(json(AddressFormat::Padded), to_json_value(&ty, &values).unwrap());
+        assert_eq!(
+            json(AddressFormat::Padded),
+            json!({
+                "owner": "0x02fd23d9182193775423497fc0c472e156c57c69e4089a1967fb288a2d84e914",
+                "balance": "0x2a",
+            })
+        );
+        assert_eq!(
+            json(AddressFormat::Checksummed),
+            json!({
+                "owner": "0x02Fd23d9182193775423497fc0c472E156C57C69E4089A1967fb288A2d84e914",
+                "balance": "0x2a",
+            })
+        );
+        assert_eq!(
+            json(AddressFormat::Felt),
+            json!({
+                "owner": "0x2fd23d9182193775423497fc0c472e156c57c69e4089a1967fb288a2d84e914",
+                "balance": "0x2a",
+            })
+        );
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        let ty =
+            Ty::Tuple(vec![Ty::Primitive(Primitive::U8(None)), Ty::Primitive(Primitive::U8(None))]);
+
+        assert!(to_json_value(&ty, &[FieldElement::ONE]).is_err());
+    }
+}
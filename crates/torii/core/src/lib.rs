@@ -1,3 +1,4 @@
+pub use dojo_types::storage::EntityIdScheme;
 use serde::Deserialize;
 use sqlx::FromRow;
 
@@ -5,6 +6,7 @@ use crate::types::SQLFieldElement;
 
 pub mod engine;
 pub mod error;
+pub mod json;
 pub mod model;
 pub mod processors;
 pub mod simple_broker;
@@ -1,10 +1,79 @@
+use std::str::FromStr;
+
 use async_trait::async_trait;
-use dojo_types::schema::{Enum, EnumOption, Member, Struct, Ty};
+use dojo_types::primitive::{Primitive, SqlType};
+use dojo_types::schema::{
+    AttributeClause, ComparisonOperator, Enum, EnumOption, Member, Struct, Ty, Value,
+};
 use dojo_world::contracts::model::ModelReader;
-use sqlx::{Pool, Sqlite};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Pool, Row, Sqlite};
 use starknet::core::types::FieldElement;
+use starknet::core::utils::{cairo_short_string_to_felt, parse_cairo_short_string};
+
+use super::error::{self, Error, QueryError};
 
-use super::error::{self, Error};
+/// Identifies a model, either by its name or by its selector. The selector of a model is its name
+/// encoded as a Cairo short string, so both are interchangeable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelIdentifier {
+    Name(String),
+    Selector(FieldElement),
+}
+
+impl ModelIdentifier {
+    /// Returns the name of the model, as stored in the `models` table.
+    pub fn name(&self) -> Result<String, error::ParseError> {
+        match self {
+            Self::Name(name) => Ok(name.clone()),
+            Self::Selector(selector) => parse_cairo_short_string(selector).map_err(|source| {
+                error::ParseError::InvalidModelSelector { value: *selector, source }
+            }),
+        }
+    }
+
+    /// Returns the selector of the model, as used to compute the storage addresses of its entities.
+    pub fn selector(&self) -> Result<FieldElement, error::ParseError> {
+        match self {
+            Self::Name(name) => cairo_short_string_to_felt(name).map_err(|source| {
+                error::ParseError::InvalidModelName { value: name.clone(), source }
+            }),
+            Self::Selector(selector) => Ok(*selector),
+        }
+    }
+}
+
+/// Parses a model identifier from a string: a hex string prefixed with `0x` is a selector, anything
+/// else is a name. Cairo identifiers can't start with a digit, so there is no ambiguity.
+impl FromStr for ModelIdentifier {
+    type Err = error::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("0x") {
+            Ok(Self::Selector(FieldElement::from_hex_be(s)?))
+        } else {
+            Ok(Self::Name(s.to_owned()))
+        }
+    }
+}
+
+impl From<FieldElement> for ModelIdentifier {
+    fn from(selector: FieldElement) -> Self {
+        Self::Selector(selector)
+    }
+}
+
+impl From<&str> for ModelIdentifier {
+    fn from(name: &str) -> Self {
+        Self::Name(name.to_owned())
+    }
+}
+
+impl From<String> for ModelIdentifier {
+    fn from(name: String) -> Self {
+        Self::Name(name)
+    }
+}
 
 pub struct ModelSQLReader {
     /// The name of the model
@@ -60,7 +129,7 @@ impl ModelReader<Error> for ModelSQLReader {
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(parse_sql_model_members(&self.name, &model_members))
+        parse_sql_model_members(&self.name, &model_members)
     }
 
     async fn packed_size(&self) -> Result<FieldElement, Error> {
@@ -91,65 +160,368 @@ pub struct SqlModelMember {
 
 // assume that the model members are sorted by model_idx and member_idx
 // `id` is the type id of the model member
-/// A helper function to parse the model members from sql table to `Ty`
-pub fn parse_sql_model_members(model: &str, model_members_all: &[SqlModelMember]) -> Ty {
-    fn parse_sql_model_members_impl(path: &str, model_members_all: &[SqlModelMember]) -> Ty {
+/// A helper function to parse the model members from sql table to `Ty`. Fails with
+/// [`Error::UndecodableModel`] if the members are malformed, eg. of an unknown type.
+pub fn parse_sql_model_members(
+    model: &str,
+    model_members_all: &[SqlModelMember],
+) -> Result<Ty, Error> {
+    fn parse_sql_model_members_impl(
+        model: &str,
+        path: &str,
+        model_members_all: &[SqlModelMember],
+    ) -> Result<Ty, Error> {
+        let undecodable = || Error::UndecodableModel { model: model.to_string() };
+
         let children = model_members_all
             .iter()
             .filter(|member| member.id == path)
-            .map(|child| match child.type_enum.as_ref() {
-                "Primitive" => Member {
-                    key: child.key,
-                    name: child.name.to_owned(),
-                    ty: Ty::Primitive(child.r#type.parse().unwrap()),
-                },
+            .map(|child| {
+                let ty = match child.type_enum.as_ref() {
+                    "Primitive" => Ty::Primitive(child.r#type.parse().map_err(|_| undecodable())?),
 
-                "Struct" => Member {
-                    key: child.key,
-                    name: child.name.to_owned(),
-                    ty: parse_sql_model_members_impl(
+                    "Struct" => parse_sql_model_members_impl(
+                        model,
                         &format!("{}${}", child.id, child.r#type),
                         model_members_all,
-                    ),
-                },
+                    )?,
 
-                "Enum" => Member {
-                    key: child.key,
-                    name: child.name.to_owned(),
-                    ty: Ty::Enum(Enum {
+                    "Enum" => Ty::Enum(Enum {
                         option: None,
                         name: child.r#type.to_owned(),
                         options: child
                             .enum_options
                             .as_ref()
-                            .expect("qed; enum_options should exist")
+                            .ok_or_else(undecodable)?
                             .split(',')
-                            .map(|s| EnumOption { name: s.to_owned(), ty: Ty::Tuple(vec![]) })
+                            // an enum without options is stored as an empty list
+                            .filter(|s| !s.is_empty())
+                            .map(|s| EnumOption {
+                                name: s.to_owned(),
+                                ty: enum_option_ty(&child.r#type, s),
+                            })
                             .collect::<Vec<_>>(),
                     }),
-                },
 
-                ty => {
-                    unimplemented!("unimplemented type_enum: {ty}");
-                }
+                    _ => return Err(undecodable()),
+                };
+
+                Ok(Member { key: child.key, name: child.name.to_owned(), ty })
             })
-            .collect::<Vec<Member>>();
+            .collect::<Result<Vec<Member>, Error>>()?;
 
         // refer to the sql table for `model_members`
         let model_name = path.split('$').last().unwrap_or(path);
 
-        Ty::Struct(Struct { name: model_name.to_owned(), children })
+        Ok(Ty::Struct(Struct { name: model_name.to_owned(), children }))
+    }
+
+    parse_sql_model_members_impl(model, model, model_members_all)
+}
+
+/// Returns the type of the value held by the `option` of the enum `ty`. Only the options of
+/// `Option<T>`, with `T` a primitive, are known to hold a value: `Some` holds a `T` and `None`
+/// holds nothing.
+fn enum_option_ty(ty: &str, option: &str) -> Ty {
+    let inner = ty
+        .strip_prefix("Option<")
+        .and_then(|ty| ty.strip_suffix('>'))
+        .and_then(|ty| ty.parse::<Primitive>().ok());
+
+    match (inner, option) {
+        (Some(primitive), "Some") => Ty::Tuple(vec![Ty::Primitive(primitive)]),
+        _ => Ty::Tuple(vec![]),
+    }
+}
+
+/// Returns the name of the table storing the members of `path`, which is either the name of a model
+/// or the path of a struct nested in a model, eg. `Position$Vec2`.
+///
+/// Tables are named after their path. When the tables of several worlds live in the same database,
+/// each world gets its own `namespace`, and the names are prefixed with it: `Position$Vec2` is
+/// stored in `world1_Position$Vec2` for the namespace `world1`. The name must be quoted in queries,
+/// eg. `[world1_Position$Vec2]`.
+pub fn table_name(namespace: Option<&str>, path: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{namespace}_{path}"),
+        None => path.to_string(),
+    }
+}
+
+/// Builds a query selecting the values of all the members of a model, from the model table and the
+/// tables of its nested structs, along with the id, keys and last event of the entities, and the
+/// hash of the transaction that emitted the event. The tables are looked up in `namespace`, see
+/// [`table_name`].
+///
+/// Each member is selected as `"{path}.{member}"` (eg. `"Position$Vec2.x"`), whatever the
+/// namespace, so that the rows can be mapped back to the model schema with [`map_row_to_ty`]. The
+/// model table is referenced by its name, so the caller can append `WHERE`, `ORDER BY` and `LIMIT`
+/// clauses to the query.
+pub fn build_sql_query(model: &Ty, namespace: Option<&str>) -> String {
+    fn parse_struct(
+        namespace: Option<&str>,
+        path: &str,
+        schema: &Struct,
+        selections: &mut Vec<String>,
+        tables: &mut Vec<String>,
+    ) {
+        let table = table_name(namespace, path);
+        for member in &schema.children {
+            match &member.ty {
+                Ty::Struct(s) => {
+                    let path = format!("{path}${}", s.name);
+                    tables.push(table_name(namespace, &path));
+                    parse_struct(namespace, &path, s, selections, tables);
+                }
+                Ty::Primitive(_) | Ty::Enum(_) => selections.push(format!(
+                    "[{table}].external_{name} AS \"{path}.{name}\"",
+                    name = member.name
+                )),
+                // tuples are not stored
+                Ty::Tuple(_) => {}
+            }
+        }
+    }
+
+    let model_name = model.name();
+    let model_table = table_name(namespace, &model_name);
+    let mut selections = vec![
+        "entities.id".to_string(),
+        "entities.keys".to_string(),
+        "entities.event_id".to_string(),
+        "events.transaction_hash".to_string(),
+    ];
+    let mut tables = Vec::new();
+
+    if let Ty::Struct(s) = model {
+        parse_struct(namespace, &model_name, s, &mut selections, &mut tables);
+    }
+
+    let joins = tables
+        .iter()
+        .map(|table| {
+            format!(" LEFT JOIN [{table}] ON [{model_table}].entity_id = [{table}].entity_id")
+        })
+        .collect::<String>();
+
+    format!(
+        "SELECT {} FROM [{model_table}] JOIN entities ON entities.id = \
+         [{model_table}].entity_id{joins} LEFT JOIN events ON events.id = entities.event_id",
+        selections.join(", ")
+    )
+}
+
+/// A value bound to the predicate built by [`attribute_predicate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqlValue {
+    Integer(i64),
+    Text(String),
+}
+
+/// Builds the predicate of an attribute clause on the model `schema`, stored in `table`, to append
+/// to a query built with [`build_sql_query`], along with the value to bind to it.
+///
+/// The comparison depends on the type of the member, as stored by the indexer:
+/// * integers up to 64 bits and booleans are compared as SQL integers.
+/// * `u128` and `u256` are stored as hex strings padded to 64 digits, whose lexicographic order is
+///   their numeric order.
+/// * felts, addresses, class hashes, short strings and `i128`, whose felt encoding doesn't preserve
+///   the order of negative values, only support (in)equality.
+/// * enums only support (in)equality, with the name of an option.
+///
+/// Only the members of the model itself, not the ones of its nested structs, can be compared.
+pub fn attribute_predicate(
+    schema: &Ty,
+    table: &str,
+    clause: &AttributeClause,
+) -> Result<(String, SqlValue), QueryError> {
+    let member = schema
+        .as_struct()
+        .and_then(|s| s.children.iter().find(|m| m.name == clause.attribute))
+        .ok_or_else(|| QueryError::MemberNotFound(clause.attribute.clone()))?;
+
+    let operator = match clause.operator {
+        ComparisonOperator::Eq => "=",
+        ComparisonOperator::Neq => "!=",
+        ComparisonOperator::Gt => ">",
+        ComparisonOperator::Gte => ">=",
+        ComparisonOperator::Lt => "<",
+        ComparisonOperator::Lte => "<=",
+    };
+    let ordered = !matches!(clause.operator, ComparisonOperator::Eq | ComparisonOperator::Neq);
+
+    let column = format!("[{table}].external_{}", member.name);
+    let invalid_value = || QueryError::InvalidComparisonValue {
+        member: member.name.clone(),
+        ty: member.ty.name(),
+        value: format!("{:?}", clause.value),
+    };
+    let unsupported = || QueryError::UnsupportedComparison {
+        member: member.name.clone(),
+        ty: member.ty.name(),
+        operator,
+    };
+
+    match &member.ty {
+        Ty::Primitive(primitive) if primitive.to_sql_type() == SqlType::Integer => {
+            let value = match clause.value {
+                Value::Int(value) => value,
+                Value::UInt(value) => i64::try_from(value).map_err(|_| invalid_value())?,
+                Value::Bool(value) => value.into(),
+                Value::String(ref value) => value.parse().map_err(|_| invalid_value())?,
+                Value::Bytes(_) => return Err(invalid_value()),
+            };
+
+            // cast so that the values are never compared as text, where "10" < "2"
+            Ok((format!("CAST({column} AS INTEGER) {operator} ?"), SqlValue::Integer(value)))
+        }
+        Ty::Primitive(primitive) => {
+            if ordered && !matches!(primitive, Primitive::U128(_) | Primitive::U256(_)) {
+                return Err(unsupported());
+            }
+
+            // negative values are only valid for the signed integers and the felts
+            if matches!(clause.value, Value::Int(value) if value < 0)
+                && !matches!(primitive, Primitive::I128(_) | Primitive::Felt252(_))
+            {
+                return Err(invalid_value());
+            }
+
+            let value = match (&clause.value, primitive) {
+                (Value::String(value), Primitive::ShortString(_)) if !value.starts_with("0x") => {
+                    cairo_short_string_to_felt(value).map_err(|_| invalid_value())?
+                }
+                (Value::String(value), _) => {
+                    FieldElement::from_str(value).map_err(|_| invalid_value())?
+                }
+                (Value::Int(value), _) => {
+                    let abs = FieldElement::from(value.unsigned_abs());
+                    if *value < 0 { FieldElement::ZERO - abs } else { abs }
+                }
+                (Value::UInt(value), _) => FieldElement::from(*value),
+                (Value::Bytes(value), _) => {
+                    FieldElement::from_byte_slice_be(value).map_err(|_| invalid_value())?
+                }
+                (Value::Bool(_), _) => return Err(invalid_value()),
+            };
+
+            Ok((format!("{column} {operator} ?"), SqlValue::Text(format!("0x{value:064x}"))))
+        }
+        Ty::Enum(e) => {
+            if ordered {
+                return Err(unsupported());
+            }
+
+            match &clause.value {
+                Value::String(option) if e.options.iter().any(|o| o.name == *option) => {
+                    Ok((format!("{column} {operator} ?"), SqlValue::Text(option.clone())))
+                }
+                _ => Err(invalid_value()),
+            }
+        }
+        Ty::Struct(_) | Ty::Tuple(_) => Err(unsupported()),
     }
+}
+
+/// Populates the values of `ty` from a row returned by a query built with [`build_sql_query`].
+///
+/// `path` is the table the member is stored in and `name` the name of the member.
+///
+/// The members added to a model by an upgrade have no value for the entities set before it. They
+/// are set to their default value instead, ie. zero or the first option of an enum, and `true` is
+/// returned if any value of `ty` was defaulted.
+pub fn map_row_to_ty(path: &str, name: &str, ty: &mut Ty, row: &SqliteRow) -> Result<bool, Error> {
+    let column_name = format!("{path}.{name}");
+
+    match ty {
+        Ty::Primitive(primitive) => {
+            let felts = match primitive.to_sql_type() {
+                SqlType::Integer => {
+                    // signed integers are stored as is, convert negative values back to their
+                    // felt encoding
+                    row.try_get::<Option<i64>, &str>(&column_name)?.map(|value| {
+                        let abs = FieldElement::from(value.unsigned_abs());
+                        vec![if value < 0 { FieldElement::ZERO - abs } else { abs }]
+                    })
+                }
+                SqlType::Text if matches!(primitive, Primitive::U256(_)) => {
+                    // stored as a 32 bytes big-endian hex string, split it into its low and high
+                    // 128 bits parts
+                    match row.try_get::<Option<String>, &str>(&column_name)? {
+                        Some(value) => {
+                            let value = format!("{:0>64}", value.trim_start_matches("0x"));
+                            let (high, low) = value.split_at(32);
+                            Some(vec![
+                                FieldElement::from_hex_be(low)
+                                    .map_err(error::ParseError::FromStr)?,
+                                FieldElement::from_hex_be(high)
+                                    .map_err(error::ParseError::FromStr)?,
+                            ])
+                        }
+                        None => None,
+                    }
+                }
+                SqlType::Text => row
+                    .try_get::<Option<String>, &str>(&column_name)?
+                    .map(|value| FieldElement::from_hex_be(&value))
+                    .transpose()
+                    .map_err(error::ParseError::FromStr)?
+                    .map(|value| vec![value]),
+            };
 
-    parse_sql_model_members_impl(model, model_members_all)
+            let defaulted = felts.is_none();
+            let mut felts = felts.unwrap_or_else(|| {
+                vec![
+                    FieldElement::ZERO;
+                    if matches!(primitive, Primitive::U256(_)) { 2 } else { 1 }
+                ]
+            });
+            primitive.deserialize(&mut felts).map_err(error::ParseError::Primitive)?;
+
+            Ok(defaulted)
+        }
+
+        Ty::Enum(e) => match row.try_get::<Option<String>, &str>(&column_name)? {
+            Some(value) => {
+                e.set_option(&value).map_err(error::ParseError::Enum)?;
+                Ok(false)
+            }
+            None => {
+                e.option = Some(0);
+                Ok(true)
+            }
+        },
+
+        Ty::Struct(s) => {
+            let path = format!("{path}${}", s.name);
+            let mut defaulted = false;
+            for member in s.children.iter_mut() {
+                defaulted |= map_row_to_ty(&path, &member.name, &mut member.ty, row)?;
+            }
+
+            Ok(defaulted)
+        }
+
+        // tuples are not stored
+        Ty::Tuple(_) => Ok(false),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use dojo_types::schema::{Enum, EnumOption, Member, Struct, Ty};
+    use dojo_types::primitive::Primitive;
+    use dojo_types::schema::{
+        AttributeClause, ComparisonOperator, Enum, EnumOption, Member, Struct, Ty, Value,
+    };
+    use dojo_world::contracts::model::ModelReader;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use starknet::core::types::FieldElement;
+    use starknet::core::utils::cairo_short_string_to_felt;
 
-    use super::SqlModelMember;
-    use crate::model::parse_sql_model_members;
+    use super::{ModelIdentifier, ModelSQLReader, SqlModelMember, SqlValue};
+    use crate::error::QueryError;
+    use crate::model::{attribute_predicate, build_sql_query, parse_sql_model_members, table_name};
+    use crate::sql::Sql;
 
     #[test]
     fn parse_simple_model_members_to_ty() {
@@ -192,7 +564,7 @@ mod tests {
             ],
         });
 
-        assert_eq!(parse_sql_model_members("Position", &model_members), expected_ty);
+        assert_eq!(parse_sql_model_members("Position", &model_members).unwrap(), expected_ty);
     }
 
     #[test]
@@ -285,7 +657,7 @@ mod tests {
             ],
         });
 
-        assert_eq!(parse_sql_model_members("Position", &model_members), expected_ty);
+        assert_eq!(parse_sql_model_members("Position", &model_members).unwrap(), expected_ty);
     }
 
     #[test]
@@ -319,6 +691,303 @@ mod tests {
             }],
         });
 
-        assert_eq!(parse_sql_model_members("Moves", &model_members), expected_ty);
+        assert_eq!(parse_sql_model_members("Moves", &model_members).unwrap(), expected_ty);
+    }
+
+    #[tokio::test]
+    async fn enum_option_names_round_trip() {
+        let pool =
+            SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("../migrations").run(&pool).await.unwrap();
+
+        let options = ["Up", "Down", "Left", "Right"];
+        let moves = Ty::Struct(Struct {
+            name: "Moves".into(),
+            children: vec![
+                Member {
+                    name: "player".into(),
+                    key: true,
+                    ty: Ty::Primitive(Primitive::ContractAddress(None)),
+                },
+                Member {
+                    name: "last_direction".into(),
+                    key: false,
+                    ty: Ty::Enum(Enum {
+                        name: "Direction".into(),
+                        option: None,
+                        options: options
+                            .iter()
+                            .map(|name| EnumOption {
+                                name: name.to_string(),
+                                ty: Ty::Tuple(vec![]),
+                            })
+                            .collect(),
+                    }),
+                },
+            ],
+        });
+
+        let mut db = Sql::new(pool.clone(), FieldElement::ONE).await.unwrap();
+        db.register_model(moves.clone(), vec![FieldElement::from(8u8)], FieldElement::TWO, 1, 1)
+            .await
+            .unwrap();
+
+        let schema = ModelSQLReader::new("Moves", pool).await.unwrap().schema().await.unwrap();
+        assert_eq!(schema, moves);
+
+        // the index of the option of a value maps back to its name
+        let Some(Ty::Enum(mut direction)) =
+            schema.as_struct().unwrap().get("last_direction").cloned()
+        else {
+            panic!("`last_direction` should be an enum");
+        };
+        for (idx, name) in options.iter().enumerate() {
+            direction.option = Some(idx as u8);
+            assert_eq!(direction.option().unwrap(), *name);
+        }
+    }
+
+    #[test]
+    fn parse_model_members_with_option_to_ty() {
+        let model_members = vec![SqlModelMember {
+            id: "Inventory".into(),
+            name: "slot".into(),
+            r#type: "Option<u32>".into(),
+            key: false,
+            model_idx: 0,
+            member_idx: 0,
+            type_enum: "Enum".into(),
+            enum_options: Some("Some,None".into()),
+        }];
+
+        let expected_ty = Ty::Struct(Struct {
+            name: "Inventory".into(),
+            children: vec![dojo_types::schema::Member {
+                name: "slot".into(),
+                key: false,
+                ty: Ty::Enum(Enum {
+                    name: "Option<u32>".into(),
+                    option: None,
+                    options: vec![
+                        EnumOption {
+                            name: "Some".into(),
+                            ty: Ty::Tuple(vec![Ty::Primitive("u32".parse().unwrap())]),
+                        },
+                        EnumOption { name: "None".into(), ty: Ty::Tuple(vec![]) },
+                    ],
+                }),
+            }],
+        });
+
+        assert_eq!(parse_sql_model_members("Inventory", &model_members).unwrap(), expected_ty);
+    }
+
+    #[test]
+    fn parse_signed_model_members_to_ty() {
+        let model_members = vec![
+            SqlModelMember {
+                id: "Offset".into(),
+                name: "index".into(),
+                r#type: "i32".into(),
+                key: true,
+                model_idx: 0,
+                member_idx: 0,
+                type_enum: "Primitive".into(),
+                enum_options: None,
+            },
+            SqlModelMember {
+                id: "Offset".into(),
+                name: "value".into(),
+                r#type: "i32".into(),
+                key: false,
+                model_idx: 0,
+                member_idx: 1,
+                type_enum: "Primitive".into(),
+                enum_options: None,
+            },
+        ];
+
+        let expected_ty = Ty::Struct(Struct {
+            name: "Offset".into(),
+            children: vec![
+                Member { name: "index".into(), key: true, ty: Ty::Primitive(Primitive::I32(None)) },
+                Member {
+                    name: "value".into(),
+                    key: false,
+                    ty: Ty::Primitive(Primitive::I32(None)),
+                },
+            ],
+        });
+
+        let ty = parse_sql_model_members("Offset", &model_members).unwrap();
+        assert_eq!(ty, expected_ty);
+
+        // negative values are decoded from their felt encoding and stored as signed integers
+        let Ty::Struct(mut model) = ty else { unreachable!() };
+        let Ty::Primitive(value) = &mut model.children[1].ty else { unreachable!() };
+        value.deserialize(&mut vec![FieldElement::ZERO - FieldElement::from(42u8)]).unwrap();
+        assert_eq!(value.to_sql_value().unwrap(), "'-42'");
+        assert_eq!(value.as_i32(), Some(-42));
+    }
+
+    #[test]
+    fn build_sql_query_with_nested_struct() {
+        let model = Ty::Struct(Struct {
+            name: "Position".into(),
+            children: vec![
+                Member {
+                    name: "player".into(),
+                    key: true,
+                    ty: Ty::Primitive("ContractAddress".parse().unwrap()),
+                },
+                Member {
+                    name: "vec".into(),
+                    key: false,
+                    ty: Ty::Struct(Struct {
+                        name: "Vec2".into(),
+                        children: vec![
+                            Member {
+                                name: "x".into(),
+                                key: false,
+                                ty: Ty::Primitive("u32".parse().unwrap()),
+                            },
+                            Member {
+                                name: "y".into(),
+                                key: false,
+                                ty: Ty::Primitive("u32".parse().unwrap()),
+                            },
+                        ],
+                    }),
+                },
+            ],
+        });
+
+        assert_eq!(
+            build_sql_query(&model, None),
+            "SELECT entities.id, entities.keys, entities.event_id, events.transaction_hash, \
+             [Position].external_player AS \"Position.player\", [Position$Vec2].external_x AS \
+             \"Position$Vec2.x\", [Position$Vec2].external_y AS \"Position$Vec2.y\" FROM \
+             [Position] JOIN entities ON entities.id = [Position].entity_id LEFT JOIN \
+             [Position$Vec2] ON [Position].entity_id = [Position$Vec2].entity_id LEFT JOIN events \
+             ON events.id = entities.event_id"
+        );
+    }
+
+    #[test]
+    fn build_sql_query_in_namespace() {
+        let model = Ty::Struct(Struct {
+            name: "Position".into(),
+            children: vec![Member {
+                name: "vec".into(),
+                key: false,
+                ty: Ty::Struct(Struct {
+                    name: "Vec2".into(),
+                    children: vec![Member {
+                        name: "x".into(),
+                        key: false,
+                        ty: Ty::Primitive("u32".parse().unwrap()),
+                    }],
+                }),
+            }],
+        });
+
+        assert_eq!(table_name(Some("w1"), "Position$Vec2"), "w1_Position$Vec2");
+        assert_eq!(table_name(None, "Position$Vec2"), "Position$Vec2");
+
+        // the tables are prefixed, but not the selected columns
+        assert_eq!(
+            build_sql_query(&model, Some("w1")),
+            "SELECT entities.id, entities.keys, entities.event_id, events.transaction_hash, \
+             [w1_Position$Vec2].external_x AS \"Position$Vec2.x\" FROM [w1_Position] JOIN \
+             entities ON entities.id = [w1_Position].entity_id LEFT JOIN [w1_Position$Vec2] ON \
+             [w1_Position].entity_id = [w1_Position$Vec2].entity_id LEFT JOIN events ON events.id \
+             = entities.event_id"
+        );
+    }
+
+    #[test]
+    fn attribute_predicates_follow_the_member_type() {
+        let member = |name: &str, ty: &str| Member {
+            name: name.into(),
+            key: false,
+            ty: Ty::Primitive(ty.parse().unwrap()),
+        };
+        let model = Ty::Struct(Struct {
+            name: "Player".into(),
+            children: vec![
+                member("health", "u8"),
+                member("gold", "u128"),
+                member("owner", "ContractAddress"),
+                Member {
+                    name: "class".into(),
+                    key: false,
+                    ty: Ty::Enum(Enum {
+                        name: "Class".into(),
+                        option: None,
+                        options: vec![EnumOption { name: "Mage".into(), ty: Ty::Tuple(vec![]) }],
+                    }),
+                },
+            ],
+        });
+        let predicate = |attribute: &str, operator, value| {
+            attribute_predicate(
+                &model,
+                "Player",
+                &AttributeClause { attribute: attribute.into(), operator, value },
+            )
+        };
+
+        assert_eq!(
+            predicate("health", ComparisonOperator::Lt, Value::UInt(10)).unwrap(),
+            ("CAST([Player].external_health AS INTEGER) < ?".into(), SqlValue::Integer(10))
+        );
+        assert_eq!(
+            predicate("gold", ComparisonOperator::Gte, Value::String("0x100".into())).unwrap(),
+            ("[Player].external_gold >= ?".into(), SqlValue::Text(format!("0x{:064x}", 256)))
+        );
+        assert_eq!(
+            predicate("owner", ComparisonOperator::Eq, Value::UInt(1)).unwrap(),
+            ("[Player].external_owner = ?".into(), SqlValue::Text(format!("0x{:064x}", 1)))
+        );
+        assert_eq!(
+            predicate("class", ComparisonOperator::Neq, Value::String("Mage".into())).unwrap(),
+            ("[Player].external_class != ?".into(), SqlValue::Text("Mage".into()))
+        );
+
+        assert!(matches!(
+            predicate("owner", ComparisonOperator::Gt, Value::UInt(1)),
+            Err(QueryError::UnsupportedComparison { operator: ">", .. })
+        ));
+        assert!(matches!(
+            predicate("class", ComparisonOperator::Eq, Value::String("Warrior".into())),
+            Err(QueryError::InvalidComparisonValue { .. })
+        ));
+        assert!(matches!(
+            predicate("gold", ComparisonOperator::Gt, Value::Int(-1)),
+            Err(QueryError::InvalidComparisonValue { .. })
+        ));
+        assert!(matches!(
+            predicate("mana", ComparisonOperator::Eq, Value::UInt(1)),
+            Err(QueryError::MemberNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn model_identifiers_are_interchangeable() {
+        let selector = cairo_short_string_to_felt("Position").unwrap();
+
+        let by_name: ModelIdentifier = "Position".parse().unwrap();
+        let by_selector: ModelIdentifier = format!("{selector:#x}").parse().unwrap();
+
+        assert_eq!(by_name, ModelIdentifier::Name("Position".into()));
+        assert_eq!(by_selector, ModelIdentifier::Selector(selector));
+        assert_eq!(by_selector.name().unwrap(), "Position");
+        assert_eq!(by_name.selector().unwrap(), selector);
+
+        assert!("0xzz".parse::<ModelIdentifier>().is_err());
+
+        // 32 bytes long, so not a valid short string
+        let selector = FieldElement::from_hex_be(&format!("0x1{}", "0".repeat(62))).unwrap();
+        assert!(ModelIdentifier::Selector(selector).name().is_err());
     }
 }
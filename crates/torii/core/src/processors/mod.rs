@@ -10,6 +10,7 @@ pub mod metadata_update;
 pub mod register_model;
 pub mod store_set_record;
 pub mod store_transaction;
+pub mod writer_updated;
 
 #[async_trait]
 pub trait EventProcessor<P>
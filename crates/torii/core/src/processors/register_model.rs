@@ -2,7 +2,9 @@ use anyhow::{Error, Ok, Result};
 use async_trait::async_trait;
 use dojo_world::contracts::model::ModelReader;
 use dojo_world::contracts::world::WorldContractReader;
-use starknet::core::types::{BlockWithTxs, Event, InvokeTransactionReceipt};
+use starknet::core::types::{
+    BlockWithTxs, Event, InvokeTransaction, InvokeTransactionReceipt, Transaction,
+};
 use starknet::core::utils::parse_cairo_short_string;
 use starknet::providers::Provider;
 use tracing::info;
@@ -38,8 +40,8 @@ where
         &self,
         world: &WorldContractReader<P>,
         db: &mut Sql,
-        _block: &BlockWithTxs,
-        _invoke_receipt: &InvokeTransactionReceipt,
+        block: &BlockWithTxs,
+        invoke_receipt: &InvokeTransactionReceipt,
         _event_id: &str,
         event: &Event,
     ) -> Result<(), Error> {
@@ -56,6 +58,25 @@ where
 
         db.register_model(schema, layout, event.data[1], packed_size, unpacked_size).await?;
 
+        // the world records the caller of `register_model` as the owner of the model, the account
+        // is the contract invoked by the transaction before v1
+        let sender = block.transactions.iter().find_map(|transaction| match transaction {
+            Transaction::Invoke(InvokeTransaction::V0(transaction))
+                if transaction.transaction_hash == invoke_receipt.transaction_hash =>
+            {
+                Some(transaction.contract_address)
+            }
+            Transaction::Invoke(InvokeTransaction::V1(transaction))
+                if transaction.transaction_hash == invoke_receipt.transaction_hash =>
+            {
+                Some(transaction.sender_address)
+            }
+            _ => None,
+        });
+        if let Some(sender) = sender {
+            db.set_model_registered_by(&name, sender);
+        }
+
         Ok(())
     }
 }
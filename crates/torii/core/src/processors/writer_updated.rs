@@ -0,0 +1,61 @@
+use anyhow::{Error, Ok, Result};
+use async_trait::async_trait;
+use dojo_world::contracts::world::WorldContractReader;
+use starknet::core::types::{BlockWithTxs, Event, InvokeTransactionReceipt};
+use starknet::core::utils::parse_cairo_short_string;
+use starknet::providers::Provider;
+use starknet_crypto::FieldElement;
+use tracing::info;
+
+use super::EventProcessor;
+use crate::sql::Sql;
+
+#[derive(Default)]
+pub struct WriterUpdatedProcessor;
+
+#[async_trait]
+impl<P> EventProcessor<P> for WriterUpdatedProcessor
+where
+    P: Provider + Send + Sync,
+{
+    fn event_key(&self) -> String {
+        "WriterUpdated".to_string()
+    }
+
+    fn validate(&self, event: &Event) -> bool {
+        if event.keys.len() > 1 || event.data.len() != 3 {
+            info!(
+                "invalid keys for event {}: {}",
+                <WriterUpdatedProcessor as EventProcessor<P>>::event_key(self),
+                <WriterUpdatedProcessor as EventProcessor<P>>::event_keys_as_string(self, event),
+            );
+            return false;
+        }
+        true
+    }
+
+    async fn process(
+        &self,
+        _world: &WorldContractReader<P>,
+        db: &mut Sql,
+        _block: &BlockWithTxs,
+        _invoke_receipt: &InvokeTransactionReceipt,
+        _event_id: &str,
+        event: &Event,
+    ) -> Result<(), Error> {
+        // the model is identified by its name
+        let model = parse_cairo_short_string(&event.data[0])?;
+        let system = event.data[1];
+        let granted = event.data[2] != FieldElement::ZERO;
+
+        info!(
+            "Writer {:#x} of model {} {}",
+            system,
+            model,
+            if granted { "granted" } else { "revoked" }
+        );
+        db.set_model_writer(&model, system, granted);
+
+        Ok(())
+    }
+}
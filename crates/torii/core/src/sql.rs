@@ -4,29 +4,45 @@ use std::str::FromStr;
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use dojo_types::primitive::Primitive;
-use dojo_types::schema::Ty;
+use dojo_types::schema::{Struct, Ty};
 use dojo_world::metadata::WorldMetadata;
 use sqlx::pool::PoolConnection;
 use sqlx::{Executor, Pool, Sqlite};
 use starknet::core::types::{Event, FieldElement, InvokeTransactionV1};
-use starknet_crypto::poseidon_hash_many;
 
-use super::World;
+use super::{EntityIdScheme, World};
 use crate::model::ModelSQLReader;
 use crate::simple_broker::SimpleBroker;
 use crate::types::{Entity, Model as ModelType};
 
 pub const FELT_DELIMITER: &str = "/";
 
+/// The version of the database schema, stored in the `user_version` pragma by the migrations.
+/// Bumped by every migration that changes the schema.
+pub const SCHEMA_VERSION: i64 = 4;
+
 #[cfg(test)]
 #[path = "sql_test.rs"]
 mod test;
 
+/// Checks that the schema of the database is the one this version of Torii expects, so that
+/// reading a database created by an incompatible version fails early with a clear error.
+pub async fn verify_schema_version(pool: &Pool<Sqlite>) -> Result<(), crate::error::Error> {
+    let (found,): (i64,) = sqlx::query_as("PRAGMA user_version").fetch_one(pool).await?;
+
+    if found != SCHEMA_VERSION {
+        return Err(crate::error::Error::IncompatibleSchema { found, expected: SCHEMA_VERSION });
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct Sql {
     world_address: FieldElement,
     pool: Pool<Sqlite>,
     query_queue: Vec<String>,
+    entity_id_scheme: EntityIdScheme,
 }
 
 impl Sql {
@@ -50,7 +66,18 @@ impl Sql {
 
         tx.commit().await?;
 
-        Ok(Self { pool, world_address, query_queue: vec![] })
+        Ok(Self {
+            pool,
+            world_address,
+            query_queue: vec![],
+            entity_id_scheme: EntityIdScheme::default(),
+        })
+    }
+
+    /// Derives the ids of the indexed entities with `scheme`, which must be the one of the world.
+    pub fn with_entity_id_scheme(mut self, scheme: EntityIdScheme) -> Self {
+        self.entity_id_scheme = scheme;
+        self
     }
 
     pub async fn head(&self) -> Result<u64> {
@@ -106,6 +133,8 @@ impl Sql {
         // execute first to get created_at
         let query_result: (DateTime<Utc>,) =
             sqlx::query_as(&insert_models).fetch_one(&self.pool).await?;
+        // before the indices of the tables are created on the new columns
+        self.build_upgrade_queries(&model).await?;
         let mut model_idx = 0_usize;
         self.build_register_queries_recursive(&model, vec![model.name()], &mut model_idx);
         self.execute().await?;
@@ -131,7 +160,7 @@ impl Sql {
             return Err(anyhow!("Entity is not a struct"));
         };
 
-        let entity_id = format!("{:#x}", poseidon_hash_many(&keys));
+        let entity_id = format!("{:#x}", self.entity_id_scheme.entity_id(&keys));
         let existing: Option<(String,)> =
             sqlx::query_as("SELECT model_names FROM entities WHERE id = ?")
                 .bind(&entity_id)
@@ -185,6 +214,29 @@ impl Sql {
         ));
     }
 
+    /// Records the address of the account which sent the transaction registering `model`,
+    /// identified by its name. Only the first registration is recorded, not the upgrades.
+    pub fn set_model_registered_by(&mut self, model: &str, account: FieldElement) {
+        self.query_queue.push(format!(
+            "UPDATE models SET registered_by = '{account:#x}' WHERE id = '{model}' AND \
+             registered_by IS NULL"
+        ));
+    }
+
+    /// Grants or revokes the right of `writer` to write `model`, identified by its name.
+    pub fn set_model_writer(&mut self, model: &str, writer: FieldElement, granted: bool) {
+        self.query_queue.push(if granted {
+            format!(
+                "INSERT OR IGNORE INTO model_writers (model_id, writer) VALUES ('{model}', \
+                 '{writer:#x}')"
+            )
+        } else {
+            format!(
+                "DELETE FROM model_writers WHERE model_id = '{model}' AND writer = '{writer:#x}'"
+            )
+        });
+    }
+
     pub async fn update_metadata(
         &mut self,
         resource: &FieldElement,
@@ -273,6 +325,51 @@ impl Sql {
         self.query_queue.push(query);
     }
 
+    /// Adds the columns of the members gained by an upgraded model to its existing tables. The
+    /// entities set before the upgrade hold `NULL` in them, which are read as default values.
+    async fn build_upgrade_queries(&mut self, model: &Ty) -> Result<()> {
+        let mut tables = vec![];
+        model_tables(model, vec![model.name()], &mut tables);
+
+        for (table_id, s) in tables {
+            let columns: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info(?)")
+                .bind(&table_id)
+                .fetch_all(&self.pool)
+                .await?;
+            // new tables are created with all their columns
+            if columns.is_empty() {
+                continue;
+            }
+
+            for member in &s.children {
+                let column = format!("external_{}", member.name);
+                if columns.iter().any(|(name,)| *name == column) {
+                    continue;
+                }
+
+                // unlike when the table is created, the enum column can't be `NOT NULL` as the
+                // existing rows have no value
+                let column_type = match &member.ty {
+                    Ty::Primitive(primitive) => primitive.to_sql_type().to_string(),
+                    Ty::Enum(e) => format!(
+                        "TEXT CHECK({column} IN ({}))",
+                        e.options
+                            .iter()
+                            .map(|c| format!("'{}'", c.name))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    _ => continue,
+                };
+
+                self.query_queue
+                    .push(format!("ALTER TABLE [{table_id}] ADD COLUMN {column} {column_type}"));
+            }
+        }
+
+        Ok(())
+    }
+
     fn build_register_queries_recursive(
         &mut self,
         model: &Ty,
@@ -398,8 +495,10 @@ impl Sql {
                          (external_{name});"
                     ));
 
+                    // the names of the options, in order, so the index of the option of a value
+                    // maps back to its name
                     options = Some(format!(
-                        r#""{}""#,
+                        "'{}'",
                         e.options.iter().map(|c| c.name.clone()).collect::<Vec<_>>().join(",")
                     ));
                 }
@@ -447,7 +546,20 @@ impl Sql {
     }
 }
 
-fn felts_sql_string(felts: &[FieldElement]) -> String {
+pub fn felts_sql_string(felts: &[FieldElement]) -> String {
     felts.iter().map(|k| format!("{:#x}", k)).collect::<Vec<String>>().join(FELT_DELIMITER)
         + FELT_DELIMITER
 }
+
+/// Appends the tables storing `model` to `tables`, by id, along with the struct each one stores.
+fn model_tables<'a>(model: &'a Ty, path: Vec<String>, tables: &mut Vec<(String, &'a Struct)>) {
+    if let Ty::Struct(s) = model {
+        tables.push((path.join("$"), s));
+
+        for member in &s.children {
+            let mut path = path.clone();
+            path.push(member.ty.name());
+            model_tables(&member.ty, path, tables);
+        }
+    }
+}
@@ -6,6 +6,7 @@ mod mapping;
 mod query;
 pub mod route;
 pub mod schema;
+pub mod sdl;
 pub mod types;
 mod utils;
 
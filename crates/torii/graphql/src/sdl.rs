@@ -0,0 +1,117 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use dojo_types::primitive::Primitive;
+use dojo_types::schema::Ty;
+
+/// Generates a GraphQL schema, in the schema definition language (SDL), describing the `models`
+/// schemas. It only holds the model types, for use by external tooling, not the queries served by
+/// Torii.
+///
+/// Models and nested structs are object types, whose fields are their members:
+/// * booleans and the integers fitting in a 32 bits signed integer are the builtin `Boolean` and
+///   `Int` scalars. The other primitives, eg. felts, addresses and larger integers, are custom
+///   scalars named after their Cairo type, as in the Torii GraphQL API.
+/// * enums are GraphQL enums of their options. The values held by the options are not described, as
+///   they are not indexed.
+/// * tuples are object types with a field per element, named `_0`, `_1`...
+pub fn models_sdl<'a>(models: impl IntoIterator<Item = &'a Ty>) -> String {
+    let mut definitions = Definitions::default();
+    for model in models {
+        definitions.type_name(model);
+    }
+
+    definitions.to_sdl()
+}
+
+/// The definitions of the types used by the models, by name.
+#[derive(Debug, Default)]
+struct Definitions {
+    scalars: BTreeSet<String>,
+    enums: BTreeMap<String, Vec<String>>,
+    objects: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl Definitions {
+    /// Returns the name of the GraphQL type of `ty`, defining it if needed.
+    fn type_name(&mut self, ty: &Ty) -> String {
+        match ty {
+            Ty::Primitive(primitive) => self.scalar_name(primitive),
+            Ty::Struct(s) => {
+                let fields = s
+                    .children
+                    .iter()
+                    .map(|member| (member.name.clone(), self.type_name(&member.ty)))
+                    .collect();
+
+                let name = graphql_name(&s.name);
+                self.objects.insert(name.clone(), fields);
+                name
+            }
+            Ty::Enum(e) => {
+                let name = graphql_name(&e.name);
+                self.enums.insert(name.clone(), e.options.iter().map(|o| o.name.clone()).collect());
+                name
+            }
+            // an object type needs at least one field
+            Ty::Tuple(tys) if tys.is_empty() => {
+                self.scalars.insert("Unit".into());
+                "Unit".into()
+            }
+            Ty::Tuple(tys) => {
+                let fields = tys
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, ty)| (format!("_{idx}"), self.type_name(ty)))
+                    .collect::<Vec<_>>();
+
+                let name = format!(
+                    "Tuple_{}",
+                    fields.iter().map(|(_, ty)| ty.as_str()).collect::<Vec<_>>().join("_")
+                );
+                self.objects.insert(name.clone(), fields);
+                name
+            }
+        }
+    }
+
+    fn scalar_name(&mut self, primitive: &Primitive) -> String {
+        match primitive {
+            Primitive::Bool(_) => "Boolean".into(),
+            Primitive::U8(_)
+            | Primitive::U16(_)
+            | Primitive::I8(_)
+            | Primitive::I16(_)
+            | Primitive::I32(_) => "Int".into(),
+            _ => {
+                let name = primitive.to_string();
+                self.scalars.insert(name.clone());
+                name
+            }
+        }
+    }
+
+    fn to_sdl(&self) -> String {
+        let scalars = self.scalars.iter().map(|name| format!("scalar {name}\n"));
+
+        let enums = self.enums.iter().map(|(name, options)| {
+            let values = options.iter().map(|option| format!("  {option}\n")).collect::<String>();
+            format!("enum {name} {{\n{values}}}\n")
+        });
+
+        let objects = self.objects.iter().map(|(name, fields)| {
+            let fields =
+                fields.iter().map(|(field, ty)| format!("  {field}: {ty}!\n")).collect::<String>();
+            format!("type {name} {{\n{fields}}}\n")
+        });
+
+        scalars.chain(enums).chain(objects).collect::<Vec<_>>().join("\n")
+    }
+}
+
+/// Turns a Cairo type name into a valid GraphQL name, eg. `Option<u32>` into `Option_u32`.
+fn graphql_name(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
@@ -4,7 +4,8 @@ mod tests {
     use anyhow::Result;
     use async_graphql::dynamic::Schema;
     use serde_json::Value;
-    use starknet_crypto::{poseidon_hash_many, FieldElement};
+    use starknet_crypto::FieldElement;
+    use torii_core::EntityIdScheme;
 
     use crate::schema::build_schema;
     use crate::tests::{
@@ -181,14 +182,14 @@ mod tests {
         assert_eq!(connection.edges.len(), 0);
 
         // entity model union
-        let id = poseidon_hash_many(&[FieldElement::ZERO]);
+        let id = EntityIdScheme::default().entity_id(&[FieldElement::ZERO]);
         let entity = entity_model_query(&schema, &id).await;
         let models = entity.get("models").ok_or("no models found").unwrap();
         let record: Record = serde_json::from_value(models[0].clone()).unwrap();
         assert_eq!(&record.__typename, "Record");
         assert_eq!(record.record_id, 0);
 
-        let id = poseidon_hash_many(&[FieldElement::ZERO, FieldElement::ONE]);
+        let id = EntityIdScheme::default().entity_id(&[FieldElement::ZERO, FieldElement::ONE]);
         let entity = entity_model_query(&schema, &id).await;
         let models = entity.get("models").ok_or("no models found").unwrap();
         let subrecord: Subrecord = serde_json::from_value(models[0].clone()).unwrap();
@@ -31,6 +31,7 @@ use torii_core::sql::Sql;
 mod entities_test;
 mod metadata_test;
 mod models_test;
+mod sdl_test;
 mod subscription_test;
 
 use crate::schema::build_schema;
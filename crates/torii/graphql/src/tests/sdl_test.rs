@@ -0,0 +1,95 @@
+#[cfg(test)]
+mod tests {
+    use dojo_types::primitive::Primitive;
+    use dojo_types::schema::{Enum, EnumOption, Member, Struct, Ty};
+
+    use crate::sdl::models_sdl;
+
+    fn member(name: &str, ty: Ty) -> Member {
+        Member { name: name.into(), ty, key: false }
+    }
+
+    #[test]
+    fn test_models_sdl() {
+        let position = Ty::Struct(Struct {
+            name: "Position".into(),
+            children: vec![
+                Member {
+                    name: "player".into(),
+                    ty: Ty::Primitive(Primitive::ContractAddress(None)),
+                    key: true,
+                },
+                member(
+                    "vec",
+                    Ty::Struct(Struct {
+                        name: "Vec2".into(),
+                        children: vec![
+                            member("x", Ty::Primitive(Primitive::U32(None))),
+                            member("y", Ty::Primitive(Primitive::U8(None))),
+                        ],
+                    }),
+                ),
+            ],
+        });
+
+        let moves = Ty::Struct(Struct {
+            name: "Moves".into(),
+            children: vec![
+                member("remaining", Ty::Primitive(Primitive::U8(None))),
+                member(
+                    "last_direction",
+                    Ty::Enum(Enum {
+                        name: "Direction".into(),
+                        option: None,
+                        options: vec![
+                            EnumOption { name: "Left".into(), ty: Ty::Tuple(vec![]) },
+                            EnumOption { name: "Right".into(), ty: Ty::Tuple(vec![]) },
+                        ],
+                    }),
+                ),
+                member(
+                    "bounds",
+                    Ty::Tuple(vec![
+                        Ty::Primitive(Primitive::Bool(None)),
+                        Ty::Primitive(Primitive::Felt252(None)),
+                    ]),
+                ),
+            ],
+        });
+
+        let expected = r#"scalar ContractAddress
+
+scalar felt252
+
+scalar u32
+
+enum Direction {
+  Left
+  Right
+}
+
+type Moves {
+  remaining: Int!
+  last_direction: Direction!
+  bounds: Tuple_Boolean_felt252!
+}
+
+type Position {
+  player: ContractAddress!
+  vec: Vec2!
+}
+
+type Tuple_Boolean_felt252 {
+  _0: Boolean!
+  _1: felt252!
+}
+
+type Vec2 {
+  x: u32!
+  y: Int!
+}
+"#;
+
+        assert_eq!(models_sdl([&position, &moves]), expected);
+    }
+}
@@ -6,10 +6,11 @@ mod tests {
     use dojo_types::primitive::Primitive;
     use dojo_types::schema::{Enum, EnumOption, Member, Struct, Ty};
     use sqlx::SqlitePool;
-    use starknet_crypto::{poseidon_hash_many, FieldElement};
+    use starknet_crypto::FieldElement;
     use tokio::sync::mpsc;
     // use tokio_util::sync::CancellationToken;
     use torii_core::sql::Sql;
+    use torii_core::EntityIdScheme;
 
     use crate::tests::{model_fixtures, run_graphql_subscription};
 
@@ -23,7 +24,7 @@ mod tests {
         model_fixtures(&mut db).await;
         // 0. Preprocess expected entity value
         let key = vec![FieldElement::ONE];
-        let entity_id = format!("{:#x}", poseidon_hash_many(&key));
+        let entity_id = format!("{:#x}", EntityIdScheme::default().entity_id(&key));
         let keys_str = key.iter().map(|k| format!("{:#x}", k)).collect::<Vec<String>>().join(",");
         let expected_value: async_graphql::Value = value!({
                             "entityUpdated": { "id": entity_id, "keys":vec![keys_str], "model_names": "Moves" }
@@ -102,7 +103,7 @@ mod tests {
         model_fixtures(&mut db).await;
         // 0. Preprocess expected entity value
         let key = vec![FieldElement::ONE];
-        let entity_id = format!("{:#x}", poseidon_hash_many(&key));
+        let entity_id = format!("{:#x}", EntityIdScheme::default().entity_id(&key));
         let keys_str = key.iter().map(|k| format!("{:#x}", k)).collect::<Vec<String>>().join(",");
         let expected_value: async_graphql::Value = value!({
                                                 "entityUpdated": { "id": entity_id, "keys":vec![keys_str], "model_names": "Moves" }
@@ -11,12 +11,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         wasm_tonic_build::configure()
             .build_server(false)
             .build_client(feature_client.is_ok())
-            .compile(&["proto/world.proto"], &["proto"])?;
+            .compile(&["proto/world.proto", "proto/health.proto"], &["proto"])?;
     } else {
         tonic_build::configure()
             .build_server(feature_server.is_ok())
             .build_client(feature_client.is_ok())
-            .compile(&["proto/world.proto"], &["proto"])?;
+            .compile(&["proto/world.proto", "proto/health.proto"], &["proto"])?;
     }
     Ok(())
 }
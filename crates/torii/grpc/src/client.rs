@@ -1,13 +1,34 @@
 //! Client implementation for the gRPC service.
 
-use futures_util::stream::MapOk;
-use futures_util::{Stream, StreamExt, TryStreamExt};
-use protos::world::{world_client, SubscribeEntitiesRequest};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use dojo_types::schema::{Clause, KeysClause, Ty};
+use dojo_types::storage::EntityIdScheme;
+use futures_util::{Stream, StreamExt};
+use protos::world::{world_client, SubscribeEntitiesRequest, SubscribeMetadataRequest};
 use starknet::core::types::{FromStrError, StateUpdate};
 use starknet_crypto::FieldElement;
+#[cfg(not(target_arch = "wasm32"))]
+use tonic::codec::CompressionEncoding;
 
-use crate::protos::world::{MetadataRequest, SubscribeEntitiesResponse};
+use crate::conversion::{TyDecodeError, TyProtoBytes};
+use crate::protos::world::hydrate_response::Content;
+use crate::protos::world::subscribe_metadata_response::Update;
+use crate::protos::world::{
+    ChainInfoRequest, CloseReason, DecodeEntityRequest, ExportEntitiesRequest,
+    ExportEntitiesResponse, FindModelsRequest, GetEntityByIdRequest, GetEntityRequest,
+    HydrateCompleted, HydrateRequest, HydrateResponse, ListEntityIdsRequest, MetadataRequest,
+    MetadataResponse, RecentlyChangedEntitiesRequest, RetrieveEntitiesRequest, ServerInfoRequest,
+    SubscribeAggregateRequest, SubscribeAggregateResponse, SubscribeEntitiesResponse,
+    SubscribeMetadataResponse, ValidateSchemaRequest,
+};
 use crate::protos::{self};
+use crate::WORLD_ADDRESS_METADATA_KEY;
+
+/// The maximum size, in bytes, of a response message, matching the server default.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_DECODING_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -17,15 +38,49 @@ pub enum Error {
     MissingExpectedData,
     #[error(transparent)]
     Parsing(FromStrError),
+    #[error(transparent)]
+    Decoding(TyDecodeError),
+    #[error("{0}")]
+    UnknownEntityIdScheme(String),
 
     #[cfg(not(target_arch = "wasm32"))]
     #[error(transparent)]
     Transport(tonic::transport::Error),
 }
 
+/// Whether a call failing with a given status can be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusClass {
+    /// The server couldn't serve the call at the time, the same call can succeed later, ideally
+    /// after a backoff.
+    Retryable,
+    /// The call is invalid or can't be served by this server, retrying it fails the same way.
+    Fatal,
+}
+
+/// Classifies a status returned by the server, or ending a subscription stream, following the
+/// codes used by the server:
+/// * `unavailable` when the server shuts down or can't reach its node, `deadline_exceeded` and
+///   `aborted` are transient failures.
+/// * `resource_exhausted` when the server is busy or closed a subscription not keeping up with the
+///   updates, and `data_loss` when a subscriber missed updates. Subscribing again recovers.
+/// * all the other codes, eg. `invalid_argument`, `not_found` or `failed_precondition`, are fatal.
+///   So is `internal`, which the server reports for the failures it can't recover from.
+pub fn classify_status(status: &tonic::Status) -> StatusClass {
+    match status.code() {
+        tonic::Code::Unavailable
+        | tonic::Code::DeadlineExceeded
+        | tonic::Code::Aborted
+        | tonic::Code::ResourceExhausted
+        | tonic::Code::DataLoss => StatusClass::Retryable,
+        _ => StatusClass::Fatal,
+    }
+}
+
 /// A lightweight wrapper around the grpc client.
+#[derive(Clone)]
 pub struct WorldClient {
-    _world_address: FieldElement,
+    world_address: FieldElement,
     #[cfg(not(target_arch = "wasm32"))]
     inner: world_client::WorldClient<tonic::transport::Channel>,
     #[cfg(target_arch = "wasm32")]
@@ -34,70 +89,951 @@ pub struct WorldClient {
 
 impl WorldClient {
     #[cfg(not(target_arch = "wasm32"))]
-    pub async fn new<D>(dst: D, _world_address: FieldElement) -> Result<Self, Error>
+    pub async fn new<D>(dst: D, world_address: FieldElement) -> Result<Self, Error>
     where
         D: TryInto<tonic::transport::Endpoint>,
         D::Error: Into<Box<(dyn std::error::Error + Send + Sync + 'static)>>,
     {
-        Ok(Self {
-            _world_address,
-            inner: world_client::WorldClient::connect(dst).await.map_err(Error::Transport)?,
-        })
+        let inner = world_client::WorldClient::connect(dst)
+            .await
+            .map_err(Error::Transport)?
+            .accept_compressed(CompressionEncoding::Gzip)
+            .max_decoding_message_size(MAX_DECODING_MESSAGE_SIZE);
+
+        Ok(Self { world_address, inner })
     }
 
     // we make this function async so that we can keep the function signature similar
     #[cfg(target_arch = "wasm32")]
-    pub async fn new(endpoint: String, _world_address: FieldElement) -> Result<Self, Error> {
+    pub async fn new(endpoint: String, world_address: FieldElement) -> Result<Self, Error> {
         Ok(Self {
-            _world_address,
+            world_address,
             inner: world_client::WorldClient::new(tonic_web_wasm_client::Client::new(endpoint)),
         })
     }
 
+    /// Wraps `message` in a request targeting the world of the client, for the servers hosting
+    /// several worlds.
+    fn request<T>(&self, message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        let address = format!("{:#x}", self.world_address).parse().expect("valid metadata value");
+        request.metadata_mut().insert(WORLD_ADDRESS_METADATA_KEY, address);
+        request
+    }
+
+    /// Retrieve the versions of the server, to check that it's compatible before other calls.
+    pub async fn server_info(&mut self) -> Result<ServerInfo, Error> {
+        let info = self
+            .inner
+            .server_info(self.request(ServerInfoRequest {}))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner();
+
+        // the servers which don't send the scheme only support poseidon
+        let entity_id_scheme = match info.entity_id_scheme.as_str() {
+            "" => EntityIdScheme::default(),
+            scheme => scheme.parse().map_err(Error::UnknownEntityIdScheme)?,
+        };
+
+        Ok(ServerInfo {
+            version: info.version,
+            protocol_version: info.protocol_version,
+            schema_version: info.schema_version,
+            entity_id_scheme,
+        })
+    }
+
     /// Retrieve the metadata of the World.
     pub async fn metadata(&mut self) -> Result<dojo_types::WorldMetadata, Error> {
+        self.fetch_metadata(false).await
+    }
+
+    /// Retrieve the metadata of the world, along with the number of entities of each model.
+    pub async fn metadata_with_entity_count(&mut self) -> Result<dojo_types::WorldMetadata, Error> {
+        self.fetch_metadata(true).await
+    }
+
+    /// Retrieve the chain followed by the indexer, to send transactions to the same chain.
+    pub async fn chain_info(&mut self) -> Result<ChainInfo, Error> {
+        let res = self
+            .inner
+            .chain_info(self.request(ChainInfoRequest {}))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner();
+
+        Ok(ChainInfo {
+            chain_id: FieldElement::from_str(&res.chain_id).map_err(Error::Parsing)?,
+            rpc_url: res.rpc_url,
+        })
+    }
+
+    /// Retrieve the metadata of the world at `block_tag`. The metadata of the pending block is
+    /// only returned if the indexer indexes it, the metadata of the latest block is returned
+    /// otherwise.
+    pub async fn metadata_at(
+        &mut self,
+        block_tag: protos::types::BlockTag,
+    ) -> Result<BlockMetadata, Error> {
+        let request = MetadataRequest {
+            include_entity_count: false,
+            block_tag: block_tag as i32,
+            digest_only: false,
+        };
+        let response =
+            self.inner.world_metadata(self.request(request)).await.map_err(Error::Grpc)?;
+
+        let MetadataResponse { metadata, pending_unavailable, models_digest } =
+            response.into_inner();
+        let metadata = metadata.ok_or(Error::MissingExpectedData)?;
+        Ok(BlockMetadata {
+            metadata: metadata.try_into().map_err(Error::Decoding)?,
+            pending_unavailable,
+            models_digest: FieldElement::from_str(&models_digest).map_err(Error::Parsing)?,
+        })
+    }
+
+    /// Retrieve the digest of the models of the world, which only changes when a model is
+    /// registered or upgraded. Cheaper than retrieving the metadata, to poll for model changes.
+    pub async fn models_digest(&mut self) -> Result<FieldElement, Error> {
+        let request = MetadataRequest {
+            include_entity_count: false,
+            block_tag: protos::types::BlockTag::Latest as i32,
+            digest_only: true,
+        };
+        let response =
+            self.inner.world_metadata(self.request(request)).await.map_err(Error::Grpc)?;
+
+        FieldElement::from_str(&response.into_inner().models_digest).map_err(Error::Parsing)
+    }
+
+    async fn fetch_metadata(
+        &mut self,
+        include_entity_count: bool,
+    ) -> Result<dojo_types::WorldMetadata, Error> {
+        let request = MetadataRequest {
+            include_entity_count,
+            block_tag: protos::types::BlockTag::Latest as i32,
+            digest_only: false,
+        };
         self.inner
-            .world_metadata(MetadataRequest {})
+            .world_metadata(self.request(request))
             .await
             .map_err(Error::Grpc)
             .and_then(|res| res.into_inner().metadata.ok_or(Error::MissingExpectedData))
-            .and_then(|metadata| metadata.try_into().map_err(Error::Parsing))
+            .and_then(|metadata| metadata.try_into().map_err(Error::Decoding))
+    }
+
+    /// Find the models whose name starts with `prefix`, ignoring case.
+    pub async fn find_models(
+        &mut self,
+        prefix: impl Into<String>,
+    ) -> Result<Vec<dojo_types::schema::ModelMetadata>, Error> {
+        self.inner
+            .find_models(self.request(FindModelsRequest { prefix: prefix.into() }))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner()
+            .models
+            .into_iter()
+            .map(|model| model.try_into().map_err(Error::Decoding))
+            .collect()
+    }
+
+    /// Check whether `schema_hash` is the current schema hash of `model`. Returns the current
+    /// metadata of the model if it isn't, eg. because the model was upgraded.
+    pub async fn validate_schema(
+        &mut self,
+        model: impl Into<String>,
+        schema_hash: FieldElement,
+    ) -> Result<Option<dojo_types::schema::ModelMetadata>, Error> {
+        let request =
+            ValidateSchemaRequest { model: model.into(), schema_hash: format!("{schema_hash:#x}") };
+
+        self.inner
+            .validate_schema(self.request(request))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner()
+            .model
+            .map(|model| model.try_into().map_err(Error::Decoding))
+            .transpose()
+    }
+
+    /// Retrieve the entities of a model matching `query`, sorted by `order_by` or by the entity
+    /// ids if not set. A `limit` of 0 means no limit.
+    pub async fn retrieve_entities(
+        &mut self,
+        query: dojo_types::schema::EntityQuery,
+        limit: u32,
+        offset: u32,
+        order_by: Option<dojo_types::schema::OrderBy>,
+    ) -> Result<Vec<dojo_types::schema::Entity>, Error> {
+        self.inner
+            .retrieve_entities(self.request(RetrieveEntitiesRequest {
+                query: Some(query.into()),
+                limit,
+                offset,
+                order_by: order_by.map(|o| o.into()),
+            }))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner()
+            .entities
+            .into_iter()
+            .map(|entity| entity.try_into().map_err(Error::Decoding))
+            .collect()
+    }
+
+    /// Retrieve the entities of a model matching `query` like [`WorldClient::retrieve_entities`],
+    /// with their values packed as the world stores them, to be unpacked with
+    /// [`dojo_types::packing::unpack`] following the layout of the model, and then deserialized
+    /// following its schema. The query can't list members.
+    ///
+    /// The server derives the packed values from the indexed values of the members, so the
+    /// entities aren't read any faster than with [`WorldClient::retrieve_entities`].
+    pub async fn retrieve_entities_raw(
+        &mut self,
+        query: dojo_types::schema::EntityQuery,
+        limit: u32,
+        offset: u32,
+        order_by: Option<dojo_types::schema::OrderBy>,
+    ) -> Result<Vec<RawEntity>, Error> {
+        let felts = |values: &[String]| {
+            values.iter().map(|value| FieldElement::from_str(value)).collect::<Result<Vec<_>, _>>()
+        };
+
+        self.inner
+            .retrieve_entities_raw(self.request(RetrieveEntitiesRequest {
+                query: Some(query.into()),
+                limit,
+                offset,
+                order_by: order_by.map(|o| o.into()),
+            }))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner()
+            .entities
+            .into_iter()
+            .map(|entity| {
+                Ok(RawEntity {
+                    id: FieldElement::from_str(&entity.id)?,
+                    keys: felts(&entity.keys)?,
+                    values: felts(&entity.values)?,
+                })
+            })
+            .collect::<Result<_, FromStrError>>()
+            .map_err(Error::Parsing)
+    }
+
+    /// Retrieve the entities of a model whose last change is in `since_block` or after, sorted by
+    /// block. Polling it with the block following the last change received keeps a client roughly
+    /// in sync without subscribing. A `limit` of 0 means no limit.
+    pub async fn recently_changed_entities(
+        &mut self,
+        model: impl Into<String>,
+        since_block: u64,
+        limit: u32,
+    ) -> Result<Vec<dojo_types::schema::Entity>, Error> {
+        self.inner
+            .recently_changed_entities(self.request(RecentlyChangedEntitiesRequest {
+                model: model.into(),
+                since_block,
+                limit,
+            }))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner()
+            .entities
+            .into_iter()
+            .map(|entity| entity.try_into().map_err(Error::Decoding))
+            .collect()
+    }
+
+    /// List the ids and keys of the entities of a model, sorted by the numeric value of their id,
+    /// without decoding their values. A `limit` of 0 means no limit.
+    pub async fn list_entity_ids(
+        &mut self,
+        model: impl Into<String>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<EntityIds, Error> {
+        let res = self
+            .inner
+            .list_entity_ids(self.request(ListEntityIdsRequest {
+                model: model.into(),
+                limit,
+                offset,
+            }))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner();
+
+        let entities = res
+            .entities
+            .into_iter()
+            .map(|entity| -> Result<_, FromStrError> {
+                let id = FieldElement::from_str(&entity.id)?;
+                let keys = entity
+                    .keys
+                    .iter()
+                    .map(|key| FieldElement::from_str(key))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((id, keys))
+            })
+            .collect::<Result<_, _>>()
+            .map_err(Error::Parsing)?;
+
+        Ok(EntityIds { entities, total: res.total })
+    }
+
+    /// Export all the entities of a model, sorted by id, in batches of at most `batch_size`
+    /// entities, or the maximum allowed by the server if 0.
+    pub async fn export_entities(
+        &mut self,
+        model: impl Into<String>,
+        batch_size: u32,
+    ) -> Result<EntityExportStreaming, Error> {
+        let stream = self
+            .inner
+            .export_entities(
+                self.request(ExportEntitiesRequest { model: model.into(), batch_size }),
+            )
+            .await
+            .map_err(Error::Grpc)
+            .map(|res| res.into_inner())?;
+
+        Ok(EntityExportStreaming { stream, total: None })
+    }
+
+    /// Streams the current entities of `models`, or of all the models of the world if empty, from
+    /// a single snapshot, in batches of at most `batch_size` entities, or the maximum allowed by
+    /// the server if 0. The block of the snapshot is known once the stream ends, to subscribe to
+    /// the changes made since then. The server limits the number of hydrations running at once,
+    /// and their duration.
+    pub async fn hydrate(
+        &mut self,
+        models: Vec<String>,
+        batch_size: u32,
+    ) -> Result<HydrateStreaming, Error> {
+        let stream = self
+            .inner
+            .hydrate(self.request(HydrateRequest { models, batch_size }))
+            .await
+            .map_err(Error::Grpc)
+            .map(|res| res.into_inner())?;
+
+        Ok(HydrateStreaming { stream, completed: None })
+    }
+
+    /// Retrieve an entity of a model by its id, ie. the hash of its keys.
+    pub async fn get_entity_by_id(
+        &mut self,
+        model: impl Into<String>,
+        entity_id: FieldElement,
+    ) -> Result<dojo_types::schema::Entity, Error> {
+        self.inner
+            .get_entity_by_id(self.request(GetEntityByIdRequest {
+                model: model.into(),
+                entity_id: format!("{entity_id:#x}"),
+            }))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner()
+            .entity
+            .ok_or(Error::MissingExpectedData)?
+            .try_into()
+            .map_err(Error::Decoding)
+    }
+
+    /// Retrieve the values of several `models` of the entity with the given `keys`, read at the
+    /// same block. The models the entity doesn't have are `None`.
+    pub async fn get_entity(
+        &mut self,
+        keys: &[FieldElement],
+        models: &[&str],
+    ) -> Result<HashMap<String, Option<Ty>>, Error> {
+        let entity = self
+            .inner
+            .get_entity(self.request(GetEntityRequest {
+                keys: keys.iter().map(|key| format!("{key:#x}")).collect(),
+                models: models.iter().map(|model| model.to_string()).collect(),
+            }))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner()
+            .entity
+            .map(dojo_types::schema::Entity::try_from)
+            .transpose()
+            .map_err(Error::Decoding)?;
+
+        let mut values: HashMap<_, _> =
+            models.iter().map(|model| (model.to_string(), None)).collect();
+        for value in entity.map(|entity| entity.models).unwrap_or_default() {
+            values.insert(value.name(), Some(value));
+        }
+
+        Ok(values)
+    }
+
+    /// Decodes the raw `values` of an entity of `model`, eg. read from the world storage or from
+    /// its events, following the schema of the model on the server. The values are the serialized
+    /// keys of the entity, followed by its values packed following the layout of the model.
+    pub async fn decode_entity(
+        &mut self,
+        model: impl Into<String>,
+        values: &[FieldElement],
+    ) -> Result<Ty, Error> {
+        let model = self
+            .inner
+            .decode_entity(self.request(DecodeEntityRequest {
+                model: model.into(),
+                values: values.iter().map(|value| format!("{value:#x}")).collect(),
+            }))
+            .await
+            .map_err(Error::Grpc)?
+            .into_inner()
+            .model
+            .ok_or(Error::MissingExpectedData)?;
+
+        Ty::from_proto_bytes(&model.value).map_err(Error::Decoding)
     }
 
     /// Subscribe to the state diff for a set of entities of a World.
+    ///
+    /// If `batch_updates` is true, the updates of all the entities within a block are received as
+    /// a single message, and exactly one message is received per block. Otherwise, a message is
+    /// received for every entity that changed.
     pub async fn subscribe_entities(
         &mut self,
         queries: Vec<dojo_types::schema::EntityQuery>,
+        batch_updates: bool,
+    ) -> Result<EntityUpdateStreaming, Error> {
+        self.subscribe(SubscribeEntitiesRequest {
+            queries: queries.into_iter().map(|e| e.into()).collect(),
+            batch_updates,
+            latest_only: false,
+            max_updates_per_second: 0,
+            from_block: None,
+            to_block: None,
+            allow_full_scan: false,
+            delta_updates: false,
+        })
+        .await
+    }
+
+    /// Subscribe to the changes of a set of entities of a World, starting with their full values,
+    /// ie. the values of all their storage slots. The following updates only hold the storage
+    /// slots whose value changed since the last update, to be applied to the values received so
+    /// far.
+    pub async fn subscribe_entities_delta(
+        &mut self,
+        queries: Vec<dojo_types::schema::EntityQuery>,
+        batch_updates: bool,
+    ) -> Result<EntityUpdateStreaming, Error> {
+        self.subscribe(SubscribeEntitiesRequest {
+            queries: queries.into_iter().map(|e| e.into()).collect(),
+            batch_updates,
+            latest_only: false,
+            max_updates_per_second: 0,
+            from_block: None,
+            to_block: None,
+            allow_full_scan: false,
+            delta_updates: true,
+        })
+        .await
+    }
+
+    /// Subscribe to the state diff of all the entities of `model`, as indexed when subscribing.
+    /// The entities created afterwards aren't watched.
+    pub async fn subscribe_model_entities(
+        &mut self,
+        model: impl Into<String>,
+        batch_updates: bool,
+    ) -> Result<EntityUpdateStreaming, Error> {
+        self.subscribe(SubscribeEntitiesRequest {
+            queries: vec![protos::types::EntityQuery {
+                model: model.into(),
+                clause: Some(Clause::Keys(KeysClause { keys: vec![] }).into()),
+                members: vec![],
+            }],
+            batch_updates,
+            latest_only: false,
+            max_updates_per_second: 0,
+            from_block: None,
+            to_block: None,
+            allow_full_scan: true,
+            delta_updates: false,
+        })
+        .await
+    }
+
+    /// Subscribe to the state diff for a set of entities of a World, receiving only the latest
+    /// update of each entity when the stream isn't consumed fast enough. The superseded updates
+    /// are merged into the latest one, so no changed storage slot is missed, but the updates of
+    /// different entities are not received in block order.
+    pub async fn subscribe_latest_entities(
+        &mut self,
+        queries: Vec<dojo_types::schema::EntityQuery>,
+    ) -> Result<EntityUpdateStreaming, Error> {
+        self.subscribe(SubscribeEntitiesRequest {
+            queries: queries.into_iter().map(|e| e.into()).collect(),
+            batch_updates: false,
+            latest_only: true,
+            max_updates_per_second: 0,
+            from_block: None,
+            to_block: None,
+            allow_full_scan: false,
+            delta_updates: false,
+        })
+        .await
+    }
+
+    /// Replays the state diffs of a set of entities in the blocks from `from_block` to `to_block`
+    /// included, as received by [`WorldClient::subscribe_entities`]. The stream ends after the
+    /// updates of `to_block`. Fails with an `out_of_range` status if the blocks can't be replayed
+    /// by the server.
+    pub async fn replay_entities(
+        &mut self,
+        queries: Vec<dojo_types::schema::EntityQuery>,
+        batch_updates: bool,
+        from_block: u64,
+        to_block: u64,
     ) -> Result<EntityUpdateStreaming, Error> {
+        self.subscribe(SubscribeEntitiesRequest {
+            queries: queries.into_iter().map(|e| e.into()).collect(),
+            batch_updates,
+            latest_only: false,
+            max_updates_per_second: 0,
+            from_block: Some(from_block),
+            to_block: Some(to_block),
+            allow_full_scan: false,
+            delta_updates: false,
+        })
+        .await
+    }
+
+    /// Subscribe to the changes of the world metadata, to keep a local copy of it up to date with
+    /// [`MetadataUpdate::apply`]. If `initial_snapshot` is true, the first update is the full
+    /// metadata of the world.
+    pub async fn subscribe_metadata(
+        &mut self,
+        initial_snapshot: bool,
+    ) -> Result<MetadataUpdateStreaming, Error> {
         let stream = self
             .inner
-            .subscribe_entities(SubscribeEntitiesRequest {
-                queries: queries.into_iter().map(|e| e.into()).collect(),
-            })
+            .subscribe_metadata(self.request(SubscribeMetadataRequest { initial_snapshot }))
             .await
             .map_err(Error::Grpc)
             .map(|res| res.into_inner())?;
 
-        Ok(EntityUpdateStreaming(stream.map_ok(Box::new(|res| {
-            let update = res.entity_update.expect("qed; state update must exist");
-            TryInto::<StateUpdate>::try_into(update).expect("must able to serialize")
-        }))))
+        Ok(MetadataUpdateStreaming(stream))
     }
+
+    /// Subscribe to the aggregate of `member` over the entities of `model`, starting with its
+    /// current value, then sent each time it changes. The member is ignored when counting the
+    /// entities, and must otherwise be an unsigned integer of at most 128 bits.
+    pub async fn subscribe_aggregate(
+        &mut self,
+        model: impl Into<String>,
+        member: impl Into<String>,
+        operator: protos::types::AggregateOperator,
+    ) -> Result<AggregateStreaming, Error> {
+        let request = SubscribeAggregateRequest {
+            model: model.into(),
+            member: member.into(),
+            operator: operator as i32,
+        };
+        let stream = self
+            .inner
+            .subscribe_aggregate(self.request(request))
+            .await
+            .map_err(Error::Grpc)
+            .map(|res| res.into_inner())?;
+
+        Ok(AggregateStreaming(stream))
+    }
+
+    async fn subscribe(
+        &mut self,
+        request: SubscribeEntitiesRequest,
+    ) -> Result<EntityUpdateStreaming, Error> {
+        let models = request.queries.iter().map(|query| query.model.clone()).collect();
+        let stream = self
+            .inner
+            .subscribe_entities(self.request(request))
+            .await
+            .map_err(Error::Grpc)
+            .map(|res| res.into_inner())?;
+
+        Ok(EntityUpdateStreaming { stream, models })
+    }
+}
+
+/// A stream of entity updates. Heartbeat messages sent by the server to keep the stream alive are
+/// filtered out.
+///
+/// The stream ends when the server closes the subscription normally. It fails with a
+/// `resource_exhausted` status if the subscription was closed for not keeping up with the updates,
+/// `unavailable` if the server shut down, and `internal` if the server failed to follow the chain.
+pub struct EntityUpdateStreaming {
+    stream: tonic::Streaming<SubscribeEntitiesResponse>,
+    /// The model of each subscribed query.
+    models: Vec<String>,
+}
+
+/// The versions of a server, and the entity id scheme of its world.
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    /// The version of Torii.
+    pub version: String,
+    /// The version of the protocol of the server, compatible with this client if it's
+    /// [`crate::PROTOCOL_VERSION`].
+    pub protocol_version: u32,
+    /// The version of the schema of the indexer database.
+    pub schema_version: i64,
+    /// The hash the world derives the entity ids from the keys with, to compute the storage
+    /// addresses of the entities with.
+    pub entity_id_scheme: EntityIdScheme,
+}
+
+/// The metadata of the world at a block.
+#[derive(Debug, Clone)]
+pub struct BlockMetadata {
+    pub metadata: dojo_types::WorldMetadata,
+    /// Whether the pending block was requested but isn't indexed, the metadata only reflecting the
+    /// confirmed blocks.
+    pub pending_unavailable: bool,
+    /// The digest of the models, see [`WorldClient::models_digest`].
+    pub models_digest: FieldElement,
+}
+
+/// The chain followed by the indexer.
+#[derive(Debug, Clone)]
+pub struct ChainInfo {
+    pub chain_id: FieldElement,
+    /// The URL of the node followed by the indexer, without its credentials, if advertised.
+    pub rpc_url: Option<String>,
+}
+
+/// A page of the entities of a model.
+#[derive(Debug, Clone)]
+pub struct EntityIds {
+    /// The id and keys of the entities of the page.
+    pub entities: Vec<(FieldElement, Vec<FieldElement>)>,
+    /// The number of entities of the model.
+    pub total: u64,
 }
 
-type MappedStream = MapOk<
-    tonic::Streaming<SubscribeEntitiesResponse>,
-    Box<dyn Fn(SubscribeEntitiesResponse) -> StateUpdate + Send>,
->;
+/// An entity with the values of a model packed as the world stores them.
+#[derive(Debug, Clone)]
+pub struct RawEntity {
+    pub id: FieldElement,
+    pub keys: Vec<FieldElement>,
+    /// The `packed_size` values of the model, to unpack following its layout.
+    pub values: Vec<FieldElement>,
+}
 
-pub struct EntityUpdateStreaming(MappedStream);
+/// An update of the subscribed entities.
+#[derive(Debug, Clone)]
+pub struct EntityUpdate {
+    /// The number of the block the update comes from.
+    pub block_number: u64,
+    pub state_update: StateUpdate,
+    /// The indices, in the subscribed queries, of the queries whose entities changed, in ascending
+    /// order. A single one unless the updates are batched.
+    pub query_indices: Vec<usize>,
+    /// The model of each query of `query_indices`, so that the updates of several models can be
+    /// told apart on a single stream.
+    pub models: Vec<String>,
+}
 
 impl Stream for EntityUpdateStreaming {
-    type Item = <MappedStream as Stream>::Item;
+    type Item = Result<EntityUpdate, tonic::Status>;
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        loop {
+            let res = match futures_util::ready!(self.stream.poll_next_unpin(cx)) {
+                Some(Ok(res)) if res.heartbeat => continue,
+                Some(Ok(SubscribeEntitiesResponse { close_reason: Some(reason), .. })) => {
+                    return std::task::Poll::Ready(close_status(reason).map(Err));
+                }
+                Some(res) => res,
+                None => return std::task::Poll::Ready(None),
+            };
+
+            return std::task::Poll::Ready(Some(res.map(|res| {
+                let update = res.entity_update.expect("qed; state update must exist");
+                let block_number = update.block_number;
+                let state_update =
+                    TryInto::<StateUpdate>::try_into(update).expect("must able to serialize");
+
+                let query_indices =
+                    res.query_indices.into_iter().map(|idx| idx as usize).collect::<Vec<_>>();
+                let models = query_indices
+                    .iter()
+                    .map(|idx| self.models.get(*idx).cloned().unwrap_or_default())
+                    .collect();
+
+                EntityUpdate { block_number, state_update, query_indices, models }
+            })));
+        }
+    }
+}
+
+/// Maps the reason why the server closed a subscription to the error ending the stream, if any.
+fn close_status(reason: i32) -> Option<tonic::Status> {
+    match CloseReason::try_from(reason) {
+        Ok(CloseReason::Normal) => None,
+        Ok(CloseReason::Backpressure) => Some(tonic::Status::resource_exhausted(
+            "subscription closed, the updates weren't consumed fast enough",
+        )),
+        Ok(CloseReason::ServerShutdown) => {
+            Some(tonic::Status::unavailable("subscription closed, the server is shutting down"))
+        }
+        Ok(CloseReason::InternalError) => {
+            Some(tonic::Status::internal("subscription closed, the server missed some updates"))
+        }
+        Ok(CloseReason::RateLimited) => Some(tonic::Status::resource_exhausted(
+            "subscription closed, the entities changed faster than its rate limit",
+        )),
+        Err(_) => Some(tonic::Status::unknown(format!("subscription closed for reason {reason}"))),
+    }
+}
+
+/// A stream of the batches of entities of an export.
+///
+/// The stream ends once all the entities have been received, the number of exported entities is
+/// then given by [`EntityExportStreaming::total`].
+pub struct EntityExportStreaming {
+    stream: tonic::Streaming<ExportEntitiesResponse>,
+    total: Option<u64>,
+}
+
+impl EntityExportStreaming {
+    /// The number of exported entities, only known once the export is complete.
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+}
+
+impl Stream for EntityExportStreaming {
+    type Item = Result<Vec<dojo_types::schema::Entity>, Error>;
     fn poll_next(
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        self.0.poll_next_unpin(cx)
+        let res = match futures_util::ready!(self.stream.poll_next_unpin(cx)) {
+            Some(Ok(ExportEntitiesResponse { total: Some(total), .. })) => {
+                self.total = Some(total);
+                return std::task::Poll::Ready(None);
+            }
+            Some(res) => res,
+            None => return std::task::Poll::Ready(None),
+        };
+
+        std::task::Poll::Ready(Some(res.map_err(Error::Grpc).and_then(|res| {
+            res.entities
+                .into_iter()
+                .map(|entity| entity.try_into().map_err(Error::Decoding))
+                .collect()
+        })))
+    }
+}
+
+/// A stream of the batches of entities of a hydration, tagged with their model.
+///
+/// The stream ends once all the entities of the snapshot have been received, its last indexed
+/// block is then given by [`HydrateStreaming::block_number`].
+pub struct HydrateStreaming {
+    stream: tonic::Streaming<HydrateResponse>,
+    completed: Option<HydrateCompleted>,
+}
+
+impl HydrateStreaming {
+    /// The last indexed block of the snapshot, only known once the hydration is complete. The
+    /// changes of the entities are to be subscribed to from the next block.
+    pub fn block_number(&self) -> Option<u64> {
+        self.completed.as_ref().map(|completed| completed.block_number)
+    }
+
+    /// The number of received entities, only known once the hydration is complete.
+    pub fn total(&self) -> Option<u64> {
+        self.completed.as_ref().map(|completed| completed.total)
+    }
+}
+
+impl Stream for HydrateStreaming {
+    type Item = Result<(String, Vec<dojo_types::schema::Entity>), Error>;
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let res = match futures_util::ready!(self.stream.poll_next_unpin(cx)) {
+            Some(Ok(HydrateResponse { content: Some(Content::Completed(completed)) })) => {
+                self.completed = Some(completed);
+                return std::task::Poll::Ready(None);
+            }
+            Some(Ok(HydrateResponse { content: Some(Content::Entities(batch)) })) => Ok(batch),
+            Some(Ok(HydrateResponse { content: None })) => Err(Error::MissingExpectedData),
+            Some(Err(status)) => Err(Error::Grpc(status)),
+            None => return std::task::Poll::Ready(None),
+        };
+
+        std::task::Poll::Ready(Some(res.and_then(|batch| {
+            let entities = batch
+                .entities
+                .into_iter()
+                .map(|entity| entity.try_into().map_err(Error::Decoding))
+                .collect::<Result<_, _>>()?;
+            Ok((batch.model, entities))
+        })))
+    }
+}
+
+/// A stream of changes of the world metadata.
+pub struct MetadataUpdateStreaming(tonic::Streaming<SubscribeMetadataResponse>);
+
+/// A change of the world metadata.
+#[derive(Debug, Clone)]
+pub enum MetadataUpdate {
+    /// The full metadata of the world, without the number of entities of the models.
+    Snapshot(dojo_types::WorldMetadata),
+    ModelAdded(dojo_types::schema::ModelMetadata),
+    /// The class of a model changed, the metadata holds its new class hash and schema hash.
+    ModelUpgraded(dojo_types::schema::ModelMetadata),
+    ExecutorChanged { address: FieldElement, class_hash: FieldElement },
+}
+
+impl MetadataUpdate {
+    /// Applies the change to a local copy of the world metadata.
+    pub fn apply(self, metadata: &mut dojo_types::WorldMetadata) {
+        match self {
+            MetadataUpdate::Snapshot(snapshot) => *metadata = snapshot,
+            MetadataUpdate::ModelAdded(model) | MetadataUpdate::ModelUpgraded(model) => {
+                metadata.models.insert(model.name.clone(), model);
+            }
+            MetadataUpdate::ExecutorChanged { address, class_hash } => {
+                metadata.executor_address = Some(address);
+                metadata.executor_class_hash = Some(class_hash);
+            }
+        }
+    }
+}
+
+impl TryFrom<Update> for MetadataUpdate {
+    type Error = TyDecodeError;
+    fn try_from(value: Update) -> Result<Self, Self::Error> {
+        Ok(match value {
+            Update::Snapshot(snapshot) => MetadataUpdate::Snapshot(snapshot.try_into()?),
+            Update::ModelAdded(model) => MetadataUpdate::ModelAdded(model.try_into()?),
+            Update::ModelUpgraded(model) => MetadataUpdate::ModelUpgraded(model.try_into()?),
+            Update::ExecutorChanged(executor) => MetadataUpdate::ExecutorChanged {
+                address: FieldElement::from_str(&executor.executor_address)?,
+                class_hash: FieldElement::from_str(&executor.executor_class_hash)?,
+            },
+        })
+    }
+}
+
+impl Stream for MetadataUpdateStreaming {
+    type Item = Result<MetadataUpdate, Error>;
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let res = match futures_util::ready!(self.0.poll_next_unpin(cx)) {
+            Some(res) => res,
+            None => return std::task::Poll::Ready(None),
+        };
+
+        std::task::Poll::Ready(Some(
+            res.map_err(Error::Grpc)
+                .and_then(|res| res.update.ok_or(Error::MissingExpectedData))
+                .and_then(|update| update.try_into().map_err(Error::Decoding)),
+        ))
+    }
+}
+
+/// A stream of the values of an aggregate.
+pub struct AggregateStreaming(tonic::Streaming<SubscribeAggregateResponse>);
+
+/// The value of an aggregate once a block was indexed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregateUpdate {
+    pub block_number: u64,
+    /// The value of the aggregate, unset for the minimum and maximum of no entities.
+    pub value: Option<FieldElement>,
+}
+
+impl Stream for AggregateStreaming {
+    type Item = Result<AggregateUpdate, Error>;
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let res = match futures_util::ready!(self.0.poll_next_unpin(cx)) {
+            Some(res) => res,
+            None => return std::task::Poll::Ready(None),
+        };
+
+        std::task::Poll::Ready(Some(res.map_err(Error::Grpc).and_then(|res| {
+            Ok(AggregateUpdate {
+                block_number: res.block_number,
+                value: res
+                    .value
+                    .map(|value| FieldElement::from_str(&value))
+                    .transpose()
+                    .map_err(Error::Parsing)?,
+            })
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::{Code, Status};
+
+    use super::{classify_status, close_status, StatusClass};
+    use crate::protos::world::CloseReason;
+
+    #[test]
+    fn classify_statuses() {
+        for code in [
+            Code::Unavailable,
+            Code::DeadlineExceeded,
+            Code::Aborted,
+            Code::ResourceExhausted,
+            Code::DataLoss,
+        ] {
+            assert_eq!(classify_status(&Status::new(code, "")), StatusClass::Retryable, "{code:?}");
+        }
+
+        for code in [
+            Code::InvalidArgument,
+            Code::NotFound,
+            Code::FailedPrecondition,
+            Code::OutOfRange,
+            Code::Unimplemented,
+            Code::Internal,
+            Code::Unknown,
+            Code::PermissionDenied,
+        ] {
+            assert_eq!(classify_status(&Status::new(code, "")), StatusClass::Fatal, "{code:?}");
+        }
+    }
+
+    #[test]
+    fn classify_close_reasons() {
+        let class = |reason: CloseReason| close_status(reason as i32).map(|s| classify_status(&s));
+
+        assert_eq!(class(CloseReason::Normal), None);
+        assert_eq!(class(CloseReason::Backpressure), Some(StatusClass::Retryable));
+        assert_eq!(class(CloseReason::ServerShutdown), Some(StatusClass::Retryable));
+        assert_eq!(class(CloseReason::InternalError), Some(StatusClass::Fatal));
+        assert_eq!(class(CloseReason::RateLimited), Some(StatusClass::Retryable));
     }
 }
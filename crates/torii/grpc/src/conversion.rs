@@ -1,20 +1,176 @@
 use std::collections::HashMap;
 use std::str::FromStr;
 
+use dojo_types::primitive::{Primitive, PrimitiveError};
 use dojo_types::schema::{
-    AttributeClause, Clause, CompositeClause, EntityQuery, KeysClause, Ty, Value,
+    AttributeClause, Clause, CompositeClause, Entity, EntityQuery, Enum, EnumOption, KeysClause,
+    Member, OrderBy, OrderDirection, Struct, Ty, Value,
 };
+use prost::Message;
 use starknet::core::types::{
     ContractStorageDiffItem, FromByteSliceError, FromStrError, StateDiff, StateUpdate, StorageEntry,
 };
 use starknet_crypto::FieldElement;
 
 use crate::protos;
+use crate::protos::types::ty::TyType;
+
+/// The encoding of the schemas and entity values held by the `bytes` fields of the protos, eg.
+/// `ModelMetadata.schema`: a serialized `types.Ty` message.
+///
+/// Servers used to send them as JSON, which [`TyProtoBytes::from_proto_bytes`] still decodes.
+pub trait TyProtoBytes: Sized {
+    fn to_proto_bytes(&self) -> Vec<u8>;
+    fn from_proto_bytes(bytes: &[u8]) -> Result<Self, TyDecodeError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TyDecodeError {
+    #[error(transparent)]
+    Proto(#[from] prost::DecodeError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("missing type")]
+    MissingType,
+    #[error("unknown primitive type `{0}`")]
+    UnknownPrimitive(String),
+    #[error(transparent)]
+    FromByteSlice(#[from] FromByteSliceError),
+    #[error(transparent)]
+    Primitive(#[from] PrimitiveError),
+    #[error("enum `{0}` has no option {1}")]
+    InvalidOption(String, u32),
+    #[error(transparent)]
+    FromStr(#[from] FromStrError),
+}
+
+impl TyProtoBytes for Ty {
+    fn to_proto_bytes(&self) -> Vec<u8> {
+        protos::types::Ty::from(self.clone()).encode_to_vec()
+    }
+
+    fn from_proto_bytes(bytes: &[u8]) -> Result<Self, TyDecodeError> {
+        // a serialized `Ty` message starts with the tag of one of its fields, never with `{`
+        if bytes.first() == Some(&b'{') {
+            return Ok(serde_json::from_slice(bytes)?);
+        }
+
+        protos::types::Ty::decode(bytes)?.try_into()
+    }
+}
+
+impl From<Ty> for protos::types::Ty {
+    fn from(value: Ty) -> Self {
+        let ty_type = match value {
+            Ty::Primitive(primitive) => TyType::Primitive(protos::types::Primitive {
+                scalar_type: primitive.to_string(),
+                // fails if the value isn't set
+                value: primitive
+                    .serialize()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|felt| felt.to_bytes_be().to_vec())
+                    .collect(),
+            }),
+            Ty::Struct(s) => TyType::Struct(protos::types::Struct {
+                name: s.name,
+                children: s
+                    .children
+                    .into_iter()
+                    .map(|member| protos::types::Member {
+                        name: member.name,
+                        ty: Some(member.ty.into()),
+                        key: member.key,
+                    })
+                    .collect(),
+            }),
+            Ty::Enum(e) => TyType::Enum(protos::types::Enum {
+                name: e.name,
+                option: e.option.map(u32::from),
+                options: e
+                    .options
+                    .into_iter()
+                    .map(|option| protos::types::EnumOption {
+                        name: option.name,
+                        ty: Some(option.ty.into()),
+                    })
+                    .collect(),
+            }),
+            Ty::Tuple(tys) => TyType::Tuple(protos::types::Tuple {
+                children: tys.into_iter().map(Into::into).collect(),
+            }),
+        };
+
+        Self { ty_type: Some(ty_type) }
+    }
+}
+
+impl TryFrom<protos::types::Ty> for Ty {
+    type Error = TyDecodeError;
+    fn try_from(value: protos::types::Ty) -> Result<Self, Self::Error> {
+        // the type of a member or an enum option
+        let child = |ty: Option<protos::types::Ty>| -> Result<Ty, TyDecodeError> {
+            ty.ok_or(TyDecodeError::MissingType)?.try_into()
+        };
+
+        Ok(match value.ty_type.ok_or(TyDecodeError::MissingType)? {
+            TyType::Primitive(primitive) => {
+                let mut ty = Primitive::from_str(&primitive.scalar_type)
+                    .map_err(|_| TyDecodeError::UnknownPrimitive(primitive.scalar_type))?;
+
+                if !primitive.value.is_empty() {
+                    let mut felts = primitive
+                        .value
+                        .iter()
+                        .map(|felt| FieldElement::from_byte_slice_be(felt))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    ty.deserialize(&mut felts)?;
+                }
+
+                Ty::Primitive(ty)
+            }
+            TyType::Struct(s) => Ty::Struct(Struct {
+                name: s.name,
+                children: s
+                    .children
+                    .into_iter()
+                    .map(|member| {
+                        Ok(Member { name: member.name, ty: child(member.ty)?, key: member.key })
+                    })
+                    .collect::<Result<_, TyDecodeError>>()?,
+            }),
+            TyType::Enum(e) => {
+                let option = e
+                    .option
+                    .map(|option| {
+                        u8::try_from(option)
+                            .ok()
+                            .filter(|option| usize::from(*option) < e.options.len())
+                            .ok_or_else(|| TyDecodeError::InvalidOption(e.name.clone(), option))
+                    })
+                    .transpose()?;
+
+                Ty::Enum(Enum {
+                    name: e.name,
+                    option,
+                    options: e
+                        .options
+                        .into_iter()
+                        .map(|option| Ok(EnumOption { name: option.name, ty: child(option.ty)? }))
+                        .collect::<Result<_, TyDecodeError>>()?,
+                })
+            }
+            TyType::Tuple(tuple) => {
+                Ty::Tuple(tuple.children.into_iter().map(Ty::try_from).collect::<Result<_, _>>()?)
+            }
+        })
+    }
+}
 
 impl TryFrom<protos::types::ModelMetadata> for dojo_types::schema::ModelMetadata {
-    type Error = FromStrError;
+    type Error = TyDecodeError;
     fn try_from(value: protos::types::ModelMetadata) -> Result<Self, Self::Error> {
-        let schema: Ty = serde_json::from_slice(&value.schema).unwrap();
+        let schema = Ty::from_proto_bytes(&value.schema)?;
         let layout: Vec<FieldElement> = value.layout.into_iter().map(FieldElement::from).collect();
         Ok(Self {
             schema,
@@ -23,12 +179,35 @@ impl TryFrom<protos::types::ModelMetadata> for dojo_types::schema::ModelMetadata
             packed_size: value.packed_size,
             unpacked_size: value.unpacked_size,
             class_hash: FieldElement::from_str(&value.class_hash)?,
+            schema_hash: FieldElement::from_str(&value.schema_hash)?,
+            entity_count: value.entity_count,
+            writers: value
+                .writers
+                .iter()
+                .map(|writer| FieldElement::from_str(writer))
+                .collect::<Result<_, _>>()?,
+            registered_by: value
+                .registered_by
+                .map(|address| FieldElement::from_str(&address))
+                .transpose()?,
         })
     }
 }
 
+impl From<dojo_types::schema::MemberOffset> for protos::types::MemberOffset {
+    fn from(value: dojo_types::schema::MemberOffset) -> Self {
+        Self {
+            name: value.name,
+            offset: value.offset,
+            width: value.width,
+            bit_offset: value.bit_offset,
+            bits: value.bits,
+        }
+    }
+}
+
 impl TryFrom<protos::types::WorldMetadata> for dojo_types::WorldMetadata {
-    type Error = FromStrError;
+    type Error = TyDecodeError;
     fn try_from(value: protos::types::WorldMetadata) -> Result<Self, Self::Error> {
         let models = value
             .models
@@ -40,15 +219,59 @@ impl TryFrom<protos::types::WorldMetadata> for dojo_types::WorldMetadata {
             models,
             world_address: FieldElement::from_str(&value.world_address)?,
             world_class_hash: FieldElement::from_str(&value.world_class_hash)?,
-            executor_address: FieldElement::from_str(&value.executor_address)?,
-            executor_class_hash: FieldElement::from_str(&value.executor_class_hash)?,
+            executor_address: value
+                .executor_address
+                .map(|address| FieldElement::from_str(&address))
+                .transpose()?,
+            executor_class_hash: value
+                .executor_class_hash
+                .map(|class_hash| FieldElement::from_str(&class_hash))
+                .transpose()?,
+            stale: value.stale,
+            // not part of the metadata, see `ServerInfo::entity_id_scheme`
+            entity_id_scheme: Default::default(),
+        })
+    }
+}
+
+impl TryFrom<protos::types::Entity> for Entity {
+    type Error = TyDecodeError;
+    fn try_from(value: protos::types::Entity) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: FieldElement::from_str(&value.id)?,
+            keys: value
+                .keys
+                .iter()
+                .map(|k| FieldElement::from_str(k))
+                .collect::<Result<Vec<_>, _>>()?,
+            models: value
+                .models
+                .into_iter()
+                .map(|model| Ty::from_proto_bytes(&model.value))
+                .collect::<Result<_, _>>()?,
+            last_updated_block: value.last_updated_block,
+            last_updated_transaction: value
+                .last_updated_transaction
+                .map(|hash| FieldElement::from_str(&hash))
+                .transpose()?,
         })
     }
 }
 
+impl From<OrderBy> for protos::types::OrderBy {
+    fn from(value: OrderBy) -> Self {
+        let direction = match value.direction {
+            OrderDirection::Asc => protos::types::OrderDirection::Asc,
+            OrderDirection::Desc => protos::types::OrderDirection::Desc,
+        };
+
+        Self { member: value.member, direction: direction as i32 }
+    }
+}
+
 impl From<EntityQuery> for protos::types::EntityQuery {
     fn from(value: EntityQuery) -> Self {
-        Self { model: value.model, clause: Some(value.clause.into()) }
+        Self { model: value.model, clause: Some(value.clause.into()), members: value.members }
     }
 }
 
@@ -74,14 +297,32 @@ impl From<KeysClause> for protos::types::KeysClause {
     }
 }
 
+/// A key of a keys clause which isn't a valid felt.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid key {index} `0x{}`: {source}", hex::encode(.bytes))]
+pub struct KeyDecodeError {
+    /// The position of the key in the clause.
+    pub index: usize,
+    /// The big-endian bytes of the key, as received.
+    pub bytes: Vec<u8>,
+    pub source: FromByteSliceError,
+}
+
 impl TryFrom<protos::types::KeysClause> for KeysClause {
-    type Error = FromByteSliceError;
+    type Error = KeyDecodeError;
 
     fn try_from(value: protos::types::KeysClause) -> Result<Self, Self::Error> {
         let keys = value
             .keys
             .into_iter()
-            .map(|k| FieldElement::from_byte_slice_be(&k))
+            .enumerate()
+            .map(|(index, bytes)| {
+                FieldElement::from_byte_slice_be(&bytes).map_err(|source| KeyDecodeError {
+                    index,
+                    bytes,
+                    source,
+                })
+            })
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(Self { keys })
@@ -182,3 +423,114 @@ impl TryFrom<protos::types::EntityUpdate> for StateUpdate {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use dojo_types::primitive::Primitive;
+    use dojo_types::schema::{Enum, EnumOption, KeysClause, Member, Struct, Ty};
+    use starknet_crypto::FieldElement;
+
+    use super::{KeyDecodeError, TyDecodeError, TyProtoBytes};
+    use crate::protos;
+
+    fn player(name: Option<FieldElement>, direction: Option<u8>) -> Ty {
+        Ty::Struct(Struct {
+            name: "Player".into(),
+            children: vec![
+                Member {
+                    name: "name".into(),
+                    key: true,
+                    ty: Ty::Primitive(Primitive::Felt252(name)),
+                },
+                Member {
+                    name: "balance".into(),
+                    key: false,
+                    ty: Ty::Primitive(Primitive::U256(None)),
+                },
+                Member {
+                    name: "direction".into(),
+                    key: false,
+                    ty: Ty::Enum(Enum {
+                        name: "Direction".into(),
+                        option: direction,
+                        options: vec![
+                            EnumOption { name: "Left".into(), ty: Ty::Tuple(vec![]) },
+                            EnumOption { name: "Right".into(), ty: Ty::Tuple(vec![]) },
+                        ],
+                    }),
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn ty_proto_bytes_round_trip() {
+        for ty in [player(None, None), player(Some(FieldElement::TWO), Some(1))] {
+            assert_eq!(Ty::from_proto_bytes(&ty.to_proto_bytes()).unwrap(), ty);
+        }
+
+        let mut entity = player(Some(FieldElement::ONE), Some(0));
+        let Ty::Struct(s) = &mut entity else { unreachable!() };
+        s.children[1]
+            .ty
+            .deserialize(&mut vec![FieldElement::from(5u8), FieldElement::ONE])
+            .unwrap();
+        assert_eq!(Ty::from_proto_bytes(&entity.to_proto_bytes()).unwrap(), entity);
+    }
+
+    #[test]
+    fn legacy_json_schemas_are_decoded() {
+        let ty = player(Some(FieldElement::ONE), Some(1));
+        let json = serde_json::to_vec(&ty).unwrap();
+        assert_eq!(Ty::from_proto_bytes(&json).unwrap(), ty);
+    }
+
+    #[test]
+    fn invalid_enum_option_is_rejected() {
+        let bytes = player(None, Some(2)).to_proto_bytes();
+        assert!(Ty::from_proto_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn malformed_model_schema_is_an_error() {
+        let model = protos::types::ModelMetadata {
+            name: "Player".into(),
+            class_hash: "0x1".into(),
+            schema_hash: "0x1".into(),
+            schema: vec![0xff, 0xff],
+            ..Default::default()
+        };
+
+        let err = dojo_types::schema::ModelMetadata::try_from(model).unwrap_err();
+        assert!(matches!(err, TyDecodeError::Proto(_)));
+    }
+
+    #[test]
+    fn malformed_entity_value_is_an_error() {
+        let entity = protos::types::Entity {
+            id: "0x1".into(),
+            models: vec![protos::types::Model {
+                name: "Player".into(),
+                value: vec![0xff, 0xff],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let err = dojo_types::schema::Entity::try_from(entity).unwrap_err();
+        assert!(matches!(err, TyDecodeError::Proto(_)));
+    }
+
+    #[test]
+    fn invalid_key_is_located() {
+        // a 256 bits hash truncated to 32 bytes but not reduced to a felt
+        let truncated = vec![0xff; 32];
+        let clause = protos::types::KeysClause {
+            keys: vec![FieldElement::ONE.to_bytes_be().to_vec(), truncated.clone(), vec![0x2]],
+        };
+
+        let err = KeysClause::try_from(clause).unwrap_err();
+        assert!(matches!(err, KeyDecodeError { index: 1, ref bytes, .. } if *bytes == truncated));
+        assert!(err.to_string().starts_with(&format!("invalid key 1 `0x{}`: ", "ff".repeat(32))));
+    }
+}
@@ -4,6 +4,7 @@ extern crate wasm_prost as prost;
 extern crate wasm_tonic as tonic;
 
 pub mod conversion;
+pub mod query;
 
 #[cfg(feature = "client")]
 pub mod client;
@@ -11,6 +12,17 @@ pub mod client;
 #[cfg(feature = "server")]
 pub mod server;
 
+#[cfg(feature = "testing")]
+pub mod testing;
+
+/// The request metadata key selecting the world a request targets, on servers hosting several
+/// worlds.
+pub const WORLD_ADDRESS_METADATA_KEY: &str = "world-address";
+
+/// The version of the protocol of the world service, bumped on breaking changes of its messages or
+/// of their meaning. Advertised by the servers in their server info.
+pub const PROTOCOL_VERSION: u32 = 1;
+
 pub mod protos {
     pub mod world {
         tonic::include_proto!("world");
@@ -18,4 +30,7 @@ pub mod protos {
     pub mod types {
         tonic::include_proto!("types");
     }
+    pub mod health {
+        tonic::include_proto!("grpc.health.v1");
+    }
 }
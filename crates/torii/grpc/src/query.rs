@@ -0,0 +1,114 @@
+use dojo_types::schema::{
+    AttributeClause, Clause, ComparisonOperator, EntityQuery, KeysClause, Value,
+};
+use starknet_crypto::FieldElement;
+
+use crate::protos;
+
+/// Builds an entity query, without having to assemble the nested clause types by hand.
+///
+/// ```
+/// use starknet_crypto::FieldElement;
+/// use torii_grpc::query::QueryBuilder;
+///
+/// let query = QueryBuilder::new("Position").keys(&[FieldElement::ONE]).members(&["x"]).build();
+/// assert_eq!(query.model, "Position");
+/// ```
+///
+/// The query matches all the entities of the model unless a clause is set. Setting a clause
+/// replaces the previous one.
+#[derive(Debug, Clone)]
+pub struct QueryBuilder {
+    model: String,
+    clause: Clause,
+    members: Vec<String>,
+}
+
+impl QueryBuilder {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            clause: Clause::Keys(KeysClause { keys: vec![] }),
+            members: vec![],
+        }
+    }
+
+    /// Matches the entities whose keys start with `keys`.
+    pub fn keys(mut self, keys: &[FieldElement]) -> Self {
+        self.clause = Clause::Keys(KeysClause { keys: keys.to_vec() });
+        self
+    }
+
+    /// Matches the entities whose member `attribute` compares to `value` with `operator`.
+    pub fn attribute(
+        mut self,
+        attribute: impl Into<String>,
+        operator: ComparisonOperator,
+        value: Value,
+    ) -> Self {
+        self.clause =
+            Clause::Attribute(AttributeClause { attribute: attribute.into(), operator, value });
+        self
+    }
+
+    /// Only returns the given members of the model, along with its key members.
+    pub fn members(mut self, members: &[&str]) -> Self {
+        self.members = members.iter().map(|member| member.to_string()).collect();
+        self
+    }
+
+    /// Builds the query, as expected by the client methods.
+    pub fn query(self) -> EntityQuery {
+        EntityQuery { model: self.model, clause: self.clause, members: self.members }
+    }
+
+    /// Builds the query, as sent over the wire.
+    pub fn build(self) -> protos::types::EntityQuery {
+        self.query().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dojo_types::schema::{ComparisonOperator, Value};
+    use starknet_crypto::FieldElement;
+
+    use super::QueryBuilder;
+    use crate::protos::types::clause::ClauseType;
+    use crate::protos::types::{AttributeClause, KeysClause};
+
+    #[test]
+    fn build_keys_query() {
+        let query = QueryBuilder::new("Position")
+            .keys(&[FieldElement::ONE, FieldElement::from(0x100u32)])
+            .members(&["x", "y"])
+            .build();
+
+        assert_eq!(query.model, "Position");
+        assert_eq!(query.members, vec!["x", "y"]);
+
+        let Some(ClauseType::Keys(KeysClause { keys })) = query.clause.unwrap().clause_type else {
+            panic!("expected a keys clause");
+        };
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0][31], 1);
+        assert_eq!(&keys[1][30..], &[1, 0]);
+    }
+
+    #[test]
+    fn build_attribute_query() {
+        let query = QueryBuilder::new("Moves")
+            .keys(&[FieldElement::ONE])
+            .attribute("remaining", ComparisonOperator::Gt, Value::UInt(3))
+            .build();
+
+        let Some(ClauseType::Attribute(AttributeClause { attribute, operator, value })) =
+            query.clause.unwrap().clause_type
+        else {
+            panic!("expected an attribute clause");
+        };
+        assert_eq!(attribute, "remaining");
+        assert_eq!(operator, ComparisonOperator::Gt as i32);
+        assert_eq!(value, Some(Value::UInt(3).into()));
+    }
+}
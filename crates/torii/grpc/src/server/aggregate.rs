@@ -0,0 +1,416 @@
+//! Running aggregates of a member of the entities of a model, streamed to the subscribers as the
+//! entities change.
+//!
+//! The sums are computed exactly: the members are unsigned integers of at most 128 bits, so the sum
+//! of the members of fewer than 2^64 entities stays below 2^192, which is far from the felt prime.
+//! The sums thus never overflow, and are sent as felts.
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use dojo_types::primitive::Primitive;
+use dojo_types::schema::Ty;
+use parking_lot::Mutex;
+use sqlx::sqlite::SqliteConnection;
+use starknet_crypto::FieldElement;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::watch;
+use tonic::Status;
+use torii_core::error::{Error, QueryError};
+use torii_core::model::{map_row_to_ty, ModelIdentifier};
+
+use super::DojoWorld;
+use crate::protos::types::AggregateOperator;
+use crate::protos::world::SubscribeAggregateResponse;
+
+/// The aggregate of a member over the entities of a model, updated as the entities change.
+#[derive(Debug)]
+struct Aggregate {
+    operator: AggregateOperator,
+    /// The value of the member of each entity, by entity id.
+    values: HashMap<String, u128>,
+    /// The number of entities holding each value, to find the minimum and maximum once the
+    /// entities holding them change.
+    counts: BTreeMap<u128, usize>,
+    sum: FieldElement,
+}
+
+impl Aggregate {
+    fn new(operator: AggregateOperator) -> Self {
+        Self { operator, values: HashMap::new(), counts: BTreeMap::new(), sum: FieldElement::ZERO }
+    }
+
+    /// Sets the value of the member of an entity, replacing its previous value.
+    fn set(&mut self, entity_id: String, value: u128) {
+        if let Some(previous) = self.values.insert(entity_id, value) {
+            self.sum -= FieldElement::from(previous);
+            if let Some(count) = self.counts.get_mut(&previous) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&previous);
+                }
+            }
+        }
+
+        self.sum += FieldElement::from(value);
+        *self.counts.entry(value).or_default() += 1;
+    }
+
+    /// The value of the aggregate, unset for the minimum and maximum of no entities.
+    fn value(&self) -> Option<FieldElement> {
+        match self.operator {
+            AggregateOperator::Sum => Some(self.sum),
+            AggregateOperator::Min => self.counts.keys().next().map(|v| FieldElement::from(*v)),
+            AggregateOperator::Max => {
+                self.counts.keys().next_back().map(|v| FieldElement::from(*v))
+            }
+            AggregateOperator::Count => Some(FieldElement::from(self.values.len())),
+        }
+    }
+}
+
+/// Whether `primitive` is an unsigned integer of at most 128 bits, the only members which can be
+/// aggregated.
+fn is_aggregatable(primitive: &Primitive) -> bool {
+    matches!(
+        primitive,
+        Primitive::U8(_)
+            | Primitive::U16(_)
+            | Primitive::U32(_)
+            | Primitive::U64(_)
+            | Primitive::U128(_)
+            | Primitive::USize(_)
+    )
+}
+
+/// The value of an aggregatable primitive, see [`is_aggregatable`].
+fn unsigned_value(primitive: &Primitive) -> Option<u128> {
+    match primitive {
+        Primitive::U8(value) => value.map(u128::from),
+        Primitive::U16(value) => value.map(u128::from),
+        Primitive::U32(value) | Primitive::USize(value) => value.map(u128::from),
+        Primitive::U64(value) => value.map(u128::from),
+        Primitive::U128(value) => *value,
+        _ => None,
+    }
+}
+
+/// The entities and member read from a model table to update an aggregate.
+struct AggregatedMember {
+    model: String,
+    table: String,
+    /// The aggregated member, `None` when counting the entities.
+    member: Option<(String, Ty)>,
+}
+
+impl AggregatedMember {
+    /// Reads the values of the member of the entities changed in the blocks after `from` up to
+    /// `to`, or of all the entities if `from` is `None`. The entities counted have a value of 0.
+    async fn read(
+        &self,
+        conn: &mut SqliteConnection,
+        from: Option<u64>,
+        to: u64,
+    ) -> Result<Vec<(String, u128)>, Error> {
+        let table = &self.table;
+        let column = match &self.member {
+            Some((name, _)) => format!("[{table}].external_{name} AS \"{}.{name}\"", self.model),
+            None => "NULL".to_string(),
+        };
+
+        let mut sql = format!(
+            "SELECT [{table}].entity_id, {column} FROM [{table}] JOIN entities ON entities.id = \
+             [{table}].entity_id"
+        );
+        // the ids of the events start with their zero-padded block number, the ids not built by
+        // the indexer don't have their `:` separator after the 64 digits of the block number
+        if from.is_some() {
+            sql.push_str(
+                " WHERE instr(entities.event_id, ':') = 67 AND entities.event_id >= ? AND \
+                 entities.event_id < ?",
+            );
+        }
+
+        let mut query = sqlx::query(&sql);
+        if let Some(from) = from {
+            query = query.bind(format!("0x{:064x}", from + 1)).bind(format!("0x{:064x}", to + 1));
+        }
+
+        query
+            .fetch_all(conn)
+            .await?
+            .iter()
+            .map(|row| {
+                let entity_id = sqlx::Row::try_get(row, 0)?;
+                let Some((name, ty)) = &self.member else {
+                    return Ok((entity_id, 0));
+                };
+
+                let mut ty = ty.clone();
+                map_row_to_ty(&self.model, name, &mut ty, row)?;
+                let value = match &ty {
+                    Ty::Primitive(primitive) => unsigned_value(primitive),
+                    _ => None,
+                };
+                Ok((entity_id, value.unwrap_or_default()))
+            })
+            .collect()
+    }
+}
+
+/// An update of an aggregate sent to its subscribers.
+type AggregateUpdate = Result<SubscribeAggregateResponse, Status>;
+
+/// The model, member and operator identifying an aggregate. The member is empty for `COUNT`.
+type AggregateKey = (String, String, AggregateOperator);
+
+/// The aggregates being subscribed to, each maintained once for all its subscribers. An aggregate
+/// is dropped along with its last subscriber.
+pub(super) type SharedAggregates =
+    Mutex<HashMap<AggregateKey, Arc<watch::Sender<AggregateUpdate>>>>;
+
+/// Removes the aggregate of `key` maintained with `sender` from the `aggregates`, unless it is
+/// `unused_only` and still has subscribers. Returns whether it was removed.
+fn remove_aggregate(
+    aggregates: &SharedAggregates,
+    key: &AggregateKey,
+    sender: &Arc<watch::Sender<AggregateUpdate>>,
+    unused_only: bool,
+) -> bool {
+    // the subscribers subscribe while the aggregates are locked
+    let mut aggregates = aggregates.lock();
+    if unused_only && sender.receiver_count() > 0 {
+        return false;
+    }
+
+    if aggregates.get(key).is_some_and(|shared| Arc::ptr_eq(shared, sender)) {
+        aggregates.remove(key);
+    }
+    true
+}
+
+/// Sends the updates of a shared aggregate to one of its subscribers, starting with its current
+/// value.
+async fn forward_aggregate(
+    mut updates: watch::Receiver<AggregateUpdate>,
+    sender: Sender<AggregateUpdate>,
+) {
+    loop {
+        let update = updates.borrow_and_update().clone();
+        let failed = update.is_err();
+        if sender.send(update).await.is_err() || failed {
+            return;
+        }
+
+        tokio::select! {
+            _ = sender.closed() => return,
+            changed = updates.changed() => if changed.is_err() {
+                return;
+            },
+        }
+    }
+}
+
+impl DojoWorld {
+    /// Subscribes to the aggregate of `member` over the entities of `model`, starting with its
+    /// current value. The member, which is ignored when counting the entities, must be an unsigned
+    /// integer of at most 128 bits. The subscribers of the same aggregate share it.
+    ///
+    /// The aggregate is updated incrementally from the entities changed in each indexed block, and
+    /// sent when its value changes. As the indexer sends the blocks to the server before processing
+    /// them, the changes of a block are only aggregated once the next block is received. The
+    /// deletions of entities aren't indexed, so the deleted entities are still aggregated, and
+    /// counted, with their last value.
+    pub(super) async fn subscribe_aggregate(
+        &self,
+        model: &str,
+        member: &str,
+        operator: AggregateOperator,
+    ) -> Result<Receiver<AggregateUpdate>, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // make sure the model exists before querying its table
+        let (model,): (String,) = sqlx::query_as("SELECT name FROM models WHERE id = ?")
+            .bind(model.parse::<ModelIdentifier>()?.name()?)
+            .fetch_one(&mut tx)
+            .await?;
+        self.check_exposed(&model)?;
+
+        let member = if operator == AggregateOperator::Count { "" } else { member };
+        let key = (model.clone(), member.to_string(), operator);
+
+        let shared = self.aggregates.lock().get(&key).map(|shared| shared.subscribe());
+        let updates = match shared {
+            Some(updates) => updates,
+            None => {
+                let (aggregated, aggregate, head) =
+                    self.read_aggregate(&mut tx, model, member, operator).await?;
+                let (sender, updates) = watch::channel(Ok(SubscribeAggregateResponse {
+                    block_number: head,
+                    value: aggregate.value().map(|value| format!("{value:#x}")),
+                }));
+
+                // the aggregate may have been started by another subscriber meanwhile
+                let mut aggregates = self.aggregates.lock();
+                match aggregates.entry(key.clone()) {
+                    Entry::Occupied(shared) => shared.get().subscribe(),
+                    Entry::Vacant(entry) => {
+                        let sender = Arc::new(sender);
+                        entry.insert(Arc::clone(&sender));
+                        tokio::spawn(
+                            self.clone()
+                                .maintain_aggregate(key, sender, aggregated, aggregate, head),
+                        );
+                        updates
+                    }
+                }
+            }
+        };
+        tx.commit().await?;
+
+        let (sender, receiver) = channel(16);
+        tokio::spawn(forward_aggregate(updates, sender));
+        Ok(receiver)
+    }
+
+    /// Reads the aggregate of `member` over the entities of `model` at the indexed head, which is
+    /// returned along with it.
+    async fn read_aggregate(
+        &self,
+        conn: &mut SqliteConnection,
+        model: String,
+        member: &str,
+        operator: AggregateOperator,
+    ) -> Result<(AggregatedMember, Aggregate, u64), Error> {
+        let member = if operator == AggregateOperator::Count {
+            None
+        } else {
+            let schema = self.model_schema(conn, &ModelIdentifier::Name(model.clone())).await?;
+            let ty = schema
+                .as_struct()
+                .and_then(|s| s.children.iter().find(|m| m.name == member))
+                .map(|m| m.ty.clone())
+                .ok_or_else(|| QueryError::MemberNotFound(member.into()))?;
+
+            if !matches!(&ty, Ty::Primitive(primitive) if is_aggregatable(primitive)) {
+                return Err(QueryError::NotAggregatable(member.into(), ty.name()).into());
+            }
+            Some((member.to_string(), ty))
+        };
+
+        let aggregated = AggregatedMember { table: self.table_name(&model), model, member };
+
+        let head: Option<(i64,)> = sqlx::query_as("SELECT head FROM indexers WHERE id = ?")
+            .bind(format!("{:#x}", self.world_address))
+            .fetch_optional(&mut *conn)
+            .await?;
+        let head = head.map_or(0, |(head,)| head as u64);
+
+        let mut aggregate = Aggregate::new(operator);
+        for (entity_id, value) in aggregated.read(conn, None, head).await? {
+            aggregate.set(entity_id, value);
+        }
+
+        Ok((aggregated, aggregate, head))
+    }
+
+    /// Updates the shared aggregate of `key`, read at the block `head`, until it has no
+    /// subscribers left.
+    async fn maintain_aggregate(
+        self,
+        key: AggregateKey,
+        sender: Arc<watch::Sender<AggregateUpdate>>,
+        aggregated: AggregatedMember,
+        mut aggregate: Aggregate,
+        mut head: u64,
+    ) {
+        let mut processed_blocks = self.processed_blocks.clone();
+        let mut value = aggregate.value();
+        loop {
+            tokio::select! {
+                _ = sender.closed() => if remove_aggregate(&self.aggregates, &key, &sender, true) {
+                    return;
+                },
+                changed = processed_blocks.changed() => if changed.is_err() {
+                    remove_aggregate(&self.aggregates, &key, &sender, false);
+                    return;
+                },
+            }
+
+            // the entities changed in the blocks indexed since the last update
+            let changes = async {
+                let mut tx = self.pool.begin().await?;
+                let (indexed,): (i64,) = sqlx::query_as("SELECT head FROM indexers WHERE id = ?")
+                    .bind(format!("{:#x}", self.world_address))
+                    .fetch_one(&mut tx)
+                    .await?;
+
+                let indexed = indexed as u64;
+                if indexed <= head {
+                    return Ok(None);
+                }
+
+                let changes = aggregated.read(&mut tx, Some(head), indexed).await?;
+                Ok::<_, Error>(Some((indexed, changes)))
+            };
+
+            let (indexed, changes) = match changes.await {
+                Ok(Some(changes)) => changes,
+                Ok(None) => continue,
+                Err(e) => {
+                    // the next subscribers start the aggregate again
+                    remove_aggregate(&self.aggregates, &key, &sender, false);
+                    sender.send_replace(Err(Status::internal(e.to_string())));
+                    return;
+                }
+            };
+
+            head = indexed;
+            for (entity_id, value) in changes {
+                aggregate.set(entity_id, value);
+            }
+
+            if aggregate.value() != value {
+                value = aggregate.value();
+                sender.send_replace(Ok(SubscribeAggregateResponse {
+                    block_number: head,
+                    value: value.map(|value| format!("{value:#x}")),
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet_crypto::FieldElement;
+
+    use super::Aggregate;
+    use crate::protos::types::AggregateOperator;
+
+    #[test]
+    fn aggregates_are_updated_incrementally() {
+        let aggregate = |operator| {
+            let mut aggregate = Aggregate::new(operator);
+            aggregate.set("0x1".into(), 5);
+            aggregate.set("0x2".into(), 3);
+            aggregate.set("0x3".into(), 3);
+            // the entity holding the maximum changes
+            aggregate.set("0x1".into(), 1);
+            aggregate.set("0x4".into(), u128::MAX);
+            aggregate.set("0x5".into(), u128::MAX);
+            aggregate.set("0x5".into(), 4);
+            aggregate.value()
+        };
+
+        let sum = FieldElement::from(u128::MAX) + FieldElement::from(11u8);
+        assert_eq!(aggregate(AggregateOperator::Sum), Some(sum));
+        assert_eq!(aggregate(AggregateOperator::Min), Some(FieldElement::ONE));
+        assert_eq!(aggregate(AggregateOperator::Max), Some(FieldElement::from(u128::MAX)));
+        assert_eq!(aggregate(AggregateOperator::Count), Some(FieldElement::from(5u8)));
+
+        assert_eq!(Aggregate::new(AggregateOperator::Min).value(), None);
+        assert_eq!(Aggregate::new(AggregateOperator::Sum).value(), Some(FieldElement::ZERO));
+    }
+}
@@ -0,0 +1,184 @@
+//! Changelog of the storage of the world, from which the entity changes of the last indexed
+//! blocks are replayed.
+//!
+//! The storage entries written by a block are recorded once its state diff is fetched by the
+//! subscription service, so the blocks indexed while the server isn't running can't be replayed.
+
+use std::ops::RangeInclusive;
+
+use sqlx::{Pool, Sqlite};
+use starknet::core::types::StorageEntry;
+use starknet_crypto::FieldElement;
+use torii_core::error::{Error, ParseError};
+
+/// The storage entries of the world written by a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDiff {
+    pub block_number: u64,
+    pub block_hash: FieldElement,
+    pub storage_entries: Vec<StorageEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Changelog {
+    pool: Pool<Sqlite>,
+    world_address: String,
+    /// The number of the last recorded blocks which are kept.
+    retention_blocks: u64,
+}
+
+impl Changelog {
+    pub fn new(pool: Pool<Sqlite>, world_address: FieldElement, retention_blocks: u64) -> Self {
+        Self { pool, world_address: format!("{world_address:#x}"), retention_blocks }
+    }
+
+    /// Records the storage entries of the world written by a block, and drops the blocks which
+    /// are out of retention.
+    pub async fn record(&self, diff: &BlockDiff) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO changelog_blocks (world_address, block_number, block_hash) \
+             VALUES (?, ?, ?)",
+        )
+        .bind(&self.world_address)
+        .bind(diff.block_number as i64)
+        .bind(format!("{:#x}", diff.block_hash))
+        .execute(&mut tx)
+        .await?;
+
+        for entry in &diff.storage_entries {
+            sqlx::query(
+                "INSERT OR REPLACE INTO changelog_entries (world_address, block_number, \
+                 storage_key, storage_value) VALUES (?, ?, ?, ?)",
+            )
+            .bind(&self.world_address)
+            .bind(diff.block_number as i64)
+            .bind(format!("{:#x}", entry.key))
+            .bind(format!("{:#x}", entry.value))
+            .execute(&mut tx)
+            .await?;
+        }
+
+        let oldest = (diff.block_number + 1).saturating_sub(self.retention_blocks);
+        for table in ["changelog_blocks", "changelog_entries"] {
+            sqlx::query(&format!(
+                "DELETE FROM {table} WHERE world_address = ? AND block_number < ?"
+            ))
+            .bind(&self.world_address)
+            .bind(oldest as i64)
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns the range of the recorded blocks, or `None` if no block was recorded yet.
+    pub async fn blocks(&self) -> Result<Option<RangeInclusive<u64>>, Error> {
+        let (first, last): (Option<i64>, Option<i64>) = sqlx::query_as(
+            "SELECT MIN(block_number), MAX(block_number) FROM changelog_blocks WHERE \
+             world_address = ?",
+        )
+        .bind(&self.world_address)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(first.zip(last).map(|(first, last)| first as u64..=last as u64))
+    }
+
+    /// Returns the recorded diffs of the blocks of `blocks`, in order. The blocks which weren't
+    /// recorded are missing.
+    pub async fn diffs(&self, blocks: &RangeInclusive<u64>) -> Result<Vec<BlockDiff>, Error> {
+        let block_rows: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT block_number, block_hash FROM changelog_blocks WHERE world_address = ? AND \
+             block_number BETWEEN ? AND ? ORDER BY block_number",
+        )
+        .bind(&self.world_address)
+        .bind(*blocks.start() as i64)
+        .bind(*blocks.end() as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let entry_rows: Vec<(i64, String, String)> = sqlx::query_as(
+            "SELECT block_number, storage_key, storage_value FROM changelog_entries WHERE \
+             world_address = ? AND block_number BETWEEN ? AND ? ORDER BY block_number, rowid",
+        )
+        .bind(&self.world_address)
+        .bind(*blocks.start() as i64)
+        .bind(*blocks.end() as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut diffs = block_rows
+            .into_iter()
+            .map(|(block_number, block_hash)| {
+                Ok(BlockDiff {
+                    block_number: block_number as u64,
+                    block_hash: parse_felt(&block_hash)?,
+                    storage_entries: vec![],
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        for (block_number, key, value) in entry_rows {
+            let block_number = block_number as u64;
+            // the entries are only recorded along with their block
+            let Some(diff) = diffs.iter_mut().find(|diff| diff.block_number == block_number) else {
+                continue;
+            };
+            diff.storage_entries
+                .push(StorageEntry { key: parse_felt(&key)?, value: parse_felt(&value)? });
+        }
+
+        Ok(diffs)
+    }
+}
+
+fn parse_felt(felt: &str) -> Result<FieldElement, Error> {
+    Ok(FieldElement::from_hex_be(felt).map_err(ParseError::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+    use starknet::core::types::StorageEntry;
+    use starknet_crypto::FieldElement;
+
+    use super::{BlockDiff, Changelog};
+
+    fn diff(block_number: u64, entries: &[(u64, u64)]) -> BlockDiff {
+        BlockDiff {
+            block_number,
+            block_hash: FieldElement::from(block_number + 1000),
+            storage_entries: entries
+                .iter()
+                .map(|(key, value)| StorageEntry {
+                    key: FieldElement::from(*key),
+                    value: FieldElement::from(*value),
+                })
+                .collect(),
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn recorded_blocks_are_kept_for_the_retention(pool: SqlitePool) {
+        let changelog = Changelog::new(pool.clone(), FieldElement::ONE, 3);
+        assert_eq!(changelog.blocks().await.unwrap(), None);
+
+        for block in [diff(1, &[(1, 1)]), diff(2, &[]), diff(3, &[(1, 2), (2, 1)]), diff(4, &[])] {
+            changelog.record(&block).await.unwrap();
+        }
+
+        assert_eq!(changelog.blocks().await.unwrap(), Some(2..=4));
+        assert_eq!(
+            changelog.diffs(&(1..=3)).await.unwrap(),
+            vec![diff(2, &[]), diff(3, &[(1, 2), (2, 1)])]
+        );
+
+        // the changelogs of the other worlds are separate
+        let other = Changelog::new(pool, FieldElement::TWO, 3);
+        assert_eq!(other.blocks().await.unwrap(), None);
+    }
+}
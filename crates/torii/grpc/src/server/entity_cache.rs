@@ -0,0 +1,310 @@
+//! Cache of the entities read by id, for the clients polling the same entities over and over.
+//!
+//! An entry is dropped once the indexer commits a block whose state diff, fetched by the
+//! subscription service, writes one of the storage slots of the entity. The state diff of a block
+//! is usually fetched before the block is committed, the entities read in between being cached
+//! with their previous value until the block is committed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use sqlx::{Pool, Sqlite};
+use starknet::macros::short_string;
+use starknet_crypto::{poseidon_hash_many, FieldElement};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::warn;
+
+use crate::protos;
+
+/// How often the indexed head is read while waiting for a block to be committed.
+const INDEXED_HEAD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of a block, and the storage addresses of the world written by the block.
+pub(super) type BlockWrites = (u64, Vec<FieldElement>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct EntityCacheConfig {
+    /// The maximum number of cached entities, the least recently read ones being evicted first.
+    pub capacity: usize,
+    /// How long an entity is cached if none of its storage slots is written.
+    pub ttl: Duration,
+}
+
+/// The name of the model of a cached entity, and the id of the entity.
+type EntityKey = (String, FieldElement);
+
+struct CachedEntity {
+    /// The class hash of the model when the entity was read.
+    class_hash: String,
+    entity: Option<protos::types::Entity>,
+    expires_at: Instant,
+    /// The tick of the last read of the entity, its key in `Entries::recency`.
+    last_read: u64,
+    storage_addresses: Vec<FieldElement>,
+}
+
+#[derive(Default)]
+struct Entries {
+    entities: HashMap<EntityKey, CachedEntity>,
+    /// The cached entities by last read, the least recent first.
+    recency: BTreeMap<u64, EntityKey>,
+    /// The entity whose value is stored at each storage address.
+    addresses: HashMap<FieldElement, EntityKey>,
+    tick: u64,
+    /// The number of invalidations so far.
+    generation: u64,
+}
+
+impl Entries {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, key: &EntityKey) {
+        if let Some(cached) = self.entities.remove(key) {
+            self.recency.remove(&cached.last_read);
+            for address in &cached.storage_addresses {
+                self.addresses.remove(address);
+            }
+        }
+    }
+}
+
+/// A bounded cache of the values of entities, by model and entity id. The entities without a
+/// value for the model are cached as well.
+pub struct EntityCache {
+    config: EntityCacheConfig,
+    entries: Mutex<Entries>,
+}
+
+impl EntityCache {
+    pub fn new(config: EntityCacheConfig) -> Self {
+        Self { config, entries: Default::default() }
+    }
+
+    /// The number of invalidations so far, to pass to [`EntityCache::insert`] along with an entity
+    /// read afterwards.
+    pub fn generation(&self) -> u64 {
+        self.entries.lock().generation
+    }
+
+    /// Returns the cached value of the entity `id` of `model`, read when the class hash of the
+    /// model was `class_hash`. `Some(None)` if the entity was cached without a value.
+    pub fn get(
+        &self,
+        model: &str,
+        class_hash: &str,
+        id: FieldElement,
+    ) -> Option<Option<protos::types::Entity>> {
+        let mut entries = self.entries.lock();
+        let key = (model.to_string(), id);
+
+        let cached = entries.entities.get(&key)?;
+        if cached.class_hash != class_hash || cached.expires_at <= Instant::now() {
+            entries.remove(&key);
+            return None;
+        }
+
+        let tick = entries.next_tick();
+        let cached = entries.entities.get_mut(&key).expect("qed; checked above");
+        let previous = std::mem::replace(&mut cached.last_read, tick);
+        let entity = cached.entity.clone();
+        entries.recency.remove(&previous);
+        entries.recency.insert(tick, key);
+
+        Some(entity)
+    }
+
+    /// Caches the value of the entity `id` of `model`, whose selector is `selector` and whose
+    /// values are packed in `packed_size` storage slots. The entity isn't cached if an
+    /// invalidation happened since `generation` was returned, as it may have been read before the
+    /// change.
+    #[allow(clippy::too_many_arguments)]
+    pub fn insert(
+        &self,
+        model: &str,
+        class_hash: &str,
+        selector: FieldElement,
+        packed_size: u32,
+        id: FieldElement,
+        entity: Option<protos::types::Entity>,
+        generation: u64,
+    ) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        let base = poseidon_hash_many(&[short_string!("dojo_storage"), selector, id]);
+        let storage_addresses = (0..packed_size).map(|i| base + i.into()).collect::<Vec<_>>();
+
+        let mut entries = self.entries.lock();
+        if entries.generation != generation {
+            return;
+        }
+
+        let key = (model.to_string(), id);
+        entries.remove(&key);
+        while entries.entities.len() >= self.config.capacity {
+            let Some((_, least_recent)) = entries.recency.pop_first() else { break };
+            entries.remove(&least_recent);
+        }
+
+        let tick = entries.next_tick();
+        for address in &storage_addresses {
+            entries.addresses.insert(*address, key.clone());
+        }
+        entries.recency.insert(tick, key.clone());
+        entries.entities.insert(
+            key,
+            CachedEntity {
+                class_hash: class_hash.to_string(),
+                entity,
+                expires_at: Instant::now() + self.config.ttl,
+                last_read: tick,
+                storage_addresses,
+            },
+        );
+    }
+
+    /// Removes the entities one of the storage slots of which is at one of `storage_addresses`.
+    /// The entities being read aren't cached afterwards, even if they weren't cached yet.
+    pub fn invalidate<'a>(&self, storage_addresses: impl IntoIterator<Item = &'a FieldElement>) {
+        let mut entries = self.entries.lock();
+        entries.generation += 1;
+
+        for address in storage_addresses {
+            if let Some(key) = entries.addresses.get(address).cloned() {
+                entries.remove(&key);
+            }
+        }
+    }
+
+    /// Invalidates the storage addresses written by each block of `blocks`, in order, once the
+    /// indexer committed the block, ie. once the indexed head of the world reached it.
+    pub(super) async fn invalidate_indexed(
+        self: Arc<Self>,
+        pool: Pool<Sqlite>,
+        world_address: FieldElement,
+        mut blocks: UnboundedReceiver<BlockWrites>,
+    ) {
+        let world_address = format!("{world_address:#x}");
+        while let Some((block_number, storage_addresses)) = blocks.recv().await {
+            loop {
+                let head: Result<Option<(i64,)>, _> =
+                    sqlx::query_as("SELECT head FROM indexers WHERE id = ?")
+                        .bind(&world_address)
+                        .fetch_optional(&pool)
+                        .await;
+                match head {
+                    Ok(Some((head,))) if head as u64 >= block_number => break,
+                    Ok(_) => {}
+                    Err(e) => warn!(target: "grpc", "failed to read the indexed head: {e}"),
+                }
+                tokio::time::sleep(INDEXED_HEAD_POLL_INTERVAL).await;
+            }
+
+            self.invalidate(&storage_addresses);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use sqlx::SqlitePool;
+    use starknet::macros::{felt, short_string};
+    use starknet_crypto::{poseidon_hash_many, FieldElement};
+
+    use super::{EntityCache, EntityCacheConfig};
+    use crate::protos;
+
+    fn entity(id: FieldElement) -> Option<protos::types::Entity> {
+        Some(protos::types::Entity { id: format!("{id:#x}"), ..Default::default() })
+    }
+
+    fn cache(capacity: usize) -> EntityCache {
+        EntityCache::new(EntityCacheConfig { capacity, ttl: Duration::from_secs(60) })
+    }
+
+    fn insert(cache: &EntityCache, id: FieldElement) {
+        let generation = cache.generation();
+        cache.insert("Position", "0x1", felt!("0x10"), 2, id, entity(id), generation);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_read_entity() {
+        let cache = cache(2);
+        insert(&cache, felt!("0x1"));
+        insert(&cache, felt!("0x2"));
+
+        assert!(cache.get("Position", "0x1", felt!("0x1")).is_some());
+        insert(&cache, felt!("0x3"));
+
+        assert!(cache.get("Position", "0x1", felt!("0x1")).is_some());
+        assert!(cache.get("Position", "0x1", felt!("0x2")).is_none());
+        assert!(cache.get("Position", "0x1", felt!("0x3")).is_some());
+
+        // the entities of an upgraded model are read again
+        assert!(cache.get("Position", "0x2", felt!("0x1")).is_none());
+        assert!(cache.get("Position", "0x1", felt!("0x1")).is_none());
+    }
+
+    #[test]
+    fn invalidates_the_written_entities() {
+        let cache = cache(16);
+        insert(&cache, felt!("0x1"));
+        insert(&cache, felt!("0x2"));
+
+        // the second slot of the first entity is written
+        let base =
+            poseidon_hash_many(&[short_string!("dojo_storage"), felt!("0x10"), felt!("0x1")]);
+        cache.invalidate(&[base + FieldElement::ONE]);
+
+        assert!(cache.get("Position", "0x1", felt!("0x1")).is_none());
+        assert!(cache.get("Position", "0x1", felt!("0x2")).is_some());
+
+        // an entity read before an invalidation isn't cached
+        let generation = cache.generation();
+        cache.invalidate(std::iter::empty());
+        cache.insert("Position", "0x1", felt!("0x10"), 2, felt!("0x1"), None, generation);
+        assert!(cache.get("Position", "0x1", felt!("0x1")).is_none());
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn invalidates_the_entities_once_their_block_is_indexed(pool: SqlitePool) {
+        sqlx::query("INSERT INTO indexers (id, head) VALUES ('0x1', 2)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let cache = Arc::new(cache(16));
+        insert(&cache, felt!("0x1"));
+        let (blocks, blocks_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(Arc::clone(&cache).invalidate_indexed(
+            pool.clone(),
+            FieldElement::ONE,
+            blocks_rx,
+        ));
+
+        // the state diff of the third block is fetched before the block is committed
+        let base =
+            poseidon_hash_many(&[short_string!("dojo_storage"), felt!("0x10"), felt!("0x1")]);
+        blocks.send((3, vec![base])).unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(cache.get("Position", "0x1", felt!("0x1")).is_some());
+
+        sqlx::query("UPDATE indexers SET head = 3").execute(&pool).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while cache.get("Position", "0x1", felt!("0x1")).is_some() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
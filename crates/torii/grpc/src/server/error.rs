@@ -7,4 +7,9 @@ pub enum SubscriptionError {
     Parse(#[from] ParseError),
     #[error(transparent)]
     Provider(ProviderError),
+    #[error(
+        "the values of the entities were read at block {read_at}, before the published block \
+         {published}"
+    )]
+    OutdatedValues { read_at: u64, published: u64 },
 }
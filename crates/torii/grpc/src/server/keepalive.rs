@@ -0,0 +1,58 @@
+//! Keepalive of the connections of the gRPC clients.
+//!
+//! Load balancers and reverse proxies close the connections on which nothing was sent for a while,
+//! which drops the idle subscription streams. The HTTP/2 pings sent by the server count as
+//! activity for most of them, and detect the connections dropped without being closed. The
+//! interval must thus be shorter than the idle timeout of the proxies in front of the server:
+//!
+//! | Proxy                         | Idle timeout  | Recommended interval |
+//! |-------------------------------|---------------|----------------------|
+//! | AWS Application Load Balancer | 60s           | 20s to 30s           |
+//! | Google Cloud Load Balancer    | 30s (backend) | 10s to 20s           |
+//! | nginx (`grpc_read_timeout`)   | 60s           | 20s to 30s           |
+//! | Cloudflare                    | 100s          | 30s to 60s           |
+//!
+//! The TCP keepalive probes reset the idle timeout of the network load balancers and NATs, eg. the
+//! AWS Network Load Balancer (350s), even if the HTTP/2 pings are disabled.
+//!
+//! Clients behind strict HTTP/2 implementations may close the connection with a `too_many_pings`
+//! error when pinged more often than every 10 seconds, so shorter intervals are not recommended.
+
+use std::time::Duration;
+
+use tonic::transport::Server;
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAliveConfig {
+    /// The interval at which HTTP/2 pings are sent on the connections. Pings are not sent if
+    /// `None`.
+    pub http2_keepalive_interval: Option<Duration>,
+    /// How long to wait for the acknowledgement of a ping before closing the connection. Only
+    /// used if `http2_keepalive_interval` is set.
+    pub http2_keepalive_timeout: Duration,
+    /// The interval of the TCP keepalive probes of the connections. The probes are not sent if
+    /// `None`.
+    pub tcp_keepalive: Option<Duration>,
+}
+
+impl Default for KeepAliveConfig {
+    /// Keeps the subscriptions alive behind the proxies with an idle timeout of 30 seconds or
+    /// more.
+    fn default() -> Self {
+        Self {
+            http2_keepalive_interval: Some(Duration::from_secs(20)),
+            http2_keepalive_timeout: Duration::from_secs(20),
+            tcp_keepalive: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+impl KeepAliveConfig {
+    /// Configures the keepalive of the connections accepted by `server`.
+    pub fn apply(&self, server: Server) -> Server {
+        server
+            .http2_keepalive_interval(self.http2_keepalive_interval)
+            .http2_keepalive_timeout(Some(self.http2_keepalive_timeout))
+            .tcp_keepalive(self.tcp_keepalive)
+    }
+}
@@ -1,9 +1,12 @@
+use std::fmt::Debug;
+use std::future::Future;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use hyper::Body;
 use tonic::body::BoxBody;
 use tower::{Layer, Service};
-use tracing::info;
+use tracing::{info, warn};
 
 #[derive(Debug, Clone, Default)]
 pub struct Logger<S> {
@@ -47,3 +50,79 @@ where
         })
     }
 }
+
+/// Awaits `fut`, which runs the SQL `query` bound to `params`, and logs the query at warn level if
+/// it took longer than `threshold`.
+pub async fn log_slow_query<F: Future>(
+    threshold: Duration,
+    query: &str,
+    params: impl Debug,
+    fut: F,
+) -> F::Output {
+    let start = Instant::now();
+    let output = fut.await;
+
+    let elapsed = start.elapsed();
+    if elapsed > threshold {
+        warn!(target: "grpc", ?elapsed, ?params, "slow query: {query}");
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::log_slow_query;
+
+    /// Collects the logged lines.
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Logs {
+        fn lines(&self) -> Vec<String> {
+            let logs = self.0.lock().unwrap();
+            String::from_utf8_lossy(&logs).lines().map(String::from).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_queries_are_logged() {
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber =
+            tracing_subscriber::fmt().with_writer(move || writer.clone()).with_ansi(false).finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let fast = async { 1 };
+        let output = log_slow_query(Duration::from_secs(60), "SELECT 1", (), fast).await;
+        assert_eq!(output, 1);
+        assert!(logs.lines().is_empty());
+
+        let slow = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            2
+        };
+        let output = log_slow_query(Duration::from_millis(10), "SELECT ?", ["0x1"], slow).await;
+        assert_eq!(output, 2);
+
+        let lines = logs.lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("WARN"));
+        assert!(lines[0].contains("params=[\"0x1\"]"));
+        assert!(lines[0].contains("slow query: SELECT ?"));
+    }
+}
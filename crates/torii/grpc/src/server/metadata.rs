@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::sync::watch;
+use tonic::Status;
+use torii_core::error::Error;
+use torii_core::model::ModelIdentifier;
+use tracing::warn;
+
+use super::{fetch_world, DojoWorld};
+use crate::protos;
+use crate::protos::world::subscribe_metadata_response::Update;
+use crate::protos::world::SubscribeMetadataResponse;
+
+/// The parts of the world metadata whose changes are streamed: the class hash of each model, by
+/// model name, and the address and class hash of the executor.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct MetadataState {
+    models: BTreeMap<String, String>,
+    executor: (Option<String>, Option<String>),
+}
+
+#[derive(Debug, PartialEq)]
+enum MetadataChange<'a> {
+    ModelAdded(&'a str),
+    ModelUpgraded(&'a str),
+    ExecutorChanged { address: &'a str, class_hash: &'a str },
+}
+
+impl MetadataState {
+    /// Returns the changes from the `previous` state, models ordered by name.
+    fn changes<'a>(&'a self, previous: &Self) -> Vec<MetadataChange<'a>> {
+        let mut changes = self
+            .models
+            .iter()
+            .filter_map(|(name, class_hash)| match previous.models.get(name) {
+                None => Some(MetadataChange::ModelAdded(name)),
+                Some(previous) if previous != class_hash => {
+                    Some(MetadataChange::ModelUpgraded(name))
+                }
+                Some(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        // the executor can't be unset once it has been set
+        if let (Some(address), class_hash) = &self.executor {
+            if self.executor != previous.executor {
+                changes.push(MetadataChange::ExecutorChanged {
+                    address,
+                    class_hash: class_hash.as_deref().unwrap_or_default(),
+                });
+            }
+        }
+
+        changes
+    }
+}
+
+impl DojoWorld {
+    async fn metadata_state(&self) -> Result<MetadataState, Error> {
+        let (_, _, executor_address, executor_class_hash) =
+            fetch_world(&self.pool, self.world_address).await?;
+
+        let models: Vec<(String, String)> =
+            sqlx::query_as("SELECT name, class_hash FROM models").fetch_all(&self.pool).await?;
+
+        Ok(MetadataState {
+            models: models.into_iter().filter(|(name, _)| self.is_exposed(name)).collect(),
+            executor: (executor_address, executor_class_hash),
+        })
+    }
+
+    async fn metadata_update(&self, change: MetadataChange<'_>) -> Result<Update, Error> {
+        Ok(match change {
+            MetadataChange::ModelAdded(name) => {
+                Update::ModelAdded(self.model_metadata(&ModelIdentifier::Name(name.into())).await?)
+            }
+            MetadataChange::ModelUpgraded(name) => Update::ModelUpgraded(
+                self.model_metadata(&ModelIdentifier::Name(name.into())).await?,
+            ),
+            MetadataChange::ExecutorChanged { address, class_hash } => {
+                Update::ExecutorChanged(protos::world::ExecutorChanged {
+                    executor_address: address.into(),
+                    executor_class_hash: class_hash.into(),
+                })
+            }
+        })
+    }
+
+    /// Broadcasts the changes of the world metadata to the metadata subscribers, looking for them
+    /// after every block processed by the indexer. Runs until the blocks stop being processed.
+    pub(super) async fn watch_metadata(self, mut processed_blocks: watch::Receiver<u64>) {
+        let mut state = None;
+
+        loop {
+            match self.metadata_state().await {
+                Ok(current) => {
+                    for change in state.as_ref().map_or(vec![], |state| current.changes(state)) {
+                        match self.metadata_update(change).await {
+                            Ok(update) => {
+                                // fails if no one is subscribed, which is fine
+                                let _ = self.metadata_updates.send(update);
+                            }
+                            Err(e) => {
+                                warn!(target: "grpc", "failed to build a metadata update: {e}")
+                            }
+                        }
+                    }
+
+                    state = Some(current);
+                }
+                // eg. the world hasn't been indexed yet
+                Err(e) => warn!(target: "grpc", "failed to fetch the world metadata: {e}"),
+            }
+
+            if processed_blocks.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Subscribes to the changes of the world metadata, starting with a snapshot of the whole
+    /// metadata if `initial_snapshot` is set. The stream fails if the subscriber doesn't keep up
+    /// with the changes, as it can't be kept in sync anymore.
+    pub(super) async fn subscribe_metadata(
+        &self,
+        initial_snapshot: bool,
+    ) -> Result<Receiver<Result<SubscribeMetadataResponse, Status>>, Error> {
+        // subscribe before taking the snapshot, so that no change is missed in between
+        let mut updates = self.metadata_updates.subscribe();
+        let snapshot = if initial_snapshot { Some(self.metadata(false).await?) } else { None };
+
+        let (sender, receiver) = channel(16);
+        tokio::spawn(async move {
+            if let Some(snapshot) = snapshot {
+                let resp = SubscribeMetadataResponse { update: Some(Update::Snapshot(snapshot)) };
+                if sender.send(Ok(resp)).await.is_err() {
+                    return;
+                }
+            }
+
+            loop {
+                let update = tokio::select! {
+                    _ = sender.closed() => return,
+                    update = updates.recv() => update,
+                };
+
+                let (resp, lagged) = match update {
+                    Ok(update) => (Ok(SubscribeMetadataResponse { update: Some(update) }), false),
+                    Err(RecvError::Lagged(missed)) => (
+                        Err(Status::data_loss(format!(
+                            "missed {missed} metadata changes, subscribe again with a snapshot"
+                        ))),
+                        true,
+                    ),
+                    Err(RecvError::Closed) => return,
+                };
+
+                if sender.send(resp).await.is_err() || lagged {
+                    return;
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{MetadataChange, MetadataState};
+
+    #[test]
+    fn metadata_changes() {
+        let state = |models: &[(&str, &str)], executor: Option<&str>| MetadataState {
+            models: models.iter().map(|(n, c)| (n.to_string(), c.to_string())).collect(),
+            executor: (executor.map(Into::into), executor.map(|_| "0xe".into())),
+        };
+
+        let previous = state(&[("Moves", "0x1"), ("Position", "0x2")], None);
+        assert_eq!(previous.changes(&previous), vec![]);
+        assert_eq!(
+            previous.changes(&MetadataState { models: BTreeMap::new(), ..previous.clone() }),
+            vec![MetadataChange::ModelAdded("Moves"), MetadataChange::ModelAdded("Position")]
+        );
+
+        let current =
+            state(&[("Moves", "0x1"), ("Position", "0x3"), ("Health", "0x4")], Some("0x5"));
+        assert_eq!(
+            current.changes(&previous),
+            vec![
+                MetadataChange::ModelAdded("Health"),
+                MetadataChange::ModelUpgraded("Position"),
+                MetadataChange::ExecutorChanged { address: "0x5", class_hash: "0xe" },
+            ]
+        );
+    }
+}
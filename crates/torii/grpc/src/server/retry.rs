@@ -0,0 +1,81 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::sleep;
+use tracing::warn;
+
+/// Retries the calls failing transiently, eg. the requests to a flaky node, with an exponential
+/// backoff.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts of a call, including the first one.
+    pub max_attempts: NonZeroU32,
+    /// The delay before the first retry, doubled for every following retry.
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_attempts: NonZeroU32::new(5).unwrap(), base_delay: Duration::from_millis(200) }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retrying a call which failed `attempt` times. A random jitter of up to
+    /// half the delay is taken off, so that the calls failing together aren't retried together.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(1 << (attempt - 1).min(16));
+        delay.mul_f64(1.0 - rand::thread_rng().gen_range(0.0..0.5))
+    }
+
+    /// Calls `f` until it succeeds or has been attempted `max_attempts` times, in which case the
+    /// last error is returned. `what` describes the call in the logs of the failed attempts.
+    pub async fn retry<T, E, F, Fut>(&self, what: &str, mut f: F) -> Result<T, E>
+    where
+        E: Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Err(e) if attempt < self.max_attempts.get() => {
+                    let delay = self.delay(attempt);
+                    warn!(
+                        target: "grpc",
+                        "failed to {what} (attempt {attempt}/{}), retrying in {delay:?}: {e}",
+                        self.max_attempts
+                    );
+
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn delays_double_with_jitter() {
+        let retry = RetryPolicy {
+            max_attempts: NonZeroU32::new(3).unwrap(),
+            base_delay: Duration::from_secs(1),
+        };
+
+        for (attempt, max) in [(1, 1000), (2, 2000), (3, 4000)] {
+            let delay = retry.delay(attempt).as_millis();
+            assert!(delay >= max / 2 && delay <= max, "attempt {attempt}: {delay}ms");
+        }
+    }
+}
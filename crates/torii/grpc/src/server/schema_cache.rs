@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use dojo_types::schema::{canonical_json, compute_schema_hash, Ty};
+use parking_lot::RwLock;
+use sqlx::{Pool, Sqlite};
+use starknet_crypto::FieldElement;
+use tokio::sync::OnceCell;
+use torii_core::error::{Error, ParseError};
+use tracing::{debug, error, warn};
+
+struct CachedSchema {
+    /// The class hash of the model when the schema was cached.
+    class_hash: String,
+    schema: Ty,
+    schema_hash: FieldElement,
+}
+
+/// A cache of the decoded model schemas and of their schema hashes. It can be persisted in the
+/// `schema_cache` table so that it survives restarts.
+///
+/// An entry is only used as long as the model has not been upgraded, ie. its class hash is
+/// unchanged and, for the persisted ones, the hash of the cached schema still matches the model
+/// layout.
+pub struct SchemaCache {
+    pool: Pool<Sqlite>,
+    persist: bool,
+    schemas: RwLock<HashMap<String, CachedSchema>>,
+    /// Set once the persisted schemas are loaded.
+    loaded: OnceCell<()>,
+}
+
+impl SchemaCache {
+    pub fn new(pool: Pool<Sqlite>, persist: bool) -> Self {
+        Self { pool, persist, schemas: Default::default(), loaded: OnceCell::new() }
+    }
+
+    /// Loads the persisted schemas the first time it's called, and waits for them to be loaded
+    /// afterwards. The lookups wait for it, so that they don't miss the persisted schemas.
+    ///
+    /// The cache starts empty if the persisted schemas can't be loaded, they are then decoded
+    /// again from the model members.
+    pub async fn load(&self) {
+        self.loaded
+            .get_or_init(|| async {
+                if !self.persist {
+                    return;
+                }
+
+                if let Err(e) = self.load_persisted().await {
+                    error!(target: "grpc", "failed to load the schema cache: {e}");
+                }
+            })
+            .await;
+    }
+
+    /// Loads the persisted schemas, discarding the stale ones.
+    async fn load_persisted(&self) -> Result<(), Error> {
+        let rows: Vec<(String, String, String, String, String)> = sqlx::query_as(
+            "SELECT schema_cache.model_id, schema_cache.class_hash, schema_cache.schema_hash, \
+             schema_cache.schema, models.layout FROM schema_cache JOIN models ON models.id = \
+             schema_cache.model_id AND models.class_hash = schema_cache.class_hash",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut schemas = HashMap::with_capacity(rows.len());
+        for (model, class_hash, schema_hash, schema, layout) in rows {
+            let Ok(schema) = serde_json::from_str::<Ty>(&schema) else {
+                warn!(target: "grpc", "invalid cached schema for model {model}, ignoring");
+                continue;
+            };
+
+            let Ok(layout) = parse_layout(&layout) else {
+                warn!(target: "grpc", "invalid layout of model {model}, ignoring");
+                continue;
+            };
+
+            let current_hash = compute_schema_hash(&schema, &layout);
+            if format!("{current_hash:#x}") != schema_hash {
+                debug!(target: "grpc", "cached schema for model {model} is stale, ignoring");
+                continue;
+            }
+
+            schemas.insert(model, CachedSchema { class_hash, schema, schema_hash: current_hash });
+        }
+
+        debug!(target: "grpc", "loaded {} cached model schemas", schemas.len());
+        self.schemas.write().extend(schemas);
+
+        Ok(())
+    }
+
+    /// Returns the cached schema of `model`, if it was cached for the same `class_hash`.
+    pub async fn get(&self, model: &str, class_hash: &str) -> Option<Ty> {
+        self.load().await;
+
+        self.schemas
+            .read()
+            .get(model)
+            .filter(|cached| cached.class_hash == class_hash)
+            .map(|cached| cached.schema.clone())
+    }
+
+    /// Returns the schema hash of the cached schema of `model`, if it was cached for the same
+    /// `class_hash`.
+    pub async fn schema_hash(&self, model: &str, class_hash: &str) -> Option<FieldElement> {
+        self.load().await;
+
+        self.schemas
+            .read()
+            .get(model)
+            .filter(|cached| cached.class_hash == class_hash)
+            .map(|cached| cached.schema_hash)
+    }
+
+    /// Caches the schema of `model`, and persists it if the cache is persisted. Returns the schema
+    /// hash of the model.
+    pub async fn insert(
+        &self,
+        model: &str,
+        class_hash: &str,
+        layout: &str,
+        schema: &Ty,
+    ) -> Result<FieldElement, Error> {
+        let schema_hash = compute_schema_hash(schema, &parse_layout(layout)?);
+
+        if self.persist {
+            sqlx::query(
+                "INSERT OR REPLACE INTO schema_cache (model_id, class_hash, schema_hash, schema) \
+                 VALUES (?, ?, ?, ?)",
+            )
+            .bind(model)
+            .bind(class_hash)
+            .bind(format!("{schema_hash:#x}"))
+            .bind(canonical_json(schema))
+            .execute(&self.pool)
+            .await?;
+        }
+
+        self.schemas.write().insert(
+            model.to_string(),
+            CachedSchema {
+                class_hash: class_hash.to_string(),
+                schema: schema.clone(),
+                schema_hash,
+            },
+        );
+
+        Ok(schema_hash)
+    }
+}
+
+/// Parses the hex-encoded layout stored in the `models` table.
+fn parse_layout(layout: &str) -> Result<Vec<FieldElement>, ParseError> {
+    Ok(hex::decode(layout)?.into_iter().map(FieldElement::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use dojo_types::primitive::Primitive;
+    use dojo_types::schema::{compute_schema_hash, Member, Struct, Ty};
+    use sqlx::SqlitePool;
+    use starknet_crypto::FieldElement;
+    use torii_core::error::{Error, ParseError};
+
+    use super::SchemaCache;
+
+    fn position() -> Ty {
+        Ty::Struct(Struct {
+            name: "Position".into(),
+            children: vec![Member {
+                name: "x".into(),
+                ty: Ty::Primitive(Primitive::U32(None)),
+                key: false,
+            }],
+        })
+    }
+
+    async fn register_model(pool: &SqlitePool, layout: &str) {
+        sqlx::query(
+            "INSERT INTO models (id, name, layout, class_hash, packed_size, unpacked_size) VALUES \
+             ('Position', 'Position', ?, '0x1', 1, 1)",
+        )
+        .bind(layout)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn persisted_schemas(pool: SqlitePool) {
+        register_model(&pool, "20").await;
+
+        let cache = SchemaCache::new(pool.clone(), true);
+        assert_eq!(cache.get("Position", "0x1").await, None);
+        cache.insert("Position", "0x1", "20", &position()).await.unwrap();
+        assert_eq!(cache.get("Position", "0x1").await, Some(position()));
+        assert_eq!(cache.get("Position", "0x2").await, None);
+
+        // the schemas are loaded by the first lookup after a restart
+        let cache = SchemaCache::new(pool.clone(), true);
+        assert_eq!(cache.get("Position", "0x1").await, Some(position()));
+
+        // the schema of an upgraded model is discarded
+        sqlx::query("UPDATE models SET class_hash = '0x2'").execute(&pool).await.unwrap();
+        let cache = SchemaCache::new(pool.clone(), true);
+        assert_eq!(cache.get("Position", "0x1").await, None);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn unpersisted_schemas(pool: SqlitePool) {
+        register_model(&pool, "20").await;
+
+        let cache = SchemaCache::new(pool.clone(), false);
+        let schema_hash = cache.insert("Position", "0x1", "20", &position()).await.unwrap();
+        assert_eq!(schema_hash, compute_schema_hash(&position(), &[FieldElement::from(32_u8)]));
+        assert_eq!(cache.get("Position", "0x1").await, Some(position()));
+        assert_eq!(cache.schema_hash("Position", "0x1").await, Some(schema_hash));
+        assert_eq!(cache.schema_hash("Position", "0x2").await, None);
+
+        // nothing was persisted for the next restart
+        let cache = SchemaCache::new(pool.clone(), true);
+        assert_eq!(cache.get("Position", "0x1").await, None);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn stale_persisted_schemas(pool: SqlitePool) {
+        register_model(&pool, "20").await;
+        SchemaCache::new(pool.clone(), true)
+            .insert("Position", "0x1", "20", &position())
+            .await
+            .unwrap();
+
+        // the layout changed without the class hash changing
+        sqlx::query("UPDATE models SET layout = '08'").execute(&pool).await.unwrap();
+        let cache = SchemaCache::new(pool.clone(), true);
+        assert_eq!(cache.get("Position", "0x1").await, None);
+
+        // a malformed layout discards the schema instead of failing the load
+        sqlx::query("UPDATE models SET layout = 'zz'").execute(&pool).await.unwrap();
+        let cache = SchemaCache::new(pool.clone(), true);
+        assert_eq!(cache.get("Position", "0x1").await, None);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn malformed_layout(pool: SqlitePool) {
+        register_model(&pool, "zz").await;
+
+        let cache = SchemaCache::new(pool, true);
+        let err = cache.insert("Position", "0x1", "zz", &position()).await.unwrap_err();
+        assert!(matches!(err, Error::Parse(ParseError::InvalidLayout(_))));
+        assert_eq!(cache.get("Position", "0x1").await, None);
+    }
+}
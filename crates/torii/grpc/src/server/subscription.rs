@@ -1,10 +1,14 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::future::Future;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::Poll;
+use std::time::Duration;
 
 use futures_util::future::BoxFuture;
-use futures_util::FutureExt;
+use futures_util::{stream, FutureExt, Stream, StreamExt};
+use parking_lot::Mutex;
 use rand::Rng;
 use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use starknet::core::types::{
@@ -13,13 +17,25 @@ use starknet::core::types::{
 use starknet::macros::short_string;
 use starknet::providers::Provider;
 use starknet_crypto::{poseidon_hash_many, FieldElement};
-use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tokio::sync::RwLock;
-use tracing::{debug, error, trace};
+use tokio::sync::mpsc::error::SendTimeoutError;
+use tokio::sync::mpsc::{channel, Receiver, Sender, UnboundedSender};
+use tokio::sync::{oneshot, watch, Notify, RwLock};
+use tokio::time::{interval_at, sleep, Instant, Interval, MissedTickBehavior};
+use tokio_stream::wrappers::{ReceiverStream, WatchStream};
+use tracing::{debug, error, trace, warn};
 
+use super::changelog::{BlockDiff, Changelog};
+use super::entity_cache::BlockWrites;
 use super::error::SubscriptionError as Error;
+use super::retry::RetryPolicy;
 use crate::protos;
+use crate::protos::world::CloseReason;
 
+/// How long an update waits for room in the channel of a subscriber, before the subscriber is
+/// considered too slow and its subscription is closed.
+const BACKPRESSURE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
 pub struct ModelMetadata {
     pub name: FieldElement,
     pub packed_size: usize,
@@ -27,55 +43,504 @@ pub struct ModelMetadata {
 
 pub struct SubscribeRequest {
     pub model: ModelMetadata,
-    pub keys: Vec<FieldElement>,
+    /// The id of the entity, derived from its keys with the entity id scheme of the world.
+    pub id: FieldElement,
+    /// The indices of the packed storage slots of the entity to watch, all of them if `None`.
+    pub slots: Option<Vec<usize>>,
+    /// The index of the query the entity is subscribed by, in the subscription request. A query
+    /// matching all the entities of a model subscribes to several entities.
+    pub query_idx: u32,
+    /// The packed values of the entity when subscribing, sent as the first update of a delta
+    /// subscription.
+    pub values: Option<Vec<FieldElement>>,
 }
 
 pub struct Subscriber {
-    /// The storage addresses that the subscriber is interested in.
-    storage_addresses: HashSet<FieldElement>,
+    /// The storage addresses that the subscriber is interested in, mapped to the index of the
+    /// entity they belong to in the subscription request.
+    storage_addresses: HashMap<FieldElement, usize>,
+    /// The index of the query of each entity.
+    entity_queries: Vec<u32>,
+    /// Whether the updates of all the entities are sent in a single message per block.
+    batch_updates: bool,
+    /// The updates waiting to be forwarded to the subscriber, if only the latest update of each
+    /// entity is sent.
+    pending_updates: Option<Arc<PendingUpdates>>,
+    /// The limit of the rate at which updates are sent to the subscriber.
+    rate_limit: Arc<RateLimit>,
+    /// The last value sent of each watched storage slot, keyed by storage address, if only the
+    /// slots whose value changed are sent. Holds as many entries as `storage_addresses`.
+    last_values: Option<Mutex<HashMap<FieldElement, FieldElement>>>,
+    /// The block the initial values of a delta subscriber were read at. The changes of the blocks
+    /// up to this one are already in them, and aren't sent.
+    delta_block: Option<u64>,
     /// The channel to send the response back to the subscriber.
     sender: Sender<Result<protos::world::SubscribeEntitiesResponse, tonic::Status>>,
+    /// Sends the reason why the subscription is closed, after the pending updates.
+    close: oneshot::Sender<CloseReason>,
+    /// Stops the task removing the subscriber once its stream is dropped.
+    cancel: oneshot::Sender<()>,
 }
 
+impl Subscriber {
+    /// Removes the storage entries holding the value last sent to a delta subscriber, and the
+    /// entities left without entries.
+    fn retain_changed(&self, entities_entries: &mut BTreeMap<usize, Vec<&StorageEntry>>) {
+        let Some(last_values) = &self.last_values else {
+            return;
+        };
+
+        let last_values = last_values.lock();
+        entities_entries.retain(|_, entries| {
+            entries.retain(|entry| last_values.get(&entry.key) != Some(&entry.value));
+            !entries.is_empty()
+        });
+    }
+
+    /// Records the storage entries sent to a delta subscriber.
+    fn record_sent<'a>(&self, entries: impl IntoIterator<Item = &'a StorageEntry>) {
+        if let Some(last_values) = &self.last_values {
+            last_values.lock().extend(entries.into_iter().map(|entry| (entry.key, entry.value)));
+        }
+    }
+
+    /// Closes the subscription, the stream ends once the updates in the channel are consumed.
+    fn close(self, reason: CloseReason) {
+        // fails if the subscriber is already gone
+        let _ = self.close.send(reason);
+        let _ = self.cancel.send(());
+
+        // the forwarding task holds a sender too
+        if let Some(pending_updates) = self.pending_updates {
+            pending_updates.closed.store(true, Ordering::Relaxed);
+            pending_updates.notify.notify_one();
+        }
+    }
+}
+
+/// The updates of a latest only subscriber that haven't been sent yet, keyed by the index of the
+/// entity they belong to.
 #[derive(Default)]
+struct PendingUpdates {
+    updates: Mutex<BTreeMap<usize, PendingUpdate>>,
+    notify: Notify,
+    /// Whether the subscription was closed, and the updates shouldn't be forwarded anymore.
+    closed: AtomicBool,
+}
+
+/// The latest update of an entity, merged with the updates it superseded so that the storage
+/// slots which only changed in a superseded update are still sent.
+struct PendingUpdate {
+    block_number: u64,
+    block_hash: FieldElement,
+    /// The latest value of the storage slots that changed, keyed by storage address.
+    storage_entries: BTreeMap<FieldElement, FieldElement>,
+}
+
+impl PendingUpdates {
+    /// Queues the storage entries of an entity that changed in a block. Returns whether a pending
+    /// update of the entity was superseded.
+    fn push<'a>(
+        &self,
+        entity_idx: usize,
+        block_number: u64,
+        block_hash: FieldElement,
+        storage_entries: impl IntoIterator<Item = &'a StorageEntry>,
+    ) -> bool {
+        let mut updates = self.updates.lock();
+
+        let superseded = updates.contains_key(&entity_idx);
+        let update = updates.entry(entity_idx).or_insert_with(|| PendingUpdate {
+            block_number,
+            block_hash,
+            storage_entries: BTreeMap::new(),
+        });
+
+        update.block_number = block_number;
+        update.block_hash = block_hash;
+        update.storage_entries.extend(storage_entries.into_iter().map(|e| (e.key, e.value)));
+
+        drop(updates);
+        self.notify.notify_one();
+
+        superseded
+    }
+}
+
+/// A token bucket limiting the rate of the updates sent to a subscriber. Up to a second worth of
+/// updates can be sent in a burst.
+struct RateLimit {
+    max_per_second: NonZeroU32,
+    /// The number of updates that can be sent right away, and when it was last refilled.
+    tokens: Mutex<(f64, Instant)>,
+}
+
+impl RateLimit {
+    fn new(max_per_second: NonZeroU32) -> Self {
+        Self { max_per_second, tokens: Mutex::new((max_per_second.get() as f64, Instant::now())) }
+    }
+
+    /// Takes a token if one is available, otherwise returns how long to wait for the next one.
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let rate = self.max_per_second.get() as f64;
+        let mut tokens = self.tokens.lock();
+
+        let now = Instant::now();
+        let (available, last_refill) = *tokens;
+        let available =
+            (available + now.duration_since(last_refill).as_secs_f64() * rate).min(rate);
+
+        if available >= 1.0 {
+            *tokens = (available - 1.0, now);
+            Ok(())
+        } else {
+            *tokens = (available, now);
+            Err(Duration::from_secs_f64((1.0 - available) / rate))
+        }
+    }
+}
+
+/// A snapshot of the subscription metrics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubscriptionMetrics {
+    /// The number of currently active subscriptions.
+    pub active_subscriptions: u64,
+    /// The total number of messages sent to the subscribers, heartbeats included.
+    pub messages_sent: u64,
+    /// The total number of messages that couldn't be delivered, either because the subscriber
+    /// channel was full, because they were superseded by a newer update of the same entity for a
+    /// latest only subscriber, or because the subscription was closed before they were sent.
+    pub messages_dropped: u64,
+    /// The total number of updates that exceeded the rate limit of their subscription, and were
+    /// either delayed and coalesced for latest only subscribers, or closed their subscription.
+    pub messages_throttled: u64,
+}
+
+#[cfg(feature = "prometheus")]
+impl SubscriptionMetrics {
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let metrics = [
+            (
+                "torii_grpc_active_subscriptions",
+                "gauge",
+                "Number of active entity subscriptions.",
+                self.active_subscriptions,
+            ),
+            (
+                "torii_grpc_subscription_messages_sent_total",
+                "counter",
+                "Total number of messages sent to subscribers.",
+                self.messages_sent,
+            ),
+            (
+                "torii_grpc_subscription_messages_dropped_total",
+                "counter",
+                "Total number of messages that couldn't be delivered to subscribers.",
+                self.messages_dropped,
+            ),
+            (
+                "torii_grpc_subscription_messages_throttled_total",
+                "counter",
+                "Total number of updates that exceeded the rate limit of their subscription.",
+                self.messages_throttled,
+            ),
+        ];
+
+        metrics
+            .iter()
+            .map(|(name, kind, help, value)| {
+                format!("# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n")
+            })
+            .collect()
+    }
+}
+
 pub struct SubscriberManager {
     subscribers: RwLock<HashMap<usize, Subscriber>>,
+    active_subscriptions: AtomicU64,
+    messages_sent: AtomicU64,
+    messages_dropped: AtomicU64,
+    messages_throttled: AtomicU64,
+    /// Whether the updates are held by the [`Service`] instead of being sent to the subscribers.
+    paused: watch::Sender<bool>,
+    /// The number of the last block whose updates were sent to the subscribers, set while the
+    /// subscribers are locked.
+    published_block: AtomicU64,
+}
+
+impl Default for SubscriberManager {
+    fn default() -> Self {
+        Self {
+            subscribers: Default::default(),
+            active_subscriptions: Default::default(),
+            messages_sent: Default::default(),
+            messages_dropped: Default::default(),
+            messages_throttled: Default::default(),
+            paused: watch::channel(false).0,
+            published_block: Default::default(),
+        }
+    }
 }
 
 impl SubscriberManager {
+    /// Holds the updates of the subscriptions until [`Self::resume`] is called, eg. during a
+    /// maintenance of the database. The subscriptions stay open and keep receiving heartbeats.
+    pub(super) fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Sends the updates held since [`Self::pause`] was called, in order, and the following ones.
+    pub(super) fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub(super) fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Adds a subscriber, returning the stream of its updates. The last message of the stream
+    /// holds the reason why it was closed, unless the subscriber went away.
+    ///
+    /// `delta_block` is set for the subscribers only sent the storage slots whose value changed,
+    /// to the block the values of the `entities` were read at. Their stream starts with the full
+    /// values of the entities, followed by the changes of the next blocks. Fails if the updates of
+    /// a later block were already published, as the subscriber would miss them: the values have
+    /// to be read again.
     pub(super) async fn add_subscriber(
-        &self,
+        self: &Arc<Self>,
+        world_address: FieldElement,
         entities: Vec<SubscribeRequest>,
-    ) -> Receiver<Result<protos::world::SubscribeEntitiesResponse, tonic::Status>> {
+        batch_updates: bool,
+        latest_only: bool,
+        max_updates_per_second: NonZeroU32,
+        delta_block: Option<u64>,
+    ) -> Result<
+        impl Stream<Item = Result<protos::world::SubscribeEntitiesResponse, tonic::Status>>,
+        Error,
+    > {
+        // no block is published until the subscriber is added
+        let mut subscribers = self.subscribers.write().await;
+        if let Some(read_at) = delta_block {
+            let published = self.published_block.load(Ordering::Relaxed);
+            if published > read_at {
+                return Err(Error::OutdatedValues { read_at, published });
+            }
+        }
+
         let id = rand::thread_rng().gen::<usize>();
 
         let (sender, receiver) = channel(1);
+        let (close, close_reason) = oneshot::channel();
+        let (cancel, cancelled) = oneshot::channel();
 
-        // convert the list of entites into a list storage addresses
-        let storage_addresses = entities
-            .par_iter()
-            .map(|entity| {
-                let base = poseidon_hash_many(&[
-                    short_string!("dojo_storage"),
-                    entity.model.name,
-                    poseidon_hash_many(&entity.keys),
-                ]);
-
-                (0..entity.model.packed_size)
-                    .into_par_iter()
-                    .map(|i| base + i.into())
-                    .collect::<Vec<FieldElement>>()
+        let storage_addresses = storage_addresses(&entities);
+        let entity_queries = entities.iter().map(|entity| entity.query_idx).collect::<Vec<_>>();
+
+        let rate_limit = Arc::new(RateLimit::new(max_updates_per_second));
+
+        let mut initial_updates = vec![];
+        let last_values = delta_block.map(|block_number| {
+            let entries = initial_entries(&entities);
+            let entities_entries =
+                entries.iter().map(|(idx, entries)| (*idx, entries.iter().collect())).collect();
+
+            for (query_indices, storage_entries) in
+                updates_entries(entities_entries, &entity_queries, batch_updates)
+            {
+                initial_updates.push(Ok(protos::world::SubscribeEntitiesResponse {
+                    // the values are read from the database, which doesn't know the block hash
+                    entity_update: Some(entity_update(
+                        world_address,
+                        block_number,
+                        FieldElement::ZERO,
+                        storage_entries.iter().map(|entry| (entry.key, entry.value)),
+                    )),
+                    heartbeat: false,
+                    close_reason: None,
+                    query_indices,
+                }));
+            }
+            self.messages_sent.fetch_add(initial_updates.len() as u64, Ordering::Relaxed);
+
+            Mutex::new(
+                entries.into_values().flatten().map(|entry| (entry.key, entry.value)).collect(),
+            )
+        });
+
+        let pending_updates = latest_only.then(|| {
+            let pending_updates = Arc::new(PendingUpdates::default());
+            tokio::spawn(Self::forward_latest_updates(
+                Arc::clone(self),
+                world_address,
+                Arc::clone(&pending_updates),
+                entity_queries.clone(),
+                Arc::clone(&rate_limit),
+                sender.clone(),
+            ));
+            pending_updates
+        });
+
+        subscribers.insert(
+            id,
+            Subscriber {
+                storage_addresses,
+                entity_queries,
+                batch_updates,
+                pending_updates,
+                rate_limit,
+                last_values,
+                delta_block,
+                sender: sender.clone(),
+                close,
+                cancel,
+            },
+        );
+        drop(subscribers);
+        self.active_subscriptions.fetch_add(1, Ordering::Relaxed);
+
+        tokio::spawn(Self::remove_when_dropped(Arc::clone(self), id, sender, cancelled));
+
+        let close = stream::once(async move {
+            // the manager is only dropped when the server shuts down
+            let reason = close_reason.await.unwrap_or(CloseReason::ServerShutdown);
+            Ok(protos::world::SubscribeEntitiesResponse {
+                entity_update: None,
+                heartbeat: false,
+                close_reason: Some(reason as i32),
+                query_indices: vec![],
             })
-            .flatten()
-            .collect::<HashSet<FieldElement>>();
+        });
+
+        Ok(stream::iter(initial_updates).chain(ReceiverStream::new(receiver)).chain(close))
+    }
+
+    pub(super) async fn remove_subscriber(&self, id: usize, reason: CloseReason) {
+        if let Some(sub) = self.subscribers.write().await.remove(&id) {
+            self.active_subscriptions.fetch_sub(1, Ordering::Relaxed);
+            sub.close(reason);
+        }
+    }
+
+    /// Removes the subscriber `id` as soon as its stream is dropped, rather than on the next update
+    /// that can't be sent to it, so that churning clients don't pile up. Returns early once the
+    /// subscriber is removed for another reason.
+    async fn remove_when_dropped(
+        self: Arc<Self>,
+        id: usize,
+        sender: Sender<Result<protos::world::SubscribeEntitiesResponse, tonic::Status>>,
+        cancelled: oneshot::Receiver<()>,
+    ) {
+        tokio::select! {
+            _ = sender.closed() => {
+                trace!(target = "subscription", "stream dropped, removing subscriber idx: {id}");
+                self.remove_subscriber(id, CloseReason::Normal).await;
+            }
+            _ = cancelled => {}
+        }
+    }
 
-        self.subscribers.write().await.insert(id, Subscriber { storage_addresses, sender });
+    /// Closes all the subscriptions, eg. when the server shuts down.
+    pub(super) async fn close_all(&self, reason: CloseReason) {
+        let mut subscribers = self.subscribers.write().await;
+        self.active_subscriptions.fetch_sub(subscribers.len() as u64, Ordering::Relaxed);
 
-        receiver
+        for (_, sub) in subscribers.drain() {
+            sub.close(reason);
+        }
     }
 
-    pub(super) async fn remove_subscriber(&self, id: usize) {
-        self.subscribers.write().await.remove(&id);
+    /// Forwards the pending updates of a latest only subscriber, as soon as there is room in its
+    /// channel. Room is made before picking the update to send, so that the updates received while
+    /// the subscriber is busy supersede the pending ones instead of piling up. The same goes for
+    /// the updates received while waiting for the rate limit.
+    async fn forward_latest_updates(
+        self: Arc<Self>,
+        contract_address: FieldElement,
+        pending_updates: Arc<PendingUpdates>,
+        entity_queries: Vec<u32>,
+        rate_limit: Arc<RateLimit>,
+        sender: Sender<Result<protos::world::SubscribeEntitiesResponse, tonic::Status>>,
+    ) {
+        'forward: loop {
+            tokio::select! {
+                _ = sender.closed() => break,
+                _ = pending_updates.notify.notified() => {}
+            }
+
+            loop {
+                if pending_updates.closed.load(Ordering::Relaxed) {
+                    break 'forward;
+                }
+
+                let Ok(permit) = sender.reserve().await else {
+                    break 'forward;
+                };
+
+                if let Err(delay) = rate_limit.try_acquire() {
+                    self.messages_throttled.fetch_add(1, Ordering::Relaxed);
+                    drop(permit);
+                    sleep(delay).await;
+                    continue;
+                }
+
+                let Some((entity_idx, update)) = pending_updates.updates.lock().pop_first() else {
+                    break;
+                };
+
+                permit.send(Ok(protos::world::SubscribeEntitiesResponse {
+                    entity_update: Some(entity_update(
+                        contract_address,
+                        update.block_number,
+                        update.block_hash,
+                        update.storage_entries,
+                    )),
+                    heartbeat: false,
+                    close_reason: None,
+                    query_indices: vec![entity_queries[entity_idx]],
+                }));
+                self.messages_sent.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        // the subscription is closed, the updates left are never sent
+        let dropped = std::mem::take(&mut *pending_updates.updates.lock()).len();
+        self.messages_dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+    }
+
+    /// Returns a snapshot of the subscription metrics.
+    pub fn metrics(&self) -> SubscriptionMetrics {
+        SubscriptionMetrics {
+            active_subscriptions: self.active_subscriptions.load(Ordering::Relaxed),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_dropped: self.messages_dropped.load(Ordering::Relaxed),
+            messages_throttled: self.messages_throttled.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Send a heartbeat message to all the subscribers. Subscribers whose channel is currently
+    /// full are skipped, as the pending message will keep the stream alive anyway.
+    fn send_heartbeats(&self) {
+        // don't block the service if the subscribers are being modified, the heartbeat will be
+        // sent on the next tick instead.
+        let Ok(subscribers) = self.subscribers.try_read() else {
+            return;
+        };
+
+        for sub in subscribers.values() {
+            let resp = protos::world::SubscribeEntitiesResponse {
+                entity_update: None,
+                heartbeat: true,
+                close_reason: None,
+                query_indices: vec![],
+            };
+
+            if sub.sender.try_send(Ok(resp)).is_ok() {
+                self.messages_sent.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
     }
 }
 
@@ -91,19 +556,51 @@ pub struct Service<P: Provider> {
     state_update_req_fut: Option<BoxFuture<'static, (P, u64, RequestStateUpdateResult)>>,
     subs_manager: Arc<SubscriberManager>,
     publish_fut: Option<BoxFuture<'static, PublishStateUpdateResult>>,
+    heartbeat: Interval,
+    /// Notified of the number of every block processed by the indexer.
+    processed_blocks: watch::Sender<u64>,
+    retry: RetryPolicy,
+    /// Set while the state updates can't be fetched from the node, even after retrying.
+    provider_failing: Arc<AtomicBool>,
+    /// Whether the fan-out is paused, the blocks then being queued until it's resumed.
+    paused: WatchStream<bool>,
+    is_paused: bool,
+    /// The maximum number of blocks queued while the fan-out is paused. The subscriptions are
+    /// closed for backpressure once exceeded.
+    max_paused_blocks: usize,
+    /// Sent the storage entries of the world in the fetched state diffs, to invalidate the cached
+    /// entities once their blocks are committed.
+    invalidations: Option<UnboundedSender<BlockWrites>>,
+    /// Records the storage entries of the world in the fetched state diffs, if the blocks can be
+    /// replayed.
+    changelog: Option<Changelog>,
 }
 
 impl<P> Service<P>
 where
     P: Provider + Send,
 {
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn new_with_block_rcv(
         block_num_rcv: Receiver<u64>,
         world_address: FieldElement,
         provider: P,
         subs_manager: Arc<SubscriberManager>,
+        heartbeat_interval: Duration,
+        processed_blocks: watch::Sender<u64>,
+        retry: RetryPolicy,
+        provider_failing: Arc<AtomicBool>,
+        max_paused_blocks: usize,
+        invalidations: Option<UnboundedSender<BlockWrites>>,
+        changelog: Option<Changelog>,
     ) -> Self {
+        let mut heartbeat = interval_at(Instant::now() + heartbeat_interval, heartbeat_interval);
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let paused = WatchStream::new(subs_manager.paused.subscribe());
+
         Self {
+            heartbeat,
             subs_manager,
             world_address,
             block_num_rcv,
@@ -111,68 +608,331 @@ where
             state_update_req_fut: None,
             idle_provider: Some(provider),
             state_update_queue: VecDeque::new(),
+            processed_blocks,
+            retry,
+            provider_failing,
+            paused,
+            is_paused: false,
+            max_paused_blocks,
+            invalidations,
+            changelog,
         }
     }
 
-    async fn fetch_state_update(provider: P, block_num: u64) -> (P, u64, RequestStateUpdateResult) {
-        let res =
-            provider.get_state_update(BlockId::Number(block_num)).await.map_err(Error::Provider);
+    async fn fetch_state_update(
+        provider: P,
+        block_num: u64,
+        retry: RetryPolicy,
+    ) -> (P, u64, RequestStateUpdateResult) {
+        let res = retry
+            .retry(&format!("fetch the state update of block {block_num}"), || {
+                provider.get_state_update(BlockId::Number(block_num))
+            })
+            .await
+            .map_err(Error::Provider);
         (provider, block_num, res)
     }
 
+    /// Records the storage entries of the world written by a block in `changelog`. A block which
+    /// can't be recorded is left out of the changelog, and the replays of its changes fail.
+    async fn record_diff(
+        changelog: &Changelog,
+        world_address: FieldElement,
+        block_number: u64,
+        state_update: &StateUpdate,
+    ) {
+        let storage_entries = state_update
+            .state_diff
+            .storage_diffs
+            .iter()
+            .filter(|diff| diff.address == world_address)
+            .flat_map(|diff| diff.storage_entries.iter().cloned())
+            .collect();
+        let diff = BlockDiff { block_number, block_hash: state_update.block_hash, storage_entries };
+
+        if let Err(e) = changelog.record(&diff).await {
+            error!(
+                target = "subscription",
+                "failed to record the changes of block {block_number}: {e}"
+            );
+        }
+    }
+
     async fn publish_updates(
         subs: Arc<SubscriberManager>,
         contract_address: FieldElement,
+        block_number: u64,
         state_update: StateUpdate,
     ) -> PublishStateUpdateResult {
         let mut closed_stream = Vec::new();
 
-        let Some(ContractStorageDiffItem { storage_entries: diff_entries, .. }) =
-            state_update.state_diff.storage_diffs.iter().find(|d| d.address == contract_address)
-        else {
-            return Ok(());
-        };
+        let diff_entries = state_update
+            .state_diff
+            .storage_diffs
+            .iter()
+            .find(|d| d.address == contract_address)
+            .map(|ContractStorageDiffItem { storage_entries, .. }| storage_entries.as_slice())
+            .unwrap_or_default();
 
-        for (idx, sub) in subs.subscribers.read().await.iter() {
-            let relevant_storage_entries = diff_entries
-                .iter()
-                .filter(|entry| sub.storage_addresses.contains(&entry.key))
-                .map(|entry| {
-                    let StorageEntry { key, value } = entry;
-                    protos::types::StorageEntry {
-                        key: format!("{key:#x}"),
-                        value: format!("{value:#x}"),
+        let subscribers = subs.subscribers.read().await;
+        subs.published_block.fetch_max(block_number, Ordering::Relaxed);
+        for (idx, sub) in subscribers.iter() {
+            if sub.delta_block.is_some_and(|read_at| block_number <= read_at) {
+                continue;
+            }
+
+            let mut entities_entries = entities_entries(&sub.storage_addresses, diff_entries);
+            sub.retain_changed(&mut entities_entries);
+
+            // the pending updates are forwarded by the task of the subscriber
+            if let Some(pending_updates) = &sub.pending_updates {
+                if sub.sender.is_closed() {
+                    subs.messages_dropped
+                        .fetch_add(entities_entries.len() as u64, Ordering::Relaxed);
+                    closed_stream.push((*idx, CloseReason::Normal));
+                    continue;
+                }
+
+                for (entity_idx, storage_entries) in entities_entries {
+                    // the pending updates are always sent, unless the subscription is closed
+                    sub.record_sent(storage_entries.iter().copied());
+                    let superseded = pending_updates.push(
+                        entity_idx,
+                        block_number,
+                        state_update.block_hash,
+                        storage_entries,
+                    );
+
+                    if superseded {
+                        subs.messages_dropped.fetch_add(1, Ordering::Relaxed);
                     }
-                })
-                .collect::<Vec<protos::types::StorageEntry>>();
-
-            let entity_update = protos::types::EntityUpdate {
-                block_hash: format!("{:#x}", state_update.block_hash),
-                entity_diff: Some(protos::types::EntityDiff {
-                    storage_diffs: vec![protos::types::StorageDiff {
-                        address: format!("{contract_address:#x}"),
-                        storage_entries: relevant_storage_entries,
-                    }],
-                }),
-            };
+                }
 
-            let resp =
-                protos::world::SubscribeEntitiesResponse { entity_update: Some(entity_update) };
+                continue;
+            }
 
-            if sub.sender.send(Ok(resp)).await.is_err() {
-                closed_stream.push(*idx);
+            let updates = updates_entries(entities_entries, &sub.entity_queries, sub.batch_updates);
+            let updates_count = updates.len();
+
+            for (sent, (query_indices, storage_entries)) in updates.into_iter().enumerate() {
+                if sub.rate_limit.try_acquire().is_err() {
+                    // the updates can't be coalesced, close the subscription rather than have the
+                    // subscriber miss some of them
+                    subs.messages_throttled.fetch_add(1, Ordering::Relaxed);
+                    let dropped = updates_count - sent;
+                    subs.messages_dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+                    closed_stream.push((*idx, CloseReason::RateLimited));
+                    break;
+                }
+
+                let resp = protos::world::SubscribeEntitiesResponse {
+                    entity_update: Some(entity_update(
+                        contract_address,
+                        block_number,
+                        state_update.block_hash,
+                        storage_entries.iter().map(|entry| (entry.key, entry.value)),
+                    )),
+                    heartbeat: false,
+                    close_reason: None,
+                    query_indices,
+                };
+
+                match sub.sender.send_timeout(Ok(resp), BACKPRESSURE_TIMEOUT).await {
+                    Ok(()) => {
+                        subs.messages_sent.fetch_add(1, Ordering::Relaxed);
+                        sub.record_sent(storage_entries);
+                    }
+                    Err(e) => {
+                        // the updates left for the subscriber are dropped along with this one
+                        let dropped = updates_count - sent;
+                        subs.messages_dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+
+                        let reason = match e {
+                            SendTimeoutError::Timeout(_) => CloseReason::Backpressure,
+                            SendTimeoutError::Closed(_) => CloseReason::Normal,
+                        };
+                        closed_stream.push((*idx, reason));
+                        break;
+                    }
+                }
             }
         }
 
-        for id in closed_stream {
-            trace!(target = "subscription", "closing stream idx: {id}");
-            subs.remove_subscriber(id).await;
+        drop(subscribers);
+
+        for (id, reason) in closed_stream {
+            trace!(target = "subscription", "closing stream idx: {id}, reason: {reason:?}");
+            subs.remove_subscriber(id, reason).await;
         }
 
         Ok(())
     }
 }
 
+/// Maps the storage addresses of the subscribed entities to the index of the entity they belong
+/// to.
+fn storage_addresses(entities: &[SubscribeRequest]) -> HashMap<FieldElement, usize> {
+    entities
+        .par_iter()
+        .enumerate()
+        .map(|(idx, entity)| {
+            let base = storage_base_address(entity);
+
+            watched_slots(entity)
+                .into_par_iter()
+                .map(|i| (base + i.into(), idx))
+                .collect::<Vec<(FieldElement, usize)>>()
+        })
+        .flatten()
+        .collect()
+}
+
+/// The address of the first packed storage slot of an entity.
+fn storage_base_address(entity: &SubscribeRequest) -> FieldElement {
+    poseidon_hash_many(&[short_string!("dojo_storage"), entity.model.name, entity.id])
+}
+
+/// The indices of the packed storage slots of an entity watched by its subscriber.
+fn watched_slots(entity: &SubscribeRequest) -> Vec<usize> {
+    match &entity.slots {
+        Some(slots) => slots.clone(),
+        None => (0..entity.model.packed_size).collect(),
+    }
+}
+
+/// The storage entries of the watched slots of the entities with values, keyed by the index of
+/// the entity.
+fn initial_entries(entities: &[SubscribeRequest]) -> BTreeMap<usize, Vec<StorageEntry>> {
+    entities
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, entity)| {
+            let values = entity.values.as_ref()?;
+            let base = storage_base_address(entity);
+
+            let entries = watched_slots(entity)
+                .into_iter()
+                .filter_map(|i| {
+                    values.get(i).map(|value| StorageEntry { key: base + i.into(), value: *value })
+                })
+                .collect();
+            Some((idx, entries))
+        })
+        .collect()
+}
+
+/// Groups the storage entries of a state diff relevant to a subscriber by the entity they belong
+/// to.
+fn entities_entries<'a>(
+    storage_addresses: &HashMap<FieldElement, usize>,
+    diff_entries: &'a [StorageEntry],
+) -> BTreeMap<usize, Vec<&'a StorageEntry>> {
+    let mut entities_entries = BTreeMap::<usize, Vec<&StorageEntry>>::new();
+    for entry in diff_entries {
+        if let Some(entity_idx) = storage_addresses.get(&entry.key) {
+            entities_entries.entry(*entity_idx).or_default().push(entry);
+        }
+    }
+    entities_entries
+}
+
+/// Returns the storage entries of each update sent for a block, along with the indices of the
+/// queries of the entities they belong to, given by `entity_queries`: one update per entity, or a
+/// single one holding all the entities if `batch_updates` is set.
+fn updates_entries<'a>(
+    entities_entries: BTreeMap<usize, Vec<&'a StorageEntry>>,
+    entity_queries: &[u32],
+    batch_updates: bool,
+) -> Vec<(Vec<u32>, Vec<&'a StorageEntry>)> {
+    let entities_entries = entities_entries
+        .into_iter()
+        .map(|(entity_idx, entries)| (entity_queries[entity_idx], entries));
+
+    if batch_updates {
+        let (query_indices, entries): (BTreeSet<_>, Vec<_>) = entities_entries.unzip();
+        vec![(query_indices.into_iter().collect(), entries.into_iter().flatten().collect())]
+    } else {
+        entities_entries.map(|(query_idx, entries)| (vec![query_idx], entries)).collect()
+    }
+}
+
+/// Streams the updates of the `entities` in the `diffs` of the replayed blocks, recorded in the
+/// changelog, then closes the stream normally.
+pub(super) fn replay_updates(
+    world_address: FieldElement,
+    entities: Vec<SubscribeRequest>,
+    batch_updates: bool,
+    diffs: Vec<BlockDiff>,
+) -> impl Stream<Item = Result<protos::world::SubscribeEntitiesResponse, tonic::Status>> {
+    let (sender, receiver) = channel(1);
+    let storage_addresses = storage_addresses(&entities);
+    let entity_queries = entities.iter().map(|entity| entity.query_idx).collect::<Vec<_>>();
+
+    tokio::spawn(async move {
+        for diff in diffs {
+            let entities_entries = entities_entries(&storage_addresses, &diff.storage_entries);
+            for (query_indices, storage_entries) in
+                updates_entries(entities_entries, &entity_queries, batch_updates)
+            {
+                let resp = protos::world::SubscribeEntitiesResponse {
+                    entity_update: Some(entity_update(
+                        world_address,
+                        diff.block_number,
+                        diff.block_hash,
+                        storage_entries.iter().map(|entry| (entry.key, entry.value)),
+                    )),
+                    heartbeat: false,
+                    close_reason: None,
+                    query_indices,
+                };
+
+                // the subscriber went away
+                if sender.send(Ok(resp)).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        let resp = protos::world::SubscribeEntitiesResponse {
+            entity_update: None,
+            heartbeat: false,
+            close_reason: Some(CloseReason::Normal as i32),
+            query_indices: vec![],
+        };
+        let _ = sender.send(Ok(resp)).await;
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+/// Builds the update of the entities whose storage `storage_entries`, pairs of storage address
+/// and value, changed in a block.
+fn entity_update(
+    contract_address: FieldElement,
+    block_number: u64,
+    block_hash: FieldElement,
+    storage_entries: impl IntoIterator<Item = (FieldElement, FieldElement)>,
+) -> protos::types::EntityUpdate {
+    let storage_entries = storage_entries
+        .into_iter()
+        .map(|(key, value)| protos::types::StorageEntry {
+            key: format!("{key:#x}"),
+            value: format!("{value:#x}"),
+        })
+        .collect();
+
+    protos::types::EntityUpdate {
+        block_number,
+        block_hash: format!("{block_hash:#x}"),
+        entity_diff: Some(protos::types::EntityDiff {
+            storage_diffs: vec![protos::types::StorageDiff {
+                address: format!("{contract_address:#x}"),
+                storage_entries,
+            }],
+        }),
+    }
+}
+
 /// And endless future that will listen to incoming blocks, and request the corresponding state
 /// updates.
 impl<P> Future for Service<P>
@@ -187,32 +947,77 @@ where
     ) -> std::task::Poll<Self::Output> {
         let pin = self.get_mut();
 
+        while let Poll::Ready(Some(paused)) = pin.paused.poll_next_unpin(cx) {
+            pin.is_paused = paused;
+        }
+
         while let Poll::Ready(Some(block_num)) = pin.block_num_rcv.poll_recv(cx) {
+            pin.processed_blocks.send_replace(block_num);
             // queue block for requesting state updates
             pin.state_update_queue.push_back(block_num);
         }
 
-        if let Some(provider) = pin.idle_provider.take() {
-            if let Some(block_num) = pin.state_update_queue.pop_front() {
-                debug!(target = "subscription", "fetching state update for block {block_num}");
-                pin.state_update_req_fut =
-                    Some(Box::pin(Self::fetch_state_update(provider, block_num)));
-            } else {
-                pin.idle_provider = Some(provider);
+        if pin.is_paused && pin.state_update_queue.len() > pin.max_paused_blocks {
+            warn!(
+                target = "subscription",
+                "more than {} blocks queued while paused, closing the subscriptions",
+                pin.max_paused_blocks
+            );
+
+            // the subscribers have to start over from the current state anyway
+            pin.state_update_queue.clear();
+            let subs = Arc::clone(&pin.subs_manager);
+            tokio::spawn(async move { subs.close_all(CloseReason::Backpressure).await });
+        }
+
+        // the blocks are published one after the other, in order. The block being fetched or
+        // published when the fan-out is paused is still sent.
+        if !pin.is_paused && pin.publish_fut.is_none() {
+            if let Some(provider) = pin.idle_provider.take() {
+                if let Some(block_num) = pin.state_update_queue.pop_front() {
+                    debug!(target = "subscription", "fetching state update for block {block_num}");
+                    pin.state_update_req_fut =
+                        Some(Box::pin(Self::fetch_state_update(provider, block_num, pin.retry)));
+                } else {
+                    pin.idle_provider = Some(provider);
+                }
             }
         }
 
         if let Some(mut fut) = pin.state_update_req_fut.take() {
             if let Poll::Ready((provider, block_num, state_update)) = fut.poll_unpin(cx) {
                 pin.idle_provider = Some(provider);
+                pin.provider_failing.store(state_update.is_err(), Ordering::Relaxed);
 
                 match state_update {
                     Ok(MaybePendingStateUpdate::Update(state_update)) => {
-                        pin.publish_fut = Some(Box::pin(Self::publish_updates(
-                            Arc::clone(&pin.subs_manager),
-                            pin.world_address,
-                            state_update,
-                        )));
+                        if let Some(invalidations) = &pin.invalidations {
+                            let diffs = state_update.state_diff.storage_diffs.iter();
+                            let storage_addresses = diffs
+                                .filter(|diff| diff.address == pin.world_address)
+                                .flat_map(|diff| diff.storage_entries.iter().map(|e| e.key))
+                                .collect();
+                            // the invalidating task only stops once this sender is dropped
+                            let _ = invalidations.send((block_num, storage_addresses));
+                        }
+
+                        let subs = Arc::clone(&pin.subs_manager);
+                        let world_address = pin.world_address;
+                        let changelog = pin.changelog.clone();
+                        pin.publish_fut = Some(Box::pin(async move {
+                            if let Some(changelog) = &changelog {
+                                Self::record_diff(
+                                    changelog,
+                                    world_address,
+                                    block_num,
+                                    &state_update,
+                                )
+                                .await;
+                            }
+
+                            Self::publish_updates(subs, world_address, block_num, state_update)
+                                .await
+                        }));
                     }
 
                     Ok(MaybePendingStateUpdate::PendingUpdate(_)) => {
@@ -224,6 +1029,13 @@ where
                             target = "subscription",
                             "failed to fetch state update for block {block_num}: {e}"
                         );
+
+                        // the updates of the block are missed, the subscribers have to start
+                        // over from the current state
+                        let subs = Arc::clone(&pin.subs_manager);
+                        tokio::spawn(
+                            async move { subs.close_all(CloseReason::InternalError).await },
+                        );
                     }
                 }
             } else {
@@ -233,19 +1045,645 @@ where
 
         if let Some(mut fut) = pin.publish_fut.take() {
             if let Poll::Ready(res) = fut.poll_unpin(cx) {
-                match res {
-                    Ok(_) => {
-                        pin.state_update_queue.pop_front();
-                    }
-                    Err(e) => {
-                        error!(target = "subscription", "error when publishing state update: {e}")
-                    }
+                if let Err(e) = res {
+                    error!(target = "subscription", "error when publishing state update: {e}")
                 }
+
+                // fetch the next queued block
+                cx.waker().wake_by_ref();
             } else {
                 pin.publish_fut = Some(fut);
             }
         }
 
+        while pin.heartbeat.poll_tick(cx).is_ready() {
+            trace!(target = "subscription", "sending heartbeat to subscribers");
+            pin.subs_manager.send_heartbeats();
+        }
+
         Poll::Pending
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+    use std::sync::Arc;
+
+    use dojo_test_utils::rpc::MockJsonRpcTransport;
+    use futures_util::StreamExt;
+    use serde_json::json;
+    use starknet::core::types::{MaybePendingStateUpdate, StateUpdate, StorageEntry};
+    use starknet::macros::short_string;
+    use starknet::providers::jsonrpc::{JsonRpcClient, JsonRpcMethod};
+    use starknet_crypto::FieldElement;
+    use tokio::sync::{mpsc, watch};
+    use tokio::time::{sleep, timeout, Duration};
+
+    use super::{
+        entities_entries, replay_updates, storage_addresses, storage_base_address, updates_entries,
+        Error, ModelMetadata, RateLimit, Service, SubscribeRequest, SubscriberManager,
+        SubscriptionMetrics,
+    };
+    use crate::protos;
+    use crate::protos::world::CloseReason;
+    use crate::server::changelog::BlockDiff;
+    use crate::server::retry::RetryPolicy;
+
+    #[test]
+    fn updates_identify_their_queries() {
+        let query = |model, query_idx| SubscribeRequest {
+            model: ModelMetadata { name: model, packed_size: 1 },
+            id: FieldElement::ONE,
+            slots: None,
+            query_idx,
+            values: None,
+        };
+        let storage_addresses = storage_addresses(&[
+            query(short_string!("Position"), 0),
+            query(short_string!("Moves"), 1),
+        ]);
+
+        // a change of each model, in the reverse order of the queries
+        let mut diff_entries = storage_addresses
+            .iter()
+            .map(|(key, idx)| (*idx, StorageEntry { key: *key, value: FieldElement::TWO }))
+            .collect::<Vec<_>>();
+        diff_entries.sort_by_key(|(idx, _)| std::cmp::Reverse(*idx));
+        let diff_entries = diff_entries.into_iter().map(|(_, entry)| entry).collect::<Vec<_>>();
+
+        let updates =
+            updates_entries(entities_entries(&storage_addresses, &diff_entries), &[0, 1], false);
+        assert_eq!(
+            updates
+                .iter()
+                .map(|(indices, entries)| (indices.clone(), entries.len()))
+                .collect::<Vec<_>>(),
+            vec![(vec![0], 1), (vec![1], 1)]
+        );
+
+        let updates =
+            updates_entries(entities_entries(&storage_addresses, &diff_entries), &[0, 1], true);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].0, vec![0, 1]);
+        assert_eq!(updates[0].1.len(), 2);
+    }
+
+    #[test]
+    fn rate_limit_allows_a_second_worth_of_burst() {
+        let rate_limit = RateLimit::new(NonZeroU32::new(2).unwrap());
+
+        assert!(rate_limit.try_acquire().is_ok());
+        assert!(rate_limit.try_acquire().is_ok());
+
+        let delay = rate_limit.try_acquire().unwrap_err();
+        assert!(delay.as_millis() > 0 && delay.as_millis() <= 500);
+    }
+
+    #[tokio::test]
+    async fn closed_subscriptions_end_with_the_reason() {
+        let manager = Arc::new(SubscriberManager::default());
+        let max_updates_per_second = NonZeroU32::new(10).unwrap();
+
+        let mut streams = vec![];
+        for latest_only in [false, true] {
+            streams.push(
+                manager
+                    .add_subscriber(
+                        FieldElement::ONE,
+                        vec![],
+                        false,
+                        latest_only,
+                        max_updates_per_second,
+                        None,
+                    )
+                    .await
+                    .unwrap()
+                    .boxed(),
+            );
+        }
+
+        manager.close_all(CloseReason::Backpressure).await;
+        assert_eq!(manager.metrics().active_subscriptions, 0);
+
+        for mut stream in streams {
+            let resp = stream.next().await.unwrap().unwrap();
+            assert_eq!(resp.close_reason, Some(CloseReason::Backpressure as i32));
+            assert!(resp.entity_update.is_none());
+            assert!(stream.next().await.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn dropped_streams_are_removed() {
+        let manager = Arc::new(SubscriberManager::default());
+        let max_updates_per_second = NonZeroU32::new(10).unwrap();
+
+        let stream = manager
+            .add_subscriber(FieldElement::ONE, vec![], false, false, max_updates_per_second, None)
+            .await
+            .unwrap();
+        assert_eq!(manager.metrics().active_subscriptions, 1);
+
+        // no update is published, the subscriber is removed as soon as the stream is dropped
+        drop(stream);
+        timeout(Duration::from_secs(1), async {
+            while manager.metrics().active_subscriptions != 0 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the subscriber is removed");
+        assert!(manager.subscribers.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn delta_subscriptions_only_receive_the_changed_slots() {
+        let manager = Arc::new(SubscriberManager::default());
+        let entity = SubscribeRequest {
+            model: ModelMetadata { name: short_string!("Position"), packed_size: 2 },
+            id: FieldElement::ONE,
+            slots: None,
+            query_idx: 0,
+            values: Some(vec![FieldElement::ONE, FieldElement::TWO]),
+        };
+        let base = storage_base_address(&entity);
+
+        let mut stream = manager
+            .add_subscriber(
+                FieldElement::ONE,
+                vec![entity],
+                false,
+                false,
+                NonZeroU32::new(10).unwrap(),
+                Some(5),
+            )
+            .await
+            .unwrap()
+            .boxed();
+
+        let entries = |resp: protos::world::SubscribeEntitiesResponse| {
+            let update = resp.entity_update.unwrap();
+            let diff = update.entity_diff.unwrap().storage_diffs.remove(0);
+            let entries =
+                diff.storage_entries.into_iter().map(|e| (e.key, e.value)).collect::<Vec<_>>();
+            (update.block_number, entries)
+        };
+        let slot = |i: u8| format!("{:#x}", base + i.into());
+
+        // the full value of the entity comes first
+        assert_eq!(
+            entries(stream.next().await.unwrap().unwrap()),
+            (5, vec![(slot(0), "0x1".to_string()), (slot(1), "0x2".to_string())])
+        );
+
+        let state_update = |block_number: u64, values: &[(u8, u8)]| {
+            let storage_entries = values
+                .iter()
+                .map(|(i, value)| json!({ "key": slot(*i), "value": format!("{value:#x}") }))
+                .collect::<Vec<_>>();
+            let state_update = json!({
+                "block_hash": format!("{block_number:#x}"),
+                "new_root": "0x2",
+                "old_root": "0x3",
+                "state_diff": {
+                    "storage_diffs": [{ "address": "0x1", "storage_entries": storage_entries }],
+                    "deprecated_declared_classes": [],
+                    "declared_classes": [],
+                    "deployed_contracts": [],
+                    "replaced_classes": [],
+                    "nonces": []
+                }
+            });
+            (block_number, serde_json::from_value::<StateUpdate>(state_update).unwrap())
+        };
+
+        let publish = |(block_number, state_update)| {
+            Service::<JsonRpcClient<MockJsonRpcTransport>>::publish_updates(
+                Arc::clone(&manager),
+                FieldElement::ONE,
+                block_number,
+                state_update,
+            )
+        };
+
+        // the changes of the block the values were read at are already in them
+        publish(state_update(5, &[(0, 9)])).await.unwrap();
+
+        // the first slot is written with its current value
+        publish(state_update(6, &[(0, 1), (1, 3)])).await.unwrap();
+        assert_eq!(
+            entries(stream.next().await.unwrap().unwrap()),
+            (6, vec![(slot(1), "0x3".to_string())])
+        );
+
+        // nothing changed in the first block, so nothing is sent
+        publish(state_update(7, &[(1, 3)])).await.unwrap();
+        publish(state_update(8, &[(0, 4)])).await.unwrap();
+        assert_eq!(
+            entries(stream.next().await.unwrap().unwrap()),
+            (8, vec![(slot(0), "0x4".to_string())])
+        );
+
+        // the values read before the last published block miss its changes
+        let res = manager
+            .add_subscriber(
+                FieldElement::ONE,
+                vec![],
+                false,
+                false,
+                NonZeroU32::new(10).unwrap(),
+                Some(7),
+            )
+            .await;
+        assert!(matches!(res, Err(Error::OutdatedValues { read_at: 7, published: 8 })));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn metrics_count_every_message() {
+        let manager = Arc::new(SubscriberManager::default());
+        let entities = || {
+            (1..=3u8)
+                .map(|id| SubscribeRequest {
+                    model: ModelMetadata { name: short_string!("Position"), packed_size: 1 },
+                    id: id.into(),
+                    slots: None,
+                    query_idx: 0,
+                    values: None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the streams aren't consumed, so that their channel is full after the first update
+        let mut streams = vec![];
+        for latest_only in [false, true] {
+            let stream = manager
+                .add_subscriber(
+                    FieldElement::ONE,
+                    entities(),
+                    false,
+                    latest_only,
+                    NonZeroU32::new(10).unwrap(),
+                    None,
+                )
+                .await
+                .unwrap();
+            streams.push(stream.boxed());
+        }
+
+        let publish = |block_number: u64| {
+            let storage_entries = entities()
+                .iter()
+                .map(|entity| {
+                    json!({
+                        "key": format!("{:#x}", storage_base_address(entity)),
+                        "value": format!("{block_number:#x}")
+                    })
+                })
+                .collect::<Vec<_>>();
+            let state_update = json!({
+                "block_hash": format!("{block_number:#x}"),
+                "new_root": "0x2",
+                "old_root": "0x3",
+                "state_diff": {
+                    "storage_diffs": [{ "address": "0x1", "storage_entries": storage_entries }],
+                    "deprecated_declared_classes": [],
+                    "declared_classes": [],
+                    "deployed_contracts": [],
+                    "replaced_classes": [],
+                    "nonces": []
+                }
+            });
+
+            Service::<JsonRpcClient<MockJsonRpcTransport>>::publish_updates(
+                Arc::clone(&manager),
+                FieldElement::ONE,
+                block_number,
+                serde_json::from_value::<StateUpdate>(state_update).unwrap(),
+            )
+        };
+
+        // the first update sent to each subscriber fills its channel: the second update times out
+        // and is dropped along with the third one, while the pending updates of the latest only
+        // subscriber are superseded by the next block, then dropped along with its stream
+        publish(1).await.unwrap();
+        publish(2).await.unwrap();
+        sleep(Duration::from_millis(10)).await;
+        drop(streams);
+
+        let expected = SubscriptionMetrics {
+            active_subscriptions: 0,
+            messages_sent: 2,
+            messages_dropped: 7,
+            messages_throttled: 0,
+        };
+        timeout(Duration::from_secs(1), async {
+            while manager.metrics() != expected {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("unexpected metrics {:?}", manager.metrics()));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_subscriptions_are_closed() {
+        let manager = Arc::new(SubscriberManager::default());
+        let entities = (1..=3u8)
+            .map(|id| SubscribeRequest {
+                model: ModelMetadata { name: short_string!("Position"), packed_size: 1 },
+                id: id.into(),
+                slots: None,
+                query_idx: 0,
+                values: None,
+            })
+            .collect::<Vec<_>>();
+        let storage_entries = entities
+            .iter()
+            .map(|entity| {
+                json!({ "key": format!("{:#x}", storage_base_address(entity)), "value": "0x1" })
+            })
+            .collect::<Vec<_>>();
+
+        // a single update per second, the other entities changed in the block exceed the limit
+        let mut stream = manager
+            .add_subscriber(
+                FieldElement::ONE,
+                entities,
+                false,
+                false,
+                NonZeroU32::new(1).unwrap(),
+                None,
+            )
+            .await
+            .unwrap()
+            .boxed();
+
+        let state_update = json!({
+            "block_hash": "0x1",
+            "new_root": "0x2",
+            "old_root": "0x3",
+            "state_diff": {
+                "storage_diffs": [{ "address": "0x1", "storage_entries": storage_entries }],
+                "deprecated_declared_classes": [],
+                "declared_classes": [],
+                "deployed_contracts": [],
+                "replaced_classes": [],
+                "nonces": []
+            }
+        });
+        Service::<JsonRpcClient<MockJsonRpcTransport>>::publish_updates(
+            Arc::clone(&manager),
+            FieldElement::ONE,
+            1,
+            serde_json::from_value::<StateUpdate>(state_update).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert!(stream.next().await.unwrap().unwrap().entity_update.is_some());
+        let resp = stream.next().await.unwrap().unwrap();
+        assert_eq!(resp.close_reason, Some(CloseReason::RateLimited as i32));
+        assert!(stream.next().await.is_none());
+
+        let expected = SubscriptionMetrics {
+            active_subscriptions: 0,
+            messages_sent: 1,
+            messages_dropped: 2,
+            messages_throttled: 1,
+        };
+        assert_eq!(manager.metrics(), expected);
+    }
+
+    #[tokio::test]
+    async fn replayed_updates_end_the_stream() {
+        let entity = SubscribeRequest {
+            model: ModelMetadata { name: short_string!("Position"), packed_size: 2 },
+            id: FieldElement::ONE,
+            slots: None,
+            query_idx: 3,
+            values: None,
+        };
+        let base = storage_base_address(&entity);
+
+        let diff = |block_number: u64, entries: &[(FieldElement, u8)]| BlockDiff {
+            block_number,
+            block_hash: FieldElement::from(block_number),
+            storage_entries: entries
+                .iter()
+                .map(|(key, value)| StorageEntry { key: *key, value: (*value).into() })
+                .collect(),
+        };
+        let diffs = vec![
+            diff(5, &[(base, 1)]),
+            // the other storage entries of the world are left out
+            diff(6, &[(FieldElement::TWO, 1)]),
+            diff(7, &[(base + FieldElement::ONE, 2), (FieldElement::TWO, 2)]),
+        ];
+
+        let responses =
+            replay_updates(FieldElement::ONE, vec![entity], false, diffs).collect::<Vec<_>>().await;
+
+        let updates = responses[..2]
+            .iter()
+            .map(|resp| {
+                let resp = resp.as_ref().unwrap();
+                let update = resp.entity_update.as_ref().unwrap();
+                let diff = &update.entity_diff.as_ref().unwrap().storage_diffs[0];
+                let entries = diff
+                    .storage_entries
+                    .iter()
+                    .map(|e| (e.key.clone(), e.value.clone()))
+                    .collect::<Vec<_>>();
+                (update.block_number, resp.query_indices.clone(), entries)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            updates,
+            vec![
+                (5, vec![3], vec![(format!("{base:#x}"), "0x1".to_string())]),
+                (7, vec![3], vec![(format!("{:#x}", base + FieldElement::ONE), "0x2".to_string())]),
+            ]
+        );
+
+        assert_eq!(responses.len(), 3);
+        let close = responses[2].as_ref().unwrap();
+        assert_eq!(close.close_reason, Some(CloseReason::Normal as i32));
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn prometheus_metrics() {
+        let metrics = SubscriptionMetrics {
+            active_subscriptions: 1,
+            messages_sent: 2,
+            messages_dropped: 3,
+            messages_throttled: 4,
+        };
+
+        assert_eq!(
+            metrics.to_prometheus(),
+            concat!(
+                "# HELP torii_grpc_active_subscriptions Number of active entity subscriptions.\n",
+                "# TYPE torii_grpc_active_subscriptions gauge\n",
+                "torii_grpc_active_subscriptions 1\n",
+                "# HELP torii_grpc_subscription_messages_sent_total Total number of messages sent \
+                 to subscribers.\n",
+                "# TYPE torii_grpc_subscription_messages_sent_total counter\n",
+                "torii_grpc_subscription_messages_sent_total 2\n",
+                "# HELP torii_grpc_subscription_messages_dropped_total Total number of messages \
+                 that couldn't be delivered to subscribers.\n",
+                "# TYPE torii_grpc_subscription_messages_dropped_total counter\n",
+                "torii_grpc_subscription_messages_dropped_total 3\n",
+                "# HELP torii_grpc_subscription_messages_throttled_total Total number of updates \
+                 that exceeded the rate limit of their subscription.\n",
+                "# TYPE torii_grpc_subscription_messages_throttled_total counter\n",
+                "torii_grpc_subscription_messages_throttled_total 4\n",
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_state_update_requests_are_retried() {
+        let provider = |responses: &[serde_json::Value]| {
+            let mut transport = MockJsonRpcTransport::new();
+            for response in responses {
+                transport.set_response(
+                    JsonRpcMethod::GetStateUpdate,
+                    json!([{ "block_number": 1 }]),
+                    response.clone(),
+                );
+            }
+            JsonRpcClient::new(transport)
+        };
+
+        let failure = json!({ "id": 1, "error": { "code": -32603, "message": "Internal error" } });
+        let state_update = json!({
+            "id": 1,
+            "result": {
+                "block_hash": "0x1",
+                "new_root": "0x2",
+                "old_root": "0x3",
+                "state_diff": {
+                    "storage_diffs": [],
+                    "deprecated_declared_classes": [],
+                    "declared_classes": [],
+                    "deployed_contracts": [],
+                    "replaced_classes": [],
+                    "nonces": []
+                }
+            }
+        });
+        let retry = |max_attempts| RetryPolicy {
+            max_attempts: NonZeroU32::new(max_attempts).unwrap(),
+            base_delay: Duration::from_millis(1),
+        };
+
+        // the node fails once, then answers
+        let (_, block_num, res) = Service::fetch_state_update(
+            provider(&[failure.clone(), state_update.clone()]),
+            1,
+            retry(2),
+        )
+        .await;
+        assert_eq!(block_num, 1);
+        assert!(matches!(res, Ok(MaybePendingStateUpdate::Update(_))));
+
+        // the node fails more times than the request is attempted
+        let (_, _, res) = Service::fetch_state_update(
+            provider(&[failure.clone(), failure, state_update]),
+            1,
+            retry(2),
+        )
+        .await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn paused_updates_are_sent_once_resumed() {
+        let manager = Arc::new(SubscriberManager::default());
+        let entity = SubscribeRequest {
+            model: ModelMetadata { name: short_string!("Position"), packed_size: 1 },
+            id: FieldElement::ONE,
+            slots: None,
+            query_idx: 0,
+            values: None,
+        };
+        let slot = format!("{:#x}", storage_base_address(&entity));
+
+        let mut transport = MockJsonRpcTransport::new();
+        for block_number in 1..=2u64 {
+            let storage_entries = json!([{ "key": slot, "value": format!("{block_number:#x}") }]);
+            transport.set_response(
+                JsonRpcMethod::GetStateUpdate,
+                json!([{ "block_number": block_number }]),
+                json!({
+                    "id": 1,
+                    "result": {
+                        "block_hash": format!("{block_number:#x}"),
+                        "new_root": "0x2",
+                        "old_root": "0x3",
+                        "state_diff": {
+                            "storage_diffs": [
+                                { "address": "0x1", "storage_entries": storage_entries }
+                            ],
+                            "deprecated_declared_classes": [],
+                            "declared_classes": [],
+                            "deployed_contracts": [],
+                            "replaced_classes": [],
+                            "nonces": []
+                        }
+                    }
+                }),
+            );
+        }
+
+        let (blocks, block_rx) = mpsc::channel(16);
+        tokio::spawn(Service::new_with_block_rcv(
+            block_rx,
+            FieldElement::ONE,
+            JsonRpcClient::new(transport),
+            Arc::clone(&manager),
+            Duration::from_secs(3600),
+            watch::channel(0).0,
+            RetryPolicy::default(),
+            Default::default(),
+            1,
+            None,
+            None,
+        ));
+
+        let mut stream = manager
+            .add_subscriber(
+                FieldElement::ONE,
+                vec![entity],
+                false,
+                false,
+                NonZeroU32::new(10).unwrap(),
+                None,
+            )
+            .await
+            .unwrap()
+            .boxed();
+        let block_number = |resp: Option<Result<protos::world::SubscribeEntitiesResponse, _>>| {
+            resp.unwrap().unwrap().entity_update.unwrap().block_number
+        };
+
+        // the updates of the blocks indexed while paused are held until resumed
+        manager.pause();
+        blocks.send(1).await.unwrap();
+        assert!(timeout(Duration::from_millis(100), stream.next()).await.is_err());
+
+        manager.resume();
+        assert_eq!(block_number(stream.next().await), 1);
+        blocks.send(2).await.unwrap();
+        assert_eq!(block_number(stream.next().await), 2);
+
+        // the subscriptions are closed once more blocks than the limit are held
+        manager.pause();
+        blocks.send(3).await.unwrap();
+        blocks.send(4).await.unwrap();
+        let resp = stream.next().await.unwrap().unwrap();
+        assert_eq!(resp.close_reason, Some(CloseReason::Backpressure as i32));
+    }
+}
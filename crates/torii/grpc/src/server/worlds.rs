@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures::future::join_all;
+use futures::Stream;
+use starknet_crypto::FieldElement;
+use tonic::codec::CompressionEncoding;
+use tonic::server::NamedService;
+use tonic::{Request, Response, Status};
+
+use super::{server_info, DojoWorld};
+use crate::protos::health::health_check_response::ServingStatus;
+use crate::protos::health::health_server::{Health, HealthServer};
+use crate::protos::health::{HealthCheckRequest, HealthCheckResponse};
+use crate::protos::world::world_server::{World, WorldServer};
+use crate::protos::world::{
+    ChainInfoRequest, ChainInfoResponse, DecodeEntityRequest, DecodeEntityResponse,
+    ExportEntitiesRequest, FindModelsRequest, FindModelsResponse, GetEntityByIdRequest,
+    GetEntityByIdResponse, GetEntityRequest, GetEntityResponse, HydrateRequest,
+    ListEntityIdsRequest, ListEntityIdsResponse, MetadataRequest, MetadataResponse,
+    RecentlyChangedEntitiesRequest, RecentlyChangedEntitiesResponse, RetrieveEntitiesRawResponse,
+    RetrieveEntitiesRequest, RetrieveEntitiesResponse, ServerInfoRequest, ServerInfoResponse,
+    SubscribeAggregateRequest, SubscribeEntitiesRequest, SubscribeMetadataRequest,
+    ValidateSchemaRequest, ValidateSchemaResponse,
+};
+use crate::WORLD_ADDRESS_METADATA_KEY;
+
+/// Hosts several worlds in a single gRPC service, keyed by their address.
+///
+/// The world a request targets is selected with the [`WORLD_ADDRESS_METADATA_KEY`] metadata. It
+/// can be omitted if a single world is hosted.
+#[derive(Clone)]
+pub struct DojoWorlds {
+    worlds: Arc<HashMap<FieldElement, DojoWorld>>,
+}
+
+impl DojoWorlds {
+    pub fn new(worlds: impl IntoIterator<Item = DojoWorld>) -> Self {
+        let worlds = worlds.into_iter().map(|world| (world.world_address, world)).collect();
+        Self { worlds: Arc::new(worlds) }
+    }
+
+    /// Returns the world targeted by `request`.
+    fn world<T>(&self, request: &Request<T>) -> Result<&DojoWorld, Status> {
+        let Some(address) = request.metadata().get(WORLD_ADDRESS_METADATA_KEY) else {
+            return match self.worlds.len() {
+                1 => Ok(self.worlds.values().next().expect("qed; one world")),
+                _ => Err(Status::invalid_argument(format!(
+                    "Missing `{WORLD_ADDRESS_METADATA_KEY}` metadata"
+                ))),
+            };
+        };
+
+        let address = address
+            .to_str()
+            .ok()
+            .and_then(|address| FieldElement::from_str(address).ok())
+            .ok_or_else(|| Status::invalid_argument("Invalid world address"))?;
+
+        self.worlds.get(&address).ok_or_else(|| Status::not_found("World not found"))
+    }
+
+    /// Builds the gRPC service of the worlds. The message size limits are the largest ones
+    /// configured among the worlds.
+    pub fn into_service(self) -> WorldServer<Self> {
+        let max_encoding_message_size =
+            self.worlds.values().map(|w| w.max_encoding_message_size).max().unwrap_or_default();
+        let max_decoding_message_size =
+            self.worlds.values().map(|w| w.max_decoding_message_size).max().unwrap_or_default();
+
+        WorldServer::new(self)
+            .accept_compressed(CompressionEncoding::Gzip)
+            .send_compressed(CompressionEncoding::Gzip)
+            .max_encoding_message_size(max_encoding_message_size)
+            .max_decoding_message_size(max_decoding_message_size)
+    }
+
+    /// Builds the gRPC health service of the worlds. They are reported as serving as long as none
+    /// of them lags too far behind the chain head.
+    pub fn health_service(&self) -> HealthServer<Self> {
+        HealthServer::new(self.clone())
+    }
+}
+
+#[tonic::async_trait]
+impl World for DojoWorlds {
+    async fn server_info(
+        &self,
+        request: Request<ServerInfoRequest>,
+    ) -> Result<Response<ServerInfoResponse>, Status> {
+        let world = self.world(&request)?;
+        Ok(Response::new(server_info(world.entity_id_scheme)))
+    }
+
+    async fn world_metadata(
+        &self,
+        request: Request<MetadataRequest>,
+    ) -> Result<Response<MetadataResponse>, Status> {
+        World::world_metadata(self.world(&request)?, request).await
+    }
+
+    async fn find_models(
+        &self,
+        request: Request<FindModelsRequest>,
+    ) -> Result<Response<FindModelsResponse>, Status> {
+        World::find_models(self.world(&request)?, request).await
+    }
+
+    async fn validate_schema(
+        &self,
+        request: Request<ValidateSchemaRequest>,
+    ) -> Result<Response<ValidateSchemaResponse>, Status> {
+        World::validate_schema(self.world(&request)?, request).await
+    }
+
+    async fn retrieve_entities(
+        &self,
+        request: Request<RetrieveEntitiesRequest>,
+    ) -> Result<Response<RetrieveEntitiesResponse>, Status> {
+        World::retrieve_entities(self.world(&request)?, request).await
+    }
+
+    async fn retrieve_entities_raw(
+        &self,
+        request: Request<RetrieveEntitiesRequest>,
+    ) -> Result<Response<RetrieveEntitiesRawResponse>, Status> {
+        World::retrieve_entities_raw(self.world(&request)?, request).await
+    }
+
+    async fn list_entity_ids(
+        &self,
+        request: Request<ListEntityIdsRequest>,
+    ) -> Result<Response<ListEntityIdsResponse>, Status> {
+        World::list_entity_ids(self.world(&request)?, request).await
+    }
+
+    async fn recently_changed_entities(
+        &self,
+        request: Request<RecentlyChangedEntitiesRequest>,
+    ) -> Result<Response<RecentlyChangedEntitiesResponse>, Status> {
+        World::recently_changed_entities(self.world(&request)?, request).await
+    }
+
+    type ExportEntitiesStream = <DojoWorld as World>::ExportEntitiesStream;
+
+    async fn export_entities(
+        &self,
+        request: Request<ExportEntitiesRequest>,
+    ) -> Result<Response<Self::ExportEntitiesStream>, Status> {
+        World::export_entities(self.world(&request)?, request).await
+    }
+
+    type HydrateStream = <DojoWorld as World>::HydrateStream;
+
+    async fn hydrate(
+        &self,
+        request: Request<HydrateRequest>,
+    ) -> Result<Response<Self::HydrateStream>, Status> {
+        World::hydrate(self.world(&request)?, request).await
+    }
+
+    async fn get_entity_by_id(
+        &self,
+        request: Request<GetEntityByIdRequest>,
+    ) -> Result<Response<GetEntityByIdResponse>, Status> {
+        World::get_entity_by_id(self.world(&request)?, request).await
+    }
+
+    async fn get_entity(
+        &self,
+        request: Request<GetEntityRequest>,
+    ) -> Result<Response<GetEntityResponse>, Status> {
+        World::get_entity(self.world(&request)?, request).await
+    }
+
+    async fn decode_entity(
+        &self,
+        request: Request<DecodeEntityRequest>,
+    ) -> Result<Response<DecodeEntityResponse>, Status> {
+        World::decode_entity(self.world(&request)?, request).await
+    }
+
+    async fn chain_info(
+        &self,
+        request: Request<ChainInfoRequest>,
+    ) -> Result<Response<ChainInfoResponse>, Status> {
+        World::chain_info(self.world(&request)?, request).await
+    }
+
+    type SubscribeEntitiesStream = <DojoWorld as World>::SubscribeEntitiesStream;
+
+    async fn subscribe_entities(
+        &self,
+        request: Request<SubscribeEntitiesRequest>,
+    ) -> Result<Response<Self::SubscribeEntitiesStream>, Status> {
+        World::subscribe_entities(self.world(&request)?, request).await
+    }
+
+    type SubscribeMetadataStream = <DojoWorld as World>::SubscribeMetadataStream;
+
+    async fn subscribe_metadata(
+        &self,
+        request: Request<SubscribeMetadataRequest>,
+    ) -> Result<Response<Self::SubscribeMetadataStream>, Status> {
+        World::subscribe_metadata(self.world(&request)?, request).await
+    }
+
+    type SubscribeAggregateStream = <DojoWorld as World>::SubscribeAggregateStream;
+
+    async fn subscribe_aggregate(
+        &self,
+        request: Request<SubscribeAggregateRequest>,
+    ) -> Result<Response<Self::SubscribeAggregateStream>, Status> {
+        World::subscribe_aggregate(self.world(&request)?, request).await
+    }
+}
+
+#[tonic::async_trait]
+impl Health for DojoWorlds {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        // an empty service name refers to the server as a whole
+        let service = request.into_inner().service;
+        if !service.is_empty() && service != <WorldServer<Self> as NamedService>::NAME {
+            return Err(Status::not_found(format!("Unknown service `{service}`")));
+        }
+
+        let ready = join_all(self.worlds.values().map(DojoWorld::is_ready)).await;
+        let status = if ready.into_iter().all(|ready| ready) {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        };
+
+        Ok(Response::new(HealthCheckResponse { status: status as i32 }))
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+    async fn watch(
+        &self,
+        _request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        Err(Status::unimplemented("Watching the health of the service is not supported"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use sqlx::SqlitePool;
+    use starknet::providers::jsonrpc::HttpTransport;
+    use starknet::providers::JsonRpcClient;
+    use starknet_crypto::FieldElement;
+    use tonic::server::NamedService;
+    use tonic::{Code, Request};
+    use url::Url;
+
+    use super::DojoWorlds;
+    use crate::protos::health::health_check_response::ServingStatus;
+    use crate::protos::health::health_server::Health;
+    use crate::protos::health::HealthCheckRequest;
+    use crate::protos::world::world_server::WorldServer;
+    use crate::server::{DojoWorld, DojoWorldConfig};
+    use crate::WORLD_ADDRESS_METADATA_KEY;
+
+    fn dojo_world(
+        pool: SqlitePool,
+        world_address: FieldElement,
+        config: DojoWorldConfig,
+    ) -> DojoWorld {
+        let (_, block_rx) = tokio::sync::mpsc::channel(1);
+        let provider =
+            JsonRpcClient::new(HttpTransport::new(Url::parse("http://localhost:5050").unwrap()));
+
+        DojoWorld::new(pool, block_rx, world_address, Arc::new(provider), config)
+    }
+
+    /// The address of the world `request` is routed to, or the code of the error.
+    fn route(worlds: &DojoWorlds, world_address: Option<&str>) -> Result<FieldElement, Code> {
+        let mut request = Request::new(());
+        if let Some(address) = world_address {
+            request.metadata_mut().insert(WORLD_ADDRESS_METADATA_KEY, address.parse().unwrap());
+        }
+
+        worlds.world(&request).map(|world| world.world_address).map_err(|status| status.code())
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn requests_are_routed_by_world_address(pool: SqlitePool) {
+        let worlds = DojoWorlds::new([
+            dojo_world(pool.clone(), FieldElement::ONE, DojoWorldConfig::default()),
+            dojo_world(pool, FieldElement::TWO, DojoWorldConfig::default()),
+        ]);
+
+        assert_eq!(route(&worlds, Some("0x1")), Ok(FieldElement::ONE));
+        assert_eq!(route(&worlds, Some("0x0002")), Ok(FieldElement::TWO));
+
+        assert_eq!(route(&worlds, Some("0x3")), Err(Code::NotFound));
+        assert_eq!(route(&worlds, Some("world")), Err(Code::InvalidArgument));
+        // the world can't be guessed when several are hosted
+        assert_eq!(route(&worlds, None), Err(Code::InvalidArgument));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn single_world_is_selected_by_default(pool: SqlitePool) {
+        let worlds =
+            DojoWorlds::new([dojo_world(pool, FieldElement::ONE, DojoWorldConfig::default())]);
+
+        assert_eq!(route(&worlds, None), Ok(FieldElement::ONE));
+        assert_eq!(route(&worlds, Some("0x1")), Ok(FieldElement::ONE));
+        assert_eq!(route(&worlds, Some("0x2")), Err(Code::NotFound));
+    }
+
+    /// The serving status of `worlds`, as reported for `service`, or the code of the error.
+    async fn health(worlds: &DojoWorlds, service: &str) -> Result<ServingStatus, Code> {
+        let request = Request::new(HealthCheckRequest { service: service.to_string() });
+        let response = Health::check(worlds, request).await.map_err(|status| status.code())?;
+        Ok(response.into_inner().status())
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn health_of_the_worlds(pool: SqlitePool) {
+        let world = dojo_world(pool.clone(), FieldElement::ONE, DojoWorldConfig::default());
+        let worlds = DojoWorlds::new([world.clone()]);
+        let service = <WorldServer<DojoWorlds> as NamedService>::NAME;
+
+        // the lag isn't checked without a maximum
+        assert_eq!(health(&worlds, "").await, Ok(ServingStatus::Serving));
+        assert_eq!(health(&worlds, service).await, Ok(ServingStatus::Serving));
+        assert_eq!(health(&worlds, "grpc.health.v1.Health").await, Err(Code::NotFound));
+
+        // the state updates sent to the subscribers can't be fetched
+        world.provider_failing.store(true, Ordering::Relaxed);
+        assert_eq!(health(&worlds, "").await, Ok(ServingStatus::NotServing));
+        world.provider_failing.store(false, Ordering::Relaxed);
+        assert_eq!(health(&worlds, "").await, Ok(ServingStatus::Serving));
+
+        // the lag of a world which hasn't indexed any block can't be computed
+        let lagging = DojoWorldConfig { max_lag_blocks: Some(10), ..Default::default() };
+        let worlds = DojoWorlds::new([world, dojo_world(pool, FieldElement::TWO, lagging)]);
+        assert_eq!(health(&worlds, "").await, Ok(ServingStatus::NotServing));
+        assert_eq!(health(&worlds, service).await, Ok(ServingStatus::NotServing));
+    }
+}
@@ -0,0 +1,318 @@
+//! Helpers to test clients against a [`DojoWorld`] running in process, over an in-memory SQLite
+//! database seeded with the models and entities of the test.
+//!
+//! ```no_run
+//! # async fn example(position: dojo_types::schema::Ty) {
+//! use torii_grpc::testing::TestWorldBuilder;
+//!
+//! let world =
+//!     TestWorldBuilder::new().model(position.clone()).entity(position).build().await.unwrap();
+//! let server = world.serve().await.unwrap();
+//! let mut client = server.client().await.unwrap();
+//!
+//! let metadata = client.metadata().await.unwrap();
+//! # }
+//! ```
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use dojo_types::primitive::Primitive;
+use dojo_types::schema::Ty;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::{Pool, Sqlite};
+use starknet::core::utils::get_selector_from_name;
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::JsonRpcClient;
+use starknet_crypto::FieldElement;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::TcpListenerStream;
+use torii_core::sql::Sql;
+use url::Url;
+
+use crate::client::WorldClient;
+use crate::server::{DojoWorld, DojoWorldConfig};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Builds a [`TestWorld`]. The models are registered first, in order, then the entities are set.
+#[derive(Debug, Clone)]
+pub struct TestWorldBuilder {
+    world_address: FieldElement,
+    rpc_url: Url,
+    config: DojoWorldConfig,
+    models: Vec<Ty>,
+    entities: Vec<Ty>,
+}
+
+impl Default for TestWorldBuilder {
+    fn default() -> Self {
+        Self {
+            world_address: FieldElement::ONE,
+            rpc_url: Url::parse("http://localhost:5050").unwrap(),
+            config: DojoWorldConfig::default(),
+            models: vec![],
+            entities: vec![],
+        }
+    }
+}
+
+impl TestWorldBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn world_address(mut self, world_address: FieldElement) -> Self {
+        self.world_address = world_address;
+        self
+    }
+
+    /// The node used to check the world class hash and to fetch the state updates sent to the
+    /// subscribers. The requests needing it fail if no node is listening.
+    pub fn rpc_url(mut self, rpc_url: Url) -> Self {
+        self.rpc_url = rpc_url;
+        self
+    }
+
+    pub fn config(mut self, config: DojoWorldConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Registers a model, described by its schema. Its class hash is derived from its name and its
+    /// layout from its members, as computed by the `Introspect` derive.
+    pub fn model(mut self, model: Ty) -> Self {
+        self.models.push(model);
+        self
+    }
+
+    /// Sets the value of an entity for a model, from the schema of the model holding the values of
+    /// the entity.
+    pub fn entity(mut self, entity: Ty) -> Self {
+        self.entities.push(entity);
+        self
+    }
+
+    pub async fn build(self) -> Result<TestWorld, BoxError> {
+        let pool = SqlitePoolOptions::new().max_connections(5).connect("sqlite::memory:").await?;
+        sqlx::migrate!("../migrations").run(&pool).await?;
+
+        let mut db = Sql::new(pool.clone(), self.world_address).await?;
+        for model in self.models {
+            let mut layout = vec![];
+            model_layout(&model, &mut layout);
+
+            let class_hash = get_selector_from_name(&model.name())?;
+            let packed_size = packed_size(&layout);
+            let unpacked_size = layout.len() as u8;
+            let layout = layout.into_iter().map(FieldElement::from).collect();
+
+            db.register_model(model, layout, class_hash, packed_size, unpacked_size).await?;
+        }
+
+        for (idx, entity) in self.entities.into_iter().enumerate() {
+            db.set_entity(entity, &format!("{idx:#x}")).await?;
+        }
+
+        // the subscriptions are only notified of the blocks sent on this channel
+        let (blocks, block_rx) = mpsc::channel(16);
+        let provider = Arc::new(JsonRpcClient::new(HttpTransport::new(self.rpc_url)));
+        let world =
+            DojoWorld::new(pool.clone(), block_rx, self.world_address, provider, self.config);
+
+        Ok(TestWorld { world, pool, world_address: self.world_address, blocks })
+    }
+}
+
+/// A world served from an in-memory database.
+pub struct TestWorld {
+    world: DojoWorld,
+    pool: Pool<Sqlite>,
+    world_address: FieldElement,
+    blocks: mpsc::Sender<u64>,
+}
+
+impl TestWorld {
+    pub fn world(&self) -> &DojoWorld {
+        &self.world
+    }
+
+    /// The database of the world, to change its content during a test.
+    pub fn pool(&self) -> &Pool<Sqlite> {
+        &self.pool
+    }
+
+    /// Notifies the world that a block was indexed. The subscribers are sent the updates of the
+    /// block, fetched from the node.
+    pub async fn process_block(&self, block_number: u64) {
+        // fails if the world was dropped, in which case there's nothing to notify
+        let _ = self.blocks.send(block_number).await;
+    }
+
+    /// Serves the world on a random local port, until the returned server is dropped.
+    pub async fn serve(&self) -> Result<TestServer, BoxError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(
+            self.world
+                .keepalive()
+                .apply(tonic::transport::Server::builder())
+                .add_service(self.world.clone().into_service())
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    let _ = shutdown_rx.await;
+                }),
+        );
+
+        Ok(TestServer { addr, world_address: self.world_address, shutdown: Some(shutdown), handle })
+    }
+}
+
+/// A [`TestWorld`] served over gRPC.
+pub struct TestServer {
+    addr: SocketAddr,
+    world_address: FieldElement,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<Result<(), tonic::transport::Error>>,
+}
+
+impl TestServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Connects a client to the world.
+    pub async fn client(&self) -> Result<WorldClient, crate::client::Error> {
+        WorldClient::new(format!("http://{}", self.addr), self.world_address).await
+    }
+
+    /// Stops the server, once the pending requests are completed.
+    pub async fn shutdown(mut self) -> Result<(), BoxError> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+
+        (&mut self.handle).await??;
+        Ok(())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Appends the layout of `ty` to `layout`, ie. the number of bits of each of its felts once packed,
+/// skipping the key members of the model.
+fn model_layout(ty: &Ty, layout: &mut Vec<u8>) {
+    match ty {
+        Ty::Primitive(primitive) => match primitive {
+            Primitive::Bool(_) => layout.push(1),
+            Primitive::U8(_) => layout.push(8),
+            Primitive::U16(_) => layout.push(16),
+            Primitive::U32(_) | Primitive::USize(_) => layout.push(32),
+            Primitive::U64(_) => layout.push(64),
+            Primitive::U128(_) => layout.push(128),
+            Primitive::U256(_) => layout.extend([128, 128]),
+            _ => layout.push(251),
+        },
+        Ty::Struct(s) => {
+            s.children.iter().filter(|m| !m.key).for_each(|m| model_layout(&m.ty, layout))
+        }
+        // the options all hold the same type
+        Ty::Enum(e) => {
+            layout.push(8);
+            if let Some(option) = e.options.first() {
+                model_layout(&option.ty, layout);
+            }
+        }
+        Ty::Tuple(tys) => tys.iter().for_each(|ty| model_layout(ty, layout)),
+    }
+}
+
+/// The number of felts of the values packed following `layout`, as computed by
+/// `dojo::packing::calculate_packed_size`.
+fn packed_size(layout: &[u8]) -> u8 {
+    let mut size = 1;
+    let mut partial = 0_usize;
+
+    for &bits in layout {
+        partial += bits as usize;
+        if partial > 251 {
+            size += 1;
+            partial = bits as usize;
+        }
+    }
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use dojo_types::primitive::Primitive;
+    use dojo_types::schema::{Clause, EntityQuery, KeysClause, Member, Struct, Ty};
+    use starknet_crypto::FieldElement;
+
+    use super::TestWorldBuilder;
+
+    fn position(player: Option<FieldElement>, x: Option<u32>, z: Option<FieldElement>) -> Ty {
+        Ty::Struct(Struct {
+            name: "Position".into(),
+            children: vec![
+                Member {
+                    name: "player".into(),
+                    key: true,
+                    ty: Ty::Primitive(Primitive::ContractAddress(player)),
+                },
+                Member { name: "x".into(), key: false, ty: Ty::Primitive(Primitive::U32(x)) },
+                Member {
+                    name: "y".into(),
+                    key: false,
+                    ty: Ty::Primitive(Primitive::U128(x.map(u128::from))),
+                },
+                Member { name: "z".into(), key: false, ty: Ty::Primitive(Primitive::Felt252(z)) },
+            ],
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_the_seeded_world() {
+        let entity = position(Some(FieldElement::TWO), Some(7), Some(FieldElement::ONE));
+        let world = TestWorldBuilder::new()
+            .model(position(None, None, None))
+            .entity(entity)
+            .build()
+            .await
+            .unwrap();
+        let server = world.serve().await.unwrap();
+        let mut client = server.client().await.unwrap();
+
+        let info = client.server_info().await.unwrap();
+        assert_eq!(info.protocol_version, crate::PROTOCOL_VERSION);
+        assert_eq!(info.schema_version, torii_core::sql::SCHEMA_VERSION);
+        assert_eq!(info.entity_id_scheme, torii_core::EntityIdScheme::Poseidon);
+
+        let metadata = client.metadata_with_entity_count().await.unwrap();
+        let model = &metadata.models["Position"];
+        assert_eq!(model.layout, vec![FieldElement::from(32u8), 128u8.into(), 251u8.into()]);
+        assert_eq!((model.packed_size, model.unpacked_size), (2, 3));
+        assert_eq!(model.entity_count, Some(1));
+
+        let query = EntityQuery {
+            model: "Position".into(),
+            clause: Clause::Keys(KeysClause { keys: vec![FieldElement::TWO] }),
+            members: vec![],
+        };
+        let entities = client.retrieve_entities(query, 0, 0, None).await.unwrap();
+        assert_eq!(entities.len(), 1);
+        assert_eq!(entities[0].keys, vec![FieldElement::TWO]);
+
+        server.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,8 @@
+CREATE TABLE schema_cache (
+    model_id TEXT NOT NULL PRIMARY KEY,
+    class_hash TEXT NOT NULL,
+    schema_hash TEXT NOT NULL,
+    schema TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (model_id) REFERENCES models(id)
+);
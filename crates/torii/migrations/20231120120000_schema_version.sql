@@ -0,0 +1,3 @@
+-- The version of the database schema, checked by the servers reading the database. Every
+-- migration that changes the schema must bump it, along with `torii_core::sql::SCHEMA_VERSION`.
+PRAGMA user_version = 1;
@@ -0,0 +1,9 @@
+-- The systems allowed to write each model, granted through the `WriterUpdated` events of the world.
+CREATE TABLE model_writers (
+    model_id TEXT NOT NULL,
+    writer TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (model_id, writer)
+);
+
+PRAGMA user_version = 2;
@@ -0,0 +1,5 @@
+-- The address of the account which sent the transaction registering each model, unknown for the
+-- models registered before it was recorded.
+ALTER TABLE models ADD COLUMN registered_by TEXT;
+
+PRAGMA user_version = 3;
@@ -0,0 +1,18 @@
+-- The storage entries of the worlds written by each of their last indexed blocks, recorded by the
+-- servers so that the entity changes of these blocks can be replayed without the node.
+CREATE TABLE changelog_blocks (
+    world_address TEXT NOT NULL,
+    block_number INTEGER NOT NULL,
+    block_hash TEXT NOT NULL,
+    PRIMARY KEY (world_address, block_number)
+);
+
+CREATE TABLE changelog_entries (
+    world_address TEXT NOT NULL,
+    block_number INTEGER NOT NULL,
+    storage_key TEXT NOT NULL,
+    storage_value TEXT NOT NULL,
+    PRIMARY KEY (world_address, block_number, storage_key)
+);
+
+PRAGMA user_version = 4;
@@ -1,22 +1,36 @@
 mod server;
 use std::net::SocketAddr;
+use std::num::{NonZeroU32, NonZeroU64};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use anyhow::bail;
 use clap::Parser;
 use dojo_world::contracts::world::WorldContractReader;
-use server::Server;
+use futures::future::try_join_all;
+use http::header::HeaderName;
+use http::Method;
+use server::{CorsConfig, Server};
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Pool, Sqlite};
 use starknet::core::types::FieldElement;
 use starknet::providers::jsonrpc::HttpTransport;
-use starknet::providers::JsonRpcClient;
+use starknet::providers::{JsonRpcClient, Provider};
 use tokio_util::sync::CancellationToken;
 use torii_core::engine::{Engine, EngineConfig, Processors};
+use torii_core::json::{AddressFormat, JsonOptions};
 use torii_core::processors::metadata_update::MetadataUpdateProcessor;
 use torii_core::processors::register_model::RegisterModelProcessor;
 use torii_core::processors::store_set_record::StoreSetRecordProcessor;
 use torii_core::processors::store_transaction::StoreTransactionProcessor;
+use torii_core::processors::writer_updated::WriterUpdatedProcessor;
 use torii_core::sql::Sql;
+use torii_core::EntityIdScheme;
+use torii_grpc::server::entity_cache::EntityCacheConfig;
+use torii_grpc::server::keepalive::KeepAliveConfig;
+use torii_grpc::server::retry::RetryPolicy;
+use torii_grpc::server::DojoWorldConfig;
 use tracing::error;
 use tracing_subscriber::fmt;
 use url::Url;
@@ -25,16 +39,19 @@ use url::Url;
 #[derive(Parser, Debug)]
 #[command(name = "torii", author, version, about, long_about = None)]
 struct Args {
-    /// The world to index
-    #[arg(short, long = "world", env = "DOJO_WORLD_ADDRESS")]
-    world_address: FieldElement,
+    /// The worlds to index (comma-separated list). The gRPC api serves all of them, selected by
+    /// the `world-address` metadata of the requests, the other endpoints only serve the first one
+    #[arg(short, long = "world", env = "DOJO_WORLD_ADDRESS", required = true)]
+    #[arg(value_delimiter = ',')]
+    world_addresses: Vec<FieldElement>,
     /// The rpc endpoint to use
     #[arg(long, default_value = "http://localhost:5050")]
     rpc: String,
-    /// Database filepath (ex: indexer.db). If specified file doesn't exist, it will be
-    /// created. Defaults to in-memory database
-    #[arg(short, long, default_value = ":memory:")]
-    database: String,
+    /// Database filepaths (ex: indexer.db), one per world in the same order (comma-separated
+    /// list). If specified file doesn't exist, it will be created. Defaults to in-memory databases
+    #[arg(short, long = "database")]
+    #[arg(value_delimiter = ',')]
+    databases: Vec<String>,
     /// Specify a block to start indexing from, ignored if stored head exists
     #[arg(short, long, default_value = "0")]
     start_block: u64,
@@ -45,14 +62,117 @@ struct Args {
     #[arg(long, default_value = "8080")]
     port: u16,
     /// Specify allowed origins for api endpoints (comma-separated list of allowed origins, or "*"
-    /// for all)
-    #[arg(long, default_value = "*")]
+    /// for all). Cross-origin requests are rejected if unset. The browsers send the origin of the
+    /// requests of the GraphQL playground, which must thus be allowed to use it
+    #[arg(long)]
     #[arg(value_delimiter = ',')]
     allowed_origins: Vec<String>,
+    /// Methods allowed in the cross-origin requests to the api endpoints (comma-separated list)
+    #[arg(long, default_value = "POST,GET,OPTIONS")]
+    #[arg(value_delimiter = ',')]
+    allowed_methods: Vec<Method>,
+    /// Headers allowed in the cross-origin requests to the api endpoints (comma-separated list)
+    #[arg(long, default_value = "accept,origin,content-type,access-control-allow-origin")]
+    #[arg(value_delimiter = ',')]
+    allowed_headers: Vec<HeaderName>,
     /// The external url of the server, used for configuring the GraphQL Playground in a hosted
     /// environment
     #[arg(long)]
     external_url: Option<Url>,
+    /// Interval (in seconds) at which heartbeat messages are sent on idle subscription streams
+    #[arg(long, default_value = "30")]
+    heartbeat_interval: NonZeroU64,
+    /// Persist the decoded model schemas in the database to speed up the first requests after a
+    /// restart
+    #[arg(long)]
+    persist_schema_cache: bool,
+    /// Minimum interval, in seconds, between two checks of the indexed world class hash against
+    /// the one deployed on chain
+    #[arg(long, default_value = "60")]
+    world_class_hash_check_interval: u64,
+    /// Maximum size, in bytes, of the gRPC response messages
+    #[arg(long, default_value = "67108864")]
+    grpc_max_encoding_message_size: usize,
+    /// Maximum size, in bytes, of the gRPC request messages
+    #[arg(long, default_value = "4194304")]
+    grpc_max_decoding_message_size: usize,
+    /// Maximum number of blocks the indexer can lag behind the chain head before the gRPC health
+    /// service reports it as not serving. The lag is not checked if unset
+    #[arg(long)]
+    max_lag_blocks: Option<u64>,
+    /// Duration, in milliseconds, above which the model schema and metadata queries of the gRPC
+    /// handlers are logged as slow
+    #[arg(long, default_value = "1000")]
+    slow_query_threshold: u64,
+    /// Maximum number of updates per second sent to a subscriber, subscribers can request a lower
+    /// limit
+    #[arg(long, default_value = "100")]
+    max_updates_per_second: NonZeroU32,
+    /// Maximum number of queries of an entity subscription
+    #[arg(long, default_value = "256")]
+    max_subscription_queries: usize,
+    /// Maximum number of blocks whose updates are held while the subscriptions are paused, the
+    /// subscriptions are closed once exceeded
+    #[arg(long, default_value = "256")]
+    max_paused_blocks: usize,
+    /// Token authorizing the requests to the admin endpoints, sent as a bearer token. The admin
+    /// endpoints are disabled if unset
+    #[arg(long, env = "TORII_ADMIN_TOKEN")]
+    admin_token: Option<String>,
+    /// Names of the models served by the gRPC api (comma-separated list). The other models are
+    /// reported as not found and left out of the world metadata. All the models are served if
+    /// unset
+    #[arg(long)]
+    #[arg(value_delimiter = ',')]
+    model_allowlist: Option<Vec<String>>,
+    /// Maximum number of entities whose values read by id are cached. An entity is dropped from
+    /// the cache once written. The entities are always read from the database if unset
+    #[arg(long)]
+    entity_cache_capacity: Option<usize>,
+    /// Duration, in milliseconds, an entity stays cached if it isn't written
+    #[arg(long, default_value = "2000")]
+    entity_cache_ttl: u64,
+    /// Hash the world derives the entity ids from the keys with, `pedersen` for the worlds
+    /// deployed before the switch to `poseidon`
+    #[arg(long, default_value = "poseidon")]
+    entity_id_scheme: EntityIdScheme,
+    /// Render the values of the entities read through the gRPC api as JSON, along with their
+    /// encoded schema
+    #[arg(long)]
+    json_values: bool,
+    /// How the contract addresses and class hashes are rendered in the JSON values: `felt`,
+    /// `padded` to 64 hex digits, or `checksummed`. Only used with `--json-values`
+    #[arg(long, default_value = "padded")]
+    json_addresses: AddressFormat,
+    /// Duration, in seconds, after which a hydration fails, releasing the connection of the
+    /// database holding its snapshot
+    #[arg(long, default_value = "120")]
+    hydrate_timeout: u64,
+    /// Number of the last indexed blocks whose entity changes are recorded, to be replayed by the
+    /// subscribers
+    #[arg(long, default_value = "10000")]
+    replay_retention_blocks: u64,
+    /// Maximum number of attempts of the requests of the state updates sent to the subscribers,
+    /// when the node fails to answer them
+    #[arg(long, default_value = "5")]
+    provider_max_attempts: NonZeroU32,
+    /// Delay, in milliseconds, before the first retry of a failed request to the node, doubled
+    /// for every following retry
+    #[arg(long, default_value = "200")]
+    provider_retry_delay: u64,
+    /// Interval, in seconds, at which HTTP/2 pings are sent to keep the connections of the
+    /// clients alive. Should be shorter than the idle timeout of the proxies in front of the
+    /// server. Pings are not sent if 0
+    #[arg(long, default_value = "20")]
+    http2_keepalive_interval: u64,
+    /// Duration, in seconds, to wait for the acknowledgement of an HTTP/2 ping before closing the
+    /// connection
+    #[arg(long, default_value = "20")]
+    http2_keepalive_timeout: u64,
+    /// Interval, in seconds, of the TCP keepalive probes of the connections. Probes are not sent
+    /// if 0
+    #[arg(long, default_value = "60")]
+    tcp_keepalive: u64,
 }
 
 #[tokio::main]
@@ -75,57 +195,103 @@ async fn main() -> anyhow::Result<()> {
         }
     })?;
 
-    let database_url = format!("sqlite:{}", &args.database);
-    let options = SqliteConnectOptions::from_str(&database_url)?.create_if_missing(true);
-    let pool = SqlitePoolOptions::new()
-        .min_connections(1)
-        .max_connections(5)
-        .connect_with(options)
-        .await?;
-
-    sqlx::migrate!("../migrations").run(&pool).await?;
+    let databases = match args.databases.len() {
+        0 => vec![":memory:".to_string(); args.world_addresses.len()],
+        len if len == args.world_addresses.len() => args.databases,
+        len => bail!(
+            "expected a database per world, got {len} databases for {} worlds",
+            args.world_addresses.len()
+        ),
+    };
 
-    let provider: Arc<_> = JsonRpcClient::new(HttpTransport::new(Url::parse(&args.rpc)?)).into();
+    let rpc_url = Url::parse(&args.rpc)?;
+    let provider: Arc<_> = JsonRpcClient::new(HttpTransport::new(rpc_url.clone())).into();
 
-    // Get world address
-    let world = WorldContractReader::new(args.world_address, &provider);
+    // each world is indexed into its own database
+    let mut dbs = Vec::with_capacity(databases.len());
+    let mut block_senders = Vec::with_capacity(databases.len());
+    let mut worlds = Vec::with_capacity(databases.len());
+    for (world_address, database) in args.world_addresses.iter().zip(&databases) {
+        let pool = connect(database).await?;
+        let db = Sql::new(pool.clone(), *world_address)
+            .await?
+            .with_entity_id_scheme(args.entity_id_scheme);
+        let (block_sender, block_receiver) = tokio::sync::mpsc::channel(100);
 
-    let mut db = Sql::new(pool.clone(), args.world_address).await?;
-    let processors = Processors {
-        event: vec![
-            Box::new(RegisterModelProcessor),
-            Box::new(StoreSetRecordProcessor),
-            Box::new(MetadataUpdateProcessor),
-        ],
-        transaction: vec![Box::new(StoreTransactionProcessor)],
-        ..Processors::default()
-    };
-
-    let (block_sender, block_receiver) = tokio::sync::mpsc::channel(100);
+        dbs.push(db);
+        block_senders.push((*world_address, block_sender));
+        worlds.push((*world_address, pool, block_receiver));
+    }
 
-    let mut engine = Engine::new(
-        world,
-        &mut db,
-        &provider,
-        processors,
-        EngineConfig { start_block: args.start_block, ..Default::default() },
-        Some(block_sender),
-    );
+    let mut engines = dbs
+        .iter_mut()
+        .zip(block_senders)
+        .map(|(db, (world_address, block_sender))| {
+            Engine::new(
+                WorldContractReader::new(world_address, &provider),
+                db,
+                &provider,
+                processors(),
+                EngineConfig { start_block: args.start_block, ..Default::default() },
+                Some(block_sender),
+            )
+        })
+        .collect::<Vec<_>>();
 
     let addr: SocketAddr = format!("{}:{}", args.host, args.port).parse()?;
 
     let server = Server::new(
         addr,
-        pool,
-        block_receiver,
-        args.world_address,
+        worlds,
         Arc::clone(&provider),
-        args.allowed_origins,
+        CorsConfig {
+            allowed_origins: args.allowed_origins,
+            allowed_methods: args.allowed_methods,
+            allowed_headers: args.allowed_headers,
+        },
         args.external_url,
-    );
+        args.admin_token,
+        DojoWorldConfig {
+            heartbeat_interval: Duration::from_secs(args.heartbeat_interval.get()),
+            persist_schema_cache: args.persist_schema_cache,
+            world_class_hash_check_interval: Duration::from_secs(
+                args.world_class_hash_check_interval,
+            ),
+            max_encoding_message_size: args.grpc_max_encoding_message_size,
+            max_decoding_message_size: args.grpc_max_decoding_message_size,
+            max_lag_blocks: args.max_lag_blocks,
+            slow_query_threshold: Duration::from_millis(args.slow_query_threshold),
+            max_updates_per_second: args.max_updates_per_second,
+            max_subscription_queries: args.max_subscription_queries,
+            max_paused_blocks: args.max_paused_blocks,
+            // the indexer of this server creates the model tables without namespace
+            table_namespace: None,
+            replay_retention_blocks: args.replay_retention_blocks,
+            rpc_url: Some(rpc_url),
+            provider_retry: RetryPolicy {
+                max_attempts: args.provider_max_attempts,
+                base_delay: Duration::from_millis(args.provider_retry_delay),
+            },
+            keepalive: KeepAliveConfig {
+                http2_keepalive_interval: non_zero_secs(args.http2_keepalive_interval),
+                http2_keepalive_timeout: Duration::from_secs(args.http2_keepalive_timeout),
+                tcp_keepalive: non_zero_secs(args.tcp_keepalive),
+            },
+            model_allowlist: args.model_allowlist.map(|models| models.into_iter().collect()),
+            entity_cache: args.entity_cache_capacity.map(|capacity| EntityCacheConfig {
+                capacity,
+                ttl: Duration::from_millis(args.entity_cache_ttl),
+            }),
+            entity_id_scheme: args.entity_id_scheme,
+            json_values: args.json_values.then_some(JsonOptions { addresses: args.json_addresses }),
+            // below the connections of the pool, see `connect`
+            max_concurrent_hydrations: 2,
+            hydrate_timeout: Duration::from_secs(args.hydrate_timeout),
+        },
+    )?;
 
     tokio::select! {
-        res = engine.start(cts) => {
+        res = try_join_all(engines.iter_mut().map(|engine| engine.start(cts.clone()))) => {
             if let Err(e) = res {
                 error!("Indexer failed with error: {e}");
             }
@@ -144,3 +310,37 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Connects to the database at `path`, created if it doesn't exist, and migrates it.
+async fn connect(path: &str) -> anyhow::Result<Pool<Sqlite>> {
+    let options =
+        SqliteConnectOptions::from_str(&format!("sqlite:{path}"))?.create_if_missing(true);
+    let pool = SqlitePoolOptions::new()
+        .min_connections(1)
+        .max_connections(5)
+        .connect_with(options)
+        .await?;
+
+    sqlx::migrate!("../migrations").run(&pool).await?;
+
+    Ok(pool)
+}
+
+/// The processors of the events and transactions of an indexed world.
+fn processors<P: Provider + Send + Sync>() -> Processors<P> {
+    Processors {
+        event: vec![
+            Box::new(RegisterModelProcessor),
+            Box::new(StoreSetRecordProcessor),
+            Box::new(MetadataUpdateProcessor),
+            Box::new(WriterUpdatedProcessor),
+        ],
+        transaction: vec![Box::new(StoreTransactionProcessor)],
+        ..Processors::default()
+    }
+}
+
+/// A duration of `secs` seconds, or `None` if 0.
+fn non_zero_secs(secs: u64) -> Option<Duration> {
+    (secs != 0).then_some(Duration::from_secs(secs))
+}
@@ -6,8 +6,9 @@ use std::sync::Arc;
 use std::task::Poll;
 
 use either::Either;
-use http::header::{ACCEPT, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE, ORIGIN};
-use http::Method;
+use anyhow::anyhow;
+use http::header::{HeaderName, ACCEPT, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE, ORIGIN};
+use http::{HeaderValue, Method};
 use hyper::service::{make_service_fn, Service};
 use hyper::Uri;
 use sqlx::{Pool, Sqlite};
@@ -17,11 +18,13 @@ use starknet_crypto::FieldElement;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::Notify;
 use tokio_stream::StreamExt;
+use tonic::transport::server::Routes;
 use tonic_web::GrpcWebLayer;
 use torii_core::simple_broker::SimpleBroker;
 use torii_core::types::Model;
-use torii_grpc::protos;
-use torii_grpc::server::DojoWorld;
+use torii_grpc::protos::world::CloseReason;
+use torii_grpc::server::worlds::DojoWorlds;
+use torii_grpc::server::{DojoWorld, DojoWorldConfig};
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer as TonicCors};
 use tracing::info;
@@ -31,31 +34,74 @@ use warp::Filter;
 
 type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
 
+/// The cross-origin requests accepted from the browsers by the GraphQL and gRPC-web endpoints.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// The origins allowed to send requests, eg. `https://example.com`, or `*` for any origin.
+    /// Cross-origin requests are rejected if empty.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+}
+
+impl Default for CorsConfig {
+    /// Rejects all the cross-origin requests.
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![],
+            allowed_methods: vec![Method::POST, Method::GET, Method::OPTIONS],
+            allowed_headers: vec![ACCEPT, ORIGIN, CONTENT_TYPE, ACCESS_CONTROL_ALLOW_ORIGIN],
+        }
+    }
+}
+
 pub struct Server {
     addr: SocketAddr,
+    /// The database of the first world, served by the GraphQL api.
     pool: Pool<Sqlite>,
-    world: DojoWorld,
-    allowed_origins: Vec<String>,
+    /// The served worlds, the first one also being served by the admin endpoints and the metrics.
+    worlds: Vec<DojoWorld>,
+    cors: (WarpCors, TonicCors),
     external_url: Option<Url>,
+    admin_token: Option<String>,
 }
 
 impl Server {
+    /// Serves the `worlds`, each indexed into its own database along with the receiver of the
+    /// blocks processed by its indexer. The gRPC api serves all of them, the other endpoints only
+    /// serve the first one.
+    ///
+    /// Fails if no world is given, or if one of the allowed origins of `cors` isn't a valid
+    /// origin. The admin endpoints are only served if `admin_token` is set, see [`admin_routes`].
     pub fn new(
         addr: SocketAddr,
-        pool: Pool<Sqlite>,
-        block_rx: Receiver<u64>,
-        world_address: FieldElement,
+        worlds: Vec<(FieldElement, Pool<Sqlite>, Receiver<u64>)>,
         provider: Arc<JsonRpcClient<HttpTransport>>,
-        allowed_origins: Vec<String>,
+        cors: CorsConfig,
         external_url: Option<Url>,
-    ) -> Self {
-        let world =
-            torii_grpc::server::DojoWorld::new(pool.clone(), block_rx, world_address, provider);
-
-        Self { addr, pool, world, allowed_origins, external_url }
+        admin_token: Option<String>,
+        config: DojoWorldConfig,
+    ) -> anyhow::Result<Self> {
+        let cors = configure_cors(&cors)?;
+        let pool = worlds
+            .first()
+            .map(|(_, pool, _)| pool.clone())
+            .ok_or_else(|| anyhow!("no world to serve"))?;
+        let worlds = worlds
+            .into_iter()
+            .map(|(world_address, pool, block_rx)| {
+                DojoWorld::new(pool, block_rx, world_address, Arc::clone(&provider), config.clone())
+            })
+            .collect();
+
+        Ok(Self { addr, pool, worlds, cors, external_url, admin_token })
     }
 
     pub async fn start(&self) -> anyhow::Result<()> {
+        for world in &self.worlds {
+            world.verify().await?;
+        }
+
         let notify_restart = Arc::new(Notify::new());
 
         info!("🚀 Torii listening at {}", format!("http://{}", self.addr));
@@ -67,10 +113,11 @@ impl Server {
             let server_handle = tokio::spawn(spawn(
                 self.addr,
                 self.pool.clone(),
-                self.world.clone(),
+                self.worlds.clone(),
                 notify_restart.clone(),
-                self.allowed_origins.clone(),
+                self.cors.clone(),
                 self.external_url.clone(),
+                self.admin_token.clone(),
             ));
 
             match server_handle.await {
@@ -97,27 +144,46 @@ async fn model_registered_listener(notify_restart: Arc<Notify>) {
 async fn spawn(
     addr: SocketAddr,
     pool: Pool<Sqlite>,
-    dojo_world: DojoWorld,
+    dojo_worlds: Vec<DojoWorld>,
     notify_restart: Arc<Notify>,
-    allowed_origins: Vec<String>,
+    (warp_cors, tonic_cors): (WarpCors, TonicCors),
     external_url: Option<Url>,
+    admin_token: Option<String>,
 ) -> anyhow::Result<()> {
-    let (warp_cors, tonic_cors) = configure_cors(&allowed_origins);
-
+    let dojo_world = dojo_worlds[0].clone();
     let base_route = warp::path::end()
         .and(warp::get())
         .map(|| warp::reply::json(&serde_json::json!({ "success": true })));
-    let routes =
-        torii_graphql::route::filter(&pool, external_url).await.or(base_route).with(warp_cors);
-
-    let warp = warp::service(routes);
-
+    let routes = torii_graphql::route::filter(&pool, external_url)
+        .await
+        .or(base_route)
+        .or(admin_routes(dojo_world.clone(), admin_token));
+
+    #[cfg(feature = "prometheus")]
+    let routes = {
+        let world = dojo_world.clone();
+        let metrics_route = warp::path("metrics")
+            .and(warp::path::end())
+            .and(warp::get())
+            .map(move || world.subscription_metrics().to_prometheus());
+        routes.or(metrics_route)
+    };
+
+    let warp = warp::service(routes.with(warp_cors));
+
+    // kept to close the subscriptions on shutdown, the streams would hold the server up otherwise
+    let served_worlds = dojo_worlds.clone();
+    let keepalive = dojo_world.keepalive();
+    let worlds = DojoWorlds::new(dojo_worlds);
     let tonic = ServiceBuilder::new()
         .layer(tonic_cors)
         .layer(GrpcWebLayer::new())
-        .service(protos::world::world_server::WorldServer::new(dojo_world));
+        .service(Routes::new(worlds.health_service()).add_service(worlds.into_service()));
 
     hyper::Server::bind(&addr)
+        .http2_keep_alive_interval(keepalive.http2_keepalive_interval)
+        .http2_keep_alive_timeout(keepalive.http2_keepalive_timeout)
+        .tcp_keepalive(keepalive.tcp_keepalive)
         .serve(make_service_fn(move |_| {
             let mut tonic = tonic.clone();
             let mut warp = warp.clone();
@@ -158,36 +224,105 @@ async fn spawn(
         }))
         .with_graceful_shutdown(async {
             notify_restart.notified().await;
+            for world in &served_worlds {
+                world.close_subscriptions(CloseReason::ServerShutdown).await;
+            }
         })
         .await?;
 
     Ok(())
 }
 
+/// The endpoints of the operators of the server, authorized by `admin_token` sent as a bearer
+/// token:
+/// * `POST /admin/subscriptions/pause` holds the updates of the entity subscriptions, eg. during a
+///   maintenance of the database, without closing them.
+/// * `POST /admin/subscriptions/resume` sends the held updates and resumes the subscriptions.
+///
+/// Both answer with whether the subscriptions are paused. The endpoints don't exist if
+/// `admin_token` is `None`, and the requests without the token are answered as if they didn't.
+fn admin_routes(
+    world: DojoWorld,
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let admin_token = Arc::new(admin_token.map(|token| format!("Bearer {token}")));
+    let authorized = warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let authorized = match (admin_token.as_deref(), authorization.as_deref()) {
+                (Some(token), Some(authorization)) => {
+                    constant_time_eq(token.as_bytes(), authorization.as_bytes())
+                }
+                _ => false,
+            };
+            async move { if authorized { Ok(()) } else { Err(warp::reject::not_found()) } }
+        })
+        .untuple_one();
+
+    let paused = |world: &DojoWorld| {
+        warp::reply::json(&serde_json::json!({ "paused": world.subscriptions_paused() }))
+    };
+
+    let pause = warp::path!("admin" / "subscriptions" / "pause").and(authorized.clone()).map({
+        let world = world.clone();
+        move || {
+            info!("Pausing the entity subscriptions");
+            world.pause_subscriptions();
+            paused(&world)
+        }
+    });
+    let resume = warp::path!("admin" / "subscriptions" / "resume").and(authorized).map(move || {
+        info!("Resuming the entity subscriptions");
+        world.resume_subscriptions();
+        paused(&world)
+    });
+
+    warp::post().and(pause.or(resume))
+}
+
+/// Whether `a` and `b` are equal, compared in a time which only depends on their lengths so that
+/// the admin token can't be guessed from the response times.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
 /// Build CORS configuration for both `warp` and `tonic` service
-fn configure_cors(origins: &Vec<String>) -> (WarpCors, TonicCors) {
-    let headers = [ACCEPT, ORIGIN, CONTENT_TYPE, ACCESS_CONTROL_ALLOW_ORIGIN];
-    let methods = [Method::POST, Method::GET, Method::OPTIONS];
+fn configure_cors(cors: &CorsConfig) -> anyhow::Result<(WarpCors, TonicCors)> {
+    let headers = cors.allowed_headers.clone();
+    let methods = cors.allowed_methods.clone();
 
     let mut warp_cors = warp::cors().allow_headers(headers.clone()).allow_methods(methods.clone());
     let mut tonic_cors = TonicCors::new().allow_headers(headers).allow_methods(methods);
 
-    match origins.as_slice() {
+    match cors.allowed_origins.as_slice() {
         [origin] if origin == "*" => {
             warp_cors = warp_cors.allow_any_origin();
             tonic_cors = tonic_cors.allow_origin(Any);
         }
 
         origins => {
+            // checked first, warp panics on the invalid origins
+            let values = origins.iter().map(|o| origin(o)).collect::<anyhow::Result<Vec<_>>>()?;
+
+            // the requests of all the origins are rejected if none is allowed
             warp_cors = warp_cors
                 .allow_origins(origins.iter().map(|origin| origin.as_str()).collect::<Vec<_>>());
-            tonic_cors = tonic_cors.allow_origin(
-                origins.iter().map(|o| o.parse().expect("valid origin")).collect::<Vec<_>>(),
-            );
+            tonic_cors = tonic_cors.allow_origin(values);
         }
     }
 
-    (warp_cors.build(), tonic_cors)
+    Ok((warp_cors.build(), tonic_cors))
+}
+
+/// Parses an allowed origin, which must be serialized as sent by the browsers in the `Origin`
+/// header.
+fn origin(origin: &str) -> anyhow::Result<HeaderValue> {
+    match Url::parse(origin) {
+        Ok(url) if url.origin().ascii_serialization() == origin => Ok(origin.parse()?),
+        _ => Err(anyhow!(
+            "invalid allowed origin `{origin}`, expected a scheme, a host and an optional port, \
+             eg. `https://example.com`, or `*` alone"
+        )),
+    }
 }
 
 enum EitherBody<A, B> {
@@ -236,3 +371,117 @@ where
 fn map_option_err<T, U: Into<Error>>(err: Option<Result<T, U>>) -> Option<Result<T, Error>> {
     err.map(|e| e.map_err(Into::into))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use http::StatusCode;
+    use sqlx::SqlitePool;
+    use starknet::providers::jsonrpc::HttpTransport;
+    use starknet::providers::JsonRpcClient;
+    use starknet_crypto::FieldElement;
+    use torii_grpc::server::{DojoWorld, DojoWorldConfig};
+    use url::Url;
+    use warp::Filter;
+
+    use super::{admin_routes, configure_cors, constant_time_eq, origin, CorsConfig};
+
+    /// Returns whether a request sent from `from` is accepted with the `allowed_origins`.
+    async fn accepted(allowed_origins: &[&str], from: &str) -> bool {
+        let cors = CorsConfig {
+            allowed_origins: allowed_origins.iter().map(|origin| origin.to_string()).collect(),
+            ..Default::default()
+        };
+        let (warp_cors, _) = configure_cors(&cors).unwrap();
+        let route = warp::any().map(warp::reply).with(warp_cors);
+
+        let res = warp::test::request().header("origin", from).reply(&route).await;
+        res.status() == StatusCode::OK
+    }
+
+    #[test]
+    fn allowed_origins_are_serialized_origins() {
+        assert!(origin("https://example.com").is_ok());
+        assert!(origin("http://localhost:8080").is_ok());
+
+        for invalid in ["example.com", "https://example.com/", "https://example.com/app", "*"] {
+            assert!(origin(invalid).is_err(), "{invalid}");
+        }
+
+        let cors = CorsConfig {
+            allowed_origins: vec!["https://example.com".into(), "example.com".into()],
+            ..Default::default()
+        };
+        let err = configure_cors(&cors).err().unwrap();
+        assert!(err.to_string().starts_with("invalid allowed origin `example.com`"));
+    }
+
+    #[tokio::test]
+    async fn cross_origin_requests() {
+        assert!(accepted(&["https://example.com"], "https://example.com").await);
+        assert!(!accepted(&["https://example.com"], "https://other.com").await);
+
+        // any origin
+        assert!(accepted(&["*"], "https://other.com").await);
+
+        // no origin is allowed
+        assert!(!accepted(&[], "https://example.com").await);
+    }
+
+    #[test]
+    fn tokens_are_compared_whole() {
+        assert!(constant_time_eq(b"Bearer secret", b"Bearer secret"));
+        assert!(!constant_time_eq(b"Bearer secret", b"Bearer secreT"));
+        assert!(!constant_time_eq(b"Bearer secret", b"Bearer secret2"));
+        assert!(!constant_time_eq(b"Bearer secret", b""));
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn admin_endpoints(pool: SqlitePool) {
+        let (_, block_rx) = tokio::sync::mpsc::channel(1);
+        let provider =
+            JsonRpcClient::new(HttpTransport::new(Url::parse("http://localhost:5050").unwrap()));
+        let world = DojoWorld::new(
+            pool,
+            block_rx,
+            FieldElement::ONE,
+            Arc::new(provider),
+            DojoWorldConfig::default(),
+        );
+
+        let request = |path: &str, authorization: Option<&str>| {
+            let request = warp::test::request().method("POST").path(path);
+            match authorization {
+                Some(authorization) => request.header("authorization", authorization),
+                None => request,
+            }
+        };
+
+        // the endpoints don't exist without a token
+        let routes = admin_routes(world.clone(), None);
+        for authorization in [None, Some("Bearer "), Some("Bearer secret")] {
+            let res = request("/admin/subscriptions/pause", authorization).reply(&routes).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+        assert!(!world.subscriptions_paused());
+
+        // nor for the requests without the token
+        let routes = admin_routes(world.clone(), Some("secret".into()));
+        for authorization in [None, Some("secret"), Some("Bearer other"), Some("Bearer secret2")] {
+            let res = request("/admin/subscriptions/pause", authorization).reply(&routes).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+        assert!(!world.subscriptions_paused());
+
+        let res = request("/admin/subscriptions/pause", Some("Bearer secret")).reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body().as_ref(), br#"{"paused":true}"#);
+        assert!(world.subscriptions_paused());
+
+        let res =
+            request("/admin/subscriptions/resume", Some("Bearer secret")).reply(&routes).await;
+        assert_eq!(res.body().as_ref(), br#"{"paused":false}"#);
+        assert!(!world.subscriptions_paused());
+    }
+}